@@ -0,0 +1,70 @@
+use std::mem::MaybeUninit;
+
+/// A vector with a fixed capacity of `N` elements, which are stored inline rather than on the
+/// heap.
+///
+/// An `ArrayVec` is created by the [`collect_array`](crate::FlurryHashMap::collect_array) method
+/// on [`FlurryHashMap`](crate::FlurryHashMap). It dereferences to a slice of the elements it
+/// holds.
+pub struct ArrayVec<T, const N: usize> {
+    items: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> ArrayVec<T, N> {
+    pub(crate) fn new() -> Self {
+        ArrayVec {
+            // safety: an array of MaybeUninit does not need to be initialized
+            items: unsafe { MaybeUninit::<[MaybeUninit<T>; N]>::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    /// Adds `item` to the end, or returns it if the vector is full.
+    pub(crate) fn try_push(&mut self, item: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(item);
+        }
+        self.items[self.len] = MaybeUninit::new(item);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Returns the number of elements the vector can hold.
+    pub fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns `true` if the vector holds `N` elements.
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+}
+
+impl<T, const N: usize> std::ops::Deref for ArrayVec<T, N> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        // safety: the first len items have been initialized
+        unsafe { std::slice::from_raw_parts(self.items.as_ptr() as *const T, self.len) }
+    }
+}
+
+impl<T, const N: usize> std::ops::DerefMut for ArrayVec<T, N> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        // safety: the first len items have been initialized
+        unsafe { std::slice::from_raw_parts_mut(self.items.as_mut_ptr() as *mut T, self.len) }
+    }
+}
+
+impl<T, const N: usize> Drop for ArrayVec<T, N> {
+    fn drop(&mut self) {
+        // safety: the first len items have been initialized, and are never used again
+        unsafe { std::ptr::drop_in_place(&mut **self as *mut [T]) };
+    }
+}
+
+impl<T: std::fmt::Debug, const N: usize> std::fmt::Debug for ArrayVec<T, N> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,154 @@
+use crate::FlurryHashMap;
+use crossbeam::epoch::Guard;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// A context for performing a batch of operations on a [`FlurryHashMap`] under one `Guard`.
+///
+/// This `struct` is passed to the closure given to the [`batch`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`batch`]: crate::FlurryHashMap::batch
+pub struct BatchCtx<'m, K, V, S = RandomState> {
+    map: &'m FlurryHashMap<K, V, S>,
+    guard: RefCell<Guard>,
+    ops: usize,
+    since_pin: Cell<usize>,
+    repins: Cell<usize>,
+}
+
+impl<K, V, S> std::fmt::Debug for BatchCtx<'_, K, V, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BatchCtx")
+            .field("ops", &self.ops)
+            .field("since_pin", &self.since_pin.get())
+            .field("repins", &self.repins.get())
+            .finish()
+    }
+}
+
+impl<'m, K, V, S> BatchCtx<'m, K, V, S>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+    S: BuildHasher,
+{
+    pub(crate) fn new(map: &'m FlurryHashMap<K, V, S>, ops: usize) -> Self {
+        assert_ne!(ops, 0);
+        BatchCtx {
+            map,
+            guard: RefCell::new(crossbeam::epoch::pin()),
+            ops,
+            since_pin: Cell::new(0),
+            repins: Cell::new(0),
+        }
+    }
+
+    /// Counts one operation, and re-pins the guard if this completes a batch of `ops`.
+    fn tick(&self) {
+        let n = self.since_pin.get() + 1;
+        if n < self.ops {
+            self.since_pin.set(n);
+            return;
+        }
+        // if this operation was issued from within a closure run by another operation, that
+        // operation is still using the guard, and the re-pin is left to the next operation.
+        if let Ok(mut guard) = self.guard.try_borrow_mut() {
+            guard.repin();
+            self.since_pin.set(0);
+            self.repins.set(self.repins.get() + 1);
+        } else {
+            self.since_pin.set(n);
+        }
+    }
+
+    /// Like [`FlurryHashMap::insert`].
+    pub fn insert(&self, key: K, value: V) -> Option<()> {
+        let old = self.map.put(key, value, false, &self.guard.borrow());
+        self.tick();
+        old
+    }
+
+    /// Like [`FlurryHashMap::get_and`].
+    pub fn get_and<R, F: FnOnce(&V) -> R>(&self, key: &K, then: F) -> Option<R> {
+        let r = self.map.get(key, &self.guard.borrow()).map(then);
+        self.tick();
+        r
+    }
+
+    /// Like [`FlurryHashMap::contains_key`].
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get_and(key, |_| ()).is_some()
+    }
+
+    /// Removes `key` from the map, returning `true` if it was present.
+    ///
+    /// See [`FlurryHashMap::remove`].
+    pub fn remove(&self, key: &K) -> bool {
+        let removed = self.map.remove(key, &self.guard.borrow()).is_some();
+        self.tick();
+        removed
+    }
+
+    /// Returns how many times the guard has been re-pinned so far.
+    pub fn repins(&self) -> usize {
+        self.repins.get()
+    }
+}
+
+/// A fixed number of bits, packed into words.
+///
+/// This `struct` is returned by the [`contains_all`] method on [`FlurryHashMap`], with a bit for
+/// each of the keys it was given.
+///
+/// [`contains_all`]: crate::FlurryHashMap::contains_all
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BitVec {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitVec {
+    /// Returns `len` bits that are all unset.
+    pub(crate) fn zeroed(len: usize) -> Self {
+        BitVec {
+            words: vec![0; (len + 63) / 64],
+            len,
+        }
+    }
+
+    pub(crate) fn set(&mut self, i: usize) {
+        assert!(i < self.len);
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    /// Returns the bit at `i`, or `None` if `i` is out of bounds.
+    pub fn get(&self, i: usize) -> Option<bool> {
+        if i < self.len {
+            Some(self.words[i / 64] & (1 << (i % 64)) != 0)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bits that are set.
+    pub fn count_ones(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// An iterator over the bits, in order.
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(move |i| self.words[i / 64] & (1 << (i % 64)) != 0)
+    }
+}
@@ -0,0 +1,129 @@
+use crossbeam::epoch::{Atomic, Guard, Owned, Shared};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The number of filter bits per expected key. Together with `HASHES`, this gives a false
+/// positive rate of about 1% once the filter holds as many keys as expected.
+const BITS_PER_KEY: usize = 10;
+
+/// The number of bits set for each key.
+const HASHES: u64 = 7;
+
+#[cfg(test)]
+thread_local! {
+    static REJECTIONS: std::cell::Cell<usize> = std::cell::Cell::new(0);
+}
+
+/// A bloom filter over the hashes of the keys of a map.
+#[derive(Debug)]
+struct Filter {
+    words: Box<[AtomicU64]>,
+}
+
+impl Filter {
+    fn new(expected: usize) -> Self {
+        let bits = std::cmp::max(1, expected).saturating_mul(BITS_PER_KEY);
+        let words = ((bits + 63) / 64).next_power_of_two();
+        Filter {
+            words: (0..words).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Returns the bit positions for `hash`.
+    fn bits(&self, hash: u64) -> impl Iterator<Item = (usize, u64)> {
+        // double hashing derives all the positions from the two halves of the one hash. the
+        // step is odd, so that it visits all bits of the power-of-two sized filter.
+        let mask = (self.words.len() * 64 - 1) as u64;
+        let step = (hash >> 32) | 1;
+        (0..HASHES).map(move |i| {
+            let bit = hash.wrapping_add(i.wrapping_mul(step)) & mask;
+            ((bit / 64) as usize, 1 << (bit % 64))
+        })
+    }
+
+    fn insert(&self, hash: u64) {
+        for (word, bit) in self.bits(hash) {
+            self.words[word].fetch_or(bit, Ordering::SeqCst);
+        }
+    }
+
+    fn may_contain(&self, hash: u64) -> bool {
+        self.bits(hash)
+            .all(|(word, bit)| self.words[word].load(Ordering::SeqCst) & bit != 0)
+    }
+}
+
+/// A bloom filter in front of a map, for answering lookups of keys that are definitely absent
+/// without searching the table.
+///
+/// Keys must be added to the filter _before_ they are inserted into the map, so that a reader
+/// that finds a key missing from the filter can be sure that it is not in the map either. Keys
+/// are never removed from the filter, so it fills up with removed keys over time until it is
+/// replaced by a fresh one.
+#[derive(Debug)]
+pub(crate) struct Bloom {
+    filter: Atomic<Filter>,
+    expected: usize,
+}
+
+impl Bloom {
+    pub(crate) fn new(expected: usize) -> Self {
+        Bloom {
+            filter: Atomic::new(Filter::new(expected)),
+            expected,
+        }
+    }
+
+    /// Records that a key with the given hash may be in the map.
+    pub(crate) fn insert(&self, hash: u64, guard: &Guard) {
+        // safety: the filter is only dropped in the epoch after it has been replaced, and we read
+        // it under our guard.
+        unsafe { self.filter.load(Ordering::SeqCst, guard).deref() }.insert(hash);
+    }
+
+    /// Returns `false` if no key with the given hash is in the map.
+    pub(crate) fn may_contain(&self, hash: u64, guard: &Guard) -> bool {
+        // safety: as in insert
+        let found = unsafe { self.filter.load(Ordering::SeqCst, guard).deref() }.may_contain(hash);
+        #[cfg(test)]
+        {
+            if !found {
+                REJECTIONS.with(|r| r.set(r.get() + 1));
+            }
+        }
+        found
+    }
+
+    /// Replaces the filter with one that holds only the given hashes, sized for at least `len`
+    /// keys.
+    ///
+    /// No keys may be inserted into the map while this runs, or they may be missing from the new
+    /// filter.
+    pub(crate) fn rebuild(&self, len: usize, hashes: impl Iterator<Item = u64>, guard: &Guard) {
+        let filter = Filter::new(std::cmp::max(self.expected, len));
+        for hash in hashes {
+            filter.insert(hash);
+        }
+        let old = self
+            .filter
+            .swap(Owned::new(filter), Ordering::SeqCst, guard);
+        // safety: the old filter is no longer reachable, so only readers that loaded it earlier,
+        // and so are pinned to an epoch <= ours, can still access it.
+        unsafe { guard.defer_destroy(old) };
+    }
+}
+
+impl Drop for Bloom {
+    fn drop(&mut self) {
+        // safety: we have &mut self, so not concurrently accessed by anyone else
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+        let filter = self.filter.swap(Shared::null(), Ordering::SeqCst, guard);
+        // safety: same as above + the filter is never null until now
+        drop(unsafe { filter.into_owned() });
+    }
+}
+
+/// Returns the number of lookups on this thread so far that the filter answered on its own.
+#[cfg(test)]
+pub(crate) fn rejections() -> usize {
+    REJECTIONS.with(|r| r.get())
+}
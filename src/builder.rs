@@ -0,0 +1,236 @@
+use crate::wal::{Wal, WalRecord};
+use crate::{Adaptive, EvictionPolicy, FlurryHashMap, ResizePolicy, Resizer, Validator};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::AtomicIsize;
+use std::time::Duration;
+
+/// A builder for a [`FlurryHashMap`] with non-default options.
+///
+/// This `struct` is created by the [`builder`] method on [`FlurryHashMap`].
+///
+/// [`builder`]: crate::FlurryHashMap::builder
+#[derive(Debug)]
+pub struct Builder<K, V, S = RandomState> {
+    capacity: Option<usize>,
+    build_hasher: S,
+    wal: Option<Wal<K, V>>,
+    read_cache: bool,
+    bloom_filter: Option<usize>,
+    adaptive_resize: bool,
+    validator: Option<Validator<K, V>>,
+    visibility_barrier: bool,
+    soft_cap: Option<(usize, EvictionPolicy)>,
+    coalesce_writes: Option<Duration>,
+    resize_policy: Option<Resizer>,
+}
+
+impl<K, V> Builder<K, V, RandomState> {
+    pub(crate) fn new() -> Self {
+        Builder {
+            capacity: None,
+            build_hasher: RandomState::new(),
+            wal: None,
+            read_cache: false,
+            bloom_filter: None,
+            adaptive_resize: false,
+            validator: None,
+            visibility_barrier: false,
+            soft_cap: None,
+            coalesce_writes: None,
+            resize_policy: None,
+        }
+    }
+}
+
+impl<K, V, S> Builder<K, V, S>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+    S: BuildHasher,
+{
+    /// Sizes the initial table to accommodate `n` elements without the need to dynamically
+    /// resize.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is 0.
+    pub fn capacity(mut self, n: usize) -> Self {
+        assert_ne!(n, 0);
+        self.capacity = Some(n);
+        self
+    }
+
+    /// Uses `build_hasher` to hash keys.
+    pub fn hasher<S2>(self, build_hasher: S2) -> Builder<K, V, S2> {
+        Builder {
+            capacity: self.capacity,
+            build_hasher,
+            wal: self.wal,
+            read_cache: self.read_cache,
+            bloom_filter: self.bloom_filter,
+            adaptive_resize: self.adaptive_resize,
+            validator: self.validator,
+            visibility_barrier: self.visibility_barrier,
+            soft_cap: self.soft_cap,
+            coalesce_writes: self.coalesce_writes,
+            resize_policy: self.resize_policy,
+        }
+    }
+
+    /// Calls `wal` with a record of every mutation of the map before it becomes visible to other
+    /// threads, for example to keep a durable log from which the map can be recovered.
+    ///
+    /// Records for any one key are produced in the order their mutations take effect, since
+    /// `wal` is called while the lock for the key's bin is held. Any time spent in `wal` therefore
+    /// adds to the latency of writes to that bin. `wal` must not write to the map.
+    pub fn wal<F>(mut self, wal: F) -> Self
+    where
+        F: Fn(&WalRecord<'_, K, V>) + Send + Sync + 'static,
+    {
+        self.wal = Some(Wal(Box::new(wal)));
+        self
+    }
+
+    /// Has each thread remember the last few entries it read from the map, so that repeated
+    /// reads of the same keys can skip hashing the key and searching the table.
+    ///
+    /// This pays off when reads are heavily skewed towards a few hot keys. Every
+    /// [`get`](FlurryHashMap::get) that does not hit the cache becomes slightly more expensive,
+    /// and removals and resizes invalidate the caches of all threads.
+    pub fn read_cache(mut self) -> Self {
+        self.read_cache = true;
+        self
+    }
+
+    /// Keeps a bloom filter of the map's keys, sized for `expected` keys, so that lookups of keys
+    /// that are definitely absent can return without searching the table.
+    ///
+    /// A bloom filter is probabilistic: it never claims that a key in the map is absent, but it
+    /// may fail to tell that an absent key is absent, in which case the lookup searches the table
+    /// as usual. With `expected` keys in the map, about 1% of lookups of absent keys do. The
+    /// filter cannot forget removed keys, and does not grow with the map, so the rate rises as
+    /// keys are removed or the map grows beyond `expected`, until
+    /// [`rebuild_filter`](FlurryHashMap::rebuild_filter) is called.
+    ///
+    /// This pays off for large maps where most lookups miss. Every insertion and every lookup of
+    /// a present key becomes slightly more expensive.
+    pub fn bloom_filter(mut self, expected: usize) -> Self {
+        self.bloom_filter = Some(expected);
+        self
+    }
+
+    /// Has the map watch how often writers wait for each other's bin locks, and resize before
+    /// its [`resize_policy`](Builder::resize_policy) would if they do so often.
+    ///
+    /// This wraps the resize policy, which is [`LoadFactor`](crate::LoadFactor) unless another
+    /// one is given, in an [`Adaptive`] policy; see there for when it resizes. Watching for
+    /// contention makes every bin lock acquisition slightly more expensive. The current state is
+    /// reported by [`adaptive_state`](FlurryHashMap::adaptive_state).
+    pub fn adaptive_resize(mut self) -> Self {
+        self.adaptive_resize = true;
+        self
+    }
+
+    /// Has [`insert`](FlurryHashMap::insert) and [`try_insert`](FlurryHashMap::try_insert)
+    /// check every entry with `validator` before it is added to the map, and reject it if
+    /// `validator` returns `false`.
+    ///
+    /// This keeps entries that break an invariant of the data, such as an empty string as a
+    /// value, out of a map that many parts of a program write to. `validator` runs before any lock
+    /// is taken, so a slow validator does not hold up other writers. Other methods that write to
+    /// the map do not consult `validator`.
+    pub fn validator<F>(mut self, validator: F) -> Self
+    where
+        F: Fn(&K, &V) -> bool + Send + Sync + 'static,
+    {
+        self.validator = Some(Validator(Box::new(validator)));
+        self
+    }
+
+    /// Has [`insert`](FlurryHashMap::insert) and [`try_insert`](FlurryHashMap::try_insert)
+    /// issue a sequentially consistent fence before they return.
+    ///
+    /// An inserted entry is published with a sequentially consistent store, and so is visible
+    /// to any thread that learns of the insertion through a synchronizing channel. The fence also
+    /// orders the insertion before any later write of the inserting thread, including `Relaxed`
+    /// ones, so that a thread that reads such a write with `Acquire` ordering and then looks up
+    /// the entry is guaranteed to find it, without the writer having to release anything itself.
+    /// A full fence is among the most expensive instructions on most platforms, and every
+    /// insertion pays for it.
+    pub fn visibility_barrier(mut self) -> Self {
+        self.visibility_barrier = true;
+        self
+    }
+
+    /// Has [`insert`](FlurryHashMap::insert) and [`try_insert`](FlurryHashMap::try_insert)
+    /// evict entries picked by `policy` whenever an insertion takes the map beyond `n` entries,
+    /// which turns the map into a bounded cache.
+    ///
+    /// The entry that was just inserted is never the one evicted. Unless a
+    /// [`capacity`](Builder::capacity) is given, the table is sized for `n` entries, so that a
+    /// map that stays at its cap never resizes. Other methods that insert into the map do not
+    /// evict, and concurrent insertions may take the map slightly beyond `n` until they have all
+    /// evicted, so the cap is soft. Any policy other than [`Random`](EvictionPolicy::Random)
+    /// visits every entry of the map for each eviction.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is 0.
+    pub fn soft_cap(mut self, n: usize, policy: EvictionPolicy) -> Self {
+        assert_ne!(n, 0);
+        self.soft_cap = Some((n, policy));
+        self
+    }
+
+    /// Has [`insert_coalesced`](FlurryHashMap::insert_coalesced) stage writes, and only insert
+    /// the latest staged value of each key about once every `interval`.
+    ///
+    /// This trades how soon writes become visible for fewer acquisitions of the locks of hot
+    /// keys. Other methods that write to the map are not coalesced.
+    pub fn coalesce_writes(mut self, interval: Duration) -> Self {
+        self.coalesce_writes = Some(interval);
+        self
+    }
+
+    /// Has insertions ask `policy` when to resize the table, instead of resizing once the map is
+    /// three quarters full, as the default [`LoadFactor`](crate::LoadFactor) policy does.
+    ///
+    /// This lets the map grow by a criterion of its own, such as the length of the chains that
+    /// keys end up in, as with [`MaxChain`](crate::MaxChain). Explicit resizes, such as by
+    /// [`reserve_concurrent`](FlurryHashMap::reserve_concurrent), still happen as usual.
+    pub fn resize_policy<P>(mut self, policy: P) -> Self
+    where
+        P: ResizePolicy + 'static,
+    {
+        self.resize_policy = Some(Resizer(Box::new(policy)));
+        self
+    }
+
+    /// Creates the map.
+    pub fn build(self) -> FlurryHashMap<K, V, S> {
+        let mut map = FlurryHashMap::with_hasher(self.build_hasher);
+        if let Some(n) = self.capacity.or(self.soft_cap.map(|(n, _)| n)) {
+            map.size_ctl = AtomicIsize::new(crate::initial_capacity(n) as isize);
+        }
+        map.wal = self.wal;
+        map.validator = self.validator;
+        map.visibility_barrier = self.visibility_barrier;
+        map.soft_cap = self.soft_cap;
+        map.coalescer = self.coalesce_writes.map(crate::coalesce::Coalescer::new);
+        let policy = self.resize_policy.unwrap_or_default();
+        map.resize_policy = if self.adaptive_resize {
+            Resizer(Box::new(Adaptive(policy.0)))
+        } else {
+            policy
+        };
+        if self.read_cache {
+            map.read_cache = Some(crate::cache::ReadCache::new());
+        }
+        map.bloom = self.bloom_filter.map(crate::bloom::Bloom::new);
+        if map.resize_policy.0.watches_contention() {
+            map.contention = Some(crate::stats::Contention::default());
+        }
+        map
+    }
+}
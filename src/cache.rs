@@ -0,0 +1,123 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of recently read entries each thread remembers, across all maps.
+const SLOTS: usize = 8;
+
+/// The id of the next map to enable its read cache. Ids are never reused, so a slot can never be
+/// mistaken for one of a different map that happens to live at the same address.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(1);
+
+#[derive(Clone, Copy)]
+struct Slot {
+    /// The id of the map the node belongs to, or 0 if the slot is empty.
+    map: usize,
+    /// The map's generation at the time the node was found.
+    generation: usize,
+    node: *const (),
+}
+
+struct Slots {
+    slots: [Slot; SLOTS],
+    /// The slot to overwrite next.
+    next: usize,
+    #[cfg(test)]
+    hits: usize,
+}
+
+thread_local! {
+    static SLOTS_OF_THREAD: RefCell<Slots> = RefCell::new(Slots {
+        slots: [Slot {
+            map: 0,
+            generation: 0,
+            node: std::ptr::null(),
+        }; SLOTS],
+        next: 0,
+        #[cfg(test)]
+        hits: 0,
+    });
+}
+
+/// The per-map state of the thread-local cache of recently read nodes.
+///
+/// Cached nodes are validated against the map's generation, which is bumped whenever a node of
+/// the map is freed. A node found while the generation was `g` may therefore be used for as long
+/// as the generation is still `g`.
+#[derive(Debug)]
+pub(crate) struct ReadCache {
+    id: usize,
+    generation: AtomicUsize,
+}
+
+impl ReadCache {
+    pub(crate) fn new() -> Self {
+        ReadCache {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            generation: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the current generation.
+    ///
+    /// To cache a node, the generation must be read before the node is looked up.
+    pub(crate) fn generation(&self) -> usize {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// Marks every cached node as stale.
+    ///
+    /// Must be called after a node is made unreachable, and before it is handed to the garbage
+    /// collector.
+    pub(crate) fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns a node cached by this thread for which `matches` returns `true`.
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the type of node passed to `remember` for this map, and `generation` must have
+    /// been read while the epoch was pinned by the guard that `'g` is tied to.
+    pub(crate) unsafe fn find<'g, T>(
+        &self,
+        generation: usize,
+        matches: impl Fn(&T) -> bool,
+    ) -> Option<&'g T> {
+        // copied out, so that `matches` may itself read from a map without a double borrow
+        let slots = SLOTS_OF_THREAD.try_with(|s| s.borrow().slots).ok()?;
+        for slot in &slots {
+            if slot.map != self.id || slot.generation != generation {
+                continue;
+            }
+            // no node of this map has been freed since this one was found, and none that is
+            // freed from now on can be reclaimed while the epoch is pinned.
+            let node = &*(slot.node as *const T);
+            if matches(node) {
+                #[cfg(test)]
+                let _ = SLOTS_OF_THREAD.try_with(|s| s.borrow_mut().hits += 1);
+                return Some(node);
+            }
+        }
+        None
+    }
+
+    /// Caches `node`, which was found while the generation was `generation`.
+    pub(crate) fn remember<T>(&self, generation: usize, node: &T) {
+        let _ = SLOTS_OF_THREAD.try_with(|s| {
+            let mut s = s.borrow_mut();
+            let next = s.next;
+            s.slots[next] = Slot {
+                map: self.id,
+                generation,
+                node: node as *const T as *const (),
+            };
+            s.next = (next + 1) % SLOTS;
+        });
+    }
+}
+
+/// Returns the number of cache hits on this thread so far.
+#[cfg(test)]
+pub(crate) fn hits() -> usize {
+    SLOTS_OF_THREAD.with(|s| s.borrow().hits)
+}
@@ -0,0 +1,35 @@
+use std::collections::hash_map::RandomState;
+
+/// An owned copy of the entries of a [`FlurryHashMap`](crate::FlurryHashMap), and of the hash
+/// builder it used.
+///
+/// A `Checkpoint` is taken with [`checkpoint`](crate::FlurryHashMap::checkpoint). The map can
+/// then be rolled back to it with [`restore_from`](crate::FlurryHashMap::restore_from), or a new
+/// map can be made from it with [`from_checkpoint`](crate::FlurryHashMap::from_checkpoint).
+#[derive(Debug, Clone)]
+pub struct Checkpoint<K, V, S = RandomState> {
+    pub(crate) entries: Vec<(K, V)>,
+    pub(crate) build_hasher: S,
+}
+
+impl<K, V, S> Checkpoint<K, V, S> {
+    /// Returns the entries in the checkpoint, in arbitrary order.
+    pub fn entries(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    /// Returns the number of entries in the checkpoint.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the checkpoint contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the hash builder of the map the checkpoint was taken from.
+    pub fn hasher(&self) -> &S {
+        &self.build_hasher
+    }
+}
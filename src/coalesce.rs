@@ -0,0 +1,99 @@
+use crossbeam::utils::CachePadded;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+/// The number of shards the staged writes are spread over, so that writers of different keys
+/// rarely wait for each other.
+const SHARDS: usize = 16;
+
+#[derive(Debug)]
+struct Staged<K, V> {
+    /// The latest value staged for each key since the shard was last flushed.
+    pending: HashMap<K, V>,
+    /// When the shard was last flushed.
+    flushed: Instant,
+}
+
+#[derive(Debug)]
+struct Shard<K, V> {
+    staged: parking_lot::Mutex<Staged<K, V>>,
+    /// Held while a batch taken from the shard is applied, so that batches are applied in the
+    /// order they were taken, and a value is never overwritten by one staged before it. It is
+    /// taken before the staged writes are released, and staging may go on while it is held.
+    flushing: parking_lot::Mutex<()>,
+}
+
+/// The writes of `insert_coalesced` that have not been applied to the map yet.
+#[derive(Debug)]
+pub(crate) struct Coalescer<K, V> {
+    shards: Box<[CachePadded<Shard<K, V>>]>,
+    interval: Duration,
+}
+
+impl<K, V> Coalescer<K, V>
+where
+    K: Hash + Eq,
+{
+    pub(crate) fn new(interval: Duration) -> Self {
+        let now = Instant::now();
+        Coalescer {
+            shards: (0..SHARDS)
+                .map(|_| {
+                    CachePadded::new(Shard {
+                        staged: parking_lot::Mutex::new(Staged {
+                            pending: HashMap::new(),
+                            flushed: now,
+                        }),
+                        flushing: parking_lot::Mutex::new(()),
+                    })
+                })
+                .collect(),
+            interval,
+        }
+    }
+
+    /// Stages `value` as the latest value of `key`, which hashes to `hash`.
+    ///
+    /// If the shard of `key` has not been flushed for the coalescing interval, the shard's
+    /// staged writes are passed to `apply` instead of being kept.
+    pub(crate) fn stage<F>(&self, hash: u64, key: K, value: V, apply: F)
+    where
+        F: FnMut(K, V),
+    {
+        let shard = &self.shards[hash as usize % SHARDS];
+        let mut staged = shard.staged.lock();
+        staged.pending.insert(key, value);
+        if staged.flushed.elapsed() < self.interval {
+            return;
+        }
+        Self::flush_shard(shard, staged, apply);
+    }
+
+    /// Passes the staged writes of every shard to `apply`.
+    pub(crate) fn flush<F>(&self, mut apply: F)
+    where
+        F: FnMut(K, V),
+    {
+        for shard in self.shards.iter() {
+            Self::flush_shard(shard, shard.staged.lock(), &mut apply);
+        }
+    }
+
+    fn flush_shard<F>(
+        shard: &Shard<K, V>,
+        mut staged: parking_lot::MutexGuard<'_, Staged<K, V>>,
+        mut apply: F,
+    ) where
+        F: FnMut(K, V),
+    {
+        staged.flushed = Instant::now();
+        let pending = std::mem::take(&mut staged.pending);
+        // a batch taken earlier may still be being applied
+        let _flushing = shard.flushing.lock();
+        drop(staged);
+        for (key, value) in pending {
+            apply(key, value);
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use crossbeam::epoch::{Atomic, Guard, Owned, Shared};
+use crossbeam::utils::CachePadded;
+use std::sync::atomic::{AtomicIsize, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+/// The most cells a count is ever striped over.
+const MAX_CELLS: usize = 64;
+
+/// The most cells `approx` reads.
+pub(crate) const SAMPLED_CELLS: usize = 8;
+
+/// The probe of the next thread to update any count. Probes are handed out in order, so that
+/// threads that collide on a count spread over its cells evenly.
+static NEXT_PROBE: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The probe of this thread, and a token that is dropped when the thread exits.
+    static THREAD: (usize, Arc<()>) = (NEXT_PROBE.fetch_add(1, Ordering::Relaxed), Arc::new(()));
+}
+
+type Cell = Arc<CachePadded<AtomicIsize>>;
+
+/// The number of entries in a map, striped over a number of cells once updates start to contend.
+///
+/// This is the `CounterCell` scheme of the Java implementation: updates go to a single base count
+/// until two threads collide on it, after which each thread updates the cell its probe picks. The
+/// cells grow with the number of threads that have collided, and are only shrunk again by
+/// [`compact`](Counter::compact).
+#[derive(Debug)]
+pub(crate) struct Counter {
+    base: AtomicIsize,
+    /// Null until the first collision. Grown arrays share their cells with the arrays they
+    /// replace, so that updates that race with the growth are not lost.
+    cells: Atomic<Box<[Cell]>>,
+    /// The threads that have collided on the count. Also held while the cells are replaced.
+    contributors: parking_lot::Mutex<Vec<Weak<()>>>,
+    /// The number of updates that have gone to `add_contended`.
+    collisions: AtomicUsize,
+    /// Incremented by `compact` both before and after it moves the cells into the base, so that
+    /// it is odd while the two overlap.
+    compactions: AtomicUsize,
+}
+
+impl Counter {
+    pub(crate) fn new() -> Self {
+        Counter {
+            base: AtomicIsize::new(0),
+            cells: Atomic::null(),
+            contributors: parking_lot::Mutex::new(Vec::new()),
+            collisions: AtomicUsize::new(0),
+            compactions: AtomicUsize::new(0),
+        }
+    }
+
+    /// Adds `n` to the count.
+    pub(crate) fn add(&self, n: isize, guard: &Guard) {
+        let cells = self.cells.load(Ordering::SeqCst, guard);
+        let uncontended = if cells.is_null() {
+            let b = self.base.load(Ordering::SeqCst);
+            self.base
+                .compare_exchange(b, b + n, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        } else {
+            // safety: cells are only dropped in the epoch after they have been replaced, and we
+            // read them under our guard.
+            let cells = unsafe { cells.deref() };
+            match THREAD.try_with(|t| t.0) {
+                Ok(probe) => {
+                    let cell = &cells[probe & (cells.len() - 1)];
+                    let c = cell.load(Ordering::SeqCst);
+                    cell.compare_exchange(c, c + n, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_ok()
+                }
+                // the thread is exiting, so there is no point in striping its updates
+                Err(_) => false,
+            }
+        };
+        if !uncontended {
+            self.add_contended(n, guard);
+        }
+    }
+
+    /// Adds `n` to the count after colliding with another update, and grows the cells to fit the
+    /// threads that have collided so far.
+    pub(crate) fn add_contended(&self, n: isize, guard: &Guard) {
+        self.collisions.fetch_add(1, Ordering::Relaxed);
+        if let (Ok((_, token)), Some(mut contributors)) = (
+            THREAD.try_with(|t| (t.0, Arc::downgrade(&t.1))),
+            self.contributors.try_lock(),
+        ) {
+            contributors.retain(|c| c.strong_count() > 0);
+            if !contributors.iter().any(|c| c.ptr_eq(&token)) {
+                contributors.push(token);
+            }
+
+            let wanted = std::cmp::min(contributors.len().next_power_of_two(), MAX_CELLS);
+            let current = self.cells.load(Ordering::SeqCst, guard);
+            // safety: as in add
+            let mut cells: Vec<Cell> = match unsafe { current.as_ref() } {
+                Some(cells) => cells.to_vec(),
+                None => Vec::new(),
+            };
+            if cells.len() < wanted {
+                cells.resize_with(wanted, Default::default);
+                self.cells
+                    .store(Owned::new(cells.into_boxed_slice()), Ordering::SeqCst);
+                if !current.is_null() {
+                    // safety: the old cells are no longer reachable, so only threads that loaded
+                    // them earlier, and so are pinned to an epoch <= ours, can still access them.
+                    unsafe { guard.defer_destroy(current) };
+                }
+            }
+        }
+
+        // collisions are rare enough that they can all go to the base
+        self.base.fetch_add(n, Ordering::SeqCst);
+    }
+
+    /// Returns the sum of the base and the cells, loading each with the given ordering.
+    pub(crate) fn sum(&self, ordering: Ordering, guard: &Guard) -> usize {
+        loop {
+            let compactions = self.compactions.load(Ordering::SeqCst);
+            if compactions % 2 == 1 {
+                // compact has moved the sum of the cells into the base, but may not have replaced
+                // them yet, so both would be counted
+                std::thread::yield_now();
+                continue;
+            }
+            let cells = self.cells.load(Ordering::SeqCst, guard);
+            let mut sum = self.base.load(ordering);
+            // safety: as in add
+            if let Some(cells) = unsafe { cells.as_ref() } {
+                sum += cells.iter().map(|c| c.load(ordering)).sum::<isize>();
+            }
+            // likewise if a compaction started while we were summing
+            if self.compactions.load(Ordering::SeqCst) == compactions {
+                // a removal may be counted in a cell that was summed before the cell that counted
+                // the insertion it undoes
+                return std::cmp::max(sum, 0) as usize;
+            }
+        }
+    }
+
+    /// Estimates the sum from the base and every `k`th cell, where `k` is the number of cells
+    /// divided by `SAMPLED_CELLS`, if there are more than that. The sum of the cells that are read
+    /// is multiplied by `k`.
+    pub(crate) fn approx(&self, guard: &Guard) -> usize {
+        loop {
+            let compactions = self.compactions.load(Ordering::SeqCst);
+            let cells = self.cells.load(Ordering::Relaxed, guard);
+            let mut sum = self.base.load(Ordering::Relaxed);
+            // safety: as in add
+            if let Some(cells) = unsafe { cells.as_ref() } {
+                // the number of cells is a power of two, and so a multiple of the step
+                let step = std::cmp::max(cells.len() / SAMPLED_CELLS, 1);
+                let sampled: isize = cells
+                    .iter()
+                    .step_by(step)
+                    .map(|c| c.load(Ordering::Relaxed))
+                    .sum();
+                sum += sampled * step as isize;
+            }
+            // as in sum
+            if compactions % 2 == 0 && self.compactions.load(Ordering::SeqCst) == compactions {
+                return std::cmp::max(sum, 0) as usize;
+            }
+        }
+    }
+
+    /// Like `sum`, but only pins the current thread if the count is striped.
+    pub(crate) fn load(&self, ordering: Ordering) -> usize {
+        // safety: the cells are not dereferenced through this guard
+        let unprotected = unsafe { crossbeam::epoch::unprotected() };
+        if self.cells.load(Ordering::SeqCst, unprotected).is_null() {
+            // compact moves the cells into the base before it drops them
+            return std::cmp::max(self.base.load(ordering), 0) as usize;
+        }
+        self.sum(ordering, &crossbeam::epoch::pin())
+    }
+
+    /// Changes the count so that it sums to `n`.
+    pub(crate) fn set(&self, n: usize, guard: &Guard) {
+        let cells = self.cells.load(Ordering::SeqCst, guard);
+        // safety: as in add
+        let striped = match unsafe { cells.as_ref() } {
+            Some(cells) => cells.iter().map(|c| c.load(Ordering::SeqCst)).sum(),
+            None => 0,
+        };
+        self.base.store(n as isize - striped, Ordering::SeqCst);
+    }
+
+    /// Collapses the cells into the base, and shrinks them to fit the threads that have collided
+    /// on the count and are still running. If none are, the cells are dropped entirely.
+    ///
+    /// The count must not be updated while this runs.
+    pub(crate) fn compact(&self, guard: &Guard) {
+        let mut contributors = self.contributors.lock();
+        contributors.retain(|c| c.strong_count() > 0);
+
+        let current = self.cells.load(Ordering::SeqCst, guard);
+        // safety: as in add
+        let cells = match unsafe { current.as_ref() } {
+            Some(cells) => cells,
+            None => return,
+        };
+        // concurrent sums wait until the cells have been replaced, and start over if they overlap
+        // with this. the contributors lock keeps other compactions out.
+        self.compactions.fetch_add(1, Ordering::SeqCst);
+        let striped: isize = cells.iter().map(|c| c.load(Ordering::SeqCst)).sum();
+        self.base.fetch_add(striped, Ordering::SeqCst);
+        let compacted = if contributors.is_empty() {
+            Shared::null()
+        } else {
+            let wanted = std::cmp::min(contributors.len().next_power_of_two(), cells.len());
+            let cells: Vec<Cell> = (0..wanted).map(|_| Cell::default()).collect();
+            Owned::new(cells.into_boxed_slice()).into_shared(guard)
+        };
+        self.cells.store(compacted, Ordering::SeqCst);
+        self.compactions.fetch_add(1, Ordering::SeqCst);
+        // safety: as in add_contended
+        unsafe { guard.defer_destroy(current) };
+    }
+
+    /// Returns how the count is currently striped.
+    pub(crate) fn stats(&self, guard: &Guard) -> crate::stats::CounterStats {
+        let cells = self.cells.load(Ordering::SeqCst, guard);
+        // safety: as in add
+        let cells = unsafe { cells.as_ref() }.map_or(&[][..], |cells| &cells[..]);
+        crate::stats::CounterStats {
+            cells: cells.len(),
+            active_cells: cells
+                .iter()
+                .filter(|c| c.load(Ordering::SeqCst) != 0)
+                .count(),
+            base: self.base.load(Ordering::SeqCst),
+            collisions: self.collisions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns what each cell has counted.
+    #[cfg(test)]
+    pub(crate) fn cell_counts(&self, guard: &Guard) -> Vec<isize> {
+        // safety: as in add
+        unsafe { self.cells.load(Ordering::SeqCst, guard).as_ref() }.map_or(Vec::new(), |cells| {
+            cells.iter().map(|c| c.load(Ordering::SeqCst)).collect()
+        })
+    }
+
+    /// Returns the number of cells the count is striped over.
+    #[cfg(test)]
+    pub(crate) fn cells(&self, guard: &Guard) -> usize {
+        // safety: as in add
+        unsafe { self.cells.load(Ordering::SeqCst, guard).as_ref() }.map_or(0, |cells| cells.len())
+    }
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        // safety: we have &mut self, so not concurrently accessed by anyone else
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+        let cells = self.cells.swap(Shared::null(), Ordering::SeqCst, guard);
+        if !cells.is_null() {
+            // safety: same as above + we own the cells
+            drop(unsafe { cells.into_owned() });
+        }
+    }
+}
@@ -0,0 +1,40 @@
+/// The changes that turn the entries of one [`FlurryHashMap`](crate::FlurryHashMap) into those of
+/// another.
+///
+/// A `MapDiff` is computed with [`diff`](crate::FlurryHashMap::diff), and can then be applied to
+/// any map with [`apply_diff`](crate::FlurryHashMap::apply_diff), which need not be the map it
+/// was computed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapDiff<K, V> {
+    pub(crate) added: Vec<(K, V)>,
+    pub(crate) removed: Vec<(K, V)>,
+    pub(crate) changed: Vec<(K, V, V)>,
+}
+
+impl<K, V> MapDiff<K, V> {
+    /// Returns the entries whose keys are only in the target map, in arbitrary order.
+    pub fn added(&self) -> &[(K, V)] {
+        &self.added
+    }
+
+    /// Returns the entries whose keys are only in the original map, in arbitrary order.
+    pub fn removed(&self) -> &[(K, V)] {
+        &self.removed
+    }
+
+    /// Returns the keys that are in both maps with different values, along with the value in the
+    /// original map and the value in the target map, in arbitrary order.
+    pub fn changed(&self) -> &[(K, V, V)] {
+        &self.changed
+    }
+
+    /// Returns the number of keys that differ between the two maps.
+    pub fn len(&self) -> usize {
+        self.added.len() + self.removed.len() + self.changed.len()
+    }
+
+    /// Returns `true` if the two maps hold the same entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, Write};
+
+/// The formats that [`export`](crate::FlurryHashMap::export) can write entries in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// Comma-separated values: a `key,value` header line, followed by one line per entry.
+    ///
+    /// Fields that contain a comma, a double quote, or a line break are enclosed in double
+    /// quotes, with any double quotes in them doubled, as in RFC 4180. Lines end with `\n`.
+    Csv,
+    /// Line-delimited JSON: one `{"key":...,"value":...}` object per line.
+    ///
+    /// Numbers and booleans are written as JSON numbers and booleans, and everything else as
+    /// JSON strings.
+    JsonLines,
+}
+
+/// A key or value that [`export`](crate::FlurryHashMap::export) knows how to write.
+///
+/// This is implemented for the primitive integer types, `bool`, `char`, `str`, and `String`,
+/// and for references to any of them. Implement it for other types to export maps of them.
+pub trait ExportField {
+    /// Writes the field as a JSON value.
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()>;
+
+    /// Writes the field as a CSV field.
+    fn write_csv(&self, w: &mut dyn Write) -> io::Result<()>;
+}
+
+/// Writes `s` as a JSON string.
+pub fn write_json_str(w: &mut dyn Write, s: &str) -> io::Result<()> {
+    w.write_all(b"\"")?;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        let escaped = match c {
+            '"' => "\\\"",
+            '\\' => "\\\\",
+            '\n' => "\\n",
+            '\r' => "\\r",
+            '\t' => "\\t",
+            c if c < ' ' => "",
+            _ => continue,
+        };
+        w.write_all(&s.as_bytes()[start..i])?;
+        if escaped.is_empty() {
+            write!(w, "\\u{:04x}", c as u32)?;
+        } else {
+            w.write_all(escaped.as_bytes())?;
+        }
+        start = i + c.len_utf8();
+    }
+    w.write_all(&s.as_bytes()[start..])?;
+    w.write_all(b"\"")
+}
+
+/// Writes `s` as a CSV field, quoting it if it has to be.
+pub fn write_csv_str(w: &mut dyn Write, s: &str) -> io::Result<()> {
+    if !s.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        return w.write_all(s.as_bytes());
+    }
+    w.write_all(b"\"")?;
+    for (i, part) in s.split('"').enumerate() {
+        if i != 0 {
+            w.write_all(b"\"\"")?;
+        }
+        w.write_all(part.as_bytes())?;
+    }
+    w.write_all(b"\"")
+}
+
+macro_rules! export_display {
+    ($($t:ty)*) => {
+        $(
+            impl ExportField for $t {
+                fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+                    write!(w, "{}", self)
+                }
+
+                fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+                    write!(w, "{}", self)
+                }
+            }
+        )*
+    };
+}
+
+export_display!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize bool);
+
+impl ExportField for str {
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_json_str(w, self)
+    }
+
+    fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_csv_str(w, self)
+    }
+}
+
+impl ExportField for String {
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_json_str(w, self)
+    }
+
+    fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_csv_str(w, self)
+    }
+}
+
+impl ExportField for char {
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_json_str(w, self.encode_utf8(&mut [0; 4]))
+    }
+
+    fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+        write_csv_str(w, self.encode_utf8(&mut [0; 4]))
+    }
+}
+
+impl<T: ExportField + ?Sized> ExportField for &T {
+    fn write_json(&self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).write_json(w)
+    }
+
+    fn write_csv(&self, w: &mut dyn Write) -> io::Result<()> {
+        (**self).write_csv(w)
+    }
+}
+
+/// Writes the lines of `format` for `entries` to `w`.
+pub(crate) fn write_entries<'a, K, V, I>(
+    w: &mut dyn Write,
+    format: ExportFormat,
+    entries: I,
+) -> io::Result<()>
+where
+    K: ExportField + 'a,
+    V: ExportField + 'a,
+    I: Iterator<Item = (&'a K, &'a V)>,
+{
+    match format {
+        ExportFormat::Csv => {
+            w.write_all(b"key,value\n")?;
+            for (k, v) in entries {
+                k.write_csv(w)?;
+                w.write_all(b",")?;
+                v.write_csv(w)?;
+                w.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::JsonLines => {
+            for (k, v) in entries {
+                w.write_all(b"{\"key\":")?;
+                k.write_json(w)?;
+                w.write_all(b",\"value\":")?;
+                v.write_json(w)?;
+                w.write_all(b"}\n")?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+use std::collections::hash_map::{self, HashMap, RandomState};
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Bound, Index, RangeBounds};
+
+/// An immutable copy of the entries of a [`FlurryHashMap`](crate::FlurryHashMap), which can be
+/// read without a `Guard`.
+///
+/// A `FrozenHashMap` is taken with
+/// [`snapshot_shared`](crate::FlurryHashMap::snapshot_shared), which hands it out in an `Arc` so
+/// that any number of threads can share it, or made from the map itself with
+/// [`freeze`](crate::FlurryHashMap::freeze). Since it never changes, reading it involves no
+/// epochs or atomics at all, and every reader sees the same set of entries. To publish changes to
+/// the source map, take a new snapshot.
+#[derive(Debug, Clone)]
+pub struct FrozenHashMap<K, V, S = RandomState> {
+    pub(crate) map: HashMap<K, V, S>,
+}
+
+impl<K, V, S> FrozenHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Returns the value to which `key` is mapped.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.map.get(key)
+    }
+
+    /// Tests if `key` is a key in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.map.contains_key(key)
+    }
+}
+
+impl<K, V, S> FrozenHashMap<K, V, S> {
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    pub fn iter(&self) -> hash_map::Iter<'_, K, V> {
+        self.map.iter()
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+}
+
+impl<K, V, S> Index<&K> for FrozenHashMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Output = V;
+
+    /// Returns the value to which `key` is mapped.
+    ///
+    /// # Panics
+    ///
+    /// If `key` is not in the map.
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a FrozenHashMap<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = hash_map::Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// A sorted copy of the keys of a [`FlurryHashMap`](crate::FlurryHashMap), for ordered lookups.
+///
+/// This `struct` is created by the [`build_sorted_index`] method on [`FlurryHashMap`]. It holds
+/// the keys that were in the map when it was built, and is not updated as the map changes, so it
+/// only answers ordered queries about that point in time. To pick up later changes, build a new
+/// one.
+///
+/// [`build_sorted_index`]: crate::FlurryHashMap::build_sorted_index
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortedIndex<K> {
+    pub(crate) keys: Vec<K>,
+}
+
+impl<K: Ord> SortedIndex<K> {
+    /// Searches the index for `key`, as with [`slice::binary_search`].
+    ///
+    /// Returns `Ok` with the position of `key` if it is in the index, and otherwise `Err` with
+    /// the position at which it would have to be inserted to keep the index sorted.
+    pub fn binary_search(&self, key: &K) -> Result<usize, usize> {
+        self.keys.binary_search(key)
+    }
+
+    /// Tests if `key` is in the index.
+    pub fn contains(&self, key: &K) -> bool {
+        self.binary_search(key).is_ok()
+    }
+
+    /// Returns the keys of the index that fall within `range`, in ascending order.
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> &[K] {
+        // the position of the first key that `before` says is not before the bound
+        let first_not = |before: &dyn Fn(&K) -> bool| {
+            self.keys
+                .binary_search_by(|k| {
+                    if before(k) {
+                        std::cmp::Ordering::Less
+                    } else {
+                        std::cmp::Ordering::Greater
+                    }
+                })
+                .unwrap_err()
+        };
+        let start = match range.start_bound() {
+            Bound::Included(s) => first_not(&|k| k < s),
+            Bound::Excluded(s) => first_not(&|k| k <= s),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => first_not(&|k| k <= e),
+            Bound::Excluded(e) => first_not(&|k| k < e),
+            Bound::Unbounded => self.keys.len(),
+        };
+        if start >= end {
+            return &[];
+        }
+        &self.keys[start..end]
+    }
+}
+
+impl<K> SortedIndex<K> {
+    /// Returns the keys of the index in ascending order.
+    pub fn as_slice(&self) -> &[K] {
+        &self.keys
+    }
+
+    /// Returns the number of keys in the index.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if the index contains no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
@@ -0,0 +1,142 @@
+use crossbeam::utils::CachePadded;
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// The number of counters the writes in progress are spread over, so that writers on different
+/// threads rarely touch the same cache line.
+const STRIPES: usize = 16;
+
+/// The stripe of the next thread to write to any map.
+static NEXT_STRIPE: AtomicUsize = AtomicUsize::new(0);
+
+/// The id of the next gate. Ids are never reused, so a gate can never be mistaken for one that
+/// used to live at the same address.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The stripe of this thread, and the ids of the gates it is currently writing through.
+    static THREAD: (usize, RefCell<Vec<usize>>) =
+        (NEXT_STRIPE.fetch_add(1, Ordering::Relaxed) % STRIPES, RefCell::new(Vec::new()));
+}
+
+/// Keeps writes to a map out while it is paused.
+///
+/// Writers announce themselves in the stripe of their thread and then check that the gate is
+/// not paused, while a pause is announced before it waits for all the stripes to drain. Either
+/// the writer sees the pause and backs off, or the pause sees the writer and waits for it, so
+/// writers only ever touch their own stripe unless the map is being paused. New writers back off
+/// as soon as a pause is announced, so a pause cannot be starved by a steady stream of writes.
+#[derive(Debug)]
+pub(crate) struct WriteGate {
+    id: usize,
+    paused: AtomicBool,
+    writers: Box<[CachePadded<AtomicUsize>]>,
+    /// Held for as long as the gate is paused. Writers that back off wait for it.
+    pausing: parking_lot::Mutex<()>,
+}
+
+impl WriteGate {
+    pub(crate) fn new() -> Self {
+        WriteGate {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            paused: AtomicBool::new(false),
+            writers: (0..STRIPES).map(|_| Default::default()).collect(),
+            pausing: parking_lot::Mutex::new(()),
+        }
+    }
+
+    /// Enters the gate for a write, waiting while it is paused.
+    ///
+    /// A write that is issued from within another write through the same gate on the same
+    /// thread, such as from a closure passed to the outer one, is let through right away, since
+    /// the outer write already keeps the gate from being paused.
+    pub(crate) fn enter(&self) -> Writing<'_> {
+        let stripe = match THREAD.try_with(|t| {
+            let mut entered = t.1.borrow_mut();
+            if entered.contains(&self.id) {
+                return None;
+            }
+            entered.push(self.id);
+            Some(t.0)
+        }) {
+            Ok(Some(stripe)) => stripe,
+            Ok(None) => {
+                return Writing {
+                    gate: self,
+                    stripe: None,
+                }
+            }
+            // the thread is exiting, and cannot issue nested writes anymore
+            Err(_) => 0,
+        };
+        let writers = &self.writers[stripe];
+        loop {
+            writers.fetch_add(1, Ordering::SeqCst);
+            if !self.paused.load(Ordering::SeqCst) {
+                return Writing {
+                    gate: self,
+                    stripe: Some(stripe),
+                };
+            }
+            writers.fetch_sub(1, Ordering::SeqCst);
+            drop(self.pausing.lock());
+        }
+    }
+
+    /// Pauses the gate once all the writes that have entered it have left it. The gate stays
+    /// paused until the returned value is dropped.
+    ///
+    /// # Panics
+    ///
+    /// If this thread is itself writing through the gate, since the pause would then wait for a
+    /// write that cannot finish until the pause does.
+    pub(crate) fn pause(&self) -> Paused<'_> {
+        let writing = THREAD
+            .try_with(|t| t.1.borrow().contains(&self.id))
+            .unwrap_or(false);
+        assert!(
+            !writing,
+            "writes to a map cannot be paused from within a write to it, such as from a closure \
+             passed to one"
+        );
+        let pausing = self.pausing.lock();
+        self.paused.store(true, Ordering::SeqCst);
+        while self.writers.iter().any(|w| w.load(Ordering::SeqCst) != 0) {
+            std::thread::yield_now();
+        }
+        Paused {
+            gate: self,
+            _pausing: pausing,
+        }
+    }
+}
+
+/// A write that has entered a [`WriteGate`], and keeps it from being paused until dropped.
+pub(crate) struct Writing<'a> {
+    gate: &'a WriteGate,
+    /// The stripe this write was counted in, or `None` if it is nested in another one.
+    stripe: Option<usize>,
+}
+
+impl Drop for Writing<'_> {
+    fn drop(&mut self) {
+        if let Some(stripe) = self.stripe {
+            let id = self.gate.id;
+            let _ = THREAD.try_with(|t| t.1.borrow_mut().retain(|&e| e != id));
+            self.gate.writers[stripe].fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// A pause of a [`WriteGate`], which lets writers back in when dropped.
+pub(crate) struct Paused<'a> {
+    gate: &'a WriteGate,
+    _pausing: parking_lot::MutexGuard<'a, ()>,
+}
+
+impl Drop for Paused<'_> {
+    fn drop(&mut self) {
+        // before the lock is released, so that the writers waiting for it do not back off again
+        self.gate.paused.store(false, Ordering::SeqCst);
+    }
+}
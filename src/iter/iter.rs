@@ -1,7 +1,9 @@
 use crossbeam::epoch::Guard;
-use std::sync::atomic::Ordering;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-use crate::NodeIter;
+use crate::{Node, NodeIter, Table};
 
 /// An iterator over the entries of a `FlurryHashMap`.
 ///
@@ -13,17 +15,38 @@ use crate::NodeIter;
 #[derive(Debug)]
 pub struct Iter<'g, K, V> {
     pub(crate) node_iter: NodeIter<'g, K, V>,
-    pub(crate) guard: &'g Guard,
+}
+
+impl<'g, K, V> Iter<'g, K, V> {
+    /// Sets whether the iterator follows bins that a concurrent resize moves to the new table.
+    ///
+    /// By default it does (`true`): when the iterator reaches a bin that has been moved, it
+    /// visits the bins of the new table that the entries were moved to instead. Every entry that
+    /// is in the map for the whole iteration is then visited exactly once, but some of the
+    /// entries visited may have been written after the iteration started.
+    ///
+    /// With `false`, the iterator visits bins that are moved after it was created as they were
+    /// when they were moved, rather than following them to the new table. Every entry that is in
+    /// the map when the iterator is created is then visited exactly once, unless it is removed
+    /// before the iterator or a resize gets to its bin, and entries and values written to a bin
+    /// after it was moved are not seen. When no resize happens, both modes visit the same
+    /// entries.
+    ///
+    /// This must be set before the iterator is first advanced.
+    pub fn follow_moves(mut self, follow: bool) -> Self {
+        self.node_iter.set_follow_moves(follow);
+        self
+    }
 }
 
 impl<'g, K, V> Iterator for Iter<'g, K, V> {
     type Item = (&'g K, &'g V);
     fn next(&mut self) -> Option<Self::Item> {
         let node = self.node_iter.next()?;
-        let value = node.value.load(Ordering::SeqCst, self.guard);
+        let value = self.node_iter.value(node);
         // safety: flurry does not drop or move until after guard drop
         let value = unsafe { value.deref() };
-        return Some((&node.key, &value));
+        Some((&node.key, value))
     }
 }
 
@@ -39,11 +62,20 @@ pub struct Keys<'g, K, V> {
     pub(crate) node_iter: NodeIter<'g, K, V>,
 }
 
+impl<'g, K, V> Keys<'g, K, V> {
+    /// Sets whether the iterator follows bins that a concurrent resize moves to the new table.
+    /// See [`Iter::follow_moves`] for what this means.
+    pub fn follow_moves(mut self, follow: bool) -> Self {
+        self.node_iter.set_follow_moves(follow);
+        self
+    }
+}
+
 impl<'g, K, V> Iterator for Keys<'g, K, V> {
     type Item = &'g K;
     fn next(&mut self) -> Option<Self::Item> {
         let node = self.node_iter.next()?;
-        return Some(&node.key);
+        Some(&node.key)
     }
 }
 
@@ -57,17 +89,425 @@ impl<'g, K, V> Iterator for Keys<'g, K, V> {
 #[derive(Debug)]
 pub struct Values<'g, K, V> {
     pub(crate) node_iter: NodeIter<'g, K, V>,
-    pub(crate) guard: &'g Guard,
+}
+
+impl<'g, K, V> Values<'g, K, V> {
+    /// Sets whether the iterator follows bins that a concurrent resize moves to the new table.
+    /// See [`Iter::follow_moves`] for what this means.
+    pub fn follow_moves(mut self, follow: bool) -> Self {
+        self.node_iter.set_follow_moves(follow);
+        self
+    }
 }
 
 impl<'g, K, V> Iterator for Values<'g, K, V> {
     type Item = &'g V;
     fn next(&mut self) -> Option<Self::Item> {
         let node = self.node_iter.next()?;
-        let value = node.value.load(Ordering::SeqCst, self.guard);
+        let value = self.node_iter.value(node);
+        // safety: flurry does not drop or move until after guard drop
+        let value = unsafe { value.deref() };
+        Some(value)
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` whose keys match a predicate.
+///
+/// This `struct` is created by the [`iter_keys_matching`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_keys_matching`]: /flurry/struct.FlurryHashMap.html#method.iter_keys_matching
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct KeysMatching<'g, K, V, P> {
+    pub(crate) node_iter: NodeIter<'g, K, V>,
+    pub(crate) pred: P,
+}
+
+impl<'g, K, V, P> Iterator for KeysMatching<'g, K, V, P>
+where
+    P: Fn(&K) -> bool,
+{
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node_iter.next()?;
+            if !(self.pred)(&node.key) {
+                // only the key decides, so don't bother reading the value
+                continue;
+            }
+            let value = self.node_iter.value(node);
+            // safety: flurry does not drop or move until after guard drop
+            let value = unsafe { value.deref() };
+            return Some((&node.key, value));
+        }
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` whose values were last written before a
+/// cutoff.
+///
+/// This `struct` is created by the [`iter_older_than`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_older_than`]: /flurry/struct.FlurryHashMap.html#method.iter_older_than
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[cfg(feature = "timestamps")]
+#[derive(Debug)]
+pub struct OlderThan<'g, K, V> {
+    pub(crate) node_iter: NodeIter<'g, K, V>,
+    pub(crate) cutoff: std::time::Instant,
+}
+
+#[cfg(feature = "timestamps")]
+impl<'g, K, V> Iterator for OlderThan<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.node_iter.next()?;
+            if node.written.load() >= self.cutoff {
+                continue;
+            }
+            let value = self.node_iter.value(node);
+            // safety: flurry does not drop or move until after guard drop
+            let value = unsafe { value.deref() };
+            return Some((&node.key, value));
+        }
+    }
+}
+
+/// An entry of a `FlurryHashMap`, along with where it is stored in the map.
+///
+/// This `struct` is yielded by [`Diagnostic`].
+#[derive(Debug)]
+pub struct DiagEntry<'g, K, V> {
+    /// The key of the entry.
+    pub key: &'g K,
+    /// The value of the entry.
+    pub value: &'g V,
+    /// The hash the key was inserted with.
+    pub hash: u64,
+    /// The index of the bin the entry was found in.
+    ///
+    /// During a resize, this may be a bin of the table the entry is being moved to, rather than
+    /// of the current table.
+    pub bin_index: usize,
+    /// The number of entries ahead of this one in its bin, so 0 for the first entry of a bin.
+    pub chain_depth: usize,
+}
+
+/// An iterator over the entries of a `FlurryHashMap`, along with where each is stored.
+///
+/// This `struct` is created by the [`iter_diagnostic`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_diagnostic`]: /flurry/struct.FlurryHashMap.html#method.iter_diagnostic
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct Diagnostic<'g, K, V> {
+    pub(crate) node_iter: NodeIter<'g, K, V>,
+}
+
+impl<'g, K, V> Iterator for Diagnostic<'g, K, V> {
+    type Item = DiagEntry<'g, K, V>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node_iter.next()?;
+        let (bin_index, chain_depth) = self.node_iter.position();
+        let value = self.node_iter.value(node);
+        // safety: flurry does not drop or move until after guard drop
+        let value = unsafe { value.deref() };
+        Some(DiagEntry {
+            key: &node.key,
+            value,
+            hash: node.hash,
+            bin_index,
+            chain_depth,
+        })
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap`, along with the shard each belongs to.
+///
+/// This `struct` is created by the [`iter_with_shard`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_with_shard`]: /flurry/struct.FlurryHashMap.html#method.iter_with_shard
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct WithShard<'g, K, V> {
+    pub(crate) node_iter: NodeIter<'g, K, V>,
+    pub(crate) num_shards: u64,
+}
+
+impl<'g, K, V> Iterator for WithShard<'g, K, V> {
+    type Item = (usize, &'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.node_iter.next()?;
+        let value = self.node_iter.value(node);
+        // safety: flurry does not drop or move until after guard drop
+        let value = unsafe { value.deref() };
+        Some(((node.hash % self.num_shards) as usize, &node.key, value))
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` that stops early when cancelled.
+///
+/// This `struct` is created by the [`iter_cancellable`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_cancellable`]: /flurry/struct.FlurryHashMap.html#method.iter_cancellable
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct Cancellable<'g, K, V> {
+    pub(crate) node_iter: NodeIter<'g, K, V>,
+    pub(crate) prev: Option<&'g Node<K, V>>,
+    pub(crate) cancel: &'g AtomicBool,
+    pub(crate) cancelled: bool,
+    pub(crate) guard: &'g Guard,
+}
+
+impl<'g, K, V> Iterator for Cancellable<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cancelled {
+            return None;
+        }
+        let at_bin_end = match self.prev {
+            Some(prev) => prev.next.load(Ordering::SeqCst, self.guard).is_null(),
+            None => true,
+        };
+        if at_bin_end && self.cancel.load(Ordering::Relaxed) {
+            self.cancelled = true;
+            return None;
+        }
+
+        let node = self.node_iter.next()?;
+        self.prev = Some(node);
+        let value = self.node_iter.value(node);
         // safety: flurry does not drop or move until after guard drop
         let value = unsafe { value.deref() };
-        return Some(&value);
+        Some((&node.key, value))
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` that skips entries whose derived key has
+/// already been seen.
+///
+/// This `struct` is created by the [`iter_unique_by`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_unique_by`]: /flurry/struct.FlurryHashMap.html#method.iter_unique_by
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct UniqueBy<'g, K, V, B, F> {
+    pub(crate) iter: Iter<'g, K, V>,
+    pub(crate) seen: HashSet<B>,
+    pub(crate) f: F,
+}
+
+impl<'g, K, V, B, F> Iterator for UniqueBy<'g, K, V, B, F>
+where
+    B: Eq + Hash,
+    F: Fn(&K, &V) -> B,
+{
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (k, v) = self.iter.next()?;
+            if self.seen.insert((self.f)(k, v)) {
+                return Some((k, v));
+            }
+        }
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` in an order given by a key comparator.
+///
+/// This `struct` is created by the [`iter_sorted_by`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_sorted_by`]: /flurry/struct.FlurryHashMap.html#method.iter_sorted_by
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct SortedBy<'g, K, V> {
+    pub(crate) entries: std::vec::IntoIter<(&'g K, &'g V)>,
+}
+
+impl<'g, K, V> Iterator for SortedBy<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for SortedBy<'_, K, V> {}
+
+/// An iterator over the entries of a `FlurryHashMap` that were gathered in a pass over the map
+/// that no resize interfered with, if possible.
+///
+/// This `struct` is created by the [`iter_stable_retry`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_stable_retry`]: /flurry/struct.FlurryHashMap.html#method.iter_stable_retry
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct StableRetry<'g, K, V> {
+    pub(crate) entries: std::vec::IntoIter<(&'g K, &'g V)>,
+    pub(crate) clean: bool,
+}
+
+impl<K, V> StableRetry<'_, K, V> {
+    /// Returns `true` if the entries were gathered in a pass over the map during which it did not
+    /// resize, and `false` if every attempt ran into a resize.
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+impl<'g, K, V> Iterator for StableRetry<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for StableRetry<'_, K, V> {}
+
+/// An owning iterator over clones of the entries of a `FlurryHashMap` in ascending key order.
+///
+/// This `struct` is created by the [`sorted_run`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`sorted_run`]: /flurry/struct.FlurryHashMap.html#method.sorted_run
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct SortedRun<K, V> {
+    pub(crate) entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for SortedRun<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for SortedRun<K, V> {}
+
+/// An iterator over clones of the entries of a `FlurryHashMap`, which clones each entry only
+/// once it is reached.
+///
+/// This `struct` is created by the [`iter_owned_lazy`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_owned_lazy`]: /flurry/struct.FlurryHashMap.html#method.iter_owned_lazy
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct OwnedLazy<'g, K, V> {
+    pub(crate) iter: Iter<'g, K, V>,
+}
+
+impl<'g, K, V> Iterator for OwnedLazy<'g, K, V>
+where
+    K: Clone,
+    V: Clone,
+{
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, v) = self.iter.next()?;
+        Some((k.clone(), v.clone()))
+    }
+}
+
+/// An owning iterator over clones of one share of the entries of a `FlurryHashMap`.
+///
+/// This `struct` is created by the [`split_for`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`split_for`]: /flurry/struct.FlurryHashMap.html#method.split_for
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct OwnedShardIter<K, V> {
+    pub(crate) entries: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for OwnedShardIter<K, V> {
+    type Item = (K, V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.entries.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.entries.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for OwnedShardIter<K, V> {}
+
+/// An iterator over the entries of a `FlurryHashMap` that no writes can happen concurrently with.
+///
+/// This `struct` is passed to the closure given to the [`scoped_iter`] method on
+/// [`FlurryHashMap`]. See its documentation for more.
+///
+/// [`scoped_iter`]: /flurry/struct.FlurryHashMap.html#method.scoped_iter
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct StableIter<'g, K, V> {
+    pub(crate) iter: Iter<'g, K, V>,
+}
+
+impl<'g, K, V> Iterator for StableIter<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+}
+
+/// An iterator over the entries of a `FlurryHashMap` that visits its bins in a shuffled order.
+///
+/// This `struct` is created by the [`iter_shuffled`] method on [`FlurryHashMap`].
+/// See its documentation for more.
+///
+/// [`iter_shuffled`]: /flurry/struct.FlurryHashMap.html#method.iter_shuffled
+/// [`FlurryHashMap`]: /flurry/struct.FlurryHashMap.html
+#[derive(Debug)]
+pub struct Shuffled<'g, K, V> {
+    pub(crate) table: Option<&'g Table<K, V>>,
+    pub(crate) order: std::vec::IntoIter<usize>,
+    pub(crate) node_iter: Option<NodeIter<'g, K, V>>,
+    pub(crate) guard: &'g Guard,
+}
+
+impl<'g, K, V> Iterator for Shuffled<'g, K, V> {
+    type Item = (&'g K, &'g V);
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(node) = self.node_iter.as_mut().and_then(Iterator::next) {
+                let value = self
+                    .node_iter
+                    .as_ref()
+                    .expect("just gave a node")
+                    .value(node);
+                // safety: flurry does not drop or move until after guard drop
+                let value = unsafe { value.deref() };
+                return Some((&node.key, value));
+            }
+            let bin = self.order.next()?;
+            let table = self.table.expect("order is empty without a table");
+            self.node_iter = Some(NodeIter::with_range(table, bin, bin + 1, self.guard));
+        }
     }
 }
 
@@ -92,6 +532,64 @@ mod tests {
         );
     }
 
+    #[test]
+    fn iter_prefetch() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        let guard = epoch::pin();
+        assert_eq!(map.iter_prefetch(&guard).count(), 0);
+
+        // fewer bins than the prefetch stride, and then many more
+        for n in &[3, 10_000] {
+            for i in 0..*n {
+                map.insert(i, i * 2);
+            }
+            assert_eq!(
+                map.iter_prefetch(&guard).collect::<HashSet<_>>(),
+                map.iter(&guard).collect::<HashSet<_>>()
+            );
+            assert_eq!(map.iter_prefetch(&guard).count(), *n);
+        }
+    }
+
+    #[test]
+    fn iter_shuffled() {
+        use rand::SeedableRng;
+
+        let map = FlurryHashMap::<usize, usize>::new();
+        let guard = epoch::pin();
+        let mut rng = rand::rngs::StdRng::seed_from_u64(42);
+        assert_eq!(map.iter_shuffled(&mut rng, &guard).count(), 0);
+
+        for i in 0..1000 {
+            map.insert(i, i * 2);
+        }
+        let shuffled = |seed| {
+            let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+            map.iter_shuffled(&mut rng, &guard)
+                .map(|(&k, &v)| (k, v))
+                .collect::<Vec<_>>()
+        };
+        let a = shuffled(42);
+        assert_eq!(a, shuffled(42));
+        assert_ne!(a, shuffled(7));
+        assert_ne!(
+            a,
+            map.iter(&guard).map(|(&k, &v)| (k, v)).collect::<Vec<_>>()
+        );
+
+        // every entry exactly once
+        assert_eq!(a.len(), 1000);
+        let mut keys: Vec<_> = a
+            .iter()
+            .map(|&(k, v)| {
+                assert_eq!(v, k * 2);
+                k
+            })
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..1000).collect::<Vec<_>>());
+    }
+
     #[test]
     fn keys() {
         let map = FlurryHashMap::<usize, usize>::new();
@@ -119,4 +617,310 @@ mod tests {
             HashSet::from_iter(vec![&42, &84])
         );
     }
+
+    #[test]
+    fn iter_keys_matching() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        for i in 0..16 {
+            map.insert(i, i * 10);
+        }
+
+        let wanted: HashSet<usize> = HashSet::from_iter(vec![1, 5, 7, 42]);
+        let guard = epoch::pin();
+        assert_eq!(
+            map.iter_keys_matching(|k| wanted.contains(k), &guard)
+                .collect::<HashSet<(&usize, &usize)>>(),
+            HashSet::from_iter(vec![(&1, &10), (&5, &50), (&7, &70)])
+        );
+    }
+
+    #[test]
+    fn iter_unique_by() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        for i in 0..12 {
+            map.insert(i, i % 4);
+        }
+
+        let guard = epoch::pin();
+        let unique: Vec<_> = map.iter_unique_by(|_, v| *v, &guard).collect();
+        assert_eq!(unique.len(), 4);
+        assert_eq!(
+            unique.iter().map(|(_, v)| **v).collect::<HashSet<usize>>(),
+            HashSet::from_iter(vec![0, 1, 2, 3])
+        );
+        for (k, v) in unique {
+            assert_eq!(k % 4, *v);
+        }
+    }
+
+    #[test]
+    fn iter_sorted_by() {
+        let map = FlurryHashMap::<String, usize>::new();
+        for (i, k) in ["ccc", "a", "bb", "b", "aaa", "ab"].iter().enumerate() {
+            map.insert(k.to_string(), i);
+        }
+
+        let guard = epoch::pin();
+        let sorted = map.iter_sorted_by(|a, b| a.len().cmp(&b.len()).then(a.cmp(b)), &guard);
+        assert_eq!(sorted.len(), 6);
+        assert_eq!(
+            sorted.map(|(k, v)| (k.as_str(), *v)).collect::<Vec<_>>(),
+            vec![
+                ("a", 1),
+                ("b", 3),
+                ("ab", 5),
+                ("bb", 2),
+                ("aaa", 4),
+                ("ccc", 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn sorted_run() {
+        let left = FlurryHashMap::<usize, &str>::new();
+        let right = FlurryHashMap::<usize, usize>::new();
+        for i in 0..50 {
+            left.insert(i * 2, "left");
+            right.insert(i * 3, i);
+        }
+
+        let guard = epoch::pin();
+        let left_run = left.sorted_run(&guard);
+        let right_run = right.sorted_run(&guard);
+        drop(guard);
+        assert_eq!(left_run.len(), 50);
+
+        // merge-join on the key
+        let mut joined = Vec::new();
+        let mut left_run = left_run.peekable();
+        let mut right_run = right_run.peekable();
+        while let (Some(l), Some(r)) = (left_run.peek(), right_run.peek()) {
+            match l.0.cmp(&r.0) {
+                std::cmp::Ordering::Less => {
+                    left_run.next();
+                }
+                std::cmp::Ordering::Greater => {
+                    right_run.next();
+                }
+                std::cmp::Ordering::Equal => {
+                    joined.push((l.0, l.1, r.1));
+                    left_run.next();
+                    right_run.next();
+                }
+            }
+        }
+
+        // multiples of 6 below 98
+        assert_eq!(
+            joined,
+            (0..17).map(|i| (i * 6, "left", i * 2)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn iter_cancellable() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        let map = FlurryHashMap::<usize, usize>::new();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        let guard = epoch::pin();
+        let cancel = AtomicBool::new(false);
+        assert_eq!(map.iter_cancellable(&cancel, &guard).count(), 1000);
+
+        let mut iter = map.iter_cancellable(&cancel, &guard);
+        for _ in 0..10 {
+            assert!(iter.next().is_some());
+        }
+        cancel.store(true, Ordering::SeqCst);
+        // at most the rest of the current bin
+        let rest = iter.by_ref().count();
+        assert!(rest < 990);
+        assert_eq!(iter.next(), None);
+
+        let cancel = AtomicBool::new(true);
+        assert_eq!(map.iter_cancellable(&cancel, &guard).count(), 0);
+    }
+
+    #[test]
+    fn iter_owned_lazy() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        /// Counts how many of its clones are alive.
+        #[derive(Debug)]
+        struct Tracked(usize, Arc<AtomicUsize>);
+        impl Clone for Tracked {
+            fn clone(&self) -> Self {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                Tracked(self.0, self.1.clone())
+            }
+        }
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                self.1.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+
+        let live = Arc::new(AtomicUsize::new(0));
+        let map = FlurryHashMap::<usize, Tracked>::new();
+        for i in 0..100 {
+            live.fetch_add(1, Ordering::SeqCst);
+            map.insert(i, Tracked(i, live.clone()));
+        }
+        assert_eq!(live.load(Ordering::SeqCst), 100);
+
+        let guard = epoch::pin();
+        let mut iter = map.iter_owned_lazy(&guard);
+        // nothing is cloned up front
+        assert_eq!(live.load(Ordering::SeqCst), 100);
+        let mut keys = HashSet::new();
+        for (k, v) in &mut iter {
+            assert_eq!(k, v.0);
+            // only the clone handed out is alive on top of the map's values
+            assert_eq!(live.load(Ordering::SeqCst), 101);
+            keys.insert(k);
+        }
+        assert_eq!(keys, HashSet::from_iter(0..100));
+        assert_eq!(live.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn follow_moves() {
+        use std::hash::{BuildHasherDefault, Hasher};
+
+        // keys are their own hashes, so that they can be put in the bins of our choosing
+        #[derive(Default)]
+        struct Identity(u64);
+        impl Hasher for Identity {
+            fn finish(&self) -> u64 {
+                self.0
+            }
+            fn write(&mut self, _: &[u8]) {
+                unreachable!("only usize keys are hashed")
+            }
+            fn write_usize(&mut self, n: usize) {
+                self.0 = n as u64;
+            }
+        }
+
+        // one key in each of the first 8 of 16 bins
+        let map = FlurryHashMap::<usize, usize, _>::with_hasher(
+            BuildHasherDefault::<Identity>::default(),
+        );
+        for i in 0..8 {
+            map.insert(i, i);
+        }
+        let guard = epoch::pin();
+        assert_eq!(map.bin_stats(&guard).bins, 16);
+
+        // without a resize, both modes see the same entries
+        assert_eq!(
+            HashSet::<&usize>::from_iter(map.keys(&guard).follow_moves(false)),
+            HashSet::from_iter(map.keys(&guard))
+        );
+
+        // both iterators are left in bin 3
+        let mut follow = map.keys(&guard).follow_moves(true);
+        let mut stay = map.keys(&guard).follow_moves(false);
+        let mut followed = HashSet::<usize>::from_iter(follow.by_ref().take(4).copied());
+        let mut stayed = HashSet::<usize>::from_iter(stay.by_ref().take(4).copied());
+        assert_eq!(followed, HashSet::from_iter(0..4));
+        assert_eq!(stayed, HashSet::from_iter(0..4));
+
+        // moves every bin of the table the iterators started on
+        map.reserve_concurrent(1000, &guard);
+        assert!(map.bin_stats(&guard).bins > 16);
+        // 20 and 21 land in bins that were moved out of bins 4 and 5, which are still ahead of
+        // the iterators, while 17 lands in one moved out of bin 1, which is behind them. 19 lands
+        // in the one with key 3, which the iterators were last in. 6 is removed from the new table.
+        for &i in &[17, 19, 20, 21] {
+            map.insert(i, i);
+        }
+        map.remove(&6, &guard);
+
+        followed.extend(follow.copied());
+        stayed.extend(stay.copied());
+        // following the moves finds every entry that was there throughout, and the new ones in
+        // the bins that were still ahead
+        assert_eq!(
+            followed,
+            HashSet::from_iter((0..8).filter(|&i| i != 6).chain(vec![20, 21]))
+        );
+        // staying in the old table sees every entry that was there when it was created, and none
+        // of the writes since the bins were moved
+        assert_eq!(stayed, HashSet::from_iter(0..8));
+    }
+
+    #[test]
+    fn entries_in_bins() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        let guard = epoch::pin();
+        assert_eq!(map.entries_in_bins(0, 16, &guard).count(), 0);
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        let (_, bins) = map.load_snapshot(&guard);
+        let mut seen = Vec::new();
+        for lo in (0..bins).step_by(100) {
+            let part: Vec<_> = map.entries_in_bins(lo, lo + 100, &guard).collect();
+            assert!(part.len() < 1000);
+            seen.extend(part.into_iter().map(|(&k, &v)| {
+                assert_eq!(k, v);
+                k
+            }));
+        }
+        seen.sort_unstable();
+        // every entry is in exactly one of the ranges
+        assert_eq!(seen, (0..1000).collect::<Vec<_>>());
+        assert_eq!(map.entries_in_bins(bins, bins + 10, &guard).count(), 0);
+        assert_eq!(map.entries_in_bins(5, 5, &guard).count(), 0);
+    }
+
+    #[test]
+    fn iter_stable_retry() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+        let guard = epoch::pin();
+        let iter = map.iter_stable_retry(&guard);
+        assert!(iter.is_clean());
+        assert_eq!(iter.len(), 100);
+        assert_eq!(
+            HashSet::<usize>::from_iter(iter.map(|(&k, _)| k)),
+            HashSet::from_iter(0..100)
+        );
+    }
+
+    #[test]
+    fn iter_stable_retry_resize() {
+        let map = std::sync::Arc::new(FlurryHashMap::<usize, usize>::new());
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        let writer = {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                // enough to make the map resize several times
+                for i in 1000..50_000 {
+                    map.insert(i, i);
+                }
+            })
+        };
+        for _ in 0..20 {
+            let guard = epoch::pin();
+            let keys: Vec<_> = map.iter_stable_retry(&guard).map(|(&k, _)| k).collect();
+            let unique = HashSet::<usize>::from_iter(keys.iter().copied());
+            // no entry is seen twice, and the ones present throughout are all seen
+            assert_eq!(unique.len(), keys.len());
+            assert!((0..1000).all(|k| unique.contains(&k)));
+        }
+        writer.join().unwrap();
+    }
 }
@@ -1,4 +1,10 @@
+#[allow(clippy::module_inception)]
 mod iter;
 mod traverser;
-pub use iter::{Iter, Keys, Values};
+#[cfg(feature = "timestamps")]
+pub use iter::OlderThan;
+pub use iter::{
+    Cancellable, DiagEntry, Diagnostic, Iter, Keys, KeysMatching, OwnedLazy, OwnedShardIter,
+    Shuffled, SortedBy, SortedRun, StableIter, StableRetry, UniqueBy, Values, WithShard,
+};
 pub(crate) use traverser::NodeIter;
@@ -1,4 +1,4 @@
-use crate::{BinEntry, Node, Table};
+use crate::{next_move_stamp, next_swap_stamp, BinEntry, Node, Table};
 use crossbeam::epoch::{Guard, Shared};
 use std::sync::atomic::Ordering;
 
@@ -13,6 +13,12 @@ pub(crate) struct NodeIter<'g, K, V> {
     /// The last bin entry iterated over
     prev: Option<&'g Node<K, V>>,
 
+    /// Index of the bin that prev is in, in the table it was found in
+    prev_bin: usize,
+
+    /// Number of nodes ahead of prev in its bin
+    prev_depth: usize,
+
     /// Index of bin to use next
     index: usize,
 
@@ -25,6 +31,20 @@ pub(crate) struct NodeIter<'g, K, V> {
     /// Initial table size
     base_size: usize,
 
+    /// Whether to descend into the next table when a bin has been moved there
+    follow_moves: bool,
+
+    /// `next_move_stamp` when the iterator was created. Bins moved since then are walked as they
+    /// were when moved if `follow_moves` is not set.
+    move_stamp: usize,
+
+    /// `next_swap_stamp` when the iterator was created. Values are returned as they were before
+    /// the swaps since then, so that no two entries are seen with the same value at once.
+    swap_stamp: usize,
+
+    /// How many bins ahead of the current one to prefetch, or 0 to not prefetch
+    prefetch: usize,
+
     guard: &'g Guard,
 }
 
@@ -44,14 +64,79 @@ impl<'g, K, V> NodeIter<'g, K, V> {
             stack: None,
             spare: None,
             prev: None,
+            prev_bin: 0,
+            prev_depth: 0,
             base_size: len,
             base_index: 0,
             index: 0,
             base_limit: len,
+            follow_moves: true,
+            move_stamp: next_move_stamp(),
+            swap_stamp: next_swap_stamp(),
+            prefetch: 0,
+            guard,
+        }
+    }
+
+    /// Like `new`, but only visits the nodes that were in bins `start..end` of `table`.
+    ///
+    /// Nodes that have since been moved to a larger table by a resize are still visited. The
+    /// ranges of a set of `NodeIter`s that together cover `0..table.bins.len()` therefore visit
+    /// the same nodes as a single `NodeIter` over all of `table`.
+    pub(crate) fn with_range(
+        table: &'g Table<K, V>,
+        start: usize,
+        end: usize,
+        guard: &'g Guard,
+    ) -> Self {
+        let len = table.bins.len();
+        let end = std::cmp::min(end, len);
+        Self {
+            table: Some(table),
+            stack: None,
+            spare: None,
+            prev: None,
+            prev_bin: 0,
+            prev_depth: 0,
+            base_size: len,
+            base_index: start,
+            index: start,
+            base_limit: end,
+            follow_moves: true,
+            move_stamp: next_move_stamp(),
+            swap_stamp: next_swap_stamp(),
+            prefetch: 0,
             guard,
         }
     }
 
+    /// Returns the index of the bin that the node last returned by `next` was found in, and how
+    /// many nodes are ahead of it in that bin.
+    ///
+    /// During a resize, the bin may be one of the table that entries are being moved to.
+    pub(crate) fn position(&self) -> (usize, usize) {
+        (self.prev_bin, self.prev_depth)
+    }
+
+    /// Sets whether bins that a resize has moved to the next table since the iterator was created
+    /// are followed there, or walked as they were when they were moved. Must be set before the
+    /// first call to `next`.
+    pub(crate) fn set_follow_moves(&mut self, follow: bool) {
+        self.follow_moves = follow;
+    }
+
+    /// Returns the value of `node`, which was returned by `next`, as it was before any
+    /// `swap_values` since the iterator was created.
+    pub(crate) fn value(&self, node: &'g Node<K, V>) -> Shared<'g, V> {
+        node.value_since(self.swap_stamp, self.guard)
+    }
+
+    /// Sets how many bins ahead of the bin being visited the first entry of a bin is fetched
+    /// into the cache, so that it is there by the time it is visited. 0 disables prefetching.
+    pub(crate) fn set_prefetch(&mut self, stride: usize) {
+        self.prefetch = stride;
+    }
+
     fn push_state(&mut self, t: &'g Table<K, V>, i: usize, n: usize) {
         let mut s = self.spare.take();
         if let Some(ref mut s) = s {
@@ -111,7 +196,20 @@ impl<'g, K, V> NodeIter<'g, K, V> {
 impl<'g, K, V> Iterator for NodeIter<'g, K, V> {
     type Item = &'g Node<K, V>;
     fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let node = self.next_node()?;
+            if !self.value(node).is_null() {
+                return Some(node);
+            }
+            // the node is a reservation whose value is still being computed, so skip it
+        }
+    }
+}
+
+impl<'g, K, V> NodeIter<'g, K, V> {
+    fn next_node(&mut self) -> Option<&'g Node<K, V>> {
         let mut e = None;
+        let mut depth = self.prev_depth + 1;
         if let Some(prev) = self.prev {
             let next = prev.next.load(Ordering::SeqCst, self.guard);
             if !next.is_null() {
@@ -127,6 +225,7 @@ impl<'g, K, V> Iterator for NodeIter<'g, K, V> {
         loop {
             if let Some(e) = e {
                 self.prev = Some(e);
+                self.prev_depth = depth;
                 return Some(e);
             }
 
@@ -142,12 +241,23 @@ impl<'g, K, V> Iterator for NodeIter<'g, K, V> {
             let t = self.table.expect("is_none in if above");
             let i = self.index;
             let n = t.bins.len();
+            if self.prefetch != 0 && i + self.prefetch < n {
+                // only a hint, so any recent value of the bin will do, and it is never
+                // dereferenced here
+                prefetch(
+                    t.bins[i + self.prefetch]
+                        .load(Ordering::Relaxed, self.guard)
+                        .as_raw(),
+                );
+            }
             let bin = t.bin(i, self.guard);
             if !bin.is_null() {
                 // safety: flurry does not drop or move until after guard drop
                 let bin = unsafe { bin.deref() };
                 match bin {
-                    BinEntry::Moved(next_table) => {
+                    BinEntry::Moved {
+                        next_table, stamp, ..
+                    } if self.follow_moves || *stamp < self.move_stamp => {
                         // recurse down into the target table
                         // safety: same argument as for following Moved in BinEntry::find
                         self.table = Some(unsafe { &**next_table });
@@ -156,8 +266,25 @@ impl<'g, K, V> Iterator for NodeIter<'g, K, V> {
                         self.push_state(t, i, n);
                         continue;
                     }
+                    BinEntry::Moved { old_head, .. } => {
+                        // the bin was moved after we were created, and so after our guard was
+                        // pinned. its old nodes are retired only after that, so they are still
+                        // there for us to visit instead of the next table.
+                        if !old_head.is_null() {
+                            // safety: see above
+                            e = Some(
+                                unsafe { &**old_head }
+                                    .as_node()
+                                    .expect("only Nodes are moved"),
+                            );
+                            self.prev_bin = i;
+                            depth = 0;
+                        }
+                    }
                     BinEntry::Node(node) => {
                         e = Some(node);
+                        self.prev_bin = i;
+                        depth = 0;
                     }
                 }
             }
@@ -175,6 +302,18 @@ impl<'g, K, V> Iterator for NodeIter<'g, K, V> {
     }
 }
 
+/// Asks the processor to start loading the cache line at `p`, if it supports that.
+#[inline]
+fn prefetch<T>(p: *const T) {
+    #[cfg(target_arch = "x86_64")]
+    // safety: prefetching is only a hint, and never faults, even for invalid addresses
+    unsafe {
+        std::arch::x86_64::_mm_prefetch(p as *const i8, std::arch::x86_64::_MM_HINT_T0);
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    let _ = p;
+}
+
 #[derive(Debug)]
 struct TableStack<'g, K, V> {
     length: usize,
@@ -189,6 +328,7 @@ mod tests {
     use crate::Table;
     use crossbeam::epoch::{self, Atomic, Owned};
     use parking_lot::Mutex;
+    use std::sync::atomic::AtomicUsize;
 
     #[test]
     fn iter_new() {
@@ -221,7 +361,17 @@ mod tests {
             key: 0usize,
             value: Atomic::new(0usize),
             next: Atomic::null(),
+            swap_stamp: AtomicUsize::new(0),
+            swapped: Atomic::null(),
             lock: Mutex::new(()),
+            #[cfg(feature = "priority")]
+            priority: std::sync::atomic::AtomicU64::new(0),
+            #[cfg(feature = "timestamps")]
+            written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+            #[cfg(feature = "timestamps")]
+            inserted: std::time::Instant::now(),
+            #[cfg(feature = "entry-stats")]
+            counters: crate::stats::EntryCounters::new(1),
         }));
 
         let table = Owned::new(Table {
@@ -251,7 +401,17 @@ mod tests {
             key: 0usize,
             value: Atomic::new(0usize),
             next: Atomic::null(),
+            swap_stamp: AtomicUsize::new(0),
+            swapped: Atomic::null(),
             lock: Mutex::new(()),
+            #[cfg(feature = "priority")]
+            priority: std::sync::atomic::AtomicU64::new(0),
+            #[cfg(feature = "timestamps")]
+            written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+            #[cfg(feature = "timestamps")]
+            inserted: std::time::Instant::now(),
+            #[cfg(feature = "entry-stats")]
+            counters: crate::stats::EntryCounters::new(1),
         }));
         let mut deep_table = Owned::new(Table {
             bins: deep_bins.into_boxed_slice(),
@@ -260,7 +420,7 @@ mod tests {
         // construct the forwarded-from table
         let mut bins = vec![Atomic::null(); 16];
         for bin in &mut bins[8..] {
-            *bin = Atomic::new(BinEntry::Moved(&*deep_table as *const _));
+            *bin = Atomic::new(BinEntry::moved(&*deep_table as *const _, std::ptr::null()));
         }
         let table = Owned::new(Table::<usize, usize> {
             bins: bins.into_boxed_slice(),
@@ -116,13 +116,14 @@
 //! field `transfer_index`) before doing so, reducing contention.  A generation stamp in the field
 //! `size_ctl` ensures that resizings do not overlap. Because we are using power-of-two expansion,
 //! the elements from each bin must either stay at same index, or move with a power of two offset.
-//! We eliminate unnecessary node creation by catching cases where old nodes can be reused because
-//! their next fields won't change.  On average, only about one-sixth of them need cloning when a
-//! table doubles. The nodes they replace will be garbage collectible as soon as they are no longer
-//! referenced by any reader thread that may be in the midst of concurrently traversing table.
-//! Upon transfer, the old table bin contains only a special forwarding node (`BinEntry::Moved`)
-//! that contains the next table as its key. On encountering a forwarding node, access and update
-//! operations restart, using the new table.
+//! Unlike the Java version, every node is cloned when a table doubles, rather than reusing the
+//! tail of each list whose next fields won't change, so that the old lists keep the entries they
+//! had when they were moved. The nodes they replace will be garbage collectible as soon as they
+//! are no longer referenced by any reader thread that may be in the midst of concurrently
+//! traversing table. Upon transfer, the old table bin contains only a special forwarding node
+//! (`BinEntry::Moved`) that contains the next table as its key, and the old list for iterators
+//! that do not follow moves. On encountering a forwarding node, access and update operations
+//! restart, using the new table.
 //! /* TODO: note on TreeBins */
 //!
 //! Each bin transfer requires its bin lock, which can stall waiting for locks while resizing.
@@ -178,6 +179,15 @@
 mod node;
 use node::*;
 
+mod bloom;
+mod cache;
+mod coalesce;
+mod counter;
+mod gate;
+
+#[cfg(feature = "rayon")]
+mod par;
+
 use crossbeam::epoch::{Atomic, Guard, Owned, Shared};
 use std::collections::hash_map::RandomState;
 use std::hash::{BuildHasher, Hash};
@@ -212,17 +222,68 @@ const MIN_TRANSFER_STRIDE: isize = 16;
 /// Must be at least 6 for 32bit arrays.
 const RESIZE_STAMP_BITS: usize = 16;
 
+/// The number of bits in `size_ctl`.
+const SIZE_CTL_BITS: usize = std::mem::size_of::<isize>() * 8;
+
 /// The maximum number of threads that can help resize.
-/// Must fit in `32 - RESIZE_STAMP_BITS` bits.
-const MAX_RESIZERS: isize = (1 << (32 - RESIZE_STAMP_BITS)) - 1;
+/// Must fit in `SIZE_CTL_BITS - RESIZE_STAMP_BITS` bits.
+const MAX_RESIZERS: isize = (1 << (SIZE_CTL_BITS - RESIZE_STAMP_BITS)) - 1;
 
 /// The bit shift for recording size stamp in `size_ctl`.
-const RESIZE_STAMP_SHIFT: usize = 32 - RESIZE_STAMP_BITS;
+///
+/// The stamp is placed in the topmost bits so that `size_ctl` is negative during a resize.
+const RESIZE_STAMP_SHIFT: usize = SIZE_CTL_BITS - RESIZE_STAMP_BITS;
+
+/// How many bins ahead of the current one `iter_prefetch` prefetches.
+const PREFETCH_STRIDE: usize = 8;
 
 /// Iterator types.
 pub mod iter;
 use iter::*;
 
+/// A builder for maps with non-default options.
+pub mod builder;
+
+/// Detached map tables.
+pub mod raw;
+
+/// Batches of map operations that share an epoch pin.
+pub mod batch;
+
+/// Fixed-capacity storage that does not allocate.
+pub mod array;
+
+/// Owned copies of the state of a map.
+pub mod checkpoint;
+
+/// Differences between the entries of maps.
+pub mod diff;
+
+/// Maps split into independent shards.
+pub mod sharded;
+
+/// Concurrent sets backed by maps.
+pub mod set;
+
+/// Immutable maps with a perfect hash over their keys.
+pub mod perfect;
+
+/// Immutable copies of maps that can be read without a guard.
+pub mod frozen;
+
+/// Streaming exports of map entries.
+pub mod export;
+
+/// Write-ahead logging of map mutations.
+pub mod wal;
+use wal::WalRecord;
+
+/// Instrumentation of map internals.
+///
+/// The lock hold time statistics are only available with the `lock-stats` feature enabled.
+pub mod stats;
+use stats::Operation;
+
 /// Types needed to safely access shared data concurrently.
 pub mod epoch {
     pub use crossbeam::epoch::{pin, Guard};
@@ -243,7 +304,7 @@ pub struct FlurryHashMap<K, V, S = RandomState> {
     /// The next table index (plus one) to split while resizing.
     transfer_index: AtomicIsize,
 
-    count: AtomicUsize,
+    count: counter::Counter,
 
     /// Table initialization and resizing control.  When negative, the
     /// table is being initialized or resized: -1 for initialization,
@@ -253,7 +314,54 @@ pub struct FlurryHashMap<K, V, S = RandomState> {
     /// next element count value upon which to resize the table.
     size_ctl: AtomicIsize,
 
-    build_hasher: S,
+    /// Replaced only by `rebuild` and `swap_contents`, together with the table, while writes are
+    /// paused. Null only once the map has been taken apart.
+    build_hasher: Atomic<S>,
+
+    /// Incremented before and after the hash builder and the table are replaced, so that it is odd
+    /// while the two may not match. Lookups that miss check it to tell whether they might have
+    /// looked for the key in the wrong place.
+    rehashes: AtomicUsize,
+
+    /// Entered by every write operation, and paused by `pause_writes`.
+    write_gate: gate::WriteGate,
+
+    /// Called with every mutation before it becomes visible.
+    wal: Option<wal::Wal<K, V>>,
+
+    /// Identifies this map's nodes in the thread-local read cache, if it is enabled.
+    read_cache: Option<cache::ReadCache>,
+
+    /// Answers lookups of keys that are definitely absent, if it is enabled.
+    bloom: Option<bloom::Bloom>,
+
+    /// How contended bin locks are, if the map resizes early when they are highly contended.
+    contention: Option<stats::Contention>,
+
+    /// Decides which entries `insert` and `try_insert` accept, if there is one.
+    validator: Option<Validator<K, V>>,
+
+    /// Whether `insert` and `try_insert` end with a full fence.
+    visibility_barrier: bool,
+
+    /// The number of entries beyond which `insert` and `try_insert` evict, and how, if they do.
+    soft_cap: Option<(usize, EvictionPolicy)>,
+
+    /// The size of the map each time its table was replaced, most recent last.
+    capacity_history: stats::CapacityHistory,
+
+    /// How long resizes have taken.
+    resize_times: stats::ResizeTimes,
+
+    /// Decides when the table is resized.
+    resize_policy: Resizer,
+
+    /// The writes of `insert_coalesced` that are yet to be applied, if it coalesces writes.
+    coalescer: Option<coalesce::Coalescer<K, V>>,
+
+    /// How long each kind of operation has held bin locks.
+    #[cfg(feature = "lock-stats")]
+    lock_stats: stats::LockStats,
 }
 
 impl<K, V> Default for FlurryHashMap<K, V, RandomState>
@@ -273,31 +381,101 @@ where
 {
     /// Creates a new, empty map with the default initial table size (16).
     pub fn new() -> Self {
-        Self {
-            table: Atomic::null(),
-            next_table: Atomic::null(),
-            transfer_index: AtomicIsize::new(0),
-            count: AtomicUsize::new(0),
-            size_ctl: AtomicIsize::new(0),
-            build_hasher: RandomState::new(),
-        }
+        Self::with_hasher(RandomState::new())
     }
 
     /// Creates a new, empty map with an initial table size accommodating the specified number of
     /// elements without the need to dynamically resize.
     ///
+    /// Keys are spread across bins by their individual hashes, so only the total number of keys
+    /// matters here, not how they are skewed across tenants, categories, or other groups of the
+    /// data.
+    ///
     /// # Panics
     ///
     /// If the given capacity is 0.
     pub fn with_capacity(n: usize) -> Self {
-        assert_ne!(n, 0);
         let mut m = Self::new();
-        let size = (1.0 + (n as f64) / LOAD_FACTOR) as usize;
-        // NOTE: tableSizeFor in Java
-        let cap = std::cmp::min(MAXIMUM_CAPACITY, size.next_power_of_two());
-        m.size_ctl = AtomicIsize::new(cap as isize);
+        m.size_ctl = AtomicIsize::new(initial_capacity(n) as isize);
         m
     }
+
+    /// Returns a builder for a map with non-default options.
+    pub fn builder() -> builder::Builder<K, V> {
+        builder::Builder::new()
+    }
+
+    /// Creates a new, empty, shared map, along with a weak handle to it.
+    ///
+    /// The handle can be stored in the values of the map, so that they can refer back to the map
+    /// that contains them, for example to insert further entries, without keeping it alive: once
+    /// the last `Arc` is dropped, the map and all its values are dropped too, handles and all.
+    pub fn with_weak_self() -> (std::sync::Arc<Self>, WeakHandle<K, V>) {
+        let map = std::sync::Arc::new(Self::new());
+        let handle = WeakHandle {
+            map: std::sync::Arc::downgrade(&map),
+        };
+        (map, handle)
+    }
+}
+
+/// A handle to a map created with [`with_weak_self`], which does not keep the map alive.
+///
+/// [`with_weak_self`]: FlurryHashMap::with_weak_self
+pub struct WeakHandle<K, V, S = RandomState> {
+    map: std::sync::Weak<FlurryHashMap<K, V, S>>,
+}
+
+impl<K, V, S> WeakHandle<K, V, S> {
+    /// Returns the map, or `None` if it has already been dropped.
+    pub fn upgrade(&self) -> Option<std::sync::Arc<FlurryHashMap<K, V, S>>> {
+        self.map.upgrade()
+    }
+}
+
+impl<K, V, S> Clone for WeakHandle<K, V, S> {
+    fn clone(&self) -> Self {
+        WeakHandle {
+            map: self.map.clone(),
+        }
+    }
+}
+
+impl<K, V, S> std::fmt::Debug for WeakHandle<K, V, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("WeakHandle")
+    }
+}
+
+/// The number of times `iter_stable_retry` starts over after a pass that a resize interfered with.
+const STABLE_RETRIES: usize = 3;
+
+/// Returns the initial table size needed to hold `n` elements without resizing.
+///
+/// # Panics
+///
+/// If `n` is 0.
+fn initial_capacity(n: usize) -> usize {
+    assert_ne!(n, 0);
+    let size = (1.0 + (n as f64) / LOAD_FACTOR) as usize;
+    // NOTE: tableSizeFor in Java
+    std::cmp::min(MAXIMUM_CAPACITY, size.next_power_of_two())
+}
+
+/// Returns the table size that a resize policy's `target` calls for: the next power of two, up to
+/// the maximum table size.
+fn resize_target(target: usize) -> usize {
+    target
+        .checked_next_power_of_two()
+        .map_or(MAXIMUM_CAPACITY, |t| std::cmp::min(t, MAXIMUM_CAPACITY))
+}
+
+/// Returns the hash `build_hasher` gives `key`.
+fn hash_with<K: Hash + ?Sized, S: BuildHasher>(build_hasher: &S, key: &K) -> u64 {
+    use std::hash::Hasher;
+    let mut h = build_hasher.build_hasher();
+    key.hash(&mut h);
+    h.finish()
 }
 
 impl<K, V, S> FlurryHashMap<K, V, S>
@@ -306,11 +484,329 @@ where
     V: Sync + Send,
     S: BuildHasher,
 {
-    fn hash(&self, key: &K) -> u64 {
-        use std::hash::Hasher;
-        let mut h = self.build_hasher.build_hasher();
-        key.hash(&mut h);
-        h.finish()
+    /// Creates a new, empty map with the default initial table size (16), which will use the
+    /// given hash builder to hash keys.
+    pub fn with_hasher(build_hasher: S) -> Self {
+        Self {
+            table: Atomic::null(),
+            next_table: Atomic::null(),
+            transfer_index: AtomicIsize::new(0),
+            count: counter::Counter::new(),
+            size_ctl: AtomicIsize::new(0),
+            build_hasher: Atomic::new(build_hasher),
+            rehashes: AtomicUsize::new(0),
+            write_gate: gate::WriteGate::new(),
+            wal: None,
+            read_cache: None,
+            bloom: None,
+            contention: None,
+            validator: None,
+            visibility_barrier: false,
+            soft_cap: None,
+            capacity_history: stats::CapacityHistory::default(),
+            resize_times: stats::ResizeTimes::default(),
+            resize_policy: Resizer::default(),
+            coalescer: None,
+            #[cfg(feature = "lock-stats")]
+            lock_stats: stats::LockStats::default(),
+        }
+    }
+
+    /// Creates a map directly from a table of entries and the hash builder that was used to place
+    /// them, without re-hashing or moving any entries.
+    ///
+    /// `build_hasher` must hash keys the same way the hasher given to
+    /// [`RawTable::insert`](raw::RawTable::insert), or the hasher returned alongside the table by
+    /// [`into_raw_parts`](FlurryHashMap::into_raw_parts), did. Otherwise, lookups will fail to
+    /// find entries that are in the map.
+    pub fn from_raw_parts(mut table: raw::RawTable<K, V>, build_hasher: S) -> Self {
+        let map = Self::with_hasher(build_hasher);
+        let len = table.len;
+        let table = std::mem::replace(
+            &mut table.table,
+            Table {
+                bins: vec![].into_boxed_slice(),
+            },
+        );
+        let n = table.bins.len();
+        if n == 0 {
+            // the table was never allocated
+            return map;
+        }
+
+        map.table.store(Owned::new(table), Ordering::SeqCst);
+        // safety: the map has not been shared with anyone yet
+        map.count
+            .set(len, unsafe { crossbeam::epoch::unprotected() });
+        map.size_ctl
+            .store(n as isize - (n >> 2) as isize, Ordering::SeqCst);
+        map
+    }
+
+    /// Creates a map holding the entries of `checkpoint`, which uses the hash builder of the map
+    /// the checkpoint was taken from.
+    pub fn from_checkpoint(checkpoint: checkpoint::Checkpoint<K, V, S>) -> Self {
+        let map = Self::with_hasher(checkpoint.build_hasher);
+        if !checkpoint.entries.is_empty() {
+            map.size_ctl.store(
+                initial_capacity(checkpoint.entries.len()) as isize,
+                Ordering::SeqCst,
+            );
+        }
+        for (key, value) in checkpoint.entries {
+            map.insert(key, value);
+        }
+        map
+    }
+
+    /// Consumes the map, returning the table of entries and the hash builder that was used to
+    /// place them.
+    ///
+    /// No entries are moved or copied. The map can be re-created with
+    /// [`from_raw_parts`](FlurryHashMap::from_raw_parts).
+    pub fn into_raw_parts(self) -> (raw::RawTable<K, V>, S) {
+        self.flush_coalesced(&crossbeam::epoch::pin());
+
+        // safety: we own the map, so not concurrently accessed by anyone else
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+
+        assert!(self.next_table.load(Ordering::SeqCst, guard).is_null());
+        let table = self.table.swap(Shared::null(), Ordering::SeqCst, guard);
+        let table = if table.is_null() {
+            Table {
+                bins: vec![].into_boxed_slice(),
+            }
+        } else {
+            // safety: same as above + we own the table
+            *unsafe { table.into_owned() }.into_box()
+        };
+        let len = self.count.sum(Ordering::SeqCst, guard);
+
+        let build_hasher = self
+            .build_hasher
+            .swap(Shared::null(), Ordering::SeqCst, guard);
+        // safety: same as above + we own the hash builder
+        let build_hasher = *unsafe { build_hasher.into_owned() }.into_box();
+
+        // the table and the hash builder have been taken out, so dropping the map only drops the
+        // rest of it
+        drop(self);
+        (raw::RawTable { table, len }, build_hasher)
+    }
+
+    /// Consumes the map, returning an immutable map of the same entries in which every lookup is
+    /// a single probe.
+    ///
+    /// This builds a minimal perfect hash function over the keys of the map, which takes time
+    /// roughly linear in the number of entries, and is worth it for lookup tables that are built
+    /// once and then queried many times. See [`PerfectMap`](perfect::PerfectMap) for how it
+    /// works. Construction starts over with different hashes of the keys if some of them cannot
+    /// be told apart, so it may take a few attempts.
+    ///
+    /// # Panics
+    ///
+    /// If the map's hash builder gives some of the keys the same hash no matter what is hashed
+    /// along with them, as a hasher that ignores its input does.
+    pub fn freeze_perfect(self) -> perfect::PerfectMap<K, V, S> {
+        let (mut table, build_hasher) = self.into_raw_parts();
+        let len = table.len();
+        let entries = table.table.take_entries(len);
+        perfect::PerfectMap::build(entries, build_hasher)
+    }
+
+    /// Consumes the map, returning an immutable map of the same entries that can be read without
+    /// a `Guard`.
+    ///
+    /// Unlike [`snapshot_shared`](FlurryHashMap::snapshot_shared), this moves the entries into
+    /// the [`FrozenHashMap`](frozen::FrozenHashMap) instead of cloning them, so it suits maps
+    /// that are filled once, for example with configuration, and then only read.
+    pub fn freeze(self) -> frozen::FrozenHashMap<K, V, S> {
+        let (mut table, build_hasher) = self.into_raw_parts();
+        let len = table.len();
+        let mut map = std::collections::HashMap::with_capacity_and_hasher(len, build_hasher);
+        map.extend(table.table.take_entries(len));
+        frozen::FrozenHashMap { map }
+    }
+
+    /// Consumes the map, calling `f` with each of its entries in arbitrary order.
+    ///
+    /// Since the map is consumed, no `Guard` is needed, and the entries are handed to `f` by
+    /// value without being cloned. This makes it the way to clean up the entries of a map when
+    /// the struct that holds it is dropped, for example to release external resources that the
+    /// values stand for: the map can be moved out of the struct in its `Drop` implementation,
+    /// for instance with [`std::mem::take`], and consumed there.
+    pub fn for_each_owned<F>(self, f: F)
+    where
+        F: FnMut(K, V),
+    {
+        let (mut table, _) = self.into_raw_parts();
+        table.table.drain(f);
+    }
+
+    /// Consumes the map, returning a map of the same keys in which each key is mapped to
+    /// `f(key, value)`, where `value` is the value it was mapped to here.
+    ///
+    /// This is meant for changing the representation of the values of a map, as in a schema
+    /// migration. Since the map is consumed, no `Guard` is needed, and the keys are neither
+    /// cloned nor re-hashed: every entry stays in the bin it was in. The new map uses the same
+    /// hash builder, but none of the other options the map may have been built with.
+    pub fn migrate_values<V2, F>(self, f: F) -> FlurryHashMap<K, V2, S>
+    where
+        V2: Sync + Send,
+        F: Fn(&K, &V) -> V2,
+    {
+        let (mut table, build_hasher) = self.into_raw_parts();
+        let migrated = raw::RawTable {
+            table: table.table.map_values(f),
+            len: table.len,
+        };
+        FlurryHashMap::from_raw_parts(migrated, build_hasher)
+    }
+
+    #[allow(clippy::manual_hash_one)]
+    /// Returns the hash builder that currently places the keys of the map.
+    ///
+    /// Writes cannot overlap with it being replaced, so they may hash keys with it throughout.
+    /// Anything else must use [`with_placing_hasher`](FlurryHashMap::with_placing_hasher).
+    fn hasher<'g>(&'g self, guard: &'g Guard) -> &'g S {
+        // safety: the hash builder is only dropped in the epoch after it has been replaced, and
+        // we read it under our guard. it is only null once the map has been taken apart.
+        unsafe { self.build_hasher.load(Ordering::SeqCst, guard).deref() }
+    }
+
+    /// Runs `find` with the hash builder that placed the entries of the table it then looks in,
+    /// and runs it again if it misses because the two were being replaced in the meantime.
+    fn with_placing_hasher<'g, T, F>(&'g self, guard: &'g Guard, find: F) -> Option<T>
+    where
+        F: Fn(&'g S) -> Option<T>,
+    {
+        loop {
+            let rehashes = self.rehashes.load(Ordering::SeqCst);
+            let found = find(self.hasher(guard));
+            if found.is_some()
+                || (rehashes % 2 == 0 && self.rehashes.load(Ordering::SeqCst) == rehashes)
+            {
+                return found;
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    fn hash(&self, key: &K, guard: &Guard) -> u64 {
+        hash_with(self.hasher(guard), key)
+    }
+
+    /// Asserts that the key of `node` still hashes to the hash it was inserted with.
+    ///
+    /// A key whose hash changes while it is in the map, such as through interior mutability, is
+    /// left in a bin that lookups for it no longer visit. Since this hashes the key again, it is
+    /// only checked in debug builds.
+    #[inline]
+    fn debug_check_hash(&self, hasher: &S, node: &Node<K, V>) {
+        debug_assert_eq!(
+            hash_with(hasher, &node.key),
+            node.hash,
+            "the hash of a key changed after it was inserted"
+        );
+    }
+
+    /// Returns the number of entries in the map.
+    ///
+    /// If the map is being concurrently modified, the returned value reflects some state the map
+    /// was in during the call, and may already be stale when it is returned.
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an estimate of the number of entries in the map that is cheaper to compute than
+    /// [`len`](FlurryHashMap::len).
+    ///
+    /// Once threads contend on the count of the map, it is striped over as many as 64 cells,
+    /// which `len` sums up. This only reads 8 of them, evenly spaced, and multiplies their sum by
+    /// the number of cells there are per cell read, `k`. The estimate is therefore exact if there
+    /// are no more than 8 cells, or if the cells hold equal counts, as they do when the threads
+    /// that update the map do so at similar rates. Otherwise, it is off by at most `k - 1` times
+    /// the number of insertions and removals that have been counted in the cells, and `k` is at
+    /// most 8. [`compact_counters`](FlurryHashMap::compact_counters) moves what the cells have
+    /// counted to where it is always read, so earlier updates no longer add to the error.
+    ///
+    /// The estimate also does not synchronize with concurrent updates, so it may not include
+    /// updates that completed shortly before the call, nor exclude ones that are still in
+    /// progress.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn approx_len(&self, guard: &Guard) -> usize {
+        self.count.approx(guard)
+    }
+
+    /// Returns the number of entries in the map along with the number of bins of its table, the
+    /// two of which give its current load factor.
+    ///
+    /// Both are read from the same table: if a resize replaces the table while the entries are
+    /// counted, they are counted again, so the load factor is never computed from the count of
+    /// one table and the size of another. The number of bins is 0 if no table has been allocated
+    /// yet. While a resize is under way, it is the size of the table being resized, not the one
+    /// it is resized to.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn load_snapshot(&self, guard: &Guard) -> (usize, usize) {
+        loop {
+            let table = self.table.load(Ordering::SeqCst, guard);
+            let len = self.count.sum(Ordering::SeqCst, guard);
+            if self.table.load(Ordering::SeqCst, guard) != table {
+                continue;
+            }
+            // safety: we loaded the table while epoch was pinned. table won't be deallocated until
+            // next epoch at the earliest.
+            let bins = unsafe { table.as_ref() }.map_or(0, |table| table.bins.len());
+            return (len, bins);
+        }
+    }
+
+    /// Recounts the entries in the map, and resets the count behind [`len`](FlurryHashMap::len)
+    /// to the result. Returns the new count.
+    ///
+    /// This is a maintenance tool for repairing a count that has drifted from the true number of
+    /// entries, which should only happen because of a bug. It visits every entry, so it is as
+    /// expensive as a full iteration.
+    ///
+    /// If the map is being modified concurrently, updates that happen during the call may or may
+    /// not be reflected in the new count, so it may still be off afterwards. Once the map is not
+    /// being modified, a call brings the count back to the exact number of entries.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn reconcile_len(&self, guard: &Guard) -> usize {
+        let n = self.iter(guard).count();
+        self.count.set(n, guard);
+        n
+    }
+
+    /// Collapses the count behind [`len`](FlurryHashMap::len) back into a single number, as far
+    /// as the threads that have written to the map allow.
+    ///
+    /// Once threads start to contend on the count of the map, it is spread over a number of
+    /// cells, which grows with the number of contending threads, and which `len` has to sum up.
+    /// The cells are never shrunk on their own, even once the threads that needed them have
+    /// exited. This shrinks them back down to fit the contending threads that are still running,
+    /// or drops them entirely if there are none, which frees their memory and makes `len`
+    /// cheaper. It is useful after a burst of short-lived threads has written to the map.
+    ///
+    /// Writes to the map wait until this is done, as with
+    /// [`pause_writes`](FlurryHashMap::pause_writes), but reads do not.
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), where waiting for the writes in
+    /// progress would deadlock.
+    pub fn compact_counters(&self) {
+        let _gate = self.write_gate.pause();
+        self.count.compact(&crossbeam::epoch::pin());
     }
 
     /// Tests if `key` is a key in this table.
@@ -319,6 +815,26 @@ where
         self.get(key, &guard).is_some()
     }
 
+    /// Tests which of `keys` are keys in this table. Bit `i` of the result is set if `keys[i]`
+    /// is.
+    ///
+    /// All the lookups happen under `guard`, rather than each pinning an epoch of its own as
+    /// with [`contains_key`](FlurryHashMap::contains_key), and the answers take one bit each.
+    /// This suits checking a whole batch of requests against the map at once. If the map is
+    /// modified concurrently, each key is looked up at a different moment, so the result may
+    /// reflect some modifications and not others.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn contains_all(&self, keys: &[&K], guard: &Guard) -> batch::BitVec {
+        let mut present = batch::BitVec::zeroed(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            if self.get(key, guard).is_some() {
+                present.set(i);
+            }
+        }
+        present
+    }
+
     /// Returns the value to which `key` is mapped.
     ///
     /// Returns `None` if this map contains no mapping for the key.
@@ -326,7 +842,146 @@ where
     /// To obtain a `Guard`, use [`epoch::pin`].
     // TODO: implement a guard API of our own
     pub fn get<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
-        let h = self.hash(key);
+        let node = match self.read_cache {
+            Some(ref cache) => {
+                // read before the lookup, so that the node is not used from the cache once it
+                // may have been freed, even if that happens while we are looking it up
+                let generation = cache.generation();
+                // safety: the cache only ever holds nodes of this map, and the guard was pinned
+                // before we read the generation.
+                match unsafe { cache.find(generation, |n: &Node<K, V>| n.key == *key) } {
+                    Some(node) => node,
+                    None => {
+                        // during a resize, nodes we find may be old copies that do not see later
+                        // updates to their values
+                        let resizing = !self.next_table.load(Ordering::SeqCst, guard).is_null();
+                        let node = self.get_node(key, guard)?;
+                        if !resizing && !node.value.load(Ordering::SeqCst, guard).is_null() {
+                            cache.remember(generation, node);
+                        }
+                        node
+                    }
+                }
+            }
+            None => self.get_node(key, guard)?,
+        };
+
+        let v = node.load_value(guard);
+        if v.is_null() {
+            // the node is a reservation whose value is still being computed
+            return None;
+        }
+        #[cfg(feature = "entry-stats")]
+        node.counters.read();
+        // safety: the lifetime of the reference is bound to the guard
+        // supplied which means that the memory will not be modified
+        // until at least after the guard goes out of scope
+        unsafe { v.as_ref() }
+    }
+
+    /// Returns the value to which `key` is mapped, along with the values of the other entries in
+    /// the same bin, in the order they are chained in.
+    ///
+    /// The entries of a bin are the ones a lookup of `key` walks past anyway, so processing them
+    /// together with `key` makes good use of memory that has just been brought into the cache.
+    /// The bin is only walked once. Which keys share a bin depends on their hashes and the size
+    /// of the table, so the neighbors are typically empty or few.
+    ///
+    /// Returns `None` if this map contains no mapping for the key.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn get_with_neighbors<'g>(
+        &'g self,
+        key: &K,
+        guard: &'g Guard,
+    ) -> Option<(&'g V, Vec<&'g V>)> {
+        self.with_placing_hasher(guard, |hasher| {
+            self.get_with_neighbors_hashed(hasher, key, guard)
+        })
+    }
+
+    fn get_with_neighbors_hashed<'g>(
+        &'g self,
+        hasher: &S,
+        key: &K,
+        guard: &'g Guard,
+    ) -> Option<(&'g V, Vec<&'g V>)> {
+        let h = hash_with(hasher, key);
+        if let Some(ref bloom) = self.bloom {
+            if !bloom.may_contain(h, guard) {
+                return None;
+            }
+        }
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+        let head = loop {
+            if table.is_null() {
+                return None;
+            }
+            // safety: we loaded the table while epoch was pinned. table won't be deallocated
+            // until next epoch at the earliest. tables that a moved bin points to are newer, and
+            // so are not deallocated before it either.
+            let t = unsafe { table.deref() };
+            if t.bins.is_empty() {
+                return None;
+            }
+            let bin = t.bin(t.bini(h), guard);
+            if bin.is_null() {
+                return None;
+            }
+            // safety: as in get_node
+            match unsafe { bin.deref() } {
+                BinEntry::Moved { next_table, .. } => table = Shared::from(*next_table),
+                BinEntry::Node(head) => break head,
+            }
+        };
+
+        let mut found = None;
+        let mut neighbors = Vec::new();
+        let mut node = head;
+        loop {
+            let value = node.value.load(Ordering::SeqCst, guard);
+            // safety: the lifetime of the reference is bound to the guard supplied, and values are
+            // not dropped until the epoch after they are replaced. reservations, whose values are
+            // still being computed, are skipped.
+            if let Some(value) = unsafe { value.as_ref() } {
+                if found.is_none() && node.hash == h && node.key == *key {
+                    self.debug_check_hash(hasher, node);
+                    #[cfg(feature = "entry-stats")]
+                    node.counters.read();
+                    found = Some(value);
+                } else {
+                    neighbors.push(value);
+                }
+            }
+
+            let next = node.next.load(Ordering::SeqCst, guard);
+            if next.is_null() {
+                break;
+            }
+            // safety: as in get_node
+            node = unsafe { next.deref() }
+                .as_node()
+                .expect("only Nodes follow a Node");
+        }
+        found.map(|value| (value, neighbors))
+    }
+
+    fn get_node<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g Node<K, V>> {
+        self.with_placing_hasher(guard, |hasher| self.get_node_hashed(hasher, key, guard))
+    }
+
+    fn get_node_hashed<'g>(
+        &'g self,
+        hasher: &S,
+        key: &K,
+        guard: &'g Guard,
+    ) -> Option<&'g Node<K, V>> {
+        let h = hash_with(hasher, key);
+        if let Some(ref bloom) = self.bloom {
+            if !bloom.may_contain(h, guard) {
+                return None;
+            }
+        }
         let table = self.table.load(Ordering::SeqCst, guard);
         if table.is_null() {
             return None;
@@ -335,7 +990,7 @@ where
         // safety: we loaded the table while epoch was pinned. table won't be deallocated until
         // next epoch at the earliest.
         let table = unsafe { table.deref() };
-        if table.bins.len() == 0 {
+        if table.bins.is_empty() {
             return None;
         }
         let bini = table.bini(h);
@@ -365,15 +1020,9 @@ where
         // safety: we read the bin while pinning the epoch. a bin will never be dropped until the
         // next epoch after it is removed. since it wasn't removed, and the epoch was pinned, that
         // cannot be until after we drop our guard.
-        let node = unsafe { node.deref() };
-        let node = node.as_node().unwrap();
-
-        let v = node.value.load(Ordering::SeqCst, guard);
-        assert!(!v.is_null());
-        // safety: the lifetime of the reference is bound to the guard
-        // supplied which means that the memory will not be modified
-        // until at least after the guard goes out of scope
-        unsafe { v.as_ref() }
+        let node = unsafe { node.deref() }.as_node()?;
+        self.debug_check_hash(hasher, node);
+        Some(node)
     }
 
     /// Obtains the value to which `key` is mapped and passes it through the closure `then`.
@@ -384,6 +1033,21 @@ where
         self.get(key, guard).map(then)
     }
 
+    /// Returns whether the value to which `key` is mapped satisfies `pred`, or `None` if this
+    /// map contains no mapping for `key`.
+    ///
+    /// Unlike [`get`](FlurryHashMap::get), no reference to the value escapes into the caller's
+    /// code, which only gets to see the `bool`. That is convenient when the answer has to outlive
+    /// `guard`, but the value cannot be cloned.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn value_matches<P>(&self, key: &K, pred: P, guard: &Guard) -> Option<bool>
+    where
+        P: FnOnce(&V) -> bool,
+    {
+        self.get(key, guard).map(pred)
+    }
+
     fn init_table<'g>(&self, guard: &'g Guard) -> Shared<'g, Table<K, V>> {
         loop {
             let table = self.table.load(Ordering::SeqCst, guard);
@@ -400,7 +1064,11 @@ where
                 continue;
             }
 
-            if self.size_ctl.compare_and_swap(sc, -1, Ordering::SeqCst) == sc {
+            if self
+                .size_ctl
+                .compare_exchange(sc, -1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
                 // we get to do it!
                 let mut table = self.table.load(Ordering::SeqCst, guard);
 
@@ -417,6 +1085,7 @@ where
                     });
                     table = new_table.into_shared(guard);
                     self.table.store(table, Ordering::SeqCst);
+                    self.capacity_history.record(self.len(), n);
                     sc = n as isize - (n >> 2) as isize;
                 }
                 self.size_ctl.store(sc, Ordering::SeqCst);
@@ -428,34 +1097,477 @@ where
     /// Maps `key` to `value` in this table.
     ///
     /// The value can be retrieved by calling [`get`] with a key that is equal to the original key.
+    ///
+    /// # Panics
+    ///
+    /// If the map was built with a [`validator`](builder::Builder::validator) that rejects the
+    /// entry. Use [`try_insert`](FlurryHashMap::try_insert) to handle rejections instead.
+    ///
+    /// If the map was built with a [`soft_cap`](builder::Builder::soft_cap), inserting a new key
+    /// may evict other entries.
     pub fn insert(&self, key: K, value: V) -> Option<()> {
-        self.put(key, value, false)
+        match self.try_insert(key, value) {
+            Ok(replaced) => replaced,
+            Err(Rejected) => panic!("the entry was rejected by the map's validator"),
+        }
     }
 
-    fn put(&self, key: K, value: V, no_replacement: bool) -> Option<()> {
-        let h = self.hash(&key);
-
-        let guard = &crossbeam::epoch::pin();
-        let mut table = self.table.load(Ordering::SeqCst, guard);
-
-        let mut node = Owned::new(BinEntry::Node(Node {
-            key,
-            value: Atomic::new(value),
-            hash: h,
-            next: Atomic::null(),
-            lock: parking_lot::Mutex::new(()),
-        }));
-
-        loop {
-            // safety: see argument below for !is_null case
-            if table.is_null() || unsafe { table.deref() }.bins.len() == 0 {
-                table = self.init_table(guard);
-                continue;
+    /// Like [`insert`](FlurryHashMap::insert), but returns `Err(Rejected)` instead of panicking
+    /// if the map's [`validator`](builder::Builder::validator) rejects the entry, in which case
+    /// the map is left unchanged.
+    pub fn try_insert(&self, key: K, value: V) -> Result<Option<()>, Rejected> {
+        if let Some(ref validator) = self.validator {
+            if !(validator.0)(&key, &value) {
+                return Err(Rejected);
             }
+        }
+        Ok(self.insert_validated(key, value, &crossbeam::epoch::pin()))
+    }
 
-            // safety: table is a valid pointer.
-            //
-            // we are in one of three cases:
+    /// The part of `try_insert` that follows validation.
+    fn insert_validated(&self, key: K, value: V, guard: &Guard) -> Option<()> {
+        let inserted = self.soft_cap.map(|_| key.clone());
+        let replaced = self.put(key, value, false, guard);
+        if let (Some((cap, policy)), Some(inserted), None) = (self.soft_cap, inserted, replaced) {
+            while self.len() > cap {
+                match self.eviction_candidate(policy, Some(&inserted), guard) {
+                    Some(key) => {
+                        self.remove(key, guard);
+                    }
+                    None => break,
+                }
+            }
+        }
+        if self.visibility_barrier {
+            std::sync::atomic::fence(Ordering::SeqCst);
+        }
+        replaced
+    }
+
+    /// Like [`insert`](FlurryHashMap::insert), but if the map was built to
+    /// [`coalesce_writes`](builder::Builder::coalesce_writes), only stages `value` as the
+    /// latest value of `key`, to be inserted together with later writes.
+    ///
+    /// This is for keys that are overwritten so often, such as live metrics, that only their final
+    /// value matters. A staged value replaces any value staged for the same key before it, which is
+    /// then never inserted at all, so a burst of writes to a hot key takes its bin lock once rather
+    /// than once per write. Staged writes are spread over a fixed number of shards. When a write
+    /// finds that its shard has not been flushed for the coalescing interval, it inserts the latest
+    /// staged value of every key of the shard, after any batch the shard gave up earlier has been
+    /// inserted, so a value never overwrites one staged after it. Until then, the map does not
+    /// reflect the staged writes: reads return the value from before them, and other writes to the
+    /// same key are overwritten once they are flushed. A key whose shard is not written to again
+    /// stays staged until [`flush_coalesced`](FlurryHashMap::flush_coalesced) is called, so writers
+    /// that need their values to become visible within the interval should call it periodically.
+    /// Staged writes that are never flushed are dropped along with the map.
+    ///
+    /// Without coalescing, this is the same as `insert`.
+    ///
+    /// # Panics
+    ///
+    /// If the map was built with a [`validator`](builder::Builder::validator) that rejects the
+    /// entry. Entries are validated when they are staged.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn insert_coalesced(&self, key: K, value: V, guard: &Guard) {
+        if let Some(ref validator) = self.validator {
+            if !(validator.0)(&key, &value) {
+                panic!("the entry was rejected by the map's validator");
+            }
+        }
+        let coalescer = match self.coalescer {
+            Some(ref coalescer) => coalescer,
+            None => {
+                self.insert_validated(key, value, guard);
+                return;
+            }
+        };
+        coalescer.stage(self.hash(&key, guard), key, value, |key, value| {
+            self.insert_validated(key, value, guard);
+        });
+    }
+
+    /// Inserts the writes that [`insert_coalesced`](FlurryHashMap::insert_coalesced) has staged
+    /// so far.
+    ///
+    /// Once this returns, every write that was staged before it was called has been inserted,
+    /// even if another thread took it from its shard. Writes that are staged concurrently may or
+    /// may not be inserted. Without coalescing, this does nothing.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn flush_coalesced(&self, guard: &Guard) {
+        if let Some(ref coalescer) = self.coalescer {
+            coalescer.flush(|key, value| {
+                self.insert_validated(key, value, guard);
+            });
+        }
+    }
+
+    /// Like [`insert`](FlurryHashMap::insert), but reports what the insertion did, including the
+    /// value it replaced, if any.
+    ///
+    /// An insertion may also make the map resize, which [`InsertOutcome::resized`] reports. Since
+    /// any thread may start a resize, a resize that another thread started while this insertion
+    /// was in progress is reported as well. Allocating the map's first table does not count.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn insert_outcome<'g>(
+        &'g self,
+        key: K,
+        value: V,
+        guard: &'g Guard,
+    ) -> InsertOutcome<&'g V> {
+        let before = self.table.load(Ordering::SeqCst, guard);
+        let mut old = None;
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| {
+                old = current;
+                Action::Set(value)
+            },
+            Operation::Insert,
+            guard,
+        );
+        let resized = !before.is_null()
+            && (self.table.load(Ordering::SeqCst, guard) != before
+                || !self.next_table.load(Ordering::SeqCst, guard).is_null());
+        match computed {
+            Computed::Inserted(_) => InsertOutcome::Inserted { resized },
+            Computed::Replaced(_) => InsertOutcome::Replaced {
+                old: old.expect("a replaced value was given to the closure"),
+                resized,
+            },
+            Computed::Removed(_) | Computed::Unchanged(_) => unreachable!(),
+        }
+    }
+
+    /// Maps `key` to `value`, and calls `on_first` with `value` if this creates the entry for
+    /// `key`. Returns the value that `value` replaced, if any.
+    ///
+    /// `on_first` is called while holding the lock for `key`'s bin, just before `value` becomes
+    /// visible to other threads, so of any number of threads that race to insert the same absent
+    /// key this way, exactly one runs its `on_first`. It is not called if `key` is already
+    /// present, even though `value` then replaces the value it is mapped to. `on_first` must not
+    /// write to the map.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn insert_with_init<'g, F>(
+        &'g self,
+        key: K,
+        value: V,
+        on_first: F,
+        guard: &'g Guard,
+    ) -> Option<&'g V>
+    where
+        F: FnOnce(&V),
+    {
+        let mut old = None;
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| {
+                old = current;
+                if current.is_none() {
+                    on_first(&value);
+                }
+                Action::Set(value)
+            },
+            Operation::Insert,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(_) => None,
+            Computed::Replaced(_) => Some(old.expect("a replaced value was given to the closure")),
+            Computed::Removed(_) | Computed::Unchanged(_) => unreachable!(),
+        }
+    }
+
+    /// Maps `key` to `value` if `key` is present, or if the map holds fewer than `max_len`
+    /// entries. Returns the value that `value` replaced, if any, or `Err(AtCapacity)`, with the
+    /// map unchanged, if `key` is absent and the map is full.
+    ///
+    /// This enforces a ceiling on the size of the map where entries are inserted. The length of
+    /// the map is checked while holding the lock for `key`'s bin, so the check cannot pass for a
+    /// key that another thread is inserting at the same time. Insertions into other bins are not
+    /// held up, though, so each of them may pass the check before any of them is counted, and
+    /// the map may overshoot `max_len` by up to one less than the number of threads inserting
+    /// this way at once. Replacing the value of a present key is allowed even if the map is
+    /// full, since it does not grow the map. Other methods that insert do not check `max_len`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn insert_if_under_capacity<'g>(
+        &'g self,
+        key: K,
+        value: V,
+        max_len: usize,
+        guard: &'g Guard,
+    ) -> Result<Option<&'g V>, AtCapacity> {
+        let mut old = None;
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| {
+                if current.is_none() && self.len() >= max_len {
+                    return Action::Keep;
+                }
+                old = current;
+                Action::Set(value)
+            },
+            Operation::Insert,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(_) => Ok(None),
+            Computed::Replaced(_) => Ok(Some(
+                old.expect("a replaced value was given to the closure"),
+            )),
+            Computed::Unchanged(None) => Err(AtCapacity),
+            Computed::Removed(_) | Computed::Unchanged(Some(_)) => unreachable!(),
+        }
+    }
+
+    /// Maps `key` to `value` if it is present, and returns the value it was mapped to along with
+    /// the new one, as `(old, new)`.
+    ///
+    /// Both values are taken while holding the lock for `key`'s bin, so `old` is exactly the
+    /// value that `value` replaced. If `key` is absent, nothing is inserted, unlike with
+    /// [`insert`](FlurryHashMap::insert), and `None` is returned.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn replace_returning<'g>(
+        &'g self,
+        key: &K,
+        value: V,
+        guard: &'g Guard,
+    ) -> Option<(&'g V, &'g V)> {
+        let mut old = None;
+        let computed = self.compute_locked(
+            KeyArg::Borrowed(key),
+            |_, current| {
+                old = current;
+                Action::Set(value)
+            },
+            Operation::Replace,
+            guard,
+        );
+        match computed {
+            Computed::Replaced(new) => {
+                Some((old.expect("a replaced value was given to the closure"), new))
+            }
+            Computed::Unchanged(None) => None,
+            Computed::Inserted(_) | Computed::Removed(_) | Computed::Unchanged(Some(_)) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Removes `key` from the map, returning the value it was mapped to, if any.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn remove<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        let computed = self.compute_locked(
+            KeyArg::Borrowed(key),
+            |_, _| Action::Remove,
+            Operation::Remove,
+            guard,
+        );
+        match computed {
+            Computed::Removed(v) => Some(v),
+            Computed::Unchanged(None) => None,
+            Computed::Inserted(_) | Computed::Replaced(_) | Computed::Unchanged(Some(_)) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Removes `key` from the map if it is mapped to a value equal to `expected`, and returns a
+    /// clone of the removed value. Returns `None`, and leaves the map unchanged, if `key` is
+    /// absent or mapped to some other value.
+    ///
+    /// The comparison is made while holding the lock for `key`'s bin, so no other write to `key`
+    /// can slip in between it and the removal. This lets a thread remove an entry only if no
+    /// other thread has changed it since the thread last read it.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn compare_remove(&self, key: &K, expected: &V, guard: &Guard) -> Option<V>
+    where
+        V: Clone + PartialEq,
+    {
+        let computed = self.compute_locked(
+            KeyArg::Borrowed(key),
+            |_, current| match current {
+                Some(current) if current == expected => Action::Remove,
+                _ => Action::Keep,
+            },
+            Operation::Remove,
+            guard,
+        );
+        match computed {
+            Computed::Removed(v) => Some(v.clone()),
+            Computed::Unchanged(_) => None,
+            Computed::Inserted(_) | Computed::Replaced(_) => unreachable!(),
+        }
+    }
+
+    /// Like [`insert`](FlurryHashMap::insert), but also sets the priority of the entry for
+    /// [`evict_lowest`](FlurryHashMap::evict_lowest).
+    ///
+    /// Entries inserted without a priority have priority 0. Replacing the value of an entry with
+    /// [`insert`](FlurryHashMap::insert) keeps its priority.
+    ///
+    /// This method is only available with the `priority` feature enabled.
+    #[cfg(feature = "priority")]
+    pub fn insert_with_priority(&self, key: K, value: V, priority: u64) -> Option<()> {
+        let guard = &crossbeam::epoch::pin();
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            move |_, _| Action::SetPrioritized(value, priority),
+            Operation::Insert,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(_) => None,
+            Computed::Replaced(_) => Some(()),
+            Computed::Removed(_) | Computed::Unchanged(_) => unreachable!(),
+        }
+    }
+
+    /// Removes the `n` entries with the lowest priorities, and returns their keys. Entries with
+    /// equal priorities are removed in arbitrary order.
+    ///
+    /// The priorities of all entries are read in one pass over the map, after which the chosen
+    /// entries are removed one by one. If the map is modified concurrently, an entry may
+    /// therefore be chosen based on a priority it no longer has by the time it is removed.
+    ///
+    /// This method is only available with the `priority` feature enabled.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    #[cfg(feature = "priority")]
+    pub fn evict_lowest(&self, n: usize, guard: &Guard) -> Vec<K> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let mut entries: Vec<_> = NodeIter::new(table, guard)
+            .map(|node| (node.priority.load(Ordering::SeqCst), &node.key))
+            .collect();
+        if n < entries.len() {
+            entries.select_nth_unstable_by_key(n, |&(priority, _)| priority);
+            entries.truncate(n);
+        }
+
+        entries
+            .into_iter()
+            .filter(|&(_, key)| self.remove(key, guard).is_some())
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    /// Returns the key of the entry that `policy` would evict next, without removing it.
+    ///
+    /// Every policy but [`Random`](EvictionPolicy::Random) visits every entry of the map. Ties
+    /// are broken arbitrarily. If the map is modified concurrently, the entry may have been
+    /// removed, or stopped being the one `policy` would pick, by the time this returns.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn peek_eviction_candidate<'g>(
+        &'g self,
+        policy: EvictionPolicy,
+        guard: &'g Guard,
+    ) -> Option<&'g K> {
+        self.eviction_candidate(policy, None, guard)
+    }
+
+    /// Returns the key of the entry that `policy` would evict next, other than `spare`.
+    fn eviction_candidate<'g>(
+        &'g self,
+        policy: EvictionPolicy,
+        spare: Option<&K>,
+        guard: &'g Guard,
+    ) -> Option<&'g K> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        #[cfg(any(feature = "priority", feature = "timestamps"))]
+        let nodes = || NodeIter::new(table, guard).filter(|node| Some(&node.key) != spare);
+        let node = match policy {
+            EvictionPolicy::Random => {
+                // safety: table is only dropped on the next epoch change after it is swapped to
+                // null. we read it while holding `guard`, so the current epoch persists while we
+                // use it.
+                let t = unsafe { table.as_ref() }?;
+                let n = t.bins.len();
+                let start = rand::Rng::gen_range(&mut rand::thread_rng(), 0, std::cmp::max(n, 1));
+                // the bins from the random one to the end, and then those before it
+                NodeIter::with_range(t, start, n, guard)
+                    .chain(NodeIter::with_range(t, 0, start, guard))
+                    .find(|node| Some(&node.key) != spare)
+            }
+            #[cfg(feature = "priority")]
+            EvictionPolicy::LowestPriority => {
+                nodes().min_by_key(|node| node.priority.load(Ordering::SeqCst))
+            }
+            #[cfg(feature = "timestamps")]
+            EvictionPolicy::LeastRecentlyWritten => nodes().min_by_key(|node| node.written.load()),
+            #[cfg(feature = "timestamps")]
+            EvictionPolicy::OldestInsertion => nodes().min_by_key(|node| node.inserted),
+        };
+        node.map(|node| &node.key)
+    }
+
+    fn put(&self, key: K, value: V, no_replacement: bool, guard: &Guard) -> Option<()> {
+        if self.wal.is_some() {
+            // the write-ahead log must see the value before it is stored, which the fast path
+            // below cannot ensure
+            let computed = self.compute_locked(
+                KeyArg::Owned(key),
+                move |_, current| {
+                    if no_replacement && current.is_some() {
+                        Action::Keep
+                    } else {
+                        Action::Set(value)
+                    }
+                },
+                Operation::Insert,
+                guard,
+            );
+            return match computed {
+                Computed::Inserted(_) => None,
+                Computed::Replaced(_) | Computed::Unchanged(_) => Some(()),
+                Computed::Removed(_) => unreachable!(),
+            };
+        }
+
+        let _gate = self.write_gate.enter();
+        let h = self.hash(&key, guard);
+        if let Some(ref bloom) = self.bloom {
+            // before the key can be found in the table
+            bloom.insert(h, guard);
+        }
+
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+
+        let mut node = Owned::new(BinEntry::Node(Node {
+            key,
+            value: Atomic::new(value),
+            hash: h,
+            next: Atomic::null(),
+            swap_stamp: AtomicUsize::new(0),
+            swapped: Atomic::null(),
+            lock: parking_lot::Mutex::new(()),
+            #[cfg(feature = "priority")]
+            priority: std::sync::atomic::AtomicU64::new(0),
+            #[cfg(feature = "timestamps")]
+            written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+            #[cfg(feature = "timestamps")]
+            inserted: std::time::Instant::now(),
+            #[cfg(feature = "entry-stats")]
+            counters: stats::EntryCounters::new(1),
+        }));
+
+        loop {
+            // safety: see argument below for !is_null case
+            if table.is_null() || unsafe { table.deref() }.bins.is_empty() {
+                table = self.init_table(guard);
+                continue;
+            }
+
+            // safety: table is a valid pointer.
+            //
+            // we are in one of three cases:
             //
             //  1. if table is the one we read before the loop, then we read it while holding the
             //     guard, so it won't be dropped until after we drop that guard b/c the drop logic
@@ -509,18 +1621,21 @@ where
             // are holding up by holding on to our guard).
             let key = &node.as_node().unwrap().key;
             match *unsafe { bin.deref() } {
-                BinEntry::Moved(next_table) => {
+                BinEntry::Moved { next_table, .. } => {
                     table = self.help_transfer(table, next_table, guard);
                 }
                 BinEntry::Node(ref head)
-                    if no_replacement && head.hash == h && &head.key == key =>
+                    if no_replacement
+                        && head.hash == h
+                        && &head.key == key
+                        && !head.value.load(Ordering::SeqCst, guard).is_null() =>
                 {
                     // fast path if replacement is disallowed and first bin matches
                     return Some(());
                 }
                 BinEntry::Node(ref head) => {
                     // bin is non-empty, need to link into it, so we must take the lock
-                    let head_lock = head.lock.lock();
+                    let head_lock = self.lock_bin(head, Operation::Insert);
 
                     // need to check that this is _still_ the head
                     let current_head = t.bin(bini, guard);
@@ -545,6 +1660,7 @@ where
                         let n = unsafe { p.deref() }.as_node().unwrap();
                         if n.hash == h && &n.key == key {
                             // the key already exists in the map!
+                            self.debug_check_hash(self.hasher(guard), n);
                             if no_replacement {
                                 // the key is not absent, so don't update
                             } else if let BinEntry::Node(Node { value, .. }) = *node.into_box() {
@@ -554,6 +1670,7 @@ where
                                     Ordering::SeqCst,
                                     guard,
                                 );
+                                n.touch();
                                 // safety: need to guarantee that now_garbage is no longer
                                 // reachable. more specifically, no thread that executes _after_
                                 // this line can ever get a reference to now_garbage.
@@ -599,6 +1716,7 @@ where
                         // increment count
                         self.add_count(1, Some(bin_count), guard);
                     }
+                    self.adapt(guard);
                     guard.flush();
                     return old_val;
                 }
@@ -606,341 +1724,2450 @@ where
         }
     }
 
-    fn help_transfer<'g>(
-        &self,
-        table: Shared<'g, Table<K, V>>,
-        next_table: *const Table<K, V>,
-        guard: &'g Guard,
-    ) -> Shared<'g, Table<K, V>> {
-        if table.is_null() || next_table.is_null() {
-            return table;
+    /// Returns the value to which `key` is mapped, computing and inserting it using `f` first if
+    /// `key` is not already present in the map.
+    ///
+    /// The entire method invocation is performed atomically, so `f` is called at most once per
+    /// key. Some attempted update operations on this map by other threads may block while the
+    /// computation is in progress, so the computation should be short and simple.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn compute_if_absent<'g, F>(&'g self, key: K, f: F, guard: &'g Guard) -> &'g V
+    where
+        F: FnOnce(&K) -> V,
+    {
+        if let Some(v) = self.get(&key, guard) {
+            // fast path if the key is already present
+            return v;
         }
 
-        let next_table = Shared::from(next_table);
-
-        // safety: table is only dropped on the next epoch change after it is swapped to null.
-        // we read it as not null, so it must not be dropped until a subsequent epoch. since we
-        // held `guard` at the time, we know that the current epoch continues to persist, and that
-        // our reference is therefore valid.
-        let rs = Self::resize_stamp(unsafe { table.deref() }.bins.len()) << RESIZE_STAMP_SHIFT;
-
-        while next_table == self.next_table.load(Ordering::SeqCst, guard)
-            && table == self.table.load(Ordering::SeqCst, guard)
-        {
-            let sc = self.size_ctl.load(Ordering::SeqCst);
-            if sc >= 0
-                || sc == rs + MAX_RESIZERS
-                || sc == rs + 1
-                || self.transfer_index.load(Ordering::SeqCst) <= 0
-            {
-                break;
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |k, current| {
+                if current.is_some() {
+                    Action::Keep
+                } else {
+                    Action::Set(f(k))
+                }
+            },
+            Operation::ComputeIfAbsent,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Unchanged(Some(v)) => v,
+            Computed::Replaced(_) | Computed::Removed(_) | Computed::Unchanged(None) => {
+                unreachable!()
             }
+        }
+    }
 
-            if self.size_ctl.compare_and_swap(sc, sc + 1, Ordering::SeqCst) == sc {
-                self.transfer(table, next_table, guard);
-                break;
+    /// Like [`compute_if_absent`](FlurryHashMap::compute_if_absent), but `f` also returns a
+    /// secondary key for the new value, under which `key` is then inserted into `index`.
+    ///
+    /// `index` is written to while holding the lock for `key`'s bin, just before the value
+    /// becomes visible, so of any number of threads that race to compute the same absent key,
+    /// exactly one both creates the value and indexes it. The index entry may be seen briefly
+    /// before the value is. Since the lock of a bin of `index` is taken while the lock for
+    /// `key`'s bin is held, `index` must never be written to in a way that in turn writes to
+    /// this map, or the two could deadlock.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn compute_if_absent_indexed<'g, F, I, S2>(
+        &'g self,
+        key: K,
+        f: F,
+        index: &FlurryHashMap<I, K, S2>,
+        guard: &'g Guard,
+    ) -> &'g V
+    where
+        F: FnOnce(&K) -> (V, I),
+        I: Sync + Send + Clone + Hash + Eq,
+        S2: BuildHasher,
+    {
+        if let Some(v) = self.get(&key, guard) {
+            // fast path if the key is already present
+            return v;
+        }
+
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |k, current| {
+                if current.is_some() {
+                    return Action::Keep;
+                }
+                let (value, secondary) = f(k);
+                index.put(secondary, k.clone(), false, guard);
+                Action::Set(value)
+            },
+            Operation::ComputeIfAbsent,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Unchanged(Some(v)) => v,
+            Computed::Replaced(_) | Computed::Removed(_) | Computed::Unchanged(None) => {
+                unreachable!()
             }
         }
-        next_table
     }
 
-    fn add_count(&self, n: isize, resize_hint: Option<usize>, guard: &Guard) {
-        // TODO: implement the Java CounterCell business here
+    /// Returns the value `key` is mapped to, or if it is absent, tries to compute one with `f`
+    /// and maps `key` to it.
+    ///
+    /// Unlike with [`compute_if_absent`](FlurryHashMap::compute_if_absent), `f` is called
+    /// without holding any locks, so it may block, such as on I/O. As a consequence, several
+    /// threads that look up the same absent key at the same time may all call `f`, but only the
+    /// value of the first one to finish is stored, and all of them return that value. If `f`
+    /// fails, its error is returned, and `key` is left absent.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn get_or_try_init_blocking<'g, F, E>(
+        &'g self,
+        key: K,
+        f: F,
+        guard: &'g Guard,
+    ) -> Result<&'g V, E>
+    where
+        F: FnOnce(&K) -> Result<V, E>,
+    {
+        if let Some(v) = self.get(&key, guard) {
+            // fast path if the key is already present
+            return Ok(v);
+        }
 
-        use std::cmp;
-        let mut count = match n.cmp(&0) {
-            cmp::Ordering::Greater => {
-                let n = n as usize;
-                self.count.fetch_add(n, Ordering::SeqCst) + n
-            }
-            cmp::Ordering::Less => {
-                let n = n.abs() as usize;
-                self.count.fetch_sub(n, Ordering::SeqCst) - n
+        let value = f(&key)?;
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| {
+                if current.is_some() {
+                    // someone else initialized the key while we were computing its value
+                    Action::Keep
+                } else {
+                    Action::Set(value)
+                }
+            },
+            Operation::ComputeIfAbsent,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Unchanged(Some(v)) => Ok(v),
+            Computed::Replaced(_) | Computed::Removed(_) | Computed::Unchanged(None) => {
+                unreachable!()
             }
-            cmp::Ordering::Equal => self.count.load(Ordering::SeqCst),
-        };
+        }
+    }
 
-        // if resize_hint is None, it means the caller does not want us to consider a resize.
-        // if it is Some(n), the caller saw n entries in a bin
-        if resize_hint.is_none() {
-            return;
+    /// Computes values for all the `keys` that are absent with a single call to `f`, and inserts
+    /// them.
+    ///
+    /// `f` is given the absent keys, each only once, and must return their values in the same
+    /// order. Since `f` is not called while holding any locks, it may be expensive, such as a
+    /// single batched query to a database. Keys that are inserted by another thread while `f` is
+    /// running keep the value they were given by that thread. If all the keys are present, `f` is
+    /// not called.
+    ///
+    /// Returns the number of keys that were inserted.
+    ///
+    /// # Panics
+    ///
+    /// If `f` does not return exactly one value for every key it is given.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn compute_if_absent_many<I, F>(&self, keys: I, f: F, guard: &Guard) -> usize
+    where
+        I: IntoIterator<Item = K>,
+        F: Fn(&[&K]) -> Vec<V>,
+    {
+        let mut seen = std::collections::HashSet::new();
+        let absent: Vec<K> = keys
+            .into_iter()
+            .filter(|key| self.get(key, guard).is_none() && seen.insert(key.clone()))
+            .collect();
+        if absent.is_empty() {
+            return 0;
         }
 
-        // TODO: use the resize hint
-        let _saw_bin_length = resize_hint.unwrap();
+        let values = f(&absent.iter().collect::<Vec<_>>());
+        assert_eq!(
+            values.len(),
+            absent.len(),
+            "the computation must return one value for every key"
+        );
+        absent
+            .into_iter()
+            .zip(values)
+            .map(|(key, value)| self.put(key, value, true, guard))
+            .filter(Option::is_none)
+            .count()
+    }
 
-        loop {
-            let sc = self.size_ctl.load(Ordering::SeqCst);
-            if (count as isize) < sc {
-                // we're not at the next resize point yet
-                break;
-            }
+    /// Maps `key` to `value` if it is absent, or otherwise to `combine(current, &value)`, where
+    /// `current` is the value it is currently mapped to. Returns the value `key` is now mapped to.
+    ///
+    /// `combine` is called while holding the lock for `key`'s bin, so it should be short and
+    /// simple.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn merge<'g, F>(&'g self, key: K, value: V, combine: F, guard: &'g Guard) -> &'g V
+    where
+        F: FnOnce(&V, &V) -> V,
+    {
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| match current {
+                Some(current) => Action::Set(combine(current, &value)),
+                None => Action::Set(value),
+            },
+            Operation::Merge,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Replaced(v) => v,
+            Computed::Removed(_) | Computed::Unchanged(_) => unreachable!(),
+        }
+    }
 
-            let table = self.table.load(Ordering::SeqCst, guard);
-            if table.is_null() {
-                // table will be initalized by another thread anyway
-                break;
-            }
+    /// Maps `key` to `on_insert()` if it is absent, or otherwise to `on_update(current)`, where
+    /// `current` is the value it is currently mapped to. Returns the value `key` is now mapped to.
+    ///
+    /// Exactly one of the two closures is called, exactly once. Unlike with
+    /// [`merge`](FlurryHashMap::merge), no value has to be built up front for the case where the
+    /// key is present.
+    ///
+    /// The closure is called while holding the lock for `key`'s bin, so it should be short and
+    /// simple.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn upsert<'g, I, U>(&'g self, key: K, on_insert: I, on_update: U, guard: &'g Guard) -> &'g V
+    where
+        I: FnOnce() -> V,
+        U: FnOnce(&V) -> V,
+    {
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| match current {
+                Some(current) => Action::Set(on_update(current)),
+                None => Action::Set(on_insert()),
+            },
+            Operation::Upsert,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Replaced(v) => v,
+            Computed::Removed(_) | Computed::Unchanged(_) => unreachable!(),
+        }
+    }
 
-            // safety: table is only dropped on the next epoch change after it is swapped to null.
-            // we read it as not null, so it must not be dropped until a subsequent epoch. since we
-            // hold a Guard, we know that the current epoch will persist, and that our reference
-            // will therefore remain valid.
-            let n = unsafe { table.deref() }.bins.len();
-            if n >= MAXIMUM_CAPACITY {
-                // can't resize any more anyway
-                break;
-            }
+    /// Maps `key` to the value returned by `f`, which is given `key` and all the other entries
+    /// that are in the same bin as `key`. If `f` returns `None`, the map is left unchanged.
+    /// Returns the value `key` is then mapped to, if any.
+    ///
+    /// This is for schemes where an entry's value depends on the keys it collides with. The lock
+    /// for `key`'s bin is held from before the neighbors are read until after the value is
+    /// installed, so no entry can join or leave the bin in between, and `f` should be short and
+    /// simple. Which keys share a bin depends on the hasher and on the current size of the table.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn compute_from_bin<'g, F>(&'g self, key: K, f: F, guard: &'g Guard) -> Option<&'g V>
+    where
+        F: Fn(&K, &[(&K, &V)]) -> Option<V>,
+    {
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |key, _| {
+                let h = self.hash(key, guard);
+                let mut table = self.table.load(Ordering::SeqCst, guard);
+                let mut bin = loop {
+                    // safety: we hold the lock for the key's bin, so the table it is in cannot
+                    // be retired, and neither can the table it is being moved from, if any.
+                    let t = unsafe { table.deref() };
+                    let bin = t.bin(t.bini(h), guard);
+                    // safety: as above, for the bins in the tables
+                    match *unsafe { bin.deref() } {
+                        // we hold the lock of the bin the key was moved to
+                        BinEntry::Moved { next_table, .. } => table = Shared::from(next_table),
+                        BinEntry::Node(_) => break bin,
+                    }
+                };
 
-            let rs = Self::resize_stamp(n) << RESIZE_STAMP_SHIFT;
-            if sc < 0 {
-                // ongoing resize! can we join the resize transfer?
-                if sc == rs + MAX_RESIZERS || sc == rs + 1 {
-                    break;
-                }
-                let nt = self.next_table.load(Ordering::SeqCst, guard);
-                if nt.is_null() {
-                    break;
-                }
-                if self.transfer_index.load(Ordering::SeqCst) <= 0 {
-                    break;
+                let mut neighbors = Vec::new();
+                // safety: the nodes of a bin are only dropped once they have been unlinked under
+                // its lock, which we hold.
+                while let Some(n) = unsafe { bin.as_ref() } {
+                    let n = n.as_node().unwrap();
+                    let value = n.value.load(Ordering::SeqCst, guard);
+                    // a reservation has no value yet, and may be the key's own
+                    if !value.is_null() && n.key != *key {
+                        // safety: values are only dropped in the epoch after they have been
+                        // replaced, and we read this one under our guard.
+                        neighbors.push((&n.key, unsafe { value.deref() }));
+                    }
+                    bin = n.next.load(Ordering::SeqCst, guard);
                 }
-
-                // try to join!
-                if self.size_ctl.compare_and_swap(sc, sc + 1, Ordering::SeqCst) == sc {
-                    self.transfer(table, nt, guard);
+                match f(key, &neighbors) {
+                    Some(value) => Action::Set(value),
+                    None => Action::Keep,
                 }
-            } else if self.size_ctl.compare_and_swap(sc, rs + 2, Ordering::SeqCst) == sc {
-                // a resize is needed, but has not yet started
-                // TODO: figure out why this is rs + 2, not just rs
-                self.transfer(table, Shared::null(), guard);
-            }
+            },
+            Operation::Upsert,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(v) | Computed::Replaced(v) => Some(v),
+            Computed::Unchanged(v) => v,
+            Computed::Removed(_) => unreachable!(),
+        }
+    }
 
-            // another resize may be needed!
-            count = self.count.load(Ordering::SeqCst);
+    /// [`merge`](FlurryHashMap::merge)s each of the given key-value pairs into the map in turn,
+    /// using `combine` to combine values.
+    ///
+    /// Pairs with the same key are combined with one another just as they are with values
+    /// already in the map. Before the first pair is merged, the map grows to fit the iterator's
+    /// lower size hint (see [`reserve_concurrent`](FlurryHashMap::reserve_concurrent)).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn merge_many<I, F>(&self, pairs: I, combine: F, guard: &Guard)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        F: Fn(&V, &V) -> V,
+    {
+        let pairs = pairs.into_iter();
+        self.reserve_concurrent(pairs.size_hint().0, guard);
+        for (key, value) in pairs {
+            self.merge(key, value, &combine, guard);
         }
     }
 
-    fn transfer<'g>(
-        &self,
-        table: Shared<'g, Table<K, V>>,
-        mut next_table: Shared<'g, Table<K, V>>,
-        guard: &'g Guard,
-    ) {
-        // safety: table was read while `guard` was held. the code that drops table only drops it
-        // after it is no longer reachable, and any outstanding references are no longer active.
-        // this references is still active (marked by the guard), so the target of the references
-        // won't be dropped while the guard remains active.
-        let n = unsafe { table.deref() }.bins.len();
-
-        // TODO: use num_cpus to help determine stride
-        let stride = MIN_TRANSFER_STRIDE;
-
-        if next_table.is_null() {
-            // we are initiating a resize
-            let table = Owned::new(Table {
-                bins: vec![Atomic::null(); n << 1].into_boxed_slice(),
-            });
-
-            let now_garbage = self.next_table.swap(table, Ordering::SeqCst, guard);
-            assert!(now_garbage.is_null());
-            self.transfer_index.store(n as isize, Ordering::SeqCst);
-            next_table = self.next_table.load(Ordering::Relaxed, guard);
+    /// Adds each of the given deltas to the value its key is mapped to, or inserts the delta as
+    /// the key's value if the key is absent.
+    ///
+    /// This flushes counts that were accumulated locally, such as in a thread-local map, into a
+    /// shared map of counters. Each delta is added while holding the lock for its key's bin, so
+    /// no concurrent update of a key is lost, but other threads may see some of the deltas
+    /// applied and others not yet. The deltas are grouped by bin first, and all the deltas for
+    /// one bin are applied under a single acquisition of its lock. Deltas for the same key are
+    /// applied in the order they are given. Deltas for bins that are empty or that a resize is
+    /// moving are applied one at a time.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn apply_deltas<I>(&self, deltas: I, guard: &Guard)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        V: std::ops::Add<Output = V> + Clone,
+    {
+        // the deltas are grouped by the hashes the current hash builder gives their keys, so it
+        // must not be replaced until they have all been applied
+        let _gate = self.write_gate.enter();
+        let mut deltas: Vec<_> = deltas
+            .into_iter()
+            .map(|(key, delta)| (self.hash(&key, guard), key, delta))
+            .collect();
+        if deltas.is_empty() {
+            return;
+        }
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+        // safety: see argument in put
+        if table.is_null() || unsafe { table.deref() }.bins.is_empty() {
+            table = self.init_table(guard);
         }
+        // safety: see argument in put
+        let t = unsafe { table.deref() };
+        // the sort is stable, so deltas for the same key stay in order
+        deltas.sort_by_key(|&(h, _, _)| t.bini(h));
+        let mut deltas = deltas.into_iter().peekable();
+        while let Some(first) = deltas.next() {
+            let bini = t.bini(first.0);
+            let mut group = vec![first];
+            while deltas.peek().map_or(false, |&(h, _, _)| t.bini(h) == bini) {
+                group.push(deltas.next().expect("peeked"));
+            }
+            self.apply_bin_deltas(group, guard);
+        }
+    }
 
-        // safety: same argument as for table above
-        let next_n = unsafe { next_table.deref() }.bins.len();
+    /// Applies deltas whose keys are all in the same bin of the current table under a single
+    /// acquisition of the bin's lock, for `apply_deltas`.
+    fn apply_bin_deltas(&self, mut group: Vec<(u64, K, V)>, guard: &Guard)
+    where
+        V: std::ops::Add<Output = V> + Clone,
+    {
+        let _gate = self.write_gate.enter();
+        if let Some(ref bloom) = self.bloom {
+            // before the keys can be found in the table
+            for &(h, _, _) in &group {
+                bloom.insert(h, guard);
+            }
+        }
+        let apply_one = |key, delta: V| {
+            self.compute_locked(
+                KeyArg::Owned(key),
+                |_, current| match current {
+                    Some(current) => Action::Set(current.clone() + delta),
+                    None => Action::Set(delta),
+                },
+                Operation::ApplyDeltas,
+                guard,
+            );
+        };
 
-        let mut advance = true;
-        let mut finishing = false;
-        let mut i = 0;
-        let mut bound = 0;
         loop {
-            // try to claim a range of bins for us to transfer
-            while advance {
-                i -= 1;
-                if i >= bound || finishing {
-                    advance = false;
-                    break;
+            let table = self.table.load(Ordering::SeqCst, guard);
+            // safety: see argument in put
+            let t = match unsafe { table.as_ref() } {
+                Some(t) if !t.bins.is_empty() => t,
+                _ => break,
+            };
+            let bini = t.bini(group[0].0);
+            if group.iter().any(|&(h, _, _)| t.bini(h) != bini) {
+                // the table was replaced since the deltas were grouped
+                break;
+            }
+            let bin = t.bin(bini, guard);
+            // safety: see argument in put
+            let head = match unsafe { bin.as_ref() } {
+                Some(BinEntry::Node(head)) => head,
+                None => {
+                    // an empty bin has no lock to take, so the first delta goes through the usual
+                    // path, which leaves a head to lock for the rest
+                    let (_, key, delta) = group.remove(0);
+                    apply_one(key, delta);
+                    if group.is_empty() {
+                        return;
+                    }
+                    continue;
                 }
+                // the keys may end up in different bins of the next table
+                Some(BinEntry::Moved { .. }) => break,
+            };
+            let head_lock = self.lock_bin(head, Operation::ApplyDeltas);
+            // need to check that this is _still_ the head
+            if t.bin(bini, guard) != bin {
+                continue;
+            }
 
-                let next_index = self.transfer_index.load(Ordering::SeqCst);
-                if next_index <= 0 {
-                    i = -1;
-                    advance = false;
-                    break;
-                }
+            let mut inserted = 0;
+            // the longest chain an insertion went into, as in compute_locked
+            let mut chain = 0;
+            for (h, key, delta) in group {
+                let mut n = head;
+                let mut bin_count = 1;
+                loop {
+                    if n.hash == h && n.key == key {
+                        self.debug_check_hash(self.hasher(guard), n);
+                        // as in compute_locked, only the head of a bin can be a reservation, and
+                        // we hold its lock
+                        let current = n.value.load(Ordering::SeqCst, guard);
+                        assert!(!current.is_null());
+                        // safety: values are only dropped in the epoch after they have been
+                        // replaced, and we read this one under our guard.
+                        let current_ref = unsafe { current.deref() };
+                        let value = Owned::new(current_ref.clone() + delta);
+                        self.log(&WalRecord::Replace {
+                            key: &n.key,
+                            old: current_ref,
+                            new: &*value,
+                        });
+                        n.value.store(value, Ordering::SeqCst);
+                        n.touch();
+                        // safety: see argument in put
+                        unsafe { guard.defer_destroy(current) };
+                        break;
+                    }
 
-                let next_bound = if next_index > stride {
-                    next_index - stride
-                } else {
-                    0
-                };
-                if self
-                    .transfer_index
-                    .compare_and_swap(next_index, next_bound, Ordering::SeqCst)
-                    == next_index
-                {
-                    bound = next_bound;
-                    i = next_index;
-                    advance = false;
-                    break;
+                    let next = n.next.load(Ordering::SeqCst, guard);
+                    if next.is_null() {
+                        // we're at the end of the bin, and the key is absent
+                        let value = Owned::new(delta);
+                        self.log(&WalRecord::Insert {
+                            key: &key,
+                            value: &*value,
+                        });
+                        let node = Owned::new(BinEntry::Node(Node {
+                            key,
+                            value: Atomic::from(value),
+                            hash: h,
+                            next: Atomic::null(),
+                            swap_stamp: AtomicUsize::new(0),
+                            swapped: Atomic::null(),
+                            lock: parking_lot::Mutex::new(()),
+                            #[cfg(feature = "priority")]
+                            priority: std::sync::atomic::AtomicU64::new(0),
+                            #[cfg(feature = "timestamps")]
+                            written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+                            #[cfg(feature = "timestamps")]
+                            inserted: std::time::Instant::now(),
+                            #[cfg(feature = "entry-stats")]
+                            counters: stats::EntryCounters::new(0),
+                        }));
+                        node.as_node().unwrap().touch();
+                        n.next.store(node, Ordering::SeqCst);
+                        inserted += 1;
+                        chain = std::cmp::max(chain, bin_count);
+                        break;
+                    }
+                    // safety: see argument in put
+                    n = unsafe { next.deref() }.as_node().unwrap();
+                    bin_count += 1;
                 }
             }
+            drop(head_lock);
 
-            if i < 0 || i as usize >= n || i as usize + n >= next_n {
-                // the resize has finished
+            if inserted != 0 {
+                self.add_count(inserted, Some(chain), guard);
+            }
+            self.adapt(guard);
+            guard.flush();
+            return;
+        }
 
-                if finishing {
-                    // this branch is only taken for one thread partaking in the resize!
-                    self.next_table.store(Shared::null(), Ordering::SeqCst);
-                    let now_garbage = self.table.swap(next_table, Ordering::SeqCst, guard);
-                    // safety: need to guarantee that now_garbage is no longer reachable. more
-                    // specifically, no thread that executes _after_ this line can ever get a
-                    // reference to now_garbage.
-                    //
-                    // first, we need to argue that there is no _other_ way to get to now_garbage.
-                    //
-                    //  - it is _not_ accessible through self.table any more
-                    //  - it is _not_ accessible through self.next_table any more
-                    //  - what about forwarding nodes (BinEntry::Moved)?
-                    //    the only BinEntry::Moved that point to now_garbage, are the ones in
-                    //    _previous_ tables. to get to those previous tables, one must ultimately
-                    //    have arrived through self.table (because that's where all operations
-                    //    start their search). since self.table has now changed, only "old" threads
-                    //    can still be accessing them. no new thread can get to past tables, and
-                    //    therefore they also cannot get to ::Moved that point to now_garbage, so
-                    //    we're fine.
-                    //
-                    // this means that no _future_ thread (i.e., in a later epoch where the value
-                    // may be freed) can get a reference to now_garbage.
-                    //
-                    // next, let's talk about threads with _existing_ references to now_garbage.
-                    // such a thread must have gotten that reference before the call to swap.
-                    // because of this, that thread must be pinned to an epoch <= the epoch of our
-                    // guard (since our guard is pinning the epoch). since the garbage is placed in
-                    // our epoch, it won't be freed until the _next_ epoch, at which point, that
-                    // thread must have dropped its guard, and with it, any reference to the value.
-                    unsafe { guard.defer_destroy(now_garbage) };
-                    self.size_ctl
-                        .store(((n as isize) << 1) - ((n as isize) >> 1), Ordering::SeqCst);
-                    return;
-                }
+        for (_, key, delta) in group {
+            apply_one(key, delta);
+        }
+    }
 
-                let sc = self.size_ctl.load(Ordering::SeqCst);
-                if self.size_ctl.compare_and_swap(sc, sc - 1, Ordering::SeqCst) == sc {
-                    if (sc - 2) != Self::resize_stamp(n) << RESIZE_STAMP_SHIFT {
-                        return;
-                    }
+    /// Maps `key` to `value` if `key` is absent, or if `is_newer(&value, current)` returns `true`
+    /// for the value `current` that `key` is currently mapped to. Returns `true` if `key` is
+    /// now mapped to `value`.
+    ///
+    /// The check and the write happen together under the lock for `key`'s bin, so of several
+    /// concurrent calls for the same key, the one with the newest value always wins, regardless
+    /// of the order in which they run. `is_newer` should therefore be short and simple.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn set_if_newer<F>(&self, key: K, value: V, is_newer: F, guard: &Guard) -> bool
+    where
+        F: Fn(&V, &V) -> bool,
+    {
+        let computed = self.compute_locked(
+            KeyArg::Owned(key),
+            |_, current| match current {
+                Some(current) if !is_newer(&value, current) => Action::Keep,
+                _ => Action::Set(value),
+            },
+            Operation::SetIfNewer,
+            guard,
+        );
+        match computed {
+            Computed::Inserted(_) | Computed::Replaced(_) => true,
+            Computed::Unchanged(_) => false,
+            Computed::Removed(_) => unreachable!(),
+        }
+    }
 
-                    // we are the chosen thread to finish the resize!
-                    finishing = true;
+    /// Maps `key` to `value` if `key` is absent, or if `version_of(&value)` is greater than the
+    /// version of the value `key` is currently mapped to. Returns `true` if `key` is now mapped
+    /// to `value`.
+    ///
+    /// This is [`set_if_newer`](FlurryHashMap::set_if_newer) for values that carry their own
+    /// version number, as is common for state synchronized between replicas: a value never
+    /// replaces one with the same or a later version, however late it arrives.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn replace_if_version_gt<F>(&self, key: K, value: V, version_of: F, guard: &Guard) -> bool
+    where
+        F: Fn(&V) -> u64,
+    {
+        self.set_if_newer(
+            key,
+            value,
+            |new, current| version_of(new) > version_of(current),
+            guard,
+        )
+    }
 
-                    // ???
-                    advance = true;
+    /// Removes `from`, and folds its value into that of `into`. Returns `false`, and leaves the
+    /// map unchanged, if `from` is absent.
+    ///
+    /// If `into` is present, it is mapped to `combine(into_value, from_value)`, and otherwise it
+    /// is inserted with the value `from` had. This consolidates entries under a canonical key,
+    /// for example to merge the counters of duplicate records. As with
+    /// [`swap_values`](FlurryHashMap::swap_values), the locks for both keys' bins are held
+    /// throughout, and are taken in the order of the bins' indices, so concurrent calls cannot
+    /// deadlock. `from` is removed before `into` is updated, so no reader sees the value of `from`
+    /// both on its own and folded into `into`, but both may briefly appear to be missing it.
+    /// If `from` and `into` are the same key, the map is left unchanged, and `true` is returned
+    /// if the key is present. `combine` must not write to the map.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn fold_key_into<'g, F>(&'g self, from: &K, into: K, combine: F, guard: &'g Guard) -> bool
+    where
+        F: Fn(&V, &V) -> V,
+    {
+        if *from == into {
+            return self
+                .get_node(from, guard)
+                .map_or(false, |n| !n.value.load(Ordering::SeqCst, guard).is_null());
+        }
+        let _gate = self.write_gate.enter();
+        let (hf, hi) = (self.hash(from, guard), self.hash(&into, guard));
+        if let Some(ref bloom) = self.bloom {
+            // before the key can be found in the table
+            bloom.insert(hi, guard);
+        }
+        // the node for `into` in case it is absent, which is only given a value once we know
+        // where it goes
+        let mut node = Some(Owned::new(BinEntry::Node(Node {
+            key: into,
+            value: Atomic::null(),
+            hash: hi,
+            next: Atomic::null(),
+            swap_stamp: AtomicUsize::new(0),
+            swapped: Atomic::null(),
+            lock: parking_lot::Mutex::new(()),
+            #[cfg(feature = "priority")]
+            priority: std::sync::atomic::AtomicU64::new(0),
+            #[cfg(feature = "timestamps")]
+            written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+            #[cfg(feature = "timestamps")]
+            inserted: std::time::Instant::now(),
+            #[cfg(feature = "entry-stats")]
+            counters: stats::EntryCounters::new(0),
+        })));
+        let mut table = self.table.load(Ordering::SeqCst, guard);
 
-                    // NOTE: the java code says "recheck before commit" here
-                    i = n as isize;
-                }
+        loop {
+            if table.is_null() {
+                return false;
+            }
+            // safety: see argument in put
+            let t = unsafe { table.deref() };
+            if t.bins.is_empty() {
+                return false;
+            }
 
+            let (i_from, i_into) = (t.bini(hf), t.bini(hi));
+            let (from_bin, into_bin) = (t.bin(i_from, guard), t.bin(i_into, guard));
+            // safety: see argument in put
+            let from_head = match unsafe { from_bin.as_ref() } {
+                None => return false,
+                Some(BinEntry::Node(head)) => head,
+                Some(&BinEntry::Moved { next_table, .. }) => {
+                    table = self.help_transfer(table, next_table, guard);
+                    continue;
+                }
+            };
+            // safety: see argument in put
+            let into_head = match unsafe { into_bin.as_ref() } {
+                None => None,
+                Some(BinEntry::Node(head)) => Some(head),
+                Some(&BinEntry::Moved { next_table, .. }) => {
+                    table = self.help_transfer(table, next_table, guard);
+                    continue;
+                }
+            };
+            // an empty bin has no lock to take. if `into` goes there, it is placed in the bin as
+            // a reservation below, whose lock we take before it is made visible.
+            let _locks = match into_head {
+                Some(into_head) if i_into != i_from => {
+                    let (lo_head, hi_head) = if i_from < i_into {
+                        (from_head, into_head)
+                    } else {
+                        (into_head, from_head)
+                    };
+                    let lo_lock = self.lock_bin(lo_head, Operation::FoldKey);
+                    (lo_lock, Some(self.lock_bin(hi_head, Operation::FoldKey)))
+                }
+                _ => (self.lock_bin(from_head, Operation::FoldKey), None),
+            };
+            // need to check that these are _still_ the heads
+            if t.bin(i_from, guard) != from_bin || t.bin(i_into, guard) != into_bin {
                 continue;
             }
-            let i = i as usize;
 
-            // safety: these were read while `guard` was held. the code that drops these, only
-            // drops them after a) they are no longer reachable, and b) any outstanding references
-            // are no longer active. these references are still active (marked by the guard), so
-            // the target of these references won't be dropped while the guard remains active.
-            let table = unsafe { table.deref() };
-            let next_table = unsafe { next_table.deref() };
+            let find = |bin: Shared<'g, BinEntry<K, V>>, h: u64, key: &K| {
+                // safety: see argument in put
+                let node = unsafe { bin.deref() }.find(h, key, guard);
+                if node.is_null() {
+                    return None;
+                }
+                // safety: as in get. the node cannot be removed while we hold its bin's lock.
+                let node = unsafe { node.deref() }.as_node().unwrap();
+                if node.value.load(Ordering::SeqCst, guard).is_null() {
+                    // a reservation that was never given a value
+                    return None;
+                }
+                Some(node)
+            };
+            let nf = match find(from_bin, hf, from) {
+                Some(nf) => nf,
+                None => return false,
+            };
+            let vf = nf.value.load(Ordering::SeqCst, guard);
+            // safety: the value cannot be replaced while we hold the lock, and was read under our
+            // guard.
+            let vf_ref = unsafe { vf.deref() };
+            // `from` is unlinked before `into` is updated, which makes the node after it the head
+            // of its bin if it was the head, and so the node other writers lock. if `into` is in
+            // the same bin, that node must stay locked until `into` has been updated too.
+            let lock_next = || {
+                if i_into != i_from || !std::ptr::eq(from_head, nf) {
+                    return None;
+                }
+                let next = nf.next.load(Ordering::SeqCst, guard);
+                // safety: see argument in put
+                unsafe { next.as_ref() }.map(|next| next.as_node().unwrap().lock.lock())
+            };
 
-            let bin = table.bin(i as usize, guard);
-            if bin.is_null() {
-                advance = table
-                    .cas_bin(
-                        i,
-                        Shared::null(),
-                        Owned::new(BinEntry::Moved(next_table as *const _)),
-                        guard,
-                    )
-                    .is_ok();
-                continue;
+            let existing = match into_head {
+                Some(_) => find(into_bin, hi, &node.as_ref().unwrap().as_node().unwrap().key),
+                None => None,
+            };
+            if let Some(ni) = existing {
+                let vi = ni.value.load(Ordering::SeqCst, guard);
+                // safety: as for vf
+                let vi_ref = unsafe { vi.deref() };
+                let combined = Owned::new(combine(vi_ref, vf_ref));
+                self.log(&WalRecord::Remove {
+                    key: &nf.key,
+                    value: vf_ref,
+                });
+                self.log(&WalRecord::Replace {
+                    key: &ni.key,
+                    old: vi_ref,
+                    new: &*combined,
+                });
+                // the value of a linked node is never null, since readers that find the node go
+                // on to dereference it, so `from` is unlinked with its value in place.
+                let _next_lock = lock_next();
+                self.unlink_node(t, i_from, nf, guard);
+                ni.value.store(combined, Ordering::SeqCst);
+                ni.touch();
+                // safety: see argument in put
+                unsafe {
+                    guard.defer_destroy(vi);
+                    guard.defer_destroy(vf);
+                }
+                drop(_locks);
+
+                self.add_count(-1, None, guard);
+                guard.flush();
+                return true;
             }
 
-            // safety: bin is a valid pointer.
-            //
-            // there are two cases when a bin pointer is invalidated:
-            //
-            //  1. if the table was resized, bin is a move entry, and the resize has completed. in
-            //     that case, the table (and all its heads) will be dropped in the next epoch
-            //     following that.
-            //  2. if the table is being resized, bin may be swapped with a move entry. the old bin
-            //     will then be dropped in the following epoch after that happens.
-            //
-            // in both cases, we held the guard when we got the reference to the bin. if any such
-            // swap happened, it must have happened _after_ we read. since we did the read while
-            // pinning the epoch, the drop must happen in the _next_ epoch (i.e., the one that we
-            // are holding up by holding on to our guard).
-            match *unsafe { bin.deref() } {
-                BinEntry::Moved(_) => {
-                    // already processed
-                    advance = true;
+            let owned = node.take().expect("only taken once `into` is inserted");
+            let nn = if into_head.is_some() {
+                // `into` goes at the end of its bin, whose lock we hold
+                // safety: see argument in put
+                let mut tail = unsafe { into_bin.deref() }.as_node().unwrap();
+                loop {
+                    let next = tail.next.load(Ordering::SeqCst, guard);
+                    if next.is_null() {
+                        break;
+                    }
+                    // safety: as above
+                    tail = unsafe { next.deref() }.as_node().unwrap();
                 }
-                BinEntry::Node(ref head) => {
-                    // bin is non-empty, need to link into it, so we must take the lock
-                    let head_lock = head.lock.lock();
-
-                    // need to check that this is _still_ the head
-                    let current_head = table.bin(i, guard);
-                    if current_head.as_raw() != bin.as_raw() {
-                        // nope -- try again from the start
+                let nn = owned.into_shared(guard);
+                tail.next.store(nn, Ordering::SeqCst);
+                nn
+            } else {
+                std::mem::forget(owned.as_node().unwrap().lock.lock());
+                match t.cas_bin(i_into, into_bin, owned, guard) {
+                    Ok(reservation) => reservation,
+                    Err(changed) => {
+                        // safety: we locked the node above, and it was never made visible
+                        unsafe { changed.new.as_node().unwrap().lock.force_unlock() };
+                        node = Some(changed.new);
                         continue;
                     }
+                }
+            };
+            // safety: we just linked the node into the table, and hold its bin's lock
+            let nn = unsafe { nn.deref() }.as_node().unwrap();
+            self.log(&WalRecord::Remove {
+                key: &nf.key,
+                value: vf_ref,
+            });
+            self.log(&WalRecord::Insert {
+                key: &nn.key,
+                value: vf_ref,
+            });
+            // as above, `from` is unlinked with its value in place, so its value is never
+            // reachable through both keys at once.
+            let _next_lock = lock_next();
+            self.unlink_node(t, i_from, nf, guard);
+            nn.value.store(vf, Ordering::SeqCst);
+            nn.touch();
+            if into_head.is_none() {
+                // safety: we locked the reservation above, and have not released it since
+                unsafe { nn.lock.force_unlock() };
+            }
+            drop(_locks);
+            guard.flush();
+            return true;
+        }
+    }
 
-                    // yes, it is still the head, so we can now "own" the bin
-                    // note that there can still be readers in the bin!
+    /// Unlinks `node` from bin `i` of `t`, and schedules it to be freed. Its value is left to the
+    /// caller. The lock of the bin must be held.
+    fn unlink_node(&self, t: &Table<K, V>, i: usize, node: &Node<K, V>, guard: &Guard) {
+        let next = node.next.load(Ordering::SeqCst, guard);
+        let mut pred: Option<&Node<K, V>> = None;
+        let mut p = t.bin(i, guard);
+        loop {
+            // safety: see argument in put
+            let n = unsafe { p.deref() }.as_node().unwrap();
+            if std::ptr::eq(n, node) {
+                break;
+            }
+            pred = Some(n);
+            p = n.next.load(Ordering::SeqCst, guard);
+        }
+        match pred {
+            Some(pred) => pred.next.store(next, Ordering::SeqCst),
+            None => t.store_bin(i, next),
+        }
+        self.invalidate_read_caches();
+        // safety: the node is no longer reachable from the table, so only threads that read it
+        // earlier, and so are pinned to an epoch <= ours, can still access it or its value.
+        // threads waiting for the lock of a removed head node find that it is no longer the head
+        // once they get it, and start over.
+        unsafe { guard.defer_destroy(p) };
+    }
 
-                    // TODO: TreeBin & ReservationNode
+    /// Exchanges the values of `a` and `b`. Returns `false`, and leaves the map unchanged, if
+    /// either key is absent.
+    ///
+    /// The locks for both keys' bins are held while the values are exchanged, and are taken in
+    /// the order of the bins' indices, so concurrent calls cannot deadlock. Neither key ever
+    /// appears absent, and no reader sees both keys mapped to the same value:
+    /// [`get`](FlurryHashMap::get) waits for an exchange that is under way to finish, and
+    /// iterators keep returning the values from before the exchanges since they were created.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn swap_values(&self, a: &K, b: &K, guard: &Guard) -> bool {
+        let _gate = self.write_gate.enter();
+        let (ha, hb) = (self.hash(a, guard), self.hash(b, guard));
+        let mut table = self.table.load(Ordering::SeqCst, guard);
 
-                    let mut run_bit = head.hash & n as u64;
-                    let mut last_run = bin;
-                    let mut p = bin;
-                    loop {
-                        // safety: p is a valid pointer.
-                        //
-                        // p is only dropped in the next epoch following when its bin is replaced
-                        // with a move node (see safety comment near table.store_bin below). we
-                        // read the bin, and got to p, so its bin has not yet been swapped with a
-                        // move node. and, we have the epoch pinned, so the next epoch cannot have
-                        // arrived yet. therefore, it will be dropped in a future epoch, and is
-                        // safe to use now.
-                        let node = unsafe { p.deref() }.as_node().unwrap();
-                        let next = node.next.load(Ordering::SeqCst, guard);
+        loop {
+            if table.is_null() {
+                return false;
+            }
+            // safety: see argument in put
+            let t = unsafe { table.deref() };
+            if t.bins.is_empty() {
+                return false;
+            }
 
-                        let b = node.hash & n as u64;
-                        if b != run_bit {
-                            run_bit = b;
-                            last_run = p;
-                        }
+            let (ia, ib) = (t.bini(ha), t.bini(hb));
+            let (lo, hi) = (std::cmp::min(ia, ib), std::cmp::max(ia, ib));
+            let (lo_bin, hi_bin) = (t.bin(lo, guard), t.bin(hi, guard));
+            if lo_bin.is_null() || hi_bin.is_null() {
+                return false;
+            }
 
-                        if next.is_null() {
-                            break;
-                        }
-                        p = next;
-                    }
+            // safety: see argument in put
+            let (lo_head, hi_head) = match (unsafe { lo_bin.deref() }, unsafe { hi_bin.deref() }) {
+                (BinEntry::Node(lo_head), BinEntry::Node(hi_head)) => (lo_head, hi_head),
+                (&BinEntry::Moved { next_table, .. }, _)
+                | (_, &BinEntry::Moved { next_table, .. }) => {
+                    table = self.help_transfer(table, next_table, guard);
+                    continue;
+                }
+            };
+            let _lo_lock = self.lock_bin(lo_head, Operation::SwapValues);
+            let _hi_lock = if lo == hi {
+                None
+            } else {
+                Some(self.lock_bin(hi_head, Operation::SwapValues))
+            };
+            // need to check that these are _still_ the heads
+            if t.bin(lo, guard) != lo_bin || t.bin(hi, guard) != hi_bin {
+                continue;
+            }
 
-                    let mut low_bin = Shared::null();
-                    let mut high_bin = Shared::null();
-                    if run_bit == 0 {
-                        // last run is all in the low bin
-                        low_bin = last_run;
-                    } else {
-                        // last run is all in the high bin
-                        high_bin = last_run;
-                    }
+            let find = |h: u64, key: &K| {
+                let bin = if t.bini(h) == lo { lo_bin } else { hi_bin };
+                // safety: see argument in put
+                let node = unsafe { bin.deref() }.find(h, key, guard);
+                if node.is_null() {
+                    return None;
+                }
+                // safety: as in get. the node cannot be removed while we hold its bin's lock.
+                let node = unsafe { node.deref() }.as_node().unwrap();
+                if node.value.load(Ordering::SeqCst, guard).is_null() {
+                    // a reservation that was never given a value
+                    return None;
+                }
+                Some(node)
+            };
+            let (na, nb) = match (find(ha, a), find(hb, b)) {
+                (Some(na), Some(nb)) => (na, nb),
+                _ => return false,
+            };
+            if std::ptr::eq(na, nb) {
+                return true;
+            }
 
-                    p = bin;
-                    while p != last_run {
+            let va = na.value.load(Ordering::SeqCst, guard);
+            let vb = nb.value.load(Ordering::SeqCst, guard);
+            // safety: neither value can be replaced while we hold the locks, and both were read
+            // under our guard.
+            let (va_ref, vb_ref) = unsafe { (va.deref(), vb.deref()) };
+            self.log(&WalRecord::Replace {
+                key: &na.key,
+                old: va_ref,
+                new: vb_ref,
+            });
+            self.log(&WalRecord::Replace {
+                key: &nb.key,
+                old: vb_ref,
+                new: va_ref,
+            });
+            na.swap_value_with(nb, guard);
+            na.touch();
+            nb.touch();
+            return true;
+        }
+    }
+
+    /// Moves every entry for which `pred` returns `true` from this map to `dst`, and returns the
+    /// number of entries moved.
+    ///
+    /// Each entry is checked against `pred` and removed from this map while holding the lock for
+    /// its bin, so an entry is only moved if it still matches when it is removed. It is then
+    /// inserted into `dst`, replacing any value its key had there. In between, the entry is in
+    /// neither map, so the move is atomic in each map but not across the two.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn move_matching_to<S2, P>(
+        &self,
+        dst: &FlurryHashMap<K, V, S2>,
+        pred: P,
+        guard: &Guard,
+    ) -> usize
+    where
+        V: Clone,
+        S2: BuildHasher,
+        P: Fn(&K, &V) -> bool,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let mut moved = 0;
+        for node in NodeIter::new(table, guard) {
+            let computed = self.compute_locked(
+                KeyArg::Borrowed(&node.key),
+                |key, value| match value {
+                    Some(value) if pred(key, value) => Action::Remove,
+                    _ => Action::Keep,
+                },
+                Operation::Remove,
+                guard,
+            );
+            if let Computed::Removed(value) = computed {
+                dst.put(node.key.clone(), value.clone(), false, guard);
+                moved += 1;
+            }
+        }
+        moved
+    }
+
+    /// Copies up to `batch` entries that the replication pass at `cursor` has not copied yet into
+    /// `dst`, and returns `true` if the pass has entries left to copy.
+    ///
+    /// Calling this until it returns `false` copies every entry of the map into `dst` in bounded
+    /// chunks, so that a background task can warm a replica of a large map without holding on to
+    /// one `Guard` for the whole copy. Once a pass is done, `cursor` is reset, and the next call
+    /// with it starts a new pass. Every replica should be given a cursor of its own, so that
+    /// passes to different replicas do not split the entries between them.
+    ///
+    /// The entries are inserted into `dst` as by [`try_insert`](FlurryHashMap::try_insert):
+    /// entries that the [`validator`](builder::Builder::validator) of `dst` rejects are skipped,
+    /// and new keys may evict other entries if `dst` was built with a
+    /// [`soft_cap`](builder::Builder::soft_cap).
+    ///
+    /// Entries that are written to this map during a pass may or may not be copied. If the map
+    /// resizes during a pass, some entries may be copied twice and others not at all, so a pass
+    /// is only guaranteed to copy every entry if the map is not modified while it is under way.
+    /// The final call of a pass may copy nothing.
+    ///
+    /// # Panics
+    ///
+    /// If `batch` is 0.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn replicate_into<S2>(
+        &self,
+        dst: &FlurryHashMap<K, V, S2>,
+        cursor: &mut ReplicationCursor,
+        batch: usize,
+        guard: &Guard,
+    ) -> bool
+    where
+        V: Clone,
+        S2: BuildHasher,
+    {
+        assert_ne!(batch, 0);
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if table.is_null() {
+            *cursor = ReplicationCursor::default();
+            return false;
+        }
+        // safety: table is only dropped on the next epoch change after it is swapped to null.
+        // we read it while holding `guard`, so the current epoch persists while we use it.
+        let table = unsafe { table.deref() };
+        let n = table.bins.len();
+        if cursor.bins == 0 {
+            cursor.bins = n;
+        }
+
+        // the pass goes through the bins of the table the map had when it started. the entries
+        // of each of those are in every `cursor.bins`th bin of the current table, which may be
+        // larger.
+        let mut copied = 0;
+        while cursor.bin < cursor.bins {
+            let nodes = (cursor.bin..n)
+                .step_by(cursor.bins)
+                .flat_map(|i| NodeIter::with_range(table, i, i + 1, guard))
+                .skip(cursor.skipped);
+            for node in nodes {
+                if copied == batch {
+                    return true;
+                }
+                // safety: flurry does not drop or move until after guard drop
+                let value = unsafe { node.value.load(Ordering::SeqCst, guard).deref() };
+                let (key, value) = (node.key.clone(), value.clone());
+                if dst.validator.as_ref().map_or(true, |v| (v.0)(&key, &value)) {
+                    dst.insert_validated(key, value, guard);
+                }
+                copied += 1;
+                cursor.skipped += 1;
+            }
+            cursor.bin += 1;
+            cursor.skipped = 0;
+        }
+        *cursor = ReplicationCursor::default();
+        false
+    }
+
+    /// Advances `cursor` through the map by visiting at most `budget` bins, and returns clones of
+    /// the entries found in them.
+    ///
+    /// This bounds the work done per call, so that a scan of the whole map can be interleaved
+    /// with other work, for example in an event loop. Once a scan has visited every bin,
+    /// [`Cursor::is_finished`] returns `true`, and further calls with the same cursor return
+    /// nothing. Every entry that is in the map for the whole scan is returned at least once.
+    /// Entries that are inserted or removed during the scan may or may not be returned, and
+    /// entries may be returned more than once if the map resizes during the scan.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_step(&self, cursor: &mut Cursor, budget: usize, guard: &Guard) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        let mut entries = Vec::new();
+        if cursor.finished {
+            return entries;
+        }
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if table.is_null() {
+            // no entry has ever been inserted, so there is nothing to scan
+            cursor.finished = true;
+            return entries;
+        }
+        // safety: table is only dropped on the next epoch change after it is swapped to null.
+        // we read it while holding `guard`, so the current epoch persists while we use it.
+        let table = unsafe { table.deref() };
+        let n = table.bins.len();
+        if cursor.bins == 0 {
+            cursor.bins = n;
+        }
+
+        // as in replicate_into, the entries of each bin of the table the map had when the scan
+        // started are in every `cursor.bins`th bin of the current table. a table only grows, and
+        // the entries of the bins that have been visited move to bins that come later in that
+        // sequence, so the steps that have been taken stay valid.
+        let mut visited = 0;
+        while cursor.bin < cursor.bins {
+            let bins = (cursor.bin..n).step_by(cursor.bins).skip(cursor.step);
+            for i in bins {
+                if visited == budget {
+                    return entries;
+                }
+                for node in NodeIter::with_range(table, i, i + 1, guard) {
+                    // safety: flurry does not drop or move until after guard drop
+                    let value = unsafe { node.value.load(Ordering::SeqCst, guard).deref() };
+                    entries.push((node.key.clone(), value.clone()));
+                }
+                visited += 1;
+                cursor.step += 1;
+            }
+            cursor.bin += 1;
+            cursor.step = 0;
+        }
+        cursor.finished = true;
+        entries
+    }
+
+    /// Calls `f` on every entry of the map in arbitrary order, and keeps, replaces or removes
+    /// the entry depending on what `f` returns, until `f` returns [`WalkAction::Stop`].
+    ///
+    /// This is a single traversal that can read, conditionally update, and remove entries, and
+    /// stop early. `f` is called while holding the lock for the entry's bin, and its action is
+    /// applied before the lock is released, so each entry is updated atomically, but the map as a
+    /// whole is not. `f` must not write to the map. Entries that are inserted or removed
+    /// concurrently may or may not be visited, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn walk<F>(&self, mut f: F, guard: &Guard)
+    where
+        F: FnMut(&K, &V) -> WalkAction<V>,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        for node in NodeIter::new(table, guard) {
+            let mut stop = false;
+            self.compute_locked(
+                KeyArg::Borrowed(&node.key),
+                |key, current| match current.map(|value| f(key, value)) {
+                    Some(WalkAction::Replace(value)) => Action::Set(value),
+                    Some(WalkAction::Remove) => Action::Remove,
+                    Some(WalkAction::Stop) => {
+                        stop = true;
+                        Action::Keep
+                    }
+                    Some(WalkAction::Keep) | None => Action::Keep,
+                },
+                Operation::Replace,
+                guard,
+            );
+            if stop {
+                return;
+            }
+        }
+    }
+
+    /// Recomputes the value of every key in the map with `f`, in a single pass over the map.
+    ///
+    /// Each key is mapped to the value `f` returns for it, or removed if `f` returns `None`. `f`
+    /// is called while holding the lock for the key's bin, so each key is updated atomically, but
+    /// the map as a whole is not: other threads see some keys with their new values and others
+    /// with their old ones while the pass is under way. Keys that are inserted concurrently may
+    /// or may not be visited, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn refresh_values<F>(&self, f: F, guard: &Guard)
+    where
+        F: Fn(&K) -> Option<V>,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        for node in NodeIter::new(table, guard) {
+            self.compute_locked(
+                KeyArg::Borrowed(&node.key),
+                |key, _| match f(key) {
+                    Some(value) => Action::Set(value),
+                    None => Action::Remove,
+                },
+                Operation::Replace,
+                guard,
+            );
+        }
+    }
+
+    /// Replaces the value for `key` with `f` applied to its current value, retrying if another
+    /// thread changes the value in the meantime.
+    ///
+    /// `f` is called without holding any locks, so it may be expensive, and may be called more
+    /// than once. The new value is only stored if the value for `key` is still the one that `f`
+    /// was given. If it is not, `f` is called again with the newer value, up to `max_retries`
+    /// times after the first attempt, before giving up with [`RetryExhausted`].
+    ///
+    /// Returns `Ok(None)` if `key` is not in the map, and otherwise the newly stored value.
+    ///
+    /// The final check holds `key`'s bin lock only long enough to compare and store the value. A
+    /// lock-free compare-and-swap on the value is not possible here, since a concurrent resize
+    /// may be copying the value over to a new table.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn replace_with_retries<'g, F>(
+        &'g self,
+        key: &K,
+        f: F,
+        max_retries: usize,
+        guard: &'g Guard,
+    ) -> Result<Option<&'g V>, RetryExhausted>
+    where
+        F: Fn(&V) -> V,
+    {
+        for _ in 0..=max_retries {
+            let current = match self.get(key, guard) {
+                Some(v) => v,
+                None => return Ok(None),
+            };
+            let new = f(current);
+            let computed = self.compute_locked(
+                KeyArg::Borrowed(key),
+                |_, now| match now {
+                    Some(now) if std::ptr::eq(now, current) => Action::Set(new),
+                    _ => Action::Keep,
+                },
+                Operation::Replace,
+                guard,
+            );
+            match computed {
+                Computed::Replaced(v) => return Ok(Some(v)),
+                Computed::Unchanged(None) => return Ok(None),
+                Computed::Unchanged(Some(_)) => {}
+                Computed::Inserted(_) | Computed::Removed(_) => unreachable!(),
+            }
+        }
+        Err(RetryExhausted)
+    }
+
+    /// Calls `f` with `key` and its current value (if any) while holding the lock for `key`'s
+    /// bin. The [`Action`] returned by `f` is then carried out before the lock is released.
+    ///
+    /// If `key` is borrowed, `f` is only called if `key` is present, since it could not be
+    /// inserted anyway.
+    ///
+    /// This is the common implementation of all write operations that need to decide what to do
+    /// based on the current state of a key.
+    fn compute_locked<'g, F>(
+        &'g self,
+        key: KeyArg<'_, K>,
+        f: F,
+        op: Operation,
+        guard: &'g Guard,
+    ) -> Computed<'g, V>
+    where
+        F: FnOnce(&K, Option<&'g V>) -> Action<V>,
+    {
+        let _gate = self.write_gate.enter();
+        let (h, mut node, borrowed) = match key {
+            KeyArg::Owned(key) => {
+                let h = self.hash(&key, guard);
+                if let Some(ref bloom) = self.bloom {
+                    // before the key can be found in the table
+                    bloom.insert(h, guard);
+                }
+                // the value is only filled in once we know what it should be
+                let node = Owned::new(BinEntry::Node(Node {
+                    key,
+                    value: Atomic::null(),
+                    hash: h,
+                    next: Atomic::null(),
+                    swap_stamp: AtomicUsize::new(0),
+                    swapped: Atomic::null(),
+                    lock: parking_lot::Mutex::new(()),
+                    #[cfg(feature = "priority")]
+                    priority: std::sync::atomic::AtomicU64::new(0),
+                    #[cfg(feature = "timestamps")]
+                    written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+                    #[cfg(feature = "timestamps")]
+                    inserted: std::time::Instant::now(),
+                    #[cfg(feature = "entry-stats")]
+                    counters: stats::EntryCounters::new(0),
+                }));
+                (h, Some(node), None)
+            }
+            KeyArg::Borrowed(key) => (self.hash(key, guard), None, Some(key)),
+        };
+        let mut table = self.table.load(Ordering::SeqCst, guard);
+
+        loop {
+            // safety: see argument in put
+            if table.is_null() || unsafe { table.deref() }.bins.is_empty() {
+                table = self.init_table(guard);
+                continue;
+            }
+
+            // safety: see argument in put
+            let t = unsafe { table.deref() };
+
+            let bini = t.bini(h);
+            let mut bin = t.bin(bini, guard);
+            if bin.is_null() {
+                let owned = match node.take() {
+                    Some(owned) => owned,
+                    // the key is absent, and we are not going to insert it
+                    None => return Computed::Unchanged(None),
+                };
+                // the bin is empty, so there is no lock we can take. instead, we place our node
+                // in the bin as a reservation while we decide on its value. readers skip nodes
+                // without a value, and writers block on the reservation's lock, which we take
+                // _before_ the node is made visible.
+                std::mem::forget(owned.as_node().unwrap().lock.lock());
+                match t.cas_bin(bini, bin, owned, guard) {
+                    Ok(reservation) => {
+                        // safety: we just made the node reachable, and it is only removed by
+                        // whoever holds its lock, which is us.
+                        let n = unsafe { reservation.deref() }.as_node().unwrap();
+                        #[cfg(feature = "lock-stats")]
+                        let start = std::time::Instant::now();
+
+                        // if we do not end up inserting a value, or if we panic along the way,
+                        // the reservation must be removed again
+                        let cleanup = Reservation {
+                            table: t,
+                            bini,
+                            node: reservation,
+                            guard,
+                        };
+                        let value = match f(&n.key, None) {
+                            Action::Set(value) => Owned::new(value),
+                            #[cfg(feature = "priority")]
+                            Action::SetPrioritized(value, priority) => {
+                                n.priority.store(priority, Ordering::SeqCst);
+                                Owned::new(value)
+                            }
+                            Action::Keep | Action::Remove => {
+                                drop(cleanup);
+                                return Computed::Unchanged(None);
+                            }
+                        };
+                        self.log(&WalRecord::Insert {
+                            key: &n.key,
+                            value: &*value,
+                        });
+                        std::mem::forget(cleanup);
+
+                        let value = value.into_shared(guard);
+                        n.value.store(value, Ordering::SeqCst);
+                        n.touch();
+                        #[cfg(feature = "lock-stats")]
+                        self.lock_stats.record(op, start.elapsed());
+                        // safety: we locked the reservation above, and have not released it since
+                        unsafe { n.lock.force_unlock() };
+
+                        self.add_count(1, Some(0), guard);
+                        guard.flush();
+                        // safety: the value was just inserted under our guard, so it will not be
+                        // dropped until after the guard is dropped.
+                        return Computed::Inserted(unsafe { value.deref() });
+                    }
+                    Err(changed) => {
+                        assert!(!changed.current.is_null());
+                        // safety: we locked the node above, and it was never made visible
+                        unsafe { changed.new.as_node().unwrap().lock.force_unlock() };
+                        node = Some(changed.new);
+                        bin = changed.current;
+                    }
+                }
+            }
+
+            let key = match node {
+                Some(ref node) => &node.as_node().unwrap().key,
+                None => borrowed.unwrap(),
+            };
+            // safety: see argument in put
+            match *unsafe { bin.deref() } {
+                BinEntry::Moved { next_table, .. } => {
+                    table = self.help_transfer(table, next_table, guard);
+                }
+                BinEntry::Node(ref head) => {
+                    let head_lock = self.lock_bin(head, op);
+
+                    // need to check that this is _still_ the head
+                    let current_head = t.bin(bini, guard);
+                    if current_head != bin {
+                        // nope -- try again from the start
+                        continue;
+                    }
+
+                    let mut bin_count = 1;
+                    let mut pred: Option<&Node<K, V>> = None;
+                    let mut p = bin;
+                    let computed = loop {
+                        // safety: see argument in put
+                        let n = unsafe { p.deref() }.as_node().unwrap();
+                        if n.hash == h && &n.key == key {
+                            self.debug_check_hash(self.hasher(guard), n);
+                            // the key is already present. its value cannot be null, since only
+                            // the head of a bin can be a reservation, and we hold its lock.
+                            let current = n.value.load(Ordering::SeqCst, guard);
+                            assert!(!current.is_null());
+                            // safety: values are only dropped in the epoch after they have been
+                            // replaced, and we read this one under our guard.
+                            let current = unsafe { current.deref() };
+                            let value = match f(&n.key, Some(current)) {
+                                Action::Set(value) => Owned::new(value),
+                                #[cfg(feature = "priority")]
+                                Action::SetPrioritized(value, priority) => {
+                                    n.priority.store(priority, Ordering::SeqCst);
+                                    Owned::new(value)
+                                }
+                                Action::Keep => break Computed::Unchanged(Some(current)),
+                                Action::Remove => {
+                                    self.log(&WalRecord::Remove {
+                                        key: &n.key,
+                                        value: current,
+                                    });
+                                    let next = n.next.load(Ordering::SeqCst, guard);
+                                    match pred {
+                                        Some(pred) => pred.next.store(next, Ordering::SeqCst),
+                                        None => t.store_bin(bini, next),
+                                    }
+                                    self.invalidate_read_caches();
+                                    // safety: the node is no longer reachable from the table, so
+                                    // only threads that read it earlier, and so are pinned to an
+                                    // epoch <= ours, can still access it or its value. threads
+                                    // waiting for the lock of a removed head node find that it is
+                                    // no longer the head once they get it, and start over.
+                                    unsafe {
+                                        guard.defer_destroy(n.value.load(Ordering::SeqCst, guard));
+                                        guard.defer_destroy(p);
+                                    }
+                                    drop(head_lock);
+
+                                    self.add_count(-1, None, guard);
+                                    guard.flush();
+                                    return Computed::Removed(current);
+                                }
+                            };
+                            self.log(&WalRecord::Replace {
+                                key: &n.key,
+                                old: current,
+                                new: &*value,
+                            });
+                            let value = value.into_shared(guard);
+                            let now_garbage = n.value.swap(value, Ordering::SeqCst, guard);
+                            n.touch();
+                            // safety: see argument in put
+                            unsafe { guard.defer_destroy(now_garbage) };
+                            // safety: as for current above
+                            break Computed::Replaced(unsafe { value.deref() });
+                        }
+
+                        let next = n.next.load(Ordering::SeqCst, guard);
+                        if next.is_null() {
+                            // we're at the end of the bin, and the key is absent
+                            let node = match node {
+                                Some(node) => node,
+                                None => break Computed::Unchanged(None),
+                            };
+                            let nn = node.as_node().unwrap();
+                            let value = match f(&nn.key, None) {
+                                Action::Set(value) => Owned::new(value),
+                                #[cfg(feature = "priority")]
+                                Action::SetPrioritized(value, priority) => {
+                                    nn.priority.store(priority, Ordering::SeqCst);
+                                    Owned::new(value)
+                                }
+                                Action::Keep | Action::Remove => break Computed::Unchanged(None),
+                            };
+                            self.log(&WalRecord::Insert {
+                                key: &nn.key,
+                                value: &*value,
+                            });
+                            let value = value.into_shared(guard);
+                            nn.value.store(value, Ordering::SeqCst);
+                            nn.touch();
+                            n.next.store(node, Ordering::SeqCst);
+                            drop(head_lock);
+
+                            self.add_count(1, Some(bin_count), guard);
+                            self.adapt(guard);
+                            guard.flush();
+                            // safety: as for current above
+                            return Computed::Inserted(unsafe { value.deref() });
+                        }
+                        pred = Some(n);
+                        p = next;
+
+                        bin_count += 1;
+                    };
+                    drop(head_lock);
+                    self.adapt(guard);
+                    guard.flush();
+                    return computed;
+                }
+            }
+        }
+    }
+
+    fn invalidate_read_caches(&self) {
+        if let Some(ref cache) = self.read_cache {
+            cache.invalidate();
+        }
+    }
+
+    fn log(&self, record: &WalRecord<'_, K, V>) {
+        if let Some(ref wal) = self.wal {
+            (wal.0)(record);
+        }
+    }
+
+    /// Runs `f` while no writes to this map are in progress.
+    ///
+    /// This first waits for any ongoing write operations to complete. While `f` runs, any write
+    /// operations on the map (such as [`insert`](FlurryHashMap::insert)) block until `f` returns,
+    /// whereas read operations proceed as normal. This gives maintenance tasks like backups a
+    /// consistent view of the map without locking every bin.
+    ///
+    /// Writes that start once `pause_writes` has been called wait for it, so a steady stream of
+    /// writes cannot hold it off, but writers are starved for as long as `f` runs, so `f` should
+    /// be brief. `f` must not itself write to the map, as that would deadlock.
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), since that write would keep the
+    /// pause from ever starting.
+    pub fn pause_writes<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R,
+    {
+        let _gate = self.write_gate.pause();
+        f()
+    }
+
+    /// Resets the bloom filter of the map to hold only the keys that are currently in the map.
+    ///
+    /// Keys that are removed from the map stay in the filter, which makes it less effective over
+    /// time. Rebuilding the filter clears them out, and also grows it if the map now holds more
+    /// keys than it was sized for. Writes to the map wait until the rebuild is done, as with
+    /// [`pause_writes`](FlurryHashMap::pause_writes), but reads do not.
+    ///
+    /// Does nothing if the map was not built with a
+    /// [`bloom_filter`](builder::Builder::bloom_filter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), where waiting for the writes in
+    /// progress would deadlock.
+    pub fn rebuild_filter(&self, guard: &Guard) {
+        let bloom = match self.bloom {
+            Some(ref bloom) => bloom,
+            None => return,
+        };
+        let _gate = self.write_gate.pause();
+        let table = self.table.load(Ordering::SeqCst, guard);
+        bloom.rebuild(
+            self.len(),
+            NodeIter::new(table, guard).map(|node| node.hash),
+            guard,
+        );
+    }
+
+    /// Replaces all the entries of the map with `entries`.
+    ///
+    /// The new entries are first placed in a table of their own, off to the side, while the map
+    /// is still being read and written as usual. Writes are then paused, just like in
+    /// [`pause_writes`](FlurryHashMap::pause_writes), only for as long as it takes to swap the new
+    /// table in for the old one. Every lookup therefore sees either all of the old entries or all
+    /// of the new ones, never a partially populated map, and iterators created before the swap
+    /// keep iterating over the old entries. Writes that complete while the new table is being
+    /// built are lost along with the old entries, and a thread that looks up several keys in a
+    /// row may see the old entries for the first and the new ones for the rest. If `entries`
+    /// holds a key more than once, the last value wins.
+    ///
+    /// The entries are not checked by the map's
+    /// [`validator`](builder::Builder::validator). If the map was built with a
+    /// [`wal`](builder::Builder::wal), the removal of every old entry and the insertion of every
+    /// new one are logged while writes are paused.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), where waiting for the writes in
+    /// progress would deadlock.
+    pub fn reset_to<I>(&self, entries: I, guard: &Guard)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let place = |entries: Vec<(K, V)>, build_hasher: &S| {
+            let bins = initial_capacity(std::cmp::max(entries.len(), 1));
+            let mut raw = raw::RawTable::with_bins(bins);
+            for (key, value) in entries {
+                raw.insert(key, value, build_hasher);
+            }
+            raw
+        };
+        let build_hasher = self.hasher(guard);
+        let mut raw = place(entries.into_iter().collect(), build_hasher);
+
+        let _gate = self.write_gate.pause();
+        // the hash builder we placed the entries with is not dropped while we are pinned, so its
+        // address cannot have been reused for a new one
+        if !std::ptr::eq(self.hasher(guard), build_hasher) {
+            // the map was rebuilt with a new hash builder while we were placing the entries
+            let len = raw.len;
+            raw = place(raw.table.take_entries(len), self.hasher(guard));
+        }
+        let len = raw.len;
+        let table = Owned::new(std::mem::replace(
+            &mut raw.table,
+            Table {
+                bins: vec![].into_boxed_slice(),
+            },
+        ))
+        .into_shared(guard);
+        // safety: the table is not shared with anyone until it is installed below
+        let n = unsafe { table.deref() }.bins.len();
+        let old = self.install_table(table, None, len, guard);
+        self.size_ctl
+            .store(n as isize - (n >> 2) as isize, Ordering::SeqCst);
+
+        if !old.is_null() {
+            // safety: the old table is no longer reachable through self.table, and there is no
+            // resize under way through which it could be reached either. so, as with tables that
+            // have been resized, only threads pinned to an epoch <= ours can still access it, or
+            // the nodes and values in it. none are left by the time the closure runs.
+            unsafe {
+                guard.defer_unchecked(move || {
+                    let mut old = old.into_owned().into_box();
+                    old.drop_bins();
+                })
+            };
+        }
+    }
+
+    /// Moves the entries of the map to a new table that is sized to accommodate `new_capacity`
+    /// elements, or all the entries that are in the map, if there are more, and in which the keys
+    /// are placed by `new_hasher`, which the map hashes keys with from then on.
+    ///
+    /// This lets a map that has grown for a burst of entries shrink again once most of them are
+    /// gone, or be grown ahead of a burst in one go. A new hash builder with a fresh seed also
+    /// scatters keys that an attacker has chosen to collide under the old one. Writes to the map
+    /// are paused, just like in [`pause_writes`](FlurryHashMap::pause_writes), while the new
+    /// table is filled. The map then switches over to it together with the new hash builder, so
+    /// every lookup either hashes the key with the old hash builder and looks in the old table,
+    /// or with the new one in the new table, each with all the entries. A lookup that misses
+    /// while the two are being switched tries again. Keys are cloned into the new table, but
+    /// values are not.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), where waiting for the writes in
+    /// progress would deadlock.
+    pub fn rebuild(&self, new_capacity: usize, new_hasher: S, guard: &Guard) {
+        let _gate = self.write_gate.pause();
+        // resizes only happen during writes, and those are paused
+        assert!(self.next_table.load(Ordering::SeqCst, guard).is_null());
+        let old = self.table.load(Ordering::SeqCst, guard);
+        let len = self.count.sum(Ordering::SeqCst, guard);
+        let n = initial_capacity(std::cmp::max(std::cmp::max(new_capacity, len), 1));
+
+        let table = Table {
+            bins: vec![Atomic::null(); n].into_boxed_slice(),
+        };
+        for node in NodeIter::new(old, guard) {
+            let hash = hash_with(&new_hasher, &node.key);
+            let bini = table.bini(hash);
+            let copy = Owned::new(BinEntry::Node(Node {
+                hash,
+                key: node.key.clone(),
+                lock: parking_lot::Mutex::new(()),
+                #[cfg(feature = "priority")]
+                priority: std::sync::atomic::AtomicU64::new(node.priority.load(Ordering::SeqCst)),
+                #[cfg(feature = "timestamps")]
+                written: crossbeam::atomic::AtomicCell::new(node.written.load()),
+                #[cfg(feature = "timestamps")]
+                inserted: node.inserted,
+                #[cfg(feature = "entry-stats")]
+                counters: node.counters.clone(),
+                // the value is shared with the old node, which is dropped without it
+                value: node.value.clone(),
+                next: Atomic::from(table.bin(bini, guard)),
+                swap_stamp: AtomicUsize::new(0),
+                swapped: Atomic::null(),
+            }));
+            table.store_bin(bini, copy);
+        }
+        let table = Owned::new(table).into_shared(guard);
+
+        // lookups that miss while this is odd try again
+        self.rehashes.fetch_add(1, Ordering::SeqCst);
+        let old_hasher = self
+            .build_hasher
+            .swap(Owned::new(new_hasher), Ordering::SeqCst, guard);
+        self.table.store(table, Ordering::SeqCst);
+        if let Some(ref bloom) = self.bloom {
+            // the filter holds the hashes that the old hash builder gave the keys
+            bloom.rebuild(len, NodeIter::new(table, guard).map(|n| n.hash), guard);
+        }
+        self.rehashes.fetch_add(1, Ordering::SeqCst);
+        self.size_ctl
+            .store(n as isize - (n >> 2) as isize, Ordering::SeqCst);
+        // the caches hold nodes of the old table, which will be dropped
+        self.invalidate_read_caches();
+        self.capacity_history.record(len, n);
+
+        // safety: the old hash builder is no longer reachable through self.build_hasher, so only
+        // threads pinned to an epoch <= ours can still be hashing keys with it.
+        unsafe { guard.defer_destroy(old_hasher) };
+
+        if !old.is_null() {
+            // safety: as in reset_to. the values of the old nodes are now reachable through the
+            // new nodes instead, so only the nodes are dropped.
+            unsafe {
+                guard.defer_unchecked(move || {
+                    let mut old = old.into_owned().into_box();
+                    old.drop_nodes();
+                })
+            };
+        }
+    }
+
+    /// Swaps the entries of this map with those of `other`.
+    ///
+    /// No entries are moved or copied: the two maps exchange their tables. Writes to both maps
+    /// are paused, just like in [`pause_writes`](FlurryHashMap::pause_writes), while the tables
+    /// are exchanged, and each map switches from one table to the other with a single store, so
+    /// every lookup in either map sees either all of its old entries or all of its new ones. The
+    /// two maps do not switch at the same instant, though, so for a moment both may hold the same
+    /// entries. Iterators created before the swap keep iterating over the entries they started
+    /// with, which now belong to the other map.
+    ///
+    /// The entries of each table are placed by the hashes of their keys under the hash builder
+    /// of the map they came from, so the maps exchange their hash builders along with their
+    /// tables, and go on hashing keys the way their new entries were placed.
+    ///
+    /// Neither map's [`validator`](builder::Builder::validator) checks the entries it receives.
+    /// If a map was built with a [`wal`](builder::Builder::wal), the removal of every one of its
+    /// old entries and the insertion of every new one are logged while writes are paused.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to either map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), where waiting for the writes in
+    /// progress would deadlock.
+    pub fn swap_contents(&self, other: &Self, guard: &Guard) {
+        if std::ptr::eq(self, other) {
+            return;
+        }
+        // always pause the two maps in the same order, so that two swaps of the same maps
+        // cannot deadlock if they are called with the maps the other way around
+        let (first, second) = if (self as *const Self) < (other as *const Self) {
+            (self, other)
+        } else {
+            (other, self)
+        };
+        let _first = first.write_gate.pause();
+        let _second = second.write_gate.pause();
+
+        let (mine, my_hasher, my_len) = (
+            self.table.load(Ordering::SeqCst, guard),
+            self.build_hasher.load(Ordering::SeqCst, guard),
+            self.count.sum(Ordering::SeqCst, guard),
+        );
+        let (theirs, their_hasher, their_len) = (
+            other.table.load(Ordering::SeqCst, guard),
+            other.build_hasher.load(Ordering::SeqCst, guard),
+            other.count.sum(Ordering::SeqCst, guard),
+        );
+        self.install_table(theirs, Some(their_hasher), their_len, guard);
+        other.install_table(mine, Some(my_hasher), my_len, guard);
+        let size_ctl = self.size_ctl.load(Ordering::SeqCst);
+        self.size_ctl
+            .store(other.size_ctl.load(Ordering::SeqCst), Ordering::SeqCst);
+        other.size_ctl.store(size_ctl, Ordering::SeqCst);
+    }
+
+    /// Makes `table`, which holds `len` entries, the table of the map, and returns the table it
+    /// had before. `size_ctl` is left for the caller to set. If the entries of `table` were not
+    /// placed by the map's hash builder, `build_hasher` must be the one that placed them, and it
+    /// replaces the map's. The caller is left to drop the old one.
+    ///
+    /// Writes to the map must be paused while this runs.
+    fn install_table<'g>(
+        &self,
+        table: Shared<'g, Table<K, V>>,
+        build_hasher: Option<Shared<'g, S>>,
+        len: usize,
+        guard: &'g Guard,
+    ) -> Shared<'g, Table<K, V>> {
+        // resizes only happen during writes, and those are paused
+        assert!(self.next_table.load(Ordering::SeqCst, guard).is_null());
+        let old = self.table.load(Ordering::SeqCst, guard);
+        if self.wal.is_some() {
+            for node in NodeIter::new(old, guard) {
+                // safety: flurry does not drop or move until after guard drop
+                let value = unsafe { node.value.load(Ordering::SeqCst, guard).deref() };
+                self.log(&WalRecord::Remove {
+                    key: &node.key,
+                    value,
+                });
+            }
+            for node in NodeIter::new(table, guard) {
+                // safety: as above
+                let value = unsafe { node.value.load(Ordering::SeqCst, guard).deref() };
+                self.log(&WalRecord::Insert {
+                    key: &node.key,
+                    value,
+                });
+            }
+        }
+        if build_hasher.is_some() {
+            // lookups that miss while this is odd try again, as they may have hashed the key with
+            // the hash builder of the other table, or checked it against the wrong bloom filter
+            self.rehashes.fetch_add(1, Ordering::SeqCst);
+        }
+        if let Some(ref bloom) = self.bloom {
+            bloom.rebuild(
+                len,
+                NodeIter::new(table, guard).map(|node| node.hash),
+                guard,
+            );
+        }
+
+        if let Some(build_hasher) = build_hasher {
+            self.build_hasher.store(build_hasher, Ordering::SeqCst);
+        }
+        self.table.store(table, Ordering::SeqCst);
+        if build_hasher.is_some() {
+            self.rehashes.fetch_add(1, Ordering::SeqCst);
+        }
+        self.count.set(len, guard);
+        self.invalidate_read_caches();
+        // safety: as for reading the new table above
+        self.capacity_history
+            .record(len, unsafe { table.deref() }.bins.len());
+        old
+    }
+
+    /// Calls `f` with a context for performing many operations on the map that share one pinned
+    /// `Guard`, which is re-pinned after every `ops` operations.
+    ///
+    /// Pinning once per operation is relatively expensive, whereas holding on to one `Guard` for
+    /// a long batch keeps any memory freed during the batch from being reclaimed until the batch
+    /// ends. Re-pinning periodically strikes a balance between the two. Since references into the
+    /// map do not survive a re-pin, the context only hands out values through closures.
+    ///
+    /// # Panics
+    ///
+    /// If `ops` is 0.
+    pub fn batch<R, F>(&self, ops: usize, f: F) -> R
+    where
+        F: FnOnce(&batch::BatchCtx<'_, K, V, S>) -> R,
+    {
+        f(&batch::BatchCtx::new(self, ops))
+    }
+
+    /// Calls `f` with an iterator over all key-value pairs of the map that is guaranteed to
+    /// observe an exact snapshot of the map.
+    ///
+    /// For as long as `f` runs, no entries are inserted or removed, and the table is not
+    /// resized: just like in [`pause_writes`](FlurryHashMap::pause_writes), writers block until
+    /// `f` returns, while readers proceed as normal. Ordinary iterators like
+    /// [`iter`](FlurryHashMap::iter) never block writers, but may or may not observe writes that
+    /// happen while they are in use.
+    ///
+    /// `f` must not write to the map, as that would deadlock.
+    ///
+    /// # Panics
+    ///
+    /// If called from within a write to this map, such as from the closure passed to
+    /// [`compute_if_absent`](FlurryHashMap::compute_if_absent), since that write would keep the
+    /// iteration from ever starting.
+    pub fn scoped_iter<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(StableIter<'_, K, V>) -> R,
+    {
+        self.pause_writes(|| {
+            let guard = crossbeam::epoch::pin();
+            f(StableIter {
+                iter: self.iter(&guard),
+            })
+        })
+    }
+
+    /// Grows the table so that it can hold `additional` more elements than it currently does
+    /// without the need to dynamically resize.
+    ///
+    /// The resize happens the same way as one triggered by an insert: the bins are moved over to
+    /// the new table a range at a time, and other threads keep reading and writing the map
+    /// throughout, helping to move bins if they run into one that is being moved. This method
+    /// does its share of the moving, and returns once the new table is large enough and there
+    /// are no more bins left for it to claim, even if other threads are still moving theirs.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn reserve_concurrent(&self, additional: usize, guard: &Guard) {
+        let _gate = self.write_gate.enter();
+        let needed = self
+            .count
+            .sum(Ordering::SeqCst, guard)
+            .saturating_add(additional);
+        if needed == 0 {
+            return;
+        }
+        self.grow_to(initial_capacity(needed), guard);
+    }
+
+    /// Resizes the table until it has at least `target` bins, which must be a power of two, as
+    /// in `reserve_concurrent`.
+    fn grow_to(&self, target: usize, guard: &Guard) {
+        loop {
+            let sc = self.size_ctl.load(Ordering::SeqCst);
+            let table = self.table.load(Ordering::SeqCst, guard);
+            // safety: table is only dropped on the next epoch change after it is swapped to null.
+            // we read it while holding `guard`, so the current epoch persists while we use it.
+            if table.is_null() || unsafe { table.deref() }.bins.is_empty() {
+                if sc < 0 {
+                    // another thread is initializing the table; wait for it
+                    std::thread::yield_now();
+                    continue;
+                }
+                // have the table allocated at the target size right away
+                if sc < target as isize
+                    && self
+                        .size_ctl
+                        .compare_exchange(sc, target as isize, Ordering::SeqCst, Ordering::SeqCst)
+                        .is_err()
+                {
+                    continue;
+                }
+                self.init_table(guard);
+                continue;
+            }
+
+            // safety: same as above
+            let n = unsafe { table.deref() }.bins.len();
+            if sc < 0 {
+                // a resize is in progress, so help it along
+                let next_table = self.next_table.load(Ordering::SeqCst, guard);
+                let next_table = self.help_transfer(table, next_table.as_raw(), guard);
+                // safety: next_table was read with the same guard as table, and the same
+                // argument applies
+                if !next_table.is_null() && unsafe { next_table.deref() }.bins.len() >= target {
+                    break;
+                }
+                std::thread::yield_now();
+            } else if n >= target || n >= MAXIMUM_CAPACITY {
+                break;
+            } else {
+                let rs = Self::resize_stamp(n) << RESIZE_STAMP_SHIFT;
+                if self
+                    .size_ctl
+                    .compare_exchange(sc, rs + 2, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    // see the corresponding comment in add_count
+                    self.transfer(table, Shared::null(), guard);
+                }
+            }
+        }
+    }
+
+    fn lock_bin<'a>(&'a self, head: &'a Node<K, V>, _op: Operation) -> BinLock<'a> {
+        let lock = match self.contention {
+            Some(ref contention) => match head.lock.try_lock() {
+                Some(lock) => {
+                    contention.record(false);
+                    lock
+                }
+                None => {
+                    contention.record(true);
+                    head.lock.lock()
+                }
+            },
+            None => head.lock.lock(),
+        };
+        BinLock {
+            _guard: lock,
+            #[cfg(feature = "lock-stats")]
+            timing: (&self.lock_stats, _op, std::time::Instant::now()),
+        }
+    }
+
+    /// Returns how often the entry for `key` has been read and written, or `None` if `key` is
+    /// not in the map.
+    ///
+    /// Reads are counted by [`get`](FlurryHashMap::get) and the methods built on it, such as
+    /// [`contains_key`](FlurryHashMap::contains_key), but not by iterators. Writes are counted
+    /// whenever a value is stored for the key, starting with the one it was inserted with. The
+    /// counts are kept per entry, so they start over if the key is removed and inserted again.
+    ///
+    /// This method is only available with the `entry-stats` feature enabled.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    #[cfg(feature = "entry-stats")]
+    pub fn entry_stats(&self, key: &K, guard: &Guard) -> Option<stats::EntryStats> {
+        let node = self.get_node(key, guard)?;
+        if node.value.load(Ordering::SeqCst, guard).is_null() {
+            return None;
+        }
+        Some(node.counters.snapshot())
+    }
+
+    /// Returns how the count behind [`len`](FlurryHashMap::len) is spread over counter cells.
+    ///
+    /// Updates of the count go to a single base count until two of them collide, after which
+    /// each thread that has collided updates a cell of its own, so that threads that insert and
+    /// remove at the same time do not contend on the count. This reports whether that happens as
+    /// intended, for example on machines with many cores: under heavy write load from many
+    /// threads, there should be several active cells, and few further collisions. See also
+    /// [`compact_counters`](FlurryHashMap::compact_counters).
+    pub fn counter_cell_stats(&self) -> stats::CounterStats {
+        self.count.stats(&crossbeam::epoch::pin())
+    }
+
+    /// Returns the state of the map's adaptive resizing, or `None` if the map's resize policy does
+    /// not [watch for contention](ResizePolicy::watches_contention), as with
+    /// [`adaptive_resize`](builder::Builder::adaptive_resize).
+    pub fn adaptive_state(&self) -> Option<stats::AdaptiveState> {
+        self.contention.as_ref().map(stats::Contention::snapshot)
+    }
+
+    /// Returns the number of entries and bins the map had each time it switched to a new table,
+    /// oldest first.
+    ///
+    /// A sample is taken when the first table is allocated, when a resize completes, and when
+    /// the table is replaced wholesale, as by [`rebuild`](FlurryHashMap::rebuild). Only the 64
+    /// most recent samples are kept, so the growth of a map over time can be graphed without
+    /// sampling it from the outside. The number of entries is read just after the switch, so
+    /// it may include writes that raced with it.
+    pub fn capacity_history(&self) -> Vec<stats::CapacitySample> {
+        self.capacity_history.snapshot()
+    }
+
+    /// Returns how long the most recently completed resize of the map took, or `None` if the map
+    /// has never resized.
+    ///
+    /// A resize is timed from when the new table is allocated until the last bin has been moved
+    /// to it, across all the threads that help with it. Writers that run into a resize help move
+    /// bins, so slow resizes show up as latency spikes of writes, and sizing the map up front
+    /// (see [`with_capacity`](FlurryHashMap::with_capacity)) avoids them. Allocating the first
+    /// table does not count as a resize.
+    pub fn last_resize_duration(&self) -> Option<std::time::Duration> {
+        self.resize_times.last()
+    }
+
+    /// Returns the average of how long all completed resizes of the map took, or `None` if the
+    /// map has never resized. See [`last_resize_duration`](FlurryHashMap::last_resize_duration).
+    pub fn average_resize_duration(&self) -> Option<std::time::Duration> {
+        self.resize_times.average()
+    }
+
+    /// Returns how far the resize of the map that is currently under way has got.
+    ///
+    /// This is meant for observing resizes while debugging or testing, and visits every bin of
+    /// the table that is being resized. Other threads keep moving bins while it does, so the
+    /// numbers need not be consistent with one another, but over the course of one resize, the
+    /// number of moved bins only ever grows, and the transfer frontier only ever falls.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn resize_progress(&self, guard: &Guard) -> stats::ResizeProgress {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let resizing = !self.next_table.load(Ordering::SeqCst, guard).is_null();
+        let mut progress = stats::ResizeProgress {
+            resizing,
+            bins: 0,
+            moved_bins: 0,
+            transfer_index: 0,
+            entries_in_old: 0,
+        };
+        if table.is_null() {
+            return progress;
+        }
+        // safety: we loaded the table while epoch was pinned. table won't be deallocated until
+        // next epoch at the earliest.
+        let table = unsafe { table.deref() };
+        progress.bins = table.bins.len();
+        if !resizing {
+            return progress;
+        }
+
+        progress.transfer_index =
+            std::cmp::max(self.transfer_index.load(Ordering::SeqCst), 0) as usize;
+        for i in 0..table.bins.len() {
+            let bin = table.bin(i, guard);
+            // safety: bins of a table we read under our guard are not dropped until the epoch
+            // after they are replaced
+            match unsafe { bin.as_ref() } {
+                None => {}
+                Some(BinEntry::Moved { .. }) => progress.moved_bins += 1,
+                Some(BinEntry::Node(head)) => {
+                    let mut node = head;
+                    loop {
+                        progress.entries_in_old += 1;
+                        let next = node.next.load(Ordering::SeqCst, guard);
+                        // safety: as above
+                        match unsafe { next.as_ref() } {
+                            Some(next) => node = next.as_node().expect("only Nodes follow a Node"),
+                            None => break,
+                        }
+                    }
+                }
+            }
+        }
+        progress
+    }
+
+    /// Starts a resize if bin locks have been found to be highly contended, and the resize policy
+    /// calls for one.
+    ///
+    /// Must not be called while holding a bin lock, since the resize needs to take them all.
+    fn adapt(&self, guard: &Guard) {
+        let contention = match self.contention {
+            Some(ref contention) if contention.take_pending() => contention,
+            _ => return,
+        };
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if table.is_null() {
+            return;
+        }
+        // safety: see argument in add_count
+        let n = unsafe { table.deref() }.bins.len();
+        if n >= MAXIMUM_CAPACITY {
+            return;
+        }
+        let target = match (self.resize_policy.0).should_resize_contended(self.len(), n) {
+            Some(target) if target > n => target,
+            _ => return,
+        };
+        let sc = self.size_ctl.load(Ordering::SeqCst);
+        if sc < 0 || self.table.load(Ordering::SeqCst, guard) != table {
+            // a resize is already under way
+            return;
+        }
+        if target > n << 1 {
+            contention.record_early_resize();
+            self.grow_to(resize_target(target), guard);
+            return;
+        }
+        let rs = Self::resize_stamp(n) << RESIZE_STAMP_SHIFT;
+        if self
+            .size_ctl
+            .compare_exchange(sc, rs + 2, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+        {
+            contention.record_early_resize();
+            self.transfer(table, Shared::null(), guard);
+        }
+    }
+
+    /// Returns how long each kind of operation has held bin locks so far.
+    #[cfg(feature = "lock-stats")]
+    pub fn lock_hold_stats(&self) -> stats::LockHoldStats {
+        self.lock_stats.snapshot()
+    }
+
+    fn help_transfer<'g>(
+        &self,
+        table: Shared<'g, Table<K, V>>,
+        next_table: *const Table<K, V>,
+        guard: &'g Guard,
+    ) -> Shared<'g, Table<K, V>> {
+        if table.is_null() || next_table.is_null() {
+            return table;
+        }
+
+        let next_table = Shared::from(next_table);
+
+        // safety: table is only dropped on the next epoch change after it is swapped to null.
+        // we read it as not null, so it must not be dropped until a subsequent epoch. since we
+        // held `guard` at the time, we know that the current epoch continues to persist, and that
+        // our reference is therefore valid.
+        let rs = Self::resize_stamp(unsafe { table.deref() }.bins.len()) << RESIZE_STAMP_SHIFT;
+
+        while next_table == self.next_table.load(Ordering::SeqCst, guard)
+            && table == self.table.load(Ordering::SeqCst, guard)
+        {
+            let sc = self.size_ctl.load(Ordering::SeqCst);
+            if sc >= 0
+                || sc == rs + MAX_RESIZERS
+                || sc == rs + 1
+                || self.transfer_index.load(Ordering::SeqCst) <= 0
+            {
+                break;
+            }
+
+            if self
+                .size_ctl
+                .compare_exchange(sc, sc + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+            {
+                self.transfer(table, next_table, guard);
+                break;
+            }
+        }
+        next_table
+    }
+
+    fn add_count(&self, n: isize, resize_hint: Option<usize>, guard: &Guard) {
+        if n != 0 {
+            self.count.add(n, guard);
+        }
+
+        // if resize_hint is None, it means the caller does not want us to consider a resize.
+        // if it is Some(n), the caller saw n entries in a bin
+        if resize_hint.is_none() {
+            return;
+        }
+        let mut count = self.count.sum(Ordering::SeqCst, guard);
+        // the bin now also holds the entry that was just added to it
+        let chain_len = resize_hint.unwrap() + 1;
+        let chain_table = self.table.load(Ordering::SeqCst, guard);
+
+        loop {
+            let sc = self.size_ctl.load(Ordering::SeqCst);
+            let table = self.table.load(Ordering::SeqCst, guard);
+            if table.is_null() {
+                // table will be initalized by another thread anyway
+                break;
+            }
+
+            // safety: table is only dropped on the next epoch change after it is swapped to null.
+            // we read it as not null, so it must not be dropped until a subsequent epoch. since we
+            // hold a Guard, we know that the current epoch will persist, and that our reference
+            // will therefore remain valid.
+            let n = unsafe { table.deref() }.bins.len();
+            if n >= MAXIMUM_CAPACITY {
+                // can't resize any more anyway
+                break;
+            }
+
+            let rs = Self::resize_stamp(n) << RESIZE_STAMP_SHIFT;
+            if sc < 0 {
+                // ongoing resize! can we join the resize transfer?
+                if sc == rs + MAX_RESIZERS || sc == rs + 1 {
+                    break;
+                }
+                let nt = self.next_table.load(Ordering::SeqCst, guard);
+                if nt.is_null() {
+                    break;
+                }
+                if self.transfer_index.load(Ordering::SeqCst) <= 0 {
+                    break;
+                }
+
+                // try to join!
+                if self
+                    .size_ctl
+                    .compare_exchange(sc, sc + 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    self.transfer(table, nt, guard);
+                }
+            } else {
+                // once the table has been resized, the chain has been split up in ways we have not
+                // seen, but it still holds the entry
+                let chain_len = if table == chain_table { chain_len } else { 1 };
+                let target = match (self.resize_policy.0).should_resize(count, n, chain_len) {
+                    Some(target) if target > n => target,
+                    // we're not at the next resize point yet
+                    _ => break,
+                };
+                if target > n << 1 {
+                    // more than one doubling is called for
+                    self.grow_to(resize_target(target), guard);
+                    break;
+                }
+                if self
+                    .size_ctl
+                    .compare_exchange(sc, rs + 2, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    // a resize is needed, but has not yet started
+                    // TODO: figure out why this is rs + 2, not just rs
+                    self.transfer(table, Shared::null(), guard);
+                }
+            }
+
+            // another resize may be needed!
+            count = self.count.sum(Ordering::SeqCst, guard);
+        }
+    }
+
+    fn transfer<'g>(
+        &self,
+        table: Shared<'g, Table<K, V>>,
+        mut next_table: Shared<'g, Table<K, V>>,
+        guard: &'g Guard,
+    ) {
+        // safety: table was read while `guard` was held. the code that drops table only drops it
+        // after it is no longer reachable, and any outstanding references are no longer active.
+        // this references is still active (marked by the guard), so the target of the references
+        // won't be dropped while the guard remains active.
+        let n = unsafe { table.deref() }.bins.len();
+
+        // TODO: use num_cpus to help determine stride
+        let stride = MIN_TRANSFER_STRIDE;
+
+        if next_table.is_null() {
+            // we are initiating a resize
+            let table = Owned::new(Table {
+                bins: vec![Atomic::null(); n << 1].into_boxed_slice(),
+            });
+
+            self.resize_times.start();
+            let now_garbage = self.next_table.swap(table, Ordering::SeqCst, guard);
+            assert!(now_garbage.is_null());
+            // nodes are about to be copied to the new table, after which the old copies may still
+            // be read, but no longer see new values. see get.
+            self.invalidate_read_caches();
+            self.transfer_index.store(n as isize, Ordering::SeqCst);
+            next_table = self.next_table.load(Ordering::Relaxed, guard);
+        }
+
+        // safety: same argument as for table above
+        let next_n = unsafe { next_table.deref() }.bins.len();
+
+        let mut advance = true;
+        let mut finishing = false;
+        let mut i = 0;
+        let mut bound = 0;
+        loop {
+            // try to claim a range of bins for us to transfer
+            while advance {
+                i -= 1;
+                if i >= bound || finishing {
+                    advance = false;
+                    break;
+                }
+
+                let next_index = self.transfer_index.load(Ordering::SeqCst);
+                if next_index <= 0 {
+                    i = -1;
+                    advance = false;
+                    break;
+                }
+
+                let next_bound = if next_index > stride {
+                    next_index - stride
+                } else {
+                    0
+                };
+                if self
+                    .transfer_index
+                    .compare_exchange(next_index, next_bound, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    bound = next_bound;
+                    i = next_index;
+                    advance = false;
+                    break;
+                }
+            }
+
+            if i < 0 || i as usize >= n || i as usize + n >= next_n {
+                // the resize has finished
+
+                if finishing {
+                    // this branch is only taken for one thread partaking in the resize!
+                    self.next_table.store(Shared::null(), Ordering::SeqCst);
+                    let now_garbage = self.table.swap(next_table, Ordering::SeqCst, guard);
+                    self.invalidate_read_caches();
+                    self.resize_times.finish();
+                    self.capacity_history.record(self.len(), next_n);
+                    // safety: need to guarantee that now_garbage is no longer reachable. more
+                    // specifically, no thread that executes _after_ this line can ever get a
+                    // reference to now_garbage.
+                    //
+                    // first, we need to argue that there is no _other_ way to get to now_garbage.
+                    //
+                    //  - it is _not_ accessible through self.table any more
+                    //  - it is _not_ accessible through self.next_table any more
+                    //  - what about forwarding nodes (BinEntry::Moved)?
+                    //    the only BinEntry::Moved that point to now_garbage, are the ones in
+                    //    _previous_ tables. to get to those previous tables, one must ultimately
+                    //    have arrived through self.table (because that's where all operations
+                    //    start their search). since self.table has now changed, only "old" threads
+                    //    can still be accessing them. no new thread can get to past tables, and
+                    //    therefore they also cannot get to ::Moved that point to now_garbage, so
+                    //    we're fine.
+                    //
+                    // this means that no _future_ thread (i.e., in a later epoch where the value
+                    // may be freed) can get a reference to now_garbage.
+                    //
+                    // next, let's talk about threads with _existing_ references to now_garbage.
+                    // such a thread must have gotten that reference before the call to swap.
+                    // because of this, that thread must be pinned to an epoch <= the epoch of our
+                    // guard (since our guard is pinning the epoch). since the garbage is placed in
+                    // our epoch, it won't be freed until the _next_ epoch, at which point, that
+                    // thread must have dropped its guard, and with it, any reference to the value.
+                    unsafe { guard.defer_destroy(now_garbage) };
+                    self.size_ctl
+                        .store(((n as isize) << 1) - ((n as isize) >> 1), Ordering::SeqCst);
+                    return;
+                }
+
+                let sc = self.size_ctl.load(Ordering::SeqCst);
+                if self
+                    .size_ctl
+                    .compare_exchange(sc, sc - 1, Ordering::SeqCst, Ordering::SeqCst)
+                    .is_ok()
+                {
+                    if (sc - 2) != Self::resize_stamp(n) << RESIZE_STAMP_SHIFT {
+                        return;
+                    }
+
+                    // we are the chosen thread to finish the resize!
+                    finishing = true;
+
+                    // ???
+                    advance = true;
+
+                    // NOTE: the java code says "recheck before commit" here
+                    i = n as isize;
+                }
+
+                continue;
+            }
+            let i = i as usize;
+
+            // safety: these were read while `guard` was held. the code that drops these, only
+            // drops them after a) they are no longer reachable, and b) any outstanding references
+            // are no longer active. these references are still active (marked by the guard), so
+            // the target of these references won't be dropped while the guard remains active.
+            let table = unsafe { table.deref() };
+            let next_table = unsafe { next_table.deref() };
+
+            let bin = table.bin(i, guard);
+            if bin.is_null() {
+                advance = table
+                    .cas_bin(
+                        i,
+                        Shared::null(),
+                        Owned::new(BinEntry::moved(next_table, std::ptr::null())),
+                        guard,
+                    )
+                    .is_ok();
+                continue;
+            }
+
+            // safety: bin is a valid pointer.
+            //
+            // there are two cases when a bin pointer is invalidated:
+            //
+            //  1. if the table was resized, bin is a move entry, and the resize has completed. in
+            //     that case, the table (and all its heads) will be dropped in the next epoch
+            //     following that.
+            //  2. if the table is being resized, bin may be swapped with a move entry. the old bin
+            //     will then be dropped in the following epoch after that happens.
+            //
+            // in both cases, we held the guard when we got the reference to the bin. if any such
+            // swap happened, it must have happened _after_ we read. since we did the read while
+            // pinning the epoch, the drop must happen in the _next_ epoch (i.e., the one that we
+            // are holding up by holding on to our guard).
+            match *unsafe { bin.deref() } {
+                BinEntry::Moved { .. } => {
+                    // already processed
+                    advance = true;
+                }
+                BinEntry::Node(ref head) => {
+                    // bin is non-empty, need to link into it, so we must take the lock
+                    let head_lock = self.lock_bin(head, Operation::Transfer);
+
+                    // need to check that this is _still_ the head
+                    let current_head = table.bin(i, guard);
+                    if current_head.as_raw() != bin.as_raw() {
+                        // nope -- try again from the start
+                        continue;
+                    }
+
+                    // yes, it is still the head, so we can now "own" the bin
+                    // note that there can still be readers in the bin!
+
+                    // TODO: TreeBin & ReservationNode
+
+                    // every node is copied, rather than reusing the tail of the old list that
+                    // stays in one bin, so that the old list keeps the entries it had when it was
+                    // moved for iterators that do not follow moves.
+                    let mut low_bin = Shared::null();
+                    let mut high_bin = Shared::null();
+                    let mut p = bin;
+                    while !p.is_null() {
                         // safety: p is a valid pointer.
                         //
                         // p is only dropped in the next epoch following when its bin is replaced
@@ -951,96 +4178,1466 @@ where
                         // safe to use now.
                         let node = unsafe { p.deref() }.as_node().unwrap();
 
-                        let link = if node.hash & n as u64 == 0 {
-                            // to the low bin!
-                            &mut low_bin
-                        } else {
-                            // to the high bin!
-                            &mut high_bin
-                        };
+                        let link = if node.hash & n as u64 == 0 {
+                            // to the low bin!
+                            &mut low_bin
+                        } else {
+                            // to the high bin!
+                            &mut high_bin
+                        };
+
+                        *link = Owned::new(BinEntry::Node(Node {
+                            hash: node.hash,
+                            key: node.key.clone(),
+                            lock: parking_lot::Mutex::new(()),
+                            #[cfg(feature = "priority")]
+                            priority: std::sync::atomic::AtomicU64::new(
+                                node.priority.load(Ordering::SeqCst),
+                            ),
+                            #[cfg(feature = "timestamps")]
+                            written: crossbeam::atomic::AtomicCell::new(node.written.load()),
+                            #[cfg(feature = "timestamps")]
+                            inserted: node.inserted,
+                            #[cfg(feature = "entry-stats")]
+                            counters: node.counters.clone(),
+                            value: node.value.clone(),
+                            next: Atomic::from(*link),
+                            swap_stamp: AtomicUsize::new(node.swap_stamp.load(Ordering::SeqCst)),
+                            swapped: node.swapped.clone(),
+                        }))
+                        .into_shared(guard);
+
+                        p = node.next.load(Ordering::SeqCst, guard);
+                    }
+
+                    next_table.store_bin(i, low_bin);
+                    next_table.store_bin(i + n, high_bin);
+                    table.store_bin(i, Owned::new(BinEntry::moved(next_table, bin.as_raw())));
+
+                    // the _old_ bin linked list is now garbage. its nodes have all been
+                    // re-allocated in the new bin linked list.
+                    p = bin;
+                    while !p.is_null() {
+                        // safety:
+                        //
+                        // we need to argue that there is no longer a way to access p. the only way
+                        // to get to p is through table[i]. since table[i] has been replaced by a
+                        // BinEntry::Moved, p is no longer accessible, except through its old_head.
+                        // that is only followed by iterators that read next_move_stamp before the
+                        // stamp of this move was handed out, and so were pinned before now.
+                        //
+                        // any existing reference to p must have been taken before table.store_bin.
+                        // at that time we had the epoch pinned, so any threads that have such a
+                        // reference must be before or at our epoch. since the p isn't destroyed
+                        // until the next epoch, those old references are fine since they are tied
+                        // to those old threads' pins of the old epoch.
+                        let next = unsafe { p.deref() }
+                            .as_node()
+                            .unwrap()
+                            .next
+                            .load(Ordering::SeqCst, guard);
+                        unsafe { guard.defer_destroy(p) };
+                        p = next;
+                    }
+
+                    advance = true;
+
+                    drop(head_lock);
+                }
+            }
+        }
+    }
+
+    /// Returns the stamp bits for resizing a table of size n.
+    /// Must be negative when shifted left by RESIZE_STAMP_SHIFT.
+    fn resize_stamp(n: usize) -> isize {
+        n.leading_zeros() as isize | (1 << (RESIZE_STAMP_BITS - 1)) as isize
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order.
+    /// The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter<'g>(&self, guard: &'g Guard) -> Iter<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        Iter { node_iter }
+    }
+
+    /// Like [`iter`](FlurryHashMap::iter), but asks the processor to fetch the first entry of
+    /// each bin into the cache a few bins before the iterator gets to it.
+    ///
+    /// The entries of a large map are scattered over memory, so a scan of one spends much of its
+    /// time waiting for each bin's first entry to be loaded. Prefetching overlaps those waits
+    /// with the work on the bins in between. This only pays off for maps that do not fit in the
+    /// processor's caches, and only has an effect on `x86_64`; elsewhere, it is the same as
+    /// `iter`. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_prefetch<'g>(&self, guard: &'g Guard) -> Iter<'g, K, V> {
+        let mut iter = self.iter(guard);
+        iter.node_iter.set_prefetch(PREFETCH_STRIDE);
+        iter
+    }
+
+    /// An iterator visiting all key-value pairs, with the bins of the map visited in an order
+    /// picked by `rng`. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// [`iter`](FlurryHashMap::iter) always starts from the first bin, so consumers that stop
+    /// early, such as samplers or work that is handed out in the order it is found, favor the
+    /// same entries every time. This visits every entry that `iter` would, but shuffles the order
+    /// of the bins, while the entries within a bin are still visited in the order they are
+    /// chained in. The same `rng` state gives the same order on a map that is not being written
+    /// to. Shuffling allocates a list of all the bins of the table up front.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_shuffled<'g, R>(&self, rng: &mut R, guard: &'g Guard) -> Shuffled<'g, K, V>
+    where
+        R: rand::Rng + ?Sized,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        // safety: table is only dropped on the next epoch change after it is swapped to
+        // null. we read it while holding `guard`, so the current epoch persists while we
+        // use it.
+        let table = unsafe { table.as_ref() };
+        let mut order: Vec<usize> = (0..table.map_or(0, |t| t.bins.len())).collect();
+        rand::seq::SliceRandom::shuffle(&mut order[..], rng);
+        Shuffled {
+            table,
+            order: order.into_iter(),
+            node_iter: None,
+            guard,
+        }
+    }
+
+    /// An iterator visiting all key-value pairs whose home bin in the current table is in
+    /// `lo..hi`, in arbitrary order. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// This gives explicit control over which portion of the map is processed, for example to
+    /// spread a traversal over threads, or to process neighboring bins together. The home bin of
+    /// an entry is the bin its key hashes to in the table the map has when this method is called,
+    /// whose size is reported by [`load_snapshot`](FlurryHashMap::load_snapshot). Entries that a
+    /// concurrent resize moves to a larger table are still visited by the range their home bin is
+    /// in, so the iterators of a set of ranges that together cover all bins visit the same
+    /// entries as [`iter`](FlurryHashMap::iter). Bins past the end of the table are ignored.
+    ///
+    /// # Panics
+    ///
+    /// If `lo` is greater than `hi`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn entries_in_bins<'g>(&self, lo: usize, hi: usize, guard: &'g Guard) -> Iter<'g, K, V> {
+        assert!(lo <= hi);
+        let table = self.table.load(Ordering::SeqCst, guard);
+        // safety: table is only dropped on the next epoch change after it is swapped to null.
+        // we read it while holding `guard`, so the current epoch persists while we use it.
+        let node_iter = match unsafe { table.as_ref() } {
+            Some(table) => NodeIter::with_range(table, lo, hi, guard),
+            None => NodeIter::new(table, guard),
+        };
+        Iter { node_iter }
+    }
+
+    /// An iterator visiting all keys in arbitrary order.
+    /// The iterator element type is `&'g K`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn keys<'g>(&self, guard: &'g Guard) -> Keys<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        Keys { node_iter }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&'g V`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn values<'g>(&self, guard: &'g Guard) -> Values<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        Values { node_iter }
+    }
+
+    /// An iterator visiting clones of all key-value pairs in arbitrary order.
+    /// The iterator element type is `(K, V)`.
+    ///
+    /// Each entry is cloned only when the iterator reaches it, so unlike with
+    /// [`sorted_run`](FlurryHashMap::sorted_run), no more than one entry is held at a time beyond
+    /// what the caller keeps. The items do not borrow from the map, but the iterator itself
+    /// does, just like [`iter`](FlurryHashMap::iter): `guard` must outlive it, and memory freed
+    /// by other threads is not reclaimed until it is dropped.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_owned_lazy<'g>(&self, guard: &'g Guard) -> OwnedLazy<'g, K, V>
+    where
+        V: Clone,
+    {
+        OwnedLazy {
+            iter: self.iter(guard),
+        }
+    }
+
+    /// An iterator visiting only the first key-value pair encountered for each distinct value of
+    /// `f(key, value)`, in arbitrary order. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// This is useful for skipping entries that are logically duplicates of one another even
+    /// though they are stored under distinct keys. Every distinct result of `f` is kept in a
+    /// `HashSet` for as long as the iterator lives.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_unique_by<'g, B, F>(&self, f: F, guard: &'g Guard) -> UniqueBy<'g, K, V, B, F>
+    where
+        B: Eq + Hash,
+        F: Fn(&K, &V) -> B,
+    {
+        UniqueBy {
+            iter: self.iter(guard),
+            seen: std::collections::HashSet::new(),
+            f,
+        }
+    }
+
+    /// An iterator visiting all key-value pairs in the order given by comparing their keys with
+    /// `cmp`. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// Keys that `cmp` considers equal are yielded in arbitrary order relative to one another.
+    ///
+    /// Unlike the other iterators, this one is not lazy: every entry is first collected into a
+    /// `Vec` and sorted, which takes `O(n)` extra memory and `O(n log n)` time before the first
+    /// entry is returned.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_sorted_by<'g, F>(&self, mut cmp: F, guard: &'g Guard) -> SortedBy<'g, K, V>
+    where
+        F: FnMut(&K, &K) -> std::cmp::Ordering,
+    {
+        let mut entries: Vec<_> = self.iter(guard).collect();
+        entries.sort_by(|a, b| cmp(a.0, b.0));
+        SortedBy {
+            entries: entries.into_iter(),
+        }
+    }
+
+    /// An iterator yielding clones of all key-value pairs in ascending key order. The iterator
+    /// element type is `(K, V)`.
+    ///
+    /// Since the items are owned, the iterator does not borrow the map or the guard, which makes
+    /// it suitable for feeding into other stages of a pipeline, such as a merge-join with another
+    /// sorted stream. As with [`iter_sorted_by`](FlurryHashMap::iter_sorted_by), every entry
+    /// is first cloned into a `Vec` and sorted, which takes `O(n)` extra memory and
+    /// `O(n log n)` time before the first entry is returned.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn sorted_run(&self, guard: &Guard) -> SortedRun<K, V>
+    where
+        K: Ord,
+        V: Clone,
+    {
+        let mut entries: Vec<_> = self
+            .iter(guard)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        SortedRun {
+            entries: entries.into_iter(),
+        }
+    }
+
+    /// Returns a sorted copy of the keys of the map, which answers binary searches and range
+    /// queries over them.
+    ///
+    /// This gives occasional ordered access to a map that is mostly accessed by key, without
+    /// keeping the map itself ordered. Every key is cloned, and the keys are sorted, which takes
+    /// `O(n)` extra memory and `O(n log n)` time. The index is a snapshot: it reflects the keys
+    /// the map had when it was built, and does not see later insertions or removals. Keys that
+    /// are inserted or removed while it is being built may or may not be in it, just as with
+    /// [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn build_sorted_index(&self, guard: &Guard) -> frozen::SortedIndex<K>
+    where
+        K: Ord,
+    {
+        let mut keys: Vec<K> = self.keys(guard).cloned().collect();
+        keys.sort_unstable();
+        frozen::SortedIndex { keys }
+    }
+
+    /// Returns clones of the smallest and the largest key in the map, or `None` if the map is
+    /// empty.
+    ///
+    /// Both are found in a single pass over the map, without sorting it, and only the two keys
+    /// are cloned. If the map is modified concurrently, keys that are inserted or removed during
+    /// the call may or may not be considered, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn key_bounds(&self, guard: &Guard) -> Option<(K, K)>
+    where
+        K: Ord,
+    {
+        let mut keys = self.keys(guard);
+        let first = keys.next()?;
+        let (min, max) = keys.fold((first, first), |(min, max), key| {
+            (std::cmp::min(min, key), std::cmp::max(max, key))
+        });
+        Some((min.clone(), max.clone()))
+    }
+
+    /// Returns `true` if `f` returns `true` for any entry of the map.
+    ///
+    /// The search stops as soon as a match is found. With the `rayon` feature enabled, ranges of
+    /// bins are searched in parallel on the [`rayon`] thread pool, and all of them stop once any
+    /// of them finds a match. Which entries `f` is called with before that is unspecified, so
+    /// `f` should not have side effects that matter. If the map is modified concurrently,
+    /// entries that are inserted or removed during the call may or may not be considered, just
+    /// as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    ///
+    /// [`rayon`]: https://docs.rs/rayon
+    pub fn any<F>(&self, f: F, guard: &Guard) -> bool
+    where
+        F: Fn(&K, &V) -> bool + Sync,
+    {
+        #[cfg(feature = "rayon")]
+        {
+            self.par_any(f, guard)
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            self.iter(guard).any(|(k, v)| f(k, v))
+        }
+    }
+
+    /// Folds every entry of the map into two accumulators at once, starting from `init_a` and
+    /// `init_b`, and returns both.
+    ///
+    /// Each entry is passed to `fa` and then to `fb` as the map is traversed, so statistics that
+    /// take two accumulators, such as a sum and a count, or a minimum and a maximum, are computed
+    /// in one pass rather than two. Both folds see exactly the same entries, even if the map is
+    /// modified concurrently, in which case those are the entries seen by
+    /// [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn tee_fold<A, B, FA, FB>(
+        &self,
+        init_a: A,
+        mut fa: FA,
+        init_b: B,
+        mut fb: FB,
+        guard: &Guard,
+    ) -> (A, B)
+    where
+        FA: FnMut(A, &K, &V) -> A,
+        FB: FnMut(B, &K, &V) -> B,
+    {
+        self.iter(guard).fold((init_a, init_b), |(a, b), (k, v)| {
+            (fa(a, k, v), fb(b, k, v))
+        })
+    }
+
+    /// Returns the number of distinct values in the map.
+    ///
+    /// The values are collected into a `HashSet` by reference in a single pass over the map, so
+    /// no values are cloned, but the set holds one entry per distinct value. If the map is
+    /// modified concurrently, values that are written during the call may or may not be
+    /// counted, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn distinct_value_count(&self, guard: &Guard) -> usize
+    where
+        V: Eq + Hash,
+    {
+        self.values(guard)
+            .collect::<std::collections::HashSet<_>>()
+            .len()
+    }
+
+    /// Returns an owned copy of every entry in the map, and of its hash builder.
+    ///
+    /// The map can later be rolled back to the checkpoint with
+    /// [`restore_from`](FlurryHashMap::restore_from). The checkpoint is taken in a single pass
+    /// over the map, so if the map is modified concurrently, it may hold some of those
+    /// modifications and not others, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn checkpoint(&self, guard: &Guard) -> checkpoint::Checkpoint<K, V, S>
+    where
+        V: Clone,
+        S: Clone,
+    {
+        checkpoint::Checkpoint {
+            entries: self
+                .iter(guard)
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            build_hasher: self.hasher(guard).clone(),
+        }
+    }
+
+    /// Writes every entry of the map to `writer` in the given `format`.
+    ///
+    /// The entries are written one at a time as the map is traversed under `guard`, so a large
+    /// map can be dumped to a file or a socket without first being copied into memory. `writer`
+    /// is written to in many small pieces, so it should usually be buffered. If the map is
+    /// modified concurrently, the export may reflect some modifications and not others, just as
+    /// with [`iter`](FlurryHashMap::iter). Stops at, and returns, the first error from `writer`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn export<W>(
+        &self,
+        writer: &mut W,
+        format: export::ExportFormat,
+        guard: &Guard,
+    ) -> std::io::Result<()>
+    where
+        W: std::io::Write,
+        K: export::ExportField,
+        V: export::ExportField,
+    {
+        export::write_entries(writer, format, self.iter(guard))
+    }
+
+    /// Returns an immutable copy of the entries of the map in an `Arc`, which any number of
+    /// threads can share and read without pinning an epoch.
+    ///
+    /// This suits data that is read far more often than it changes, such as configuration: the
+    /// current snapshot is handed out to readers, and a new one is taken and handed out whenever
+    /// the map has changed enough. Readers that still hold the old snapshot keep seeing it until
+    /// they drop it. If the map is modified concurrently, the snapshot may reflect some
+    /// modifications and not others, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn snapshot_shared(&self, guard: &Guard) -> std::sync::Arc<frozen::FrozenHashMap<K, V, S>>
+    where
+        V: Clone,
+        S: Clone,
+    {
+        let mut map = std::collections::HashMap::with_capacity_and_hasher(
+            self.len(),
+            self.hasher(guard).clone(),
+        );
+        map.extend(self.iter(guard).map(|(k, v)| (k.clone(), v.clone())));
+        std::sync::Arc::new(frozen::FrozenHashMap { map })
+    }
+
+    /// Rolls the map back to `checkpoint`, so that it holds exactly the entries of the
+    /// checkpoint.
+    ///
+    /// Keys that are not in the checkpoint are removed, and all others are mapped to their values
+    /// in the checkpoint. This is done one key at a time, so other threads may see the map partly
+    /// rolled back, and writes by other threads while the map is rolled back may survive it.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn restore_from<S2>(&self, checkpoint: &checkpoint::Checkpoint<K, V, S2>, guard: &Guard)
+    where
+        V: Clone,
+    {
+        let keep: std::collections::HashSet<_> =
+            checkpoint.entries.iter().map(|(k, _)| k).collect();
+        for key in self.keys(guard) {
+            if !keep.contains(key) {
+                self.remove(key, guard);
+            }
+        }
+        for (key, value) in &checkpoint.entries {
+            self.put(key.clone(), value.clone(), false, guard);
+        }
+    }
+
+    /// Returns the changes that would make this map hold the same entries as `target`.
+    ///
+    /// Both maps are traversed, so if either is modified concurrently, the result may reflect
+    /// some modifications and not others, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn diff<S2>(&self, target: &FlurryHashMap<K, V, S2>, guard: &Guard) -> diff::MapDiff<K, V>
+    where
+        V: Clone + PartialEq,
+        S2: BuildHasher,
+    {
+        let mut diff = diff::MapDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        };
+        for (key, value) in self.iter(guard) {
+            match target.get(key, guard) {
+                None => diff.removed.push((key.clone(), value.clone())),
+                Some(new) if new != value => {
+                    diff.changed.push((key.clone(), value.clone(), new.clone()))
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, value) in target.iter(guard) {
+            if self.get(key, guard).is_none() {
+                diff.added.push((key.clone(), value.clone()));
+            }
+        }
+        diff
+    }
+
+    /// Applies the changes in `diff` to the map: keys that were added or changed are mapped to
+    /// their values in the target map, and keys that were removed are removed.
+    ///
+    /// Each key is changed atomically, but the keys are changed one at a time, so other threads
+    /// may see the diff partly applied. Changes are applied regardless of what the keys are
+    /// currently mapped to, so applying a diff to a map in the state it was computed from brings
+    /// it into the state of the target map, and so does applying it to a map that has only
+    /// diverged from that state in keys that the diff does not touch.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn apply_diff(&self, diff: &diff::MapDiff<K, V>, guard: &Guard)
+    where
+        V: Clone,
+    {
+        for (key, _) in &diff.removed {
+            self.remove(key, guard);
+        }
+        for (key, value) in &diff.added {
+            self.put(key.clone(), value.clone(), false, guard);
+        }
+        for (key, _, value) in &diff.changed {
+            self.put(key.clone(), value.clone(), false, guard);
+        }
+    }
+
+    /// Returns clones of up to `N` key-value pairs, in arbitrary order, along with the number of
+    /// entries in the map.
+    ///
+    /// The pairs are stored inline in the returned [`ArrayVec`](array::ArrayVec), so taking a
+    /// bounded sample of the map does not allocate. The map is only traversed as far as needed
+    /// to fill the array, and its entries are then counted with [`len`](FlurryHashMap::len), so
+    /// if the map is modified concurrently, the total may not match the entries that were seen.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn collect_array<const N: usize>(
+        &self,
+        guard: &Guard,
+    ) -> (array::ArrayVec<(K, V), N>, usize)
+    where
+        V: Clone,
+    {
+        let mut out = array::ArrayVec::new();
+        for (k, v) in self.iter(guard).take(N) {
+            if out.try_push((k.clone(), v.clone())).is_err() {
+                unreachable!("at most N entries are taken");
+            }
+        }
+        (out, self.len())
+    }
+
+    /// Splits clones of all key-value pairs into `n_consumers` owned iterators of roughly equal
+    /// length, whose element type is `(K, V)`.
+    ///
+    /// The iterators hold on to neither the map nor the guard, so each can be handed to a
+    /// consumer thread of its own that processes its share at its own pace. The lengths of any two
+    /// shares differ by at most one. As with [`sorted_run`](FlurryHashMap::sorted_run), every
+    /// entry is cloned before this returns.
+    ///
+    /// # Panics
+    ///
+    /// If `n_consumers` is 0.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn split_for(&self, n_consumers: usize, guard: &Guard) -> Vec<OwnedShardIter<K, V>>
+    where
+        V: Clone,
+    {
+        assert_ne!(n_consumers, 0);
+        let mut entries: Vec<_> = self
+            .iter(guard)
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        let (base, extra) = (entries.len() / n_consumers, entries.len() % n_consumers);
+
+        // taken off the end, so that no entries have to be shifted
+        let mut shares: Vec<_> = (0..n_consumers)
+            .rev()
+            .map(|i| {
+                let share = base + if i < extra { 1 } else { 0 };
+                OwnedShardIter {
+                    entries: entries.split_off(entries.len() - share).into_iter(),
+                }
+            })
+            .collect();
+        shares.reverse();
+        shares
+    }
+
+    /// Passes a clone of every key-value pair to `f`, one at a time, until `f` returns `false`.
+    ///
+    /// This is meant for handing the entries of a map to a consumer that must not hold on to
+    /// `guard`, such as another thread at the end of a bounded channel. Unlike with
+    /// [`sorted_run`](FlurryHashMap::sorted_run), entries are cloned only as they are passed on,
+    /// so no more than one is held in memory at a time, and `f` can apply backpressure by blocking
+    /// until the consumer catches up.
+    ///
+    /// Returns `true` if every entry was passed to `f`, and `false` if `f` stopped the stream
+    /// early. If the map is modified concurrently, entries that are inserted or removed during
+    /// the call may or may not be passed on, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn stream_to<F>(&self, mut f: F, guard: &Guard) -> bool
+    where
+        F: FnMut((K, V)) -> bool,
+        V: Clone,
+    {
+        self.iter(guard).all(|(k, v)| f((k.clone(), v.clone())))
+    }
+
+    /// Removes the entries of the map one at a time, and sends each one on `sender`. Returns the
+    /// number of entries sent.
+    ///
+    /// Each entry is removed while holding the lock for its bin, and then sent once the lock has
+    /// been released, so a full bounded channel holds up the drain, but not other writers. This
+    /// feeds a downstream pipeline from the map while draining it. The memory of the removed
+    /// entries can only be reclaimed once `guard` is dropped. Entries that are inserted during
+    /// the call may or may not be drained, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// If every receiver has been dropped, the entry that could not be sent is returned in the
+    /// error, and the remaining entries are left in the map.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn drain_to_sender(
+        &self,
+        sender: &crossbeam::channel::Sender<(K, V)>,
+        guard: &Guard,
+    ) -> Result<usize, crossbeam::channel::SendError<(K, V)>>
+    where
+        V: Clone,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let mut sent = 0;
+        for node in NodeIter::new(table, guard) {
+            let computed = self.compute_locked(
+                KeyArg::Borrowed(&node.key),
+                |_, _| Action::Remove,
+                Operation::Remove,
+                guard,
+            );
+            if let Computed::Removed(value) = computed {
+                sender.send((node.key.clone(), value.clone()))?;
+                sent += 1;
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Removes every entry of the map, and returns clones of them.
+    ///
+    /// Each entry is removed while holding the lock for its bin, so every entry is either left in
+    /// the map or returned, and none is returned twice even if other threads remove entries too.
+    /// The map as a whole is not emptied atomically: entries that are inserted during the call
+    /// may or may not be taken, just as with [`iter`](FlurryHashMap::iter), and the map is only
+    /// guaranteed to be empty afterwards if nothing was inserted concurrently. The memory of the
+    /// removed entries can only be reclaimed once `guard` is dropped.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn take_all(&self, guard: &Guard) -> Vec<(K, V)>
+    where
+        V: Clone,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let mut taken = Vec::with_capacity(self.len());
+        for node in NodeIter::new(table, guard) {
+            let computed = self.compute_locked(
+                KeyArg::Borrowed(&node.key),
+                |_, _| Action::Remove,
+                Operation::Remove,
+                guard,
+            );
+            if let Computed::Removed(value) = computed {
+                taken.push((node.key.clone(), value.clone()));
+            }
+        }
+        taken
+    }
+
+    /// Folds every key-value pair of the map into `init` using `f`, and returns the result.
+    ///
+    /// This allows collecting the entries into any kind of container without building an
+    /// intermediate collection.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn collect_into<C, F>(&self, init: C, mut f: F, guard: &Guard) -> C
+    where
+        F: FnMut(&mut C, &K, &V),
+    {
+        let mut c = init;
+        for (k, v) in self.iter(guard) {
+            f(&mut c, k, v);
+        }
+        c
+    }
+
+    /// Splits the entries of this map into `N` new maps in a single traversal.
+    ///
+    /// Each entry is cloned into the map at index `f(key, value)` of the returned array. Entries
+    /// for which `f` returns an index that is `N` or larger are not included in any of the maps.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn classify<const N: usize, F>(&self, f: F, guard: &Guard) -> [FlurryHashMap<K, V, S>; N]
+    where
+        F: Fn(&K, &V) -> usize,
+        V: Clone,
+        S: Default,
+    {
+        let maps: Vec<_> = (0..N)
+            .map(|_| FlurryHashMap::with_hasher(S::default()))
+            .collect();
+        for (k, v) in self.iter(guard) {
+            if let Some(map) = maps.get(f(k, v)) {
+                map.insert(k.clone(), v.clone());
+            }
+        }
+        use std::convert::TryFrom;
+        match <[_; N]>::try_from(maps) {
+            Ok(maps) => maps,
+            Err(_) => unreachable!("exactly N maps were created"),
+        }
+    }
+
+    /// Returns how the entries of the map are currently distributed over its bins.
+    ///
+    /// This visits every entry in the map. See also
+    /// [`simulate_resize`](FlurryHashMap::simulate_resize).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn bin_stats(&self, guard: &Guard) -> stats::BinStats {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let bins = if table.is_null() {
+            0
+        } else {
+            // safety: we loaded the table while epoch was pinned. table won't be deallocated until
+            // next epoch at the earliest.
+            unsafe { table.deref() }.bins.len()
+        };
+        self.simulate_bins(bins, guard)
+    }
+
+    /// Returns how unevenly the entries of the map are currently spread over its bins.
+    ///
+    /// This is [`bin_stats`](FlurryHashMap::bin_stats) boiled down to a couple of numbers that
+    /// automated tuning can compare against a threshold, for example to decide whether keys
+    /// should be hashed with a different seed. Both are 0 if the map is empty.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn skew_report(&self, guard: &Guard) -> stats::SkewReport {
+        self.bin_stats(guard).skew()
+    }
+
+    /// Returns how the entries of the map would be distributed over its bins if the table had
+    /// `new_capacity` bins, rounded up to the next power of two.
+    ///
+    /// The map itself is not changed; the keys are only re-hashed to see which bins they would
+    /// end up in. This visits every entry in the map. Comparing the result with that of
+    /// [`bin_stats`](FlurryHashMap::bin_stats) from the current layout can help pick a
+    /// capacity for [`reserve_concurrent`](FlurryHashMap::reserve_concurrent) or
+    /// [`with_capacity`](FlurryHashMap::with_capacity).
+    ///
+    /// # Panics
+    ///
+    /// If `new_capacity` is 0.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn simulate_resize(&self, new_capacity: usize, guard: &Guard) -> stats::BinStats {
+        assert_ne!(new_capacity, 0);
+        self.simulate_bins(new_capacity.next_power_of_two(), guard)
+    }
+
+    /// Returns the fraction of `sample_keys` that would land in a bin of the current table that
+    /// already holds an entry, if they were inserted now.
+    ///
+    /// This predicts how many of a batch of keys would end up in chains, and so how much slower
+    /// than average inserting and looking them up would be, before the batch is inserted. Each
+    /// key is checked against the table as it is; keys of the sample that would share a bin with
+    /// one another are not counted unless the bin is already occupied, and neither are any
+    /// resizes the insertion would cause. A sample key that is already in the map counts as
+    /// landing in an occupied bin. Returns 0 if `sample_keys` is empty or the table has not been
+    /// allocated yet.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn collision_estimate(&self, sample_keys: &[K], guard: &Guard) -> f64 {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if sample_keys.is_empty() || table.is_null() {
+            return 0.0;
+        }
+        let occupied = sample_keys
+            .iter()
+            .filter(|key| {
+                let h = self.hash(*key, guard);
+                // safety: table is only dropped on the next epoch change after it is swapped to
+                // null. we read it while holding `guard`, so the current epoch persists while we
+                // use it.
+                let mut table = unsafe { table.deref() };
+                loop {
+                    let bin = table.bin(table.bini(h), guard);
+                    // safety: as above, for the table and the bins in it
+                    match unsafe { bin.as_ref() } {
+                        // the bin is being moved; the key would go in the table it is moved to
+                        Some(BinEntry::Moved { next_table, .. }) => {
+                            table = unsafe { &**next_table }
+                        }
+                        Some(BinEntry::Node(_)) => return true,
+                        None => return false,
+                    }
+                }
+            })
+            .count();
+        occupied as f64 / sample_keys.len() as f64
+    }
+
+    /// Returns `true` if this map and `other` have the same number of bins, and each bin holds
+    /// the same keys in the same order in both maps.
+    ///
+    /// This is a debugging aid for checking that two ways of building a map, such as inserting
+    /// in bulk and one entry at a time, place the entries identically when keys are hashed
+    /// deterministically. It is stricter than comparing the entries: two maps with the same
+    /// entries may still differ in the number of bins, or in the order of the keys in a bin,
+    /// which follows the order in which they were inserted. Values are not compared. Returns
+    /// `false` if either map is being resized, since its entries are then spread over two tables.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn same_layout<S2>(&self, other: &FlurryHashMap<K, V, S2>, guard: &Guard) -> bool
+    where
+        S2: BuildHasher,
+    {
+        if !self.next_table.load(Ordering::SeqCst, guard).is_null()
+            || !other.next_table.load(Ordering::SeqCst, guard).is_null()
+        {
+            return false;
+        }
+        let (a, b) = (
+            self.table.load(Ordering::SeqCst, guard),
+            other.table.load(Ordering::SeqCst, guard),
+        );
+        // safety: we loaded the tables while epoch was pinned. they won't be deallocated until
+        // next epoch at the earliest.
+        let (a, b) = unsafe { (a.as_ref(), b.as_ref()) };
+        let (a, b) = (
+            a.map_or(&[][..], |t| &t.bins[..]),
+            b.map_or(&[][..], |t| &t.bins[..]),
+        );
+        if a.len() != b.len() {
+            return false;
+        }
+        a.iter().zip(b).all(|(a, b)| {
+            let keys = |bin: &Atomic<BinEntry<K, V>>| {
+                let mut keys = Vec::new();
+                let mut p = bin.load(Ordering::SeqCst, guard);
+                // safety: as above, for the nodes in the tables
+                while let Some(entry) = unsafe { p.as_ref() } {
+                    let n = match entry {
+                        BinEntry::Node(n) => n,
+                        // the bin was moved after we checked for a resize
+                        BinEntry::Moved { .. } => return None,
+                    };
+                    // skip reservations, which have no value yet
+                    if !n.value.load(Ordering::SeqCst, guard).is_null() {
+                        keys.push(&n.key);
+                    }
+                    p = n.next.load(Ordering::SeqCst, guard);
+                }
+                Some(keys)
+            };
+            match (keys(a), keys(b)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            }
+        })
+    }
+
+    fn simulate_bins(&self, bins: usize, guard: &Guard) -> stats::BinStats {
+        if bins == 0 {
+            // the table has not been allocated yet
+            return stats::BinStats::new(0, std::iter::empty());
+        }
+        // the same as Table::bini
+        let mask = bins as u64 - 1;
+        let indices = self
+            .keys(guard)
+            .map(|k| (self.hash(k, guard) & mask) as usize);
+        stats::BinStats::new(bins, indices)
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order, which stops early once
+    /// `cancel` is set. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// `cancel` is checked each time the iterator moves on to the next bin, so a few more
+    /// entries may be returned after it is set. This lets a long traversal be abandoned from
+    /// another thread, for example when the client that asked for it has gone away.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_cancellable<'g>(
+        &self,
+        cancel: &'g std::sync::atomic::AtomicBool,
+        guard: &'g Guard,
+    ) -> Cancellable<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        Cancellable {
+            node_iter,
+            prev: None,
+            cancel,
+            cancelled: false,
+            guard,
+        }
+    }
+
+    /// An iterator visiting all key-value pairs in arbitrary order, gathered in a pass over the map
+    /// that was not interfered with by a resize, if possible. The iterator element type is
+    /// `(&'g K, &'g V)`.
+    ///
+    /// [`iter`](FlurryHashMap::iter) visits every entry that is in the map for the whole
+    /// iteration exactly once, even during a resize. Entries that are inserted or removed while
+    /// a resize is moving them between tables, however, are more likely to be missed or seen in
+    /// both their states. This method first gathers all entries into a `Vec`, and starts over if
+    /// a resize was in progress at any point during the pass, up to a few times. This is a best
+    /// effort: if the map keeps resizing, the entries of the last pass are returned anyway, which
+    /// [`StableRetry::is_clean`] reports. Passes are not protected from concurrent insertions and
+    /// removals that do not resize the map.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_stable_retry<'g>(&self, guard: &'g Guard) -> StableRetry<'g, K, V> {
+        let mut attempts = 0;
+        loop {
+            let table = self.table.load(Ordering::SeqCst, guard);
+            let resizing = !self.next_table.load(Ordering::SeqCst, guard).is_null();
+            let entries: Vec<_> = self.iter(guard).collect();
+            // the table cannot be freed and its address reused while we hold the guard, so if it
+            // is still the current table, and no resize away from it has started, it never
+            // changed during the pass.
+            let clean = !resizing
+                && self.next_table.load(Ordering::SeqCst, guard).is_null()
+                && self.table.load(Ordering::SeqCst, guard) == table;
+            if clean || attempts == STABLE_RETRIES {
+                return StableRetry {
+                    entries: entries.into_iter(),
+                    clean,
+                };
+            }
+            attempts += 1;
+        }
+    }
+
+    /// An iterator visiting all key-value pairs whose value was last written before `cutoff`, in
+    /// arbitrary order. The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// An entry's value is written when the entry is inserted, and again whenever it is replaced,
+    /// such as by [`insert`](FlurryHashMap::insert) or [`merge`](FlurryHashMap::merge). Reads do
+    /// not count, so this finds the entries that have gone the longest without an update, for
+    /// example to expire them.
+    ///
+    /// This method is only available with the `timestamps` feature enabled.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    #[cfg(feature = "timestamps")]
+    pub fn iter_older_than<'g>(
+        &self,
+        cutoff: std::time::Instant,
+        guard: &'g Guard,
+    ) -> OlderThan<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        OlderThan { node_iter, cutoff }
+    }
+
+    /// Folds the entries whose value was last written within `window` of now into `init` using
+    /// `f`, and returns the result.
+    ///
+    /// This computes aggregates over recent activity, such as the number of requests in the last
+    /// minute, when each request updates an entry. As with
+    /// [`iter_older_than`](FlurryHashMap::iter_older_than), an entry's value is written when the
+    /// entry is inserted, and again whenever it is replaced. The start of the window is fixed
+    /// when the call begins, so entries written during the fold may or may not be included.
+    ///
+    /// This method is only available with the `timestamps` feature enabled.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    #[cfg(feature = "timestamps")]
+    pub fn window_summary<A, F>(
+        &self,
+        window: std::time::Duration,
+        init: A,
+        f: F,
+        guard: &Guard,
+    ) -> A
+    where
+        F: Fn(A, &K, &V) -> A,
+    {
+        let since = std::time::Instant::now().checked_sub(window);
+        let table = self.table.load(Ordering::SeqCst, guard);
+        NodeIter::new(table, guard)
+            .filter(|node| since.map_or(true, |since| node.written.load() >= since))
+            .fold(init, |acc, node| {
+                // safety: flurry does not drop or move until after guard drop
+                let value = unsafe { node.value.load(Ordering::SeqCst, guard).deref() };
+                f(acc, &node.key, value)
+            })
+    }
+
+    /// An iterator visiting all entries in arbitrary order, along with the hash of each key and
+    /// where in the map the entry is stored. The iterator element type is
+    /// [`DiagEntry<'g, K, V>`](DiagEntry).
+    ///
+    /// This gathers in a single pass everything needed to analyze how well keys are spread over
+    /// the map's bins. See also [`bin_stats`](FlurryHashMap::bin_stats) for a summary.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_diagnostic<'g>(&self, guard: &'g Guard) -> Diagnostic<'g, K, V> {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        Diagnostic { node_iter }
+    }
+
+    /// An iterator visiting all entries in arbitrary order, along with the shard of each, which
+    /// is the hash of its key modulo `num_shards`. The iterator element type is
+    /// `(usize, &'g K, &'g V)`.
+    ///
+    /// This routes entries to downstream consumers that are sharded the same way, such as a
+    /// separate system that hashes keys with the same [`BuildHasher`]. The hash of each key is
+    /// the one it was stored with, so it is not recomputed, and an entry's shard is the same
+    /// across calls for as long as it is in the map.
+    ///
+    /// # Panics
+    ///
+    /// If `num_shards` is 0.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_with_shard<'g>(&self, num_shards: usize, guard: &'g Guard) -> WithShard<'g, K, V> {
+        assert_ne!(num_shards, 0);
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        WithShard {
+            node_iter,
+            num_shards: num_shards as u64,
+        }
+    }
+
+    /// Returns clones of all key-value pairs, split into `N` partitions by the hash of their key
+    /// modulo `N`.
+    ///
+    /// This is [`iter_with_shard`](FlurryHashMap::iter_with_shard) with `N` shards, collected in
+    /// a single pass into one owned `Vec` per shard, which can be handed to `N` workers or
+    /// downstream shards without them holding on to the map or the guard. Within a partition,
+    /// the entries are in the order the map is traversed in. Entries that are inserted or
+    /// removed during the call may or may not be included, just as with
+    /// [`iter`](FlurryHashMap::iter).
+    ///
+    /// # Panics
+    ///
+    /// If `N` is 0.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_partitioned<const N: usize>(&self, guard: &Guard) -> [Vec<(K, V)>; N]
+    where
+        V: Clone,
+    {
+        assert_ne!(N, 0);
+        let mut partitions: Vec<Vec<(K, V)>> = (0..N).map(|_| Vec::new()).collect();
+        for (i, k, v) in self.iter_with_shard(N, guard) {
+            partitions[i].push((k.clone(), v.clone()));
+        }
+        match std::convert::TryFrom::try_from(partitions) {
+            Ok(partitions) => partitions,
+            Err(_) => unreachable!("there are N partitions"),
+        }
+    }
+
+    /// An iterator visiting all key-value pairs whose key satisfies `pred`, in arbitrary order.
+    /// The iterator element type is `(&'g K, &'g V)`.
+    ///
+    /// This is equivalent to filtering [`iter`](FlurryHashMap::iter) on the key, except that the
+    /// value of an entry is only read if its key matches.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn iter_keys_matching<'g, P>(&self, pred: P, guard: &'g Guard) -> KeysMatching<'g, K, V, P>
+    where
+        P: Fn(&K) -> bool,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        let node_iter = NodeIter::new(table, guard);
+        KeysMatching { node_iter, pred }
+    }
+}
+
+impl<K, T, S> FlurryHashMap<K, std::sync::Arc<T>, S>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    T: Sync + Send,
+    S: BuildHasher,
+{
+    /// Returns the keys of all entries whose value is the same allocation as `target`, in
+    /// arbitrary order.
+    ///
+    /// Values are compared by identity using [`Arc::ptr_eq`](std::sync::Arc::ptr_eq), not by
+    /// their contents, which makes this suitable for reverse lookups from an object to the keys
+    /// it is stored under. This visits every entry in the map.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn find_by_arc(&self, target: &std::sync::Arc<T>, guard: &Guard) -> Vec<K> {
+        self.iter(guard)
+            .filter(|(_, v)| std::sync::Arc::ptr_eq(v, target))
+            .map(|(k, _)| k.clone())
+            .collect()
+    }
+}
+
+impl<K, S> FlurryHashMap<K, usize, S>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    S: BuildHasher,
+{
+    /// Subtracts one from the count `key` is mapped to, and removes `key` if that brings the
+    /// count to zero.
+    ///
+    /// Returns the new count, which is `Some(0)` if `key` was removed, or `None` if `key` was not
+    /// in the map. The count is read, decremented, and removed while holding the lock for `key`'s
+    /// bin, so of `n` concurrent calls for a key with count `n`, exactly one removes it. A key
+    /// mapped to 0 is removed as well.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`].
+    pub fn decrement_and_remove_at_zero(&self, key: &K, guard: &Guard) -> Option<usize> {
+        let computed = self.compute_locked(
+            KeyArg::Borrowed(key),
+            |_, count| match count {
+                Some(&count) if count > 1 => Action::Set(count - 1),
+                _ => Action::Remove,
+            },
+            Operation::Decrement,
+            guard,
+        );
+        match computed {
+            Computed::Replaced(&count) => Some(count),
+            Computed::Removed(_) => Some(0),
+            Computed::Unchanged(None) => None,
+            Computed::Inserted(_) | Computed::Unchanged(Some(_)) => unreachable!(),
+        }
+    }
+}
+
+/// The error returned by [`replace_with_retries`] when the value kept changing under it.
+///
+/// [`replace_with_retries`]: FlurryHashMap::replace_with_retries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryExhausted;
+
+impl std::fmt::Display for RetryExhausted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "value was changed concurrently on every attempt")
+    }
+}
+
+impl std::error::Error for RetryExhausted {}
+
+/// The error returned by [`try_insert`] when the map's [`validator`] rejects the entry.
+///
+/// [`try_insert`]: FlurryHashMap::try_insert
+/// [`validator`]: builder::Builder::validator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rejected;
+
+impl std::fmt::Display for Rejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "entry was rejected by the validator")
+    }
+}
+
+impl std::error::Error for Rejected {}
+
+/// The error returned by [`insert_if_under_capacity`] when the map is full.
+///
+/// [`insert_if_under_capacity`]: FlurryHashMap::insert_if_under_capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AtCapacity;
+
+impl std::fmt::Display for AtCapacity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "map is at capacity")
+    }
+}
+
+impl std::error::Error for AtCapacity {}
+
+type ValidatorFn<K, V> = dyn Fn(&K, &V) -> bool + Send + Sync;
+
+/// The validator a map was built with.
+pub(crate) struct Validator<K, V>(pub(crate) Box<ValidatorFn<K, V>>);
+
+impl<K, V> std::fmt::Debug for Validator<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Validator")
+    }
+}
+
+/// Decides when a map resizes its table.
+///
+/// Maps use [`LoadFactor`] unless they are built with another
+/// [`resize_policy`](builder::Builder::resize_policy). The other built-in policies are
+/// [`MaxChain`] and [`Adaptive`]. Closures of the same signature as
+/// [`should_resize`](ResizePolicy::should_resize) are policies too.
+pub trait ResizePolicy: Send + Sync {
+    /// Returns the number of bins the table should grow to, or `None` if it should stay as it
+    /// is.
+    ///
+    /// This is called after every insertion of a new key, with the number of entries in the map,
+    /// the number of bins in its table, and the length of the chain of the bin the key was
+    /// inserted into, which the inserting thread has just walked anyway. Finding the longest
+    /// chain of the whole table would mean visiting every bin, so a policy that bounds chain
+    /// lengths acts on whichever long chain an insertion runs into first. If the table is
+    /// resized in response, the policy is asked again about the new table, with a `chain_len`
+    /// of 1, since the chain has been split up in ways the inserting thread has not seen. The
+    /// map only ever grows, so targets no larger than `capacity` are ignored, and targets that
+    /// are not a power of two are rounded up to one. Because this is called so often, it should
+    /// be cheap.
+    fn should_resize(&self, len: usize, capacity: usize, chain_len: usize) -> Option<usize>;
+
+    /// Returns `true` if the map should watch how often writers wait for each other's bin
+    /// locks, and consult [`should_resize_contended`](ResizePolicy::should_resize_contended)
+    /// when they often do.
+    ///
+    /// Watching for contention makes every bin lock acquisition slightly more expensive, so
+    /// policies do not by default.
+    fn watches_contention(&self) -> bool {
+        false
+    }
+
+    /// Returns the number of bins the table should grow to because writers have had to wait for
+    /// each other's bin locks in a large share of the recent acquisitions, or `None` if it should
+    /// stay as it is.
+    ///
+    /// This is only called if [`watches_contention`](ResizePolicy::watches_contention) returns
+    /// `true`, with the number of entries in the map and the number of bins in its table, after
+    /// a write that found the contention. Targets are treated as in
+    /// [`should_resize`](ResizePolicy::should_resize).
+    fn should_resize_contended(&self, len: usize, capacity: usize) -> Option<usize> {
+        let _ = (len, capacity);
+        None
+    }
+}
+
+impl<F> ResizePolicy for F
+where
+    F: Fn(usize, usize, usize) -> Option<usize> + Send + Sync,
+{
+    fn should_resize(&self, len: usize, capacity: usize, chain_len: usize) -> Option<usize> {
+        self(len, capacity, chain_len)
+    }
+}
+
+/// Doubles the table once the map is three quarters full.
+///
+/// This is the resize policy of maps that are not built with another one. It keeps the average
+/// chain short, whatever the lengths of individual chains.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LoadFactor;
+
+impl ResizePolicy for LoadFactor {
+    fn should_resize(&self, len: usize, capacity: usize, _: usize) -> Option<usize> {
+        if len >= capacity - (capacity >> 2) {
+            Some(capacity << 1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Doubles the table whenever an insertion makes a chain longer than the given length.
+///
+/// The table is not grown for the number of entries alone, so a map with few collisions may
+/// become more than full. Doubling the table splits every chain in two on average, which does not
+/// help if the keys of a chain have the same hash, so the bound should allow for the hasher in
+/// use.
+#[derive(Debug, Clone, Copy)]
+pub struct MaxChain(pub usize);
+
+impl ResizePolicy for MaxChain {
+    fn should_resize(&self, _: usize, capacity: usize, chain_len: usize) -> Option<usize> {
+        if chain_len > self.0 {
+            Some(capacity << 1)
+        } else {
+            None
+        }
+    }
+}
+
+/// Resizes as another policy does, and also doubles the table before that if writers often wait
+/// for each other's bin locks while the map is at least a quarter full.
+///
+/// Resizing spreads the keys over more bins, and with them, the writers over more locks. In a
+/// sparser map, contention comes from writers to the same few keys, which no number of bins can
+/// separate. The state of the contention is reported by
+/// [`adaptive_state`](FlurryHashMap::adaptive_state). See also
+/// [`adaptive_resize`](builder::Builder::adaptive_resize).
+pub struct Adaptive(Box<dyn ResizePolicy>);
+
+impl Adaptive {
+    /// Resizes as `policy` does, and also when writers contend for locks.
+    pub fn new<P>(policy: P) -> Self
+    where
+        P: ResizePolicy + 'static,
+    {
+        Adaptive(Box::new(policy))
+    }
+}
+
+impl Default for Adaptive {
+    /// Resizes as [`LoadFactor`] does, and also when writers contend for locks.
+    fn default() -> Self {
+        Self::new(LoadFactor)
+    }
+}
+
+impl std::fmt::Debug for Adaptive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Adaptive")
+    }
+}
+
+impl ResizePolicy for Adaptive {
+    fn should_resize(&self, len: usize, capacity: usize, chain_len: usize) -> Option<usize> {
+        self.0.should_resize(len, capacity, chain_len)
+    }
+
+    fn watches_contention(&self) -> bool {
+        true
+    }
+
+    fn should_resize_contended(&self, len: usize, capacity: usize) -> Option<usize> {
+        // with few entries, the contention is over a few hot keys, which more bins will not
+        // spread out
+        if len < capacity / 4 {
+            None
+        } else {
+            Some(capacity << 1)
+        }
+    }
+}
 
-                        *link = Owned::new(BinEntry::Node(Node {
-                            hash: node.hash,
-                            key: node.key.clone(),
-                            lock: parking_lot::Mutex::new(()),
-                            value: node.value.clone(),
-                            next: Atomic::from(*link),
-                        }))
-                        .into_shared(guard);
+/// The resize policy of a map.
+pub(crate) struct Resizer(pub(crate) Box<dyn ResizePolicy>);
 
-                        p = node.next.load(Ordering::SeqCst, guard);
-                    }
+impl Default for Resizer {
+    fn default() -> Self {
+        Resizer(Box::new(LoadFactor))
+    }
+}
 
-                    next_table.store_bin(i, low_bin);
-                    next_table.store_bin(i + n, high_bin);
-                    table.store_bin(i, Owned::new(BinEntry::Moved(next_table as *const _)));
+impl std::fmt::Debug for Resizer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ResizePolicy")
+    }
+}
 
-                    // everything up to last_run in the _old_ bin linked list is now garbage.
-                    // those nodes have all been re-allocated in the new bin linked list.
-                    p = bin;
-                    while p != last_run {
-                        // safety:
-                        //
-                        // we need to argue that there is no longer a way to access p. the only way
-                        // to get to p is through table[i]. since table[i] has been replaced by a
-                        // BinEntry::Moved, p is no longer accessible.
-                        //
-                        // any existing reference to p must have been taken before table.store_bin.
-                        // at that time we had the epoch pinned, so any threads that have such a
-                        // reference must be before or at our epoch. since the p isn't destroyed
-                        // until the next epoch, those old references are fine since they are tied
-                        // to those old threads' pins of the old epoch.
-                        let next = unsafe { p.deref() }
-                            .as_node()
-                            .unwrap()
-                            .next
-                            .load(Ordering::SeqCst, guard);
-                        unsafe { guard.defer_destroy(p) };
-                        p = next;
-                    }
+/// What [`insert_outcome`] did.
+///
+/// [`insert_outcome`]: FlurryHashMap::insert_outcome
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome<T> {
+    /// The key was absent, and has been inserted.
+    Inserted {
+        /// Whether the map resized while the key was inserted.
+        resized: bool,
+    },
+    /// The key was present, and its value `old` has been replaced.
+    Replaced {
+        /// The value the key was mapped to before.
+        old: T,
+        /// Whether the map resized while the value was replaced.
+        resized: bool,
+    },
+}
 
-                    advance = true;
+impl<T> InsertOutcome<T> {
+    /// Returns the value that was replaced, if any.
+    pub fn old(self) -> Option<T> {
+        match self {
+            InsertOutcome::Inserted { .. } => None,
+            InsertOutcome::Replaced { old, .. } => Some(old),
+        }
+    }
 
-                    drop(head_lock);
-                }
+    /// Returns whether the map resized while the insertion was in progress.
+    pub fn resized(&self) -> bool {
+        match *self {
+            InsertOutcome::Inserted { resized } | InsertOutcome::Replaced { resized, .. } => {
+                resized
             }
         }
     }
+}
 
-    /// Returns the stamp bits for resizing a table of size n.
-    /// Must be negative when shifted left by RESIZE_STAMP_SHIFT.
-    fn resize_stamp(n: usize) -> isize {
-        n.leading_zeros() as isize | (1 << (RESIZE_STAMP_BITS - 1)) as isize
-    }
+/// How [`peek_eviction_candidate`] picks the entry that would be evicted next, and which entries
+/// a map with a [`soft_cap`] evicts.
+///
+/// Each policy other than `Random` is only available with the feature that records the metadata
+/// it relies on.
+///
+/// [`peek_eviction_candidate`]: FlurryHashMap::peek_eviction_candidate
+/// [`soft_cap`]: builder::Builder::soft_cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// An entry picked at random, by starting from a random bin. This needs no metadata, and
+    /// does not visit every entry.
+    Random,
+    /// The entry with the lowest priority, which is what
+    /// [`evict_lowest`](FlurryHashMap::evict_lowest) removes first. Requires the `priority`
+    /// feature.
+    #[cfg(feature = "priority")]
+    LowestPriority,
+    /// The entry whose value was last written longest ago. This approximates least recently
+    /// used, except that reads do not count as uses. Requires the `timestamps` feature.
+    #[cfg(feature = "timestamps")]
+    LeastRecentlyWritten,
+    /// The entry that was inserted longest ago, whether or not its value has been replaced
+    /// since. Requires the `timestamps` feature.
+    #[cfg(feature = "timestamps")]
+    OldestInsertion,
+}
 
-    /// An iterator visiting all key-value pairs in arbitrary order.
-    /// The iterator element type is `(&'g K, &'g V)`.
-    ///
-    /// To obtain a `Guard`, use [`epoch::pin`].
-    pub fn iter<'g>(&self, guard: &'g Guard) -> Iter<'g, K, V> {
-        let table = self.table.load(Ordering::SeqCst, guard);
-        let node_iter = NodeIter::new(table, guard);
-        Iter { node_iter, guard }
-    }
+/// What [`walk`] does with an entry, and whether it goes on to the next one.
+///
+/// [`walk`]: FlurryHashMap::walk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkAction<V> {
+    /// Leave the entry as it is, and go on.
+    Keep,
+    /// Map the key of the entry to the given value, and go on.
+    Replace(V),
+    /// Remove the entry, and go on.
+    Remove,
+    /// Leave the entry as it is, and stop the walk.
+    Stop,
+}
 
-    /// An iterator visiting all keys in arbitrary order.
-    /// The iterator element type is `&'g K`.
-    ///
-    /// To obtain a `Guard`, use [`epoch::pin`].
-    pub fn keys<'g>(&self, guard: &'g Guard) -> Keys<'g, K, V> {
-        let table = self.table.load(Ordering::SeqCst, guard);
-        let node_iter = NodeIter::new(table, guard);
-        Keys { node_iter }
+/// How far a scan of a map by [`iter_step`] has got.
+///
+/// A `Cursor` does not borrow from the map, so it can be kept across calls, and across guards.
+///
+/// [`iter_step`]: FlurryHashMap::iter_step
+#[derive(Debug, Default, Clone)]
+pub struct Cursor {
+    /// The number of bins the table had when the scan started, or 0 if it has not started.
+    bins: usize,
+    /// The bin of that table whose entries are being visited.
+    bin: usize,
+    /// The number of bins of the current table that hold entries of that bin, and that have
+    /// already been visited.
+    step: usize,
+    finished: bool,
+}
+
+impl Cursor {
+    /// Creates a cursor at the start of a scan.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    /// An iterator visiting all values in arbitrary order.
-    /// The iterator element type is `&'g V`.
-    ///
-    /// To obtain a `Guard`, use [`epoch::pin`].
-    pub fn values<'g>(&self, guard: &'g Guard) -> Values<'g, K, V> {
-        let table = self.table.load(Ordering::SeqCst, guard);
-        let node_iter = NodeIter::new(table, guard);
-        Values { node_iter, guard }
+    /// Returns `true` if the scan has visited every bin.
+    pub fn is_finished(&self) -> bool {
+        self.finished
     }
 }
 
@@ -1049,6 +5646,14 @@ impl<K, V, S> Drop for FlurryHashMap<K, V, S> {
         // safety: we have &mut self, so not concurrently accessed by anyone else
         let guard = unsafe { crossbeam::epoch::unprotected() };
 
+        let build_hasher = self
+            .build_hasher
+            .swap(Shared::null(), Ordering::SeqCst, guard);
+        if !build_hasher.is_null() {
+            // safety: same as above + we own the hash builder
+            drop(unsafe { build_hasher.into_owned() });
+        }
+
         assert!(self.next_table.load(Ordering::SeqCst, guard).is_null());
         let table = self.table.swap(Shared::null(), Ordering::SeqCst, guard);
         if table.is_null() {
@@ -1062,13 +5667,206 @@ impl<K, V, S> Drop for FlurryHashMap<K, V, S> {
     }
 }
 
+/// The key passed to `compute_locked`.
+enum KeyArg<'k, K> {
+    /// The key may be inserted if it is absent.
+    Owned(K),
+    /// The key will not be inserted if it is absent.
+    Borrowed(&'k K),
+}
+
+/// What the closure passed to `compute_locked` wants done with the key.
+enum Action<V> {
+    /// Leave the key as it is.
+    Keep,
+    /// Map the key to the given value.
+    Set(V),
+    /// Map the key to the given value, and give the entry the given priority.
+    #[cfg(feature = "priority")]
+    SetPrioritized(V, u64),
+    /// Remove the key, if it is present.
+    Remove,
+}
+
+/// What `compute_locked` ended up doing.
+enum Computed<'g, V> {
+    /// The key was absent, and is now mapped to the given value.
+    Inserted(&'g V),
+    /// The key was present, and is now mapped to the given value.
+    Replaced(&'g V),
+    /// The key was present with the given value, and has been removed.
+    Removed(&'g V),
+    /// The key was left as it was, with its current value, if any.
+    Unchanged(Option<&'g V>),
+}
+
+/// How far a pass of [`replicate_into`] has got.
+///
+/// A `ReplicationCursor` does not borrow from either map, so it can be kept across calls, and
+/// across guards.
+///
+/// [`replicate_into`]: FlurryHashMap::replicate_into
+#[derive(Debug, Default, Clone)]
+pub struct ReplicationCursor {
+    /// The number of bins the table had when the pass started, or 0 if no pass is under way.
+    bins: usize,
+    /// The bin of that table whose entries are being copied.
+    bin: usize,
+    /// The number of entries of that bin that have already been copied.
+    skipped: usize,
+}
+
+impl ReplicationCursor {
+    /// Creates a cursor at the start of a replication pass.
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// A held bin lock.
+struct BinLock<'a> {
+    _guard: parking_lot::MutexGuard<'a, ()>,
+    #[cfg(feature = "lock-stats")]
+    timing: (&'a stats::LockStats, Operation, std::time::Instant),
+}
+
+#[cfg(feature = "lock-stats")]
+impl Drop for BinLock<'_> {
+    fn drop(&mut self) {
+        let (stats, op, start) = self.timing;
+        stats.record(op, start.elapsed());
+    }
+}
+
+/// A node placed in an empty bin by `compute_locked` while its value is computed.
+///
+/// If this is dropped, no value was inserted, and the reservation is removed again.
+struct Reservation<'g, K, V> {
+    table: &'g Table<K, V>,
+    bini: usize,
+    node: Shared<'g, BinEntry<K, V>>,
+    guard: &'g Guard,
+}
+
+impl<K, V> Drop for Reservation<'_, K, V> {
+    fn drop(&mut self) {
+        // we hold the reservation's lock, so no other node can have been linked after it, and the
+        // bin cannot have been moved by a resize.
+        self.table.store_bin(self.bini, Shared::null());
+        // safety: the reservation is no longer reachable from the table. threads that can still
+        // see it must be pinned to an epoch <= ours, so it is not freed until they are done.
+        unsafe {
+            self.node.deref().as_node().unwrap().lock.force_unlock();
+            self.guard.defer_destroy(self.node);
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Table<K, V> {
     bins: Box<[Atomic<BinEntry<K, V>>]>,
 }
 
 impl<K, V> Table<K, V> {
+    /// Moves all the entries out of the table, leaving it without any bins.
+    fn take_entries(&mut self, len: usize) -> Vec<(K, V)> {
+        let mut entries = Vec::with_capacity(len);
+        self.drain(|key, value| entries.push((key, value)));
+        entries
+    }
+
+    /// Moves all the entries out of the table one by one into `f`, leaving it without any bins.
+    fn drain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(K, V),
+    {
+        // safety: we have &mut self, so not concurrently accessed by anyone else
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+
+        for bin in Vec::from(std::mem::replace(&mut self.bins, vec![].into_boxed_slice())) {
+            let mut p = bin.load(Ordering::SeqCst, guard);
+            while !p.is_null() {
+                // safety: same as above + we replaced the bins with an empty slice, so we own all
+                // the nodes, and no-one can reach them any more
+                let node = match *unsafe { p.into_owned() }.into_box() {
+                    BinEntry::Node(node) => node,
+                    BinEntry::Moved { .. } => break,
+                };
+                p = node.next.load(Ordering::SeqCst, guard);
+                // safety: same as above; the value is only ever owned by its node
+                let value = *unsafe { node.value.into_owned() }.into_box();
+                f(node.key, value);
+            }
+        }
+    }
+
+    /// Moves all the entries out of the table into a table of the same size, where each is in
+    /// the same bin as before, and its value is replaced by `f(key, value)`.
+    fn map_values<V2, F>(&mut self, f: F) -> Table<K, V2>
+    where
+        F: Fn(&K, &V) -> V2,
+    {
+        // safety: we have &mut self, so not concurrently accessed by anyone else
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+
+        let mapped = Table {
+            bins: vec![Atomic::null(); self.bins.len()].into_boxed_slice(),
+        };
+        let bins = Vec::from(std::mem::replace(&mut self.bins, vec![].into_boxed_slice()));
+        for (bin, mut link) in bins.into_iter().zip(mapped.bins.iter()) {
+            let mut p = bin.load(Ordering::SeqCst, guard);
+            while !p.is_null() {
+                // safety: as in drain
+                let node = match *unsafe { p.into_owned() }.into_box() {
+                    BinEntry::Node(node) => node,
+                    BinEntry::Moved { .. } => break,
+                };
+                p = node.next.load(Ordering::SeqCst, guard);
+                // safety: as in drain
+                let value = unsafe { node.value.into_owned() };
+                let value = f(&node.key, &value);
+
+                link.store(
+                    Owned::new(BinEntry::Node(Node {
+                        hash: node.hash,
+                        key: node.key,
+                        value: Atomic::new(value),
+                        next: Atomic::null(),
+                        swap_stamp: AtomicUsize::new(0),
+                        swapped: Atomic::null(),
+                        lock: parking_lot::Mutex::new(()),
+                        #[cfg(feature = "priority")]
+                        priority: node.priority,
+                        #[cfg(feature = "timestamps")]
+                        written: node.written,
+                        #[cfg(feature = "timestamps")]
+                        inserted: node.inserted,
+                        #[cfg(feature = "entry-stats")]
+                        counters: node.counters,
+                    })),
+                    Ordering::SeqCst,
+                );
+                // safety: we just stored the node, and own it
+                link = &unsafe { link.load(Ordering::SeqCst, guard).deref() }
+                    .as_node()
+                    .expect("we just stored a Node")
+                    .next;
+            }
+        }
+        mapped
+    }
+
     fn drop_bins(&mut self) {
+        self.drop_chains(true);
+    }
+
+    /// Like `drop_bins`, but leaves the values of the nodes alone, since they have been handed
+    /// over to the nodes of another table.
+    fn drop_nodes(&mut self) {
+        self.drop_chains(false);
+    }
+
+    fn drop_chains(&mut self, values: bool) {
         // safety: we have &mut self, so not concurrently accessed by anyone else
         let guard = unsafe { crossbeam::epoch::unprotected() };
 
@@ -1081,7 +5879,7 @@ impl<K, V> Table<K, V> {
             // safety: same as above + we own the bin
             let bin = unsafe { bin.into_owned() };
             match *bin {
-                BinEntry::Moved(_) => {}
+                BinEntry::Moved { .. } => {}
                 BinEntry::Node(_) => {
                     let mut p = bin;
                     loop {
@@ -1096,8 +5894,10 @@ impl<K, V> Table<K, V> {
                             unreachable!();
                         };
 
-                        // first, drop the value in this node
-                        let _ = unsafe { node.value.into_owned() };
+                        // first, drop the value in this node, if it is ours
+                        if values {
+                            let _ = unsafe { node.value.into_owned() };
+                        }
 
                         // then we move to the next node
                         if node.next.load(Ordering::SeqCst, guard).is_null() {
@@ -1128,7 +5928,7 @@ impl<K, V> Drop for Table<K, V> {
 
             // safety: we have mut access to self, so no-one else will drop this value under us.
             let bin = unsafe { bin.into_owned() };
-            if let BinEntry::Moved(_) = *bin {
+            if let BinEntry::Moved { .. } = *bin {
             } else {
                 unreachable!("dropped table with non-empty bin");
             }
@@ -1170,4 +5970,458 @@ impl<K, V> Table<K, V> {
 }
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconcile_len() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let guard = crossbeam::epoch::pin();
+        map.count.set(42, &guard);
+        assert_eq!(map.len(), 42);
+
+        assert_eq!(map.reconcile_len(&guard), 100);
+        assert_eq!(map.len(), 100);
+
+        map.count.add(7, &guard);
+        assert_eq!(map.reconcile_len(&guard), 100);
+        assert_eq!(map.len(), 100);
+    }
+
+    #[test]
+    fn compact_counters() {
+        use std::sync::{mpsc, Arc, Barrier};
+
+        const THREADS: usize = 8;
+        let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+        let exit = Arc::new(Barrier::new(THREADS + 1));
+        let (registered, wait) = mpsc::channel();
+        let mut threads = Vec::new();
+        for t in 0..THREADS {
+            let (map, exit, registered) = (map.clone(), exit.clone(), registered.clone());
+            threads.push(std::thread::spawn(move || {
+                for i in 0..100 {
+                    map.insert(t * 100 + i, i);
+                }
+                // as if the thread had collided with another one on the count
+                map.count.add_contended(0, &crossbeam::epoch::pin());
+                registered.send(()).unwrap();
+                exit.wait();
+            }));
+            // one thread at a time, so that none of them fail to take the cells' lock
+            wait.recv().unwrap();
+        }
+
+        let guard = crossbeam::epoch::pin();
+        assert_eq!(map.count.cells(&guard), THREADS);
+        assert_eq!(map.len(), THREADS * 100);
+
+        // all the threads are still running
+        map.compact_counters();
+        assert_eq!(map.count.cells(&guard), THREADS);
+        assert_eq!(map.len(), THREADS * 100);
+
+        exit.wait();
+        for t in threads {
+            t.join().unwrap();
+        }
+        map.compact_counters();
+        assert_eq!(map.count.cells(&guard), 0);
+        assert_eq!(map.len(), THREADS * 100);
+
+        // the count keeps working once it is no longer striped
+        map.remove(&0, &guard);
+        assert_eq!(map.len(), THREADS * 100 - 1);
+    }
+
+    #[test]
+    fn counter_cell_stats() {
+        use std::sync::{mpsc, Arc, Barrier};
+
+        const THREADS: usize = 8;
+        let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+        let stats = map.counter_cell_stats();
+        assert_eq!((stats.cells, stats.active_cells, stats.base), (0, 0, 0));
+
+        let (start, exit) = (
+            Arc::new(Barrier::new(THREADS + 1)),
+            Arc::new(Barrier::new(THREADS + 1)),
+        );
+        let (registered, wait) = mpsc::channel();
+        let mut threads = Vec::new();
+        for t in 0..THREADS {
+            let (map, registered) = (map.clone(), registered.clone());
+            let (start, exit) = (start.clone(), exit.clone());
+            threads.push(std::thread::spawn(move || {
+                // as if the thread had collided with another one on the count
+                map.count.add_contended(0, &crossbeam::epoch::pin());
+                registered.send(()).unwrap();
+                start.wait();
+                for i in 0..100 {
+                    map.insert(t * 100 + i, i);
+                }
+                exit.wait();
+            }));
+            // one thread at a time, so that none of them fail to take the cells' lock
+            wait.recv().unwrap();
+        }
+        start.wait();
+
+        // wait for the inserts, while all the threads are still running
+        while map.len() < THREADS * 100 {
+            std::thread::yield_now();
+        }
+        let stats = map.counter_cell_stats();
+        assert_eq!(stats.cells, THREADS);
+        // each thread counted its inserts in its own cell
+        assert!(stats.active_cells > 1, "{:?}", stats);
+        assert!(stats.collisions >= THREADS);
+        exit.wait();
+        for t in threads {
+            t.join().unwrap();
+        }
+        map.compact_counters();
+        let stats = map.counter_cell_stats();
+        assert_eq!((stats.cells, stats.active_cells), (0, 0));
+        assert_eq!(stats.base, (THREADS * 100) as isize);
+    }
+
+    #[test]
+    fn compact_counters_partially() {
+        let map = FlurryHashMap::<usize, usize>::new();
+        let guard = crossbeam::epoch::pin();
+        for t in 0..4 {
+            let map = &map;
+            crossbeam::thread::scope(|s| {
+                s.spawn(move |_| {
+                    map.insert(t, t);
+                    map.count.add_contended(0, &crossbeam::epoch::pin());
+                });
+            })
+            .unwrap();
+        }
+        // each thread exits before the next one contends, so only one cell is ever needed
+        assert_eq!(map.count.cells(&guard), 1);
+
+        map.count.add_contended(0, &guard);
+        map.count.add_contended(0, &guard);
+        map.insert(4, 4);
+        map.compact_counters();
+        // the current thread is still running
+        assert_eq!(map.count.cells(&guard), 1);
+        assert_eq!(map.len(), 5);
+    }
+
+    #[test]
+    fn approx_len_sampled() {
+        use std::sync::{mpsc, Arc, Barrier};
+
+        const THREADS: usize = 16;
+        let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+        let exit = Arc::new(Barrier::new(THREADS + 1));
+        let (registered, wait) = mpsc::channel();
+        let mut threads = Vec::new();
+        for t in 0..THREADS {
+            let (map, exit, registered) = (map.clone(), exit.clone(), registered.clone());
+            threads.push(std::thread::spawn(move || {
+                // as if the thread had collided with another one on the count
+                map.count.add_contended(0, &crossbeam::epoch::pin());
+                // a different number of inserts from each thread, so the cells differ
+                for i in 0..(t + 1) * 10 {
+                    map.insert(t * 1000 + i, i);
+                }
+                registered.send(()).unwrap();
+                exit.wait();
+            }));
+            // one thread at a time, so that none of them fail to take the cells' lock
+            wait.recv().unwrap();
+        }
+
+        let guard = crossbeam::epoch::pin();
+        let (len, approx) = (map.len(), map.approx_len(&guard));
+        assert_eq!(len, (1..=THREADS).sum::<usize>() * 10);
+        let cells = map.count.cell_counts(&guard);
+        assert_eq!(cells.len(), THREADS);
+        // every other cell is read, and counted twice
+        let k = THREADS / counter::SAMPLED_CELLS;
+        let base = map.counter_cell_stats().base;
+        let sampled: isize = cells.iter().step_by(k).sum();
+        assert_eq!(approx as isize, base + k as isize * sampled);
+        // within the documented bound
+        let counted: isize = cells.iter().map(|c| c.abs()).sum();
+        assert!(
+            (approx as isize - len as isize).abs() <= (k as isize - 1) * counted,
+            "{} vs {}",
+            approx,
+            len
+        );
+
+        // compacting moves the counts out of the cells, which makes the estimate exact again
+        map.compact_counters();
+        assert_eq!(map.approx_len(&guard), len);
+
+        exit.wait();
+        for t in threads {
+            t.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn compact_counters_while_counting() {
+        use std::sync::atomic::{AtomicBool, AtomicUsize};
+        use std::sync::Arc;
+
+        const ROUNDS: usize = 10_000;
+        let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+        // the number of inserts that have started, and that have finished
+        let (started, finished) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let done = Arc::new(AtomicBool::new(false));
+
+        let writer = {
+            let (map, started, finished) = (map.clone(), started.clone(), finished.clone());
+            std::thread::spawn(move || {
+                // as if the thread had collided with another one on the count, so that its
+                // inserts are counted in a cell, which is then moved into the base
+                map.count.add_contended(0, &crossbeam::epoch::pin());
+                for i in 0..ROUNDS {
+                    started.fetch_add(1, Ordering::SeqCst);
+                    map.insert(i, i);
+                    finished.fetch_add(1, Ordering::SeqCst);
+                    map.compact_counters();
+                }
+            })
+        };
+        let reader = {
+            let (map, done) = (map.clone(), done.clone());
+            std::thread::spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    let low = finished.load(Ordering::SeqCst);
+                    let len = map.len();
+                    let high = started.load(Ordering::SeqCst);
+                    // a compaction must not make the inserts it moves count twice
+                    assert!(low <= len && len <= high, "{} <= {} <= {}", low, len, high);
+                }
+            })
+        };
+        writer.join().unwrap();
+        done.store(true, Ordering::SeqCst);
+        reader.join().unwrap();
+        assert_eq!(map.len(), ROUNDS);
+    }
+
+    #[test]
+    fn resize_progress() {
+        use std::sync::Arc;
+        use std::time::{Duration, Instant};
+
+        let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+        for i in 0..10 {
+            map.insert(i, i);
+        }
+        let guard = crossbeam::epoch::pin();
+        let idle = map.resize_progress(&guard);
+        assert!(!idle.resizing);
+        assert_eq!(idle.bins, 16);
+        assert_eq!(idle.fraction(), 1.0);
+
+        // the resize moves bins from the top down, and has to wait for the lock of each
+        // non-empty one. holding the locks of some lets it through one of them at a time.
+        let table = unsafe { map.table.load(Ordering::SeqCst, &guard).deref() };
+        let held: Vec<_> = (0..16)
+            .rev()
+            .filter_map(|i| unsafe { table.bin(i, &guard).as_ref() }.map(|bin| (i, bin)))
+            .map(|(i, bin)| (i, bin.as_node().unwrap().lock.lock()))
+            .take(3)
+            .collect();
+
+        let resizer = {
+            let map = map.clone();
+            std::thread::spawn(move || map.reserve_concurrent(20, &crossbeam::epoch::pin()))
+        };
+
+        let mut last = idle;
+        last.transfer_index = 16;
+        for (stop, lock) in held {
+            // wait for the resize to get stuck at the next held lock, with every bin above it moved
+            let deadline = Instant::now() + Duration::from_secs(10);
+            let progress = loop {
+                let progress = map.resize_progress(&guard);
+                if progress.resizing && progress.moved_bins == 15 - stop {
+                    break progress;
+                }
+                assert!(Instant::now() < deadline, "resize did not progress");
+                std::thread::yield_now();
+            };
+            assert_eq!(progress.bins, 16);
+            assert!(progress.moved_bins >= last.moved_bins);
+            assert!(progress.fraction() >= last.fraction() || !last.resizing);
+            assert!(progress.fraction() < 1.0);
+            assert!(progress.transfer_index <= last.transfer_index);
+            assert!(progress.transfer_index <= stop);
+            let unmoved = (0..=stop)
+                .map(|i| unsafe { table.bin(i, &guard).as_ref() }.map_or(0, |_| 1))
+                .sum::<usize>();
+            assert!(progress.entries_in_old >= unmoved);
+            last = progress;
+            drop(lock);
+        }
+
+        resizer.join().unwrap();
+        let done = map.resize_progress(&guard);
+        assert!(!done.resizing);
+        assert!(done.bins >= 32);
+        assert_eq!(done.fraction(), 1.0);
+        assert_eq!(map.len(), 10);
+    }
+
+    #[test]
+    fn read_cache_hits() {
+        let map = FlurryHashMap::<usize, usize>::builder()
+            .read_cache()
+            .build();
+        for i in 0..100 {
+            map.insert(i, i);
+        }
+
+        let guard = crossbeam::epoch::pin();
+        let before = cache::hits();
+        for _ in 0..10 {
+            assert_eq!(map.get(&42, &guard), Some(&42));
+        }
+        // the first read misses and fills the cache
+        assert_eq!(cache::hits() - before, 9);
+
+        // replacing the value does not invalidate the entry, but must be seen
+        map.insert(42, 0);
+        assert_eq!(map.get(&42, &guard), Some(&0));
+        assert_eq!(cache::hits() - before, 10);
+    }
+
+    #[test]
+    fn read_cache_remove() {
+        let map = FlurryHashMap::<usize, usize>::builder()
+            .read_cache()
+            .build();
+        map.insert(1, 1);
+        map.insert(2, 2);
+
+        let guard = crossbeam::epoch::pin();
+        assert_eq!(map.get(&1, &guard), Some(&1));
+        assert_eq!(map.get(&1, &guard), Some(&1));
+        assert_eq!(map.remove(&1, &guard), Some(&1));
+        let before = cache::hits();
+        assert_eq!(map.get(&1, &guard), None);
+        assert_eq!(cache::hits(), before);
+
+        map.insert(1, 10);
+        assert_eq!(map.get(&1, &guard), Some(&10));
+        assert_eq!(map.get(&1, &guard), Some(&10));
+        assert_eq!(cache::hits(), before + 1);
+    }
+
+    #[test]
+    fn read_cache_resize() {
+        let map = FlurryHashMap::<usize, usize>::builder()
+            .read_cache()
+            .build();
+        map.insert(0, 0);
+
+        let guard = crossbeam::epoch::pin();
+        assert_eq!(map.get(&0, &guard), Some(&0));
+        // grow the table a few times over, which copies the cached node
+        for i in 1..1000 {
+            map.insert(i, i);
+        }
+        map.insert(0, 1);
+        assert_eq!(map.get(&0, &guard), Some(&1));
+        assert_eq!(map.get(&0, &guard), Some(&1));
+    }
+
+    #[test]
+    fn bloom_filter() {
+        let map = FlurryHashMap::<usize, usize>::builder()
+            .bloom_filter(1000)
+            .build();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        let guard = crossbeam::epoch::pin();
+        let before = bloom::rejections();
+        for i in 0..1000 {
+            assert_eq!(map.get(&i, &guard), Some(&i));
+        }
+        // present keys always go to the table
+        assert_eq!(bloom::rejections(), before);
+
+        for i in 1000..11_000 {
+            assert_eq!(map.get(&i, &guard), None);
+        }
+        // all but the false positives never touch the table
+        assert!(bloom::rejections() - before > 9_500);
+        assert!(!map.contains_key(&1_000_000));
+    }
+
+    #[test]
+    fn bloom_filter_rebuild() {
+        let map = FlurryHashMap::<usize, usize>::builder()
+            .bloom_filter(100)
+            .build();
+        for i in 0..1000 {
+            map.insert(i, i);
+        }
+
+        let guard = crossbeam::epoch::pin();
+        // far beyond what it was sized for, the filter rarely tells anything apart
+        let before = bloom::rejections();
+        for i in 1000..2000 {
+            assert_eq!(map.get(&i, &guard), None);
+        }
+        assert!(bloom::rejections() - before < 500);
+
+        // removed keys linger in the filter until it is rebuilt
+        for i in 100..1000 {
+            map.remove(&i, &guard);
+        }
+        map.rebuild_filter(&guard);
+        let before = bloom::rejections();
+        for i in 100..2000 {
+            assert_eq!(map.get(&i, &guard), None);
+        }
+        assert!(bloom::rejections() - before > 1_800);
+        for i in 0..100 {
+            assert_eq!(map.get(&i, &guard), Some(&i));
+        }
+    }
+
+    #[test]
+    fn bloom_filter_rebuild_concurrent() {
+        let map = std::sync::Arc::new(
+            FlurryHashMap::<usize, usize>::builder()
+                .bloom_filter(16)
+                .build(),
+        );
+        let writer = {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..10_000 {
+                    map.insert(i, i);
+                }
+            })
+        };
+        let guard = crossbeam::epoch::pin();
+        for _ in 0..100 {
+            map.rebuild_filter(&guard);
+        }
+        writer.join().unwrap();
+
+        // no insertion slipped past a rebuild
+        for i in 0..10_000 {
+            assert_eq!(map.get(&i, &guard), Some(&i));
+        }
+    }
+}
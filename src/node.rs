@@ -1,7 +1,47 @@
 use super::Table;
-use crossbeam::epoch::{Atomic, Guard, Shared};
+use crossbeam::epoch::{Atomic, Guard, Owned, Shared};
 use parking_lot::Mutex;
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the bins that have been moved by resizes, of any map, to order moves against the
+/// creation of iterators.
+static MOVES: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the stamp that the next bin moved by a resize will get.
+///
+/// A bin whose `Moved` entry has a stamp at least as large as this was moved after this was
+/// called, so a thread that was pinned before calling this may still walk its old nodes.
+pub(crate) fn next_move_stamp() -> usize {
+    MOVES.load(Ordering::SeqCst)
+}
+
+/// Counts the calls to `swap_values`, of any map, to order them against the creation of
+/// iterators. Starts at 1, so that no iterator is older than a node that was never swapped.
+static SWAPS: AtomicUsize = AtomicUsize::new(1);
+
+/// The `swap_stamp` of a node while `swap_values` is changing its value.
+const SWAPPING: usize = usize::MAX;
+
+/// Returns the stamp that the next `swap_values` will get.
+///
+/// A thread that was pinned before calling this may use the `Swapped` records of the swaps with a
+/// stamp at least as large as this, see `Node::value_since`.
+pub(crate) fn next_swap_stamp() -> usize {
+    SWAPS.load(Ordering::SeqCst)
+}
+
+/// What the value of a node was before a `swap_values` replaced it.
+#[derive(Debug)]
+pub(crate) struct Swapped<V> {
+    value: *const V,
+    /// The record of the swap before, and the stamp of that swap, or 0 if there was none.
+    prev: *const Swapped<V>,
+    prev_stamp: usize,
+}
+
+unsafe impl<V> Send for Swapped<V> where V: Send {}
+
+unsafe impl<V> Sync for Swapped<V> where V: Sync {}
 
 /// Entry in a bin.
 ///
@@ -9,7 +49,17 @@ use std::sync::atomic::Ordering;
 #[derive(Debug)]
 pub(crate) enum BinEntry<K, V> {
     Node(Node<K, V>),
-    Moved(*const Table<K, V>),
+    /// A bin whose nodes a resize has moved to `next_table`.
+    Moved {
+        next_table: *const Table<K, V>,
+        /// The first of the nodes the bin had when it was moved, or null if it was empty.
+        ///
+        /// The nodes are retired when the bin is moved, so only threads pinned before `stamp`
+        /// was handed out may walk them.
+        old_head: *const BinEntry<K, V>,
+        /// When the bin was moved, see `next_move_stamp`.
+        stamp: usize,
+    },
 }
 
 unsafe impl<K, V> Send for BinEntry<K, V>
@@ -31,6 +81,16 @@ where
 }
 
 impl<K, V> BinEntry<K, V> {
+    /// Makes the entry that replaces a bin whose nodes, starting at `old_head`, have been moved
+    /// to `next_table`.
+    pub(crate) fn moved(next_table: *const Table<K, V>, old_head: *const BinEntry<K, V>) -> Self {
+        BinEntry::Moved {
+            next_table,
+            old_head,
+            stamp: MOVES.fetch_add(1, Ordering::SeqCst),
+        }
+    }
+
     pub(crate) fn as_node(&self) -> Option<&Node<K, V>> {
         if let BinEntry::Node(ref n) = *self {
             Some(n)
@@ -61,7 +121,7 @@ where
                     };
 
                     if n.hash == hash && &n.key == key {
-                        break Shared::from(self as *const _);
+                        break Shared::from(node as *const _);
                     }
                     let next = n.next.load(Ordering::SeqCst, guard);
                     if next.is_null() {
@@ -72,7 +132,7 @@ where
                     node = unsafe { next.deref() };
                 }
             }
-            BinEntry::Moved(next_table) => {
+            BinEntry::Moved { next_table, .. } => {
                 // safety: We have a reference to the old table, otherwise we wouldn't have a reference to
                 // self. We got that under the given Guard. Since we have not yet dropped that
                 // guard, _this_ table has not been garbage collected, and so the _later_ table in
@@ -93,7 +153,7 @@ where
 
                     match *bin {
                         BinEntry::Node(_) => break bin.find(hash, key, guard),
-                        BinEntry::Moved(next_table) => {
+                        BinEntry::Moved { next_table, .. } => {
                             // safety: same as above.
                             table = unsafe { &*next_table };
                             continue;
@@ -113,4 +173,107 @@ pub(crate) struct Node<K, V> {
     pub(crate) value: Atomic<V>,
     pub(crate) next: Atomic<BinEntry<K, V>>,
     pub(crate) lock: Mutex<()>,
+    /// Entries with lower priorities are evicted first by `evict_lowest`.
+    #[cfg(feature = "priority")]
+    pub(crate) priority: std::sync::atomic::AtomicU64,
+    /// When the value of the entry was last written, for `iter_older_than`.
+    #[cfg(feature = "timestamps")]
+    pub(crate) written: crossbeam::atomic::AtomicCell<std::time::Instant>,
+    /// When the entry was inserted, for `peek_eviction_candidate`.
+    #[cfg(feature = "timestamps")]
+    pub(crate) inserted: std::time::Instant,
+    /// How often the entry has been read and written, for `entry_stats`.
+    #[cfg(feature = "entry-stats")]
+    pub(crate) counters: crate::stats::EntryCounters,
+    /// The stamp of the last `swap_values` that changed the value, 0 if none has, or `SWAPPING`
+    /// while one does.
+    pub(crate) swap_stamp: AtomicUsize,
+    /// What the value was before the last `swap_values` that changed it. The record is retired as
+    /// soon as it is made, so only threads pinned before `swap_stamp` was handed out may read it.
+    pub(crate) swapped: Atomic<Swapped<V>>,
+}
+
+impl<K, V> Node<K, V> {
+    /// Records that the value of the entry was just written.
+    ///
+    /// Does nothing unless the `timestamps` or `entry-stats` feature is enabled.
+    #[inline]
+    pub(crate) fn touch(&self) {
+        #[cfg(feature = "timestamps")]
+        self.written.store(std::time::Instant::now());
+        #[cfg(feature = "entry-stats")]
+        self.counters.write();
+    }
+
+    /// Returns the value, waiting for a `swap_values` that is changing it to finish first.
+    pub(crate) fn load_value<'g>(&self, guard: &'g Guard) -> Shared<'g, V> {
+        // stamps never get this large, so no swap is ever undone
+        self.value_since(SWAPPING, guard)
+    }
+
+    /// Returns the value as it was before the `swap_values` that have changed it since `since`
+    /// was returned by `next_swap_stamp`, waiting for one that is changing it to finish first.
+    ///
+    /// Other writes to the value since then are not undone. `guard` must have been pinned before
+    /// `since` was read.
+    pub(crate) fn value_since<'g>(&self, since: usize, guard: &'g Guard) -> Shared<'g, V> {
+        loop {
+            let stamp = self.swap_stamp.load(Ordering::SeqCst);
+            if stamp == SWAPPING {
+                std::thread::yield_now();
+                continue;
+            }
+            let mut value = self.value.load(Ordering::SeqCst, guard);
+            if stamp >= since {
+                let mut swapped = self.swapped.load(Ordering::SeqCst, guard).as_raw();
+                loop {
+                    // safety: the record was retired when its swap was made, which was after
+                    // `since` was handed out, and so after the guard was pinned. if the record has
+                    // been replaced since we read `stamp`, we only look at it and then retry below.
+                    let s = unsafe { &*swapped };
+                    value = Shared::from(s.value);
+                    if s.prev_stamp < since {
+                        break;
+                    }
+                    swapped = s.prev;
+                }
+            }
+            if self.swap_stamp.load(Ordering::SeqCst) == stamp {
+                return value;
+            }
+        }
+    }
+
+    /// Exchanges the values of `self` and `other`, so that `value_since` still returns the values
+    /// from before for threads that read `next_swap_stamp` before. The locks of both nodes' bins
+    /// must be held.
+    pub(crate) fn swap_value_with(&self, other: &Self, guard: &Guard) {
+        let prev_stamps = [
+            self.swap_stamp.swap(SWAPPING, Ordering::SeqCst),
+            other.swap_stamp.swap(SWAPPING, Ordering::SeqCst),
+        ];
+        let stamp = SWAPS.fetch_add(1, Ordering::SeqCst);
+        let values = [
+            self.value.load(Ordering::SeqCst, guard),
+            other.value.load(Ordering::SeqCst, guard),
+        ];
+        for (i, node) in [self, other].iter().enumerate() {
+            let swapped = Owned::new(Swapped {
+                value: values[i].as_raw(),
+                prev: node.swapped.load(Ordering::SeqCst, guard).as_raw(),
+                prev_stamp: prev_stamps[i],
+            })
+            .into_shared(guard);
+            node.swapped.store(swapped, Ordering::SeqCst);
+            // safety: the record is only read by threads that were pinned before `stamp` was
+            // handed out, which is before now.
+            unsafe { guard.defer_destroy(swapped) };
+        }
+        // both nodes are linked, so neither value may ever be null: readers that find a node go on
+        // to dereference its value.
+        self.value.store(values[1], Ordering::SeqCst);
+        other.value.store(values[0], Ordering::SeqCst);
+        self.swap_stamp.store(stamp, Ordering::SeqCst);
+        other.swap_stamp.store(stamp, Ordering::SeqCst);
+    }
 }
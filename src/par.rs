@@ -0,0 +1,132 @@
+use crate::iter::NodeIter;
+use crate::FlurryHashMap;
+use crossbeam::epoch::Guard;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// The number of bins each parallel task visits at least.
+const MIN_BINS_PER_TASK: usize = 16;
+
+impl<K, V, S> FlurryHashMap<K, V, S>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+    S: BuildHasher,
+{
+    /// Groups the entries of the map by `key_fn`, maps each entry to a `T` with `map_fn`, and
+    /// reduces the `T`s of each group to a single value with `reduce_fn`, all in parallel.
+    ///
+    /// The bins of the map are split into ranges that are processed on the [`rayon`] thread
+    /// pool, each into its own `HashMap` of partial results, and those are then merged with
+    /// `reduce_fn` as well. `reduce_fn` should therefore be associative and commutative, since
+    /// the order in which values are combined is unspecified.
+    ///
+    /// If the map is modified concurrently, entries that are inserted or removed during the call
+    /// may or may not be included, just as with [`iter`](FlurryHashMap::iter).
+    ///
+    /// This method is only available with the `rayon` feature enabled.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    ///
+    /// [`rayon`]: https://docs.rs/rayon
+    pub fn par_group_reduce<B, T, KF, TF, RF>(
+        &self,
+        key_fn: KF,
+        map_fn: TF,
+        reduce_fn: RF,
+        guard: &Guard,
+    ) -> HashMap<B, T>
+    where
+        B: Hash + Eq + Send,
+        T: Send,
+        KF: Fn(&K, &V) -> B + Sync,
+        TF: Fn(&K, &V) -> T + Sync,
+        RF: Fn(T, T) -> T + Sync,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if table.is_null() {
+            return HashMap::new();
+        }
+        // safety: the table is not dropped until after `guard` is, and since `guard` stays pinned
+        // for the whole call, neither is anything the tasks read from it.
+        let table = unsafe { table.deref() };
+        let n = table.bins.len();
+        let chunk = std::cmp::max(
+            MIN_BINS_PER_TASK,
+            n / (4 * rayon::current_num_threads()).max(1),
+        );
+
+        let insert = |groups: &mut HashMap<B, T>, b: B, t: T| {
+            let t = match groups.remove(&b) {
+                Some(acc) => reduce_fn(acc, t),
+                None => t,
+            };
+            groups.insert(b, t);
+        };
+
+        (0..(n + chunk - 1) / chunk)
+            .into_par_iter()
+            .fold(HashMap::new, |mut groups, i| {
+                // every task needs a guard of its own, since guards cannot be shared between
+                // threads. the caller's guard is what keeps the entries alive, though.
+                let guard = crossbeam::epoch::pin();
+                for node in NodeIter::with_range(table, i * chunk, (i + 1) * chunk, &guard) {
+                    let value = node.value.load(Ordering::SeqCst, &guard);
+                    // safety: NodeIter only yields nodes whose value is not null, and values are
+                    // only dropped in the epoch after they have been replaced.
+                    let value = unsafe { value.deref() };
+                    insert(
+                        &mut groups,
+                        key_fn(&node.key, value),
+                        map_fn(&node.key, value),
+                    );
+                }
+                groups
+            })
+            .reduce(HashMap::new, |mut groups, other| {
+                for (b, t) in other {
+                    insert(&mut groups, b, t);
+                }
+                groups
+            })
+    }
+
+    /// The parallel implementation of [`any`](FlurryHashMap::any).
+    pub(crate) fn par_any<F>(&self, f: F, guard: &Guard) -> bool
+    where
+        F: Fn(&K, &V) -> bool + Sync,
+    {
+        let table = self.table.load(Ordering::SeqCst, guard);
+        if table.is_null() {
+            return false;
+        }
+        // safety: as in par_group_reduce
+        let table = unsafe { table.deref() };
+        let n = table.bins.len();
+        let chunk = std::cmp::max(
+            MIN_BINS_PER_TASK,
+            n / (4 * rayon::current_num_threads()).max(1),
+        );
+
+        let found = AtomicBool::new(false);
+        (0..(n + chunk - 1) / chunk).into_par_iter().for_each(|i| {
+            // as in par_group_reduce
+            let guard = crossbeam::epoch::pin();
+            for node in NodeIter::with_range(table, i * chunk, (i + 1) * chunk, &guard) {
+                if found.load(Ordering::Relaxed) {
+                    // another task found a match
+                    return;
+                }
+                // safety: as in par_group_reduce
+                let value = unsafe { node.value.load(Ordering::SeqCst, &guard).deref() };
+                if f(&node.key, value) {
+                    found.store(true, Ordering::Relaxed);
+                    return;
+                }
+            }
+        });
+        found.into_inner()
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// The average number of keys per bucket. Larger buckets make the pilot table smaller, but take
+/// longer to place.
+const KEYS_PER_BUCKET: usize = 4;
+
+/// The number of seeds tried before giving up on telling the keys apart. With a hasher that
+/// spreads the keys well, even the first seed almost always works.
+const MAX_SEEDS: u64 = 32;
+
+/// An immutable map whose keys are placed by a minimal perfect hash function, so that every key
+/// has a slot of its own and a lookup is a single probe.
+///
+/// A `PerfectMap` is created by the [`freeze_perfect`](crate::FlurryHashMap::freeze_perfect)
+/// method on [`FlurryHashMap`](crate::FlurryHashMap).
+///
+/// The keys are hashed into buckets, and each bucket is given a _pilot_ that, mixed into the hash
+/// of each of its keys, moves them to slots that no other key has taken. A lookup hashes the key,
+/// mixes in the pilot of its bucket, and compares against the one entry in the resulting slot.
+#[derive(Debug)]
+pub struct PerfectMap<K, V, S = RandomState> {
+    entries: Box<[(K, V)]>,
+    pilots: Box<[u32]>,
+    seed: u64,
+    build_hasher: S,
+}
+
+impl<K, V, S> PerfectMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    /// Places `entries`, whose keys must all be distinct.
+    ///
+    /// # Panics
+    ///
+    /// If `build_hasher` gives some of the keys the same hash for every seed mixed into it.
+    pub(crate) fn build(entries: Vec<(K, V)>, build_hasher: S) -> Self {
+        let n = entries.len();
+        let buckets = std::cmp::max(1, (n + KEYS_PER_BUCKET - 1) / KEYS_PER_BUCKET);
+        for seed in 0..MAX_SEEDS {
+            let hashes: Vec<u64> = entries
+                .iter()
+                .map(|(key, _)| hash(&build_hasher, seed, key))
+                .collect();
+            let (pilots, slots) = match place(&hashes, buckets) {
+                Some(placed) => placed,
+                // some bucket could not be placed with these hashes; try with different ones
+                None => continue,
+            };
+
+            let mut placed: Vec<Option<(K, V)>> = (0..n).map(|_| None).collect();
+            for (entry, slot) in entries.into_iter().zip(slots) {
+                placed[slot] = Some(entry);
+            }
+            return PerfectMap {
+                entries: placed.into_iter().map(Option::unwrap).collect(),
+                pilots: pilots.into_boxed_slice(),
+                seed,
+                build_hasher,
+            };
+        }
+        panic!(
+            "the hash builder cannot tell the keys apart: some of them hashed the same for all {} \
+             seeds tried",
+            MAX_SEEDS
+        );
+    }
+
+    /// Returns the slot of the entry for `key`, if there is one.
+    ///
+    /// The slots of the keys of the map are exactly the indices into
+    /// [`entries`](PerfectMap::entries).
+    pub fn index_of(&self, key: &K) -> Option<usize> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let h = hash(&self.build_hasher, self.seed, key);
+        let pilot = self.pilots[bucket(h, self.pilots.len())];
+        let i = slot(h, pilot, self.entries.len());
+        if self.entries[i].0 == *key {
+            Some(i)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value to which `key` is mapped.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.index_of(key).map(|i| &self.entries[i].1)
+    }
+
+    /// Tests if `key` is a key in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.index_of(key).is_some()
+    }
+}
+
+impl<K, V, S> PerfectMap<K, V, S> {
+    /// Returns the entries of the map, each in its slot.
+    pub fn entries(&self) -> &[(K, V)] {
+        &self.entries
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[allow(clippy::manual_hash_one)]
+fn hash<K: Hash, S: BuildHasher>(build_hasher: &S, seed: u64, key: &K) -> u64 {
+    let mut h = build_hasher.build_hasher();
+    seed.hash(&mut h);
+    key.hash(&mut h);
+    h.finish()
+}
+
+fn bucket(hash: u64, buckets: usize) -> usize {
+    // the high bits pick the bucket, and are not used again by slot
+    ((hash >> 32) % buckets as u64) as usize
+}
+
+fn slot(hash: u64, pilot: u32, slots: usize) -> usize {
+    // the finalizer of splitmix64, so that consecutive pilots move a key to unrelated slots
+    let mut z = (hash & 0xffff_ffff) ^ u64::from(pilot).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z % slots as u64) as usize
+}
+
+/// Finds a pilot for every bucket, such that the keys with the given hashes all end up in
+/// distinct slots. Returns the pilots, and the slot of each key.
+fn place(hashes: &[u64], buckets: usize) -> Option<(Vec<u32>, Vec<usize>)> {
+    let n = hashes.len();
+    let mut members = vec![Vec::new(); buckets];
+    for (i, &h) in hashes.iter().enumerate() {
+        members[bucket(h, buckets)].push(i);
+    }
+    // the largest buckets are the hardest to place, so they go while most slots are still free
+    let mut order: Vec<usize> = (0..buckets).collect();
+    order.sort_by_key(|&b| std::cmp::Reverse(members[b].len()));
+
+    // a key that shares its hash with another key of its bucket can never be placed, so give up
+    // on these hashes once a bucket has tried enough pilots that it should have found one
+    let max_pilot = std::cmp::min(u32::MAX as usize, 64 * n + 1024) as u32;
+    let mut taken = vec![false; n];
+    let mut slots = vec![0; n];
+    let mut pilots = vec![0; buckets];
+    let mut candidate = Vec::with_capacity(KEYS_PER_BUCKET);
+    for b in order {
+        let found = (0..max_pilot).find(|&pilot| {
+            candidate.clear();
+            for &i in &members[b] {
+                let s = slot(hashes[i], pilot, n);
+                if taken[s] || candidate.contains(&s) {
+                    return false;
+                }
+                candidate.push(s);
+            }
+            true
+        })?;
+        pilots[b] = found;
+        for (&i, &s) in members[b].iter().zip(&candidate) {
+            taken[s] = true;
+            slots[i] = s;
+        }
+    }
+    Some((pilots, slots))
+}
@@ -0,0 +1,123 @@
+use crate::{BinEntry, Node, Table};
+use crossbeam::epoch::{Atomic, Owned};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The array of bins backing a [`FlurryHashMap`](crate::FlurryHashMap), detached from any map.
+///
+/// A `RawTable` is obtained from an existing map through
+/// [`into_raw_parts`](crate::FlurryHashMap::into_raw_parts), or built up directly using
+/// [`with_bins`](RawTable::with_bins) and [`insert`](RawTable::insert). It can then be turned
+/// into a map with [`from_raw_parts`](crate::FlurryHashMap::from_raw_parts) without re-hashing or
+/// re-allocating any of the entries.
+///
+/// The entries of a `RawTable` are placed in bins according to the hash of their keys under a
+/// particular `BuildHasher`. The same `BuildHasher` must be given when the table is turned into a
+/// map, or lookups in that map will fail to find entries. As with a `Hash` implementation that
+/// does not match `Eq`, this is a logic error rather than undefined behavior.
+#[derive(Debug)]
+pub struct RawTable<K, V> {
+    pub(crate) table: Table<K, V>,
+    pub(crate) len: usize,
+}
+
+impl<K, V> RawTable<K, V> {
+    /// Creates an empty table with `bins` bins.
+    ///
+    /// # Panics
+    ///
+    /// If `bins` is not a power of two.
+    pub fn with_bins(bins: usize) -> Self {
+        assert!(bins.is_power_of_two());
+        RawTable {
+            table: Table {
+                bins: vec![Atomic::null(); bins].into_boxed_slice(),
+            },
+            len: 0,
+        }
+    }
+
+    /// Returns the number of bins in this table.
+    pub fn bins(&self) -> usize {
+        self.table.bins.len()
+    }
+
+    /// Returns the number of entries in this table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the table contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<K, V> RawTable<K, V>
+where
+    K: Hash + Eq,
+{
+    /// Places `key` and `value` in the bin given by the hash of `key` under `build_hasher`.
+    ///
+    /// If the table already contains `key`, its value is replaced, and `false` is returned.
+    ///
+    /// # Panics
+    ///
+    /// If the table has no bins.
+    #[allow(clippy::manual_hash_one)]
+    pub fn insert<S: BuildHasher>(&mut self, key: K, value: V, build_hasher: &S) -> bool {
+        assert!(!self.table.bins.is_empty());
+        let mut h = build_hasher.build_hasher();
+        key.hash(&mut h);
+        let hash = h.finish();
+
+        // safety: we have &mut self, so nothing else is accessing the table
+        let guard = unsafe { crossbeam::epoch::unprotected() };
+
+        let mut link = &self.table.bins[self.table.bini(hash)];
+        loop {
+            let p = link.load(Ordering::SeqCst, guard);
+            if p.is_null() {
+                break;
+            }
+            // safety: bins of a RawTable only ever hold nodes that it owns
+            let n = unsafe { p.deref() }.as_node().unwrap();
+            if n.hash == hash && n.key == key {
+                let old = n.value.swap(Owned::new(value), Ordering::SeqCst, guard);
+                // safety: same as above; we own the old value, and no-one else can observe it
+                drop(unsafe { old.into_owned() });
+                return false;
+            }
+            link = &n.next;
+        }
+
+        link.store(
+            Owned::new(BinEntry::Node(Node {
+                hash,
+                key,
+                value: Atomic::new(value),
+                next: Atomic::null(),
+                swap_stamp: AtomicUsize::new(0),
+                swapped: Atomic::null(),
+                lock: parking_lot::Mutex::new(()),
+                #[cfg(feature = "priority")]
+                priority: std::sync::atomic::AtomicU64::new(0),
+                #[cfg(feature = "timestamps")]
+                written: crossbeam::atomic::AtomicCell::new(std::time::Instant::now()),
+                #[cfg(feature = "timestamps")]
+                inserted: std::time::Instant::now(),
+                #[cfg(feature = "entry-stats")]
+                counters: crate::stats::EntryCounters::new(1),
+            })),
+            Ordering::SeqCst,
+        );
+        self.len += 1;
+        true
+    }
+}
+
+impl<K, V> Drop for RawTable<K, V> {
+    fn drop(&mut self) {
+        self.table.drop_bins();
+    }
+}
@@ -0,0 +1,106 @@
+use crate::iter::Keys;
+use crate::FlurryHashMap;
+use crossbeam::epoch::Guard;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash};
+
+/// A concurrent set, backed by a [`FlurryHashMap`] whose values are `()`.
+///
+/// It supports the same concurrent operations as the map it is backed by, with the same
+/// guarantees.
+#[derive(Debug)]
+pub struct FlurryHashSet<T, S = RandomState> {
+    map: FlurryHashMap<T, (), S>,
+}
+
+impl<T> Default for FlurryHashSet<T, RandomState>
+where
+    T: Sync + Send + Clone + Hash + Eq,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> FlurryHashSet<T, RandomState>
+where
+    T: Sync + Send + Clone + Hash + Eq,
+{
+    /// Creates a new, empty set.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<T, S> FlurryHashSet<T, S>
+where
+    T: Sync + Send + Clone + Hash + Eq,
+    S: BuildHasher,
+{
+    /// Creates a new, empty set, which will use the given hash builder to hash elements.
+    pub fn with_hasher(build_hasher: S) -> Self {
+        FlurryHashSet {
+            map: FlurryHashMap::with_hasher(build_hasher),
+        }
+    }
+
+    /// Adds `value` to the set, and returns `true` if it was not in the set yet.
+    pub fn insert(&self, value: T) -> bool {
+        self.map.insert(value, ()).is_none()
+    }
+
+    /// Tests if `value` is in the set.
+    pub fn contains(&self, value: &T) -> bool {
+        self.map.contains_key(value)
+    }
+
+    /// Removes `value` from the set, and returns `true` if it was in the set.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    pub fn remove(&self, value: &T, guard: &Guard) -> bool {
+        self.map.remove(value, guard).is_some()
+    }
+
+    /// Returns the number of elements in the set.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if the set contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// An iterator visiting all elements in arbitrary order.
+    /// The iterator element type is `&'g T`.
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    pub fn iter<'g>(&self, guard: &'g Guard) -> Keys<'g, T, ()> {
+        self.map.keys(guard)
+    }
+
+    /// Adds every element of this set that is not in `other` to `dst`, in a single pass over
+    /// this set.
+    ///
+    /// Elements are cloned into `dst` one at a time as they are found, without collecting them
+    /// first. If either set is modified concurrently, elements that are added to or removed from
+    /// it during the call may or may not be taken into account, just as with
+    /// [`iter`](FlurryHashSet::iter).
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    pub fn difference_into<S2, S3>(
+        &self,
+        other: &FlurryHashSet<T, S2>,
+        dst: &FlurryHashSet<T, S3>,
+        guard: &Guard,
+    ) where
+        S2: BuildHasher,
+        S3: BuildHasher,
+    {
+        for value in self.iter(guard) {
+            if other.map.get(value, guard).is_none() {
+                dst.map.put(value.clone(), (), false, guard);
+            }
+        }
+    }
+}
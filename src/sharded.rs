@@ -0,0 +1,143 @@
+use crate::FlurryHashMap;
+use crossbeam::epoch::Guard;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hash, Hasher};
+
+/// A map made up of `N` independent [`FlurryHashMap`]s, each holding the keys whose hashes fall
+/// into its shard.
+///
+/// Every shard has its own entry count and resizes on its own, so threads that operate on
+/// different shards never contend on either. On machines with very many cores, this can make
+/// write-heavy workloads scale better than a single map does. The price is that operations that
+/// concern the whole map, such as [`len`](ShardedMap::len), have to visit every shard.
+///
+/// Keys are routed to shards by the high bits of their hash, while each shard places keys in its
+/// bins by the low bits, so the keys of a shard are still spread evenly over its bins.
+pub struct ShardedMap<K, V, S, const N: usize> {
+    shards: Box<[FlurryHashMap<K, V, S>]>,
+    build_hasher: S,
+}
+
+impl<K, V, S, const N: usize> std::fmt::Debug for ShardedMap<K, V, S, N>
+where
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    S: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShardedMap")
+            .field("shards", &self.shards)
+            .field("build_hasher", &self.build_hasher)
+            .finish()
+    }
+}
+
+impl<K, V, const N: usize> Default for ShardedMap<K, V, RandomState, N>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, const N: usize> ShardedMap<K, V, RandomState, N>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+{
+    /// Creates a new, empty map of `N` empty shards.
+    ///
+    /// # Panics
+    ///
+    /// If `N` is 0.
+    pub fn new() -> Self {
+        Self::with_hasher(RandomState::new())
+    }
+}
+
+impl<K, V, S, const N: usize> ShardedMap<K, V, S, N>
+where
+    K: Sync + Send + Clone + Hash + Eq,
+    V: Sync + Send,
+    S: BuildHasher + Clone,
+{
+    /// Creates a new, empty map of `N` empty shards, which will all use the given hash builder
+    /// to hash keys.
+    ///
+    /// # Panics
+    ///
+    /// If `N` is 0.
+    pub fn with_hasher(build_hasher: S) -> Self {
+        assert_ne!(N, 0, "a sharded map needs at least one shard");
+        let shards = (0..N)
+            .map(|_| FlurryHashMap::with_hasher(build_hasher.clone()))
+            .collect();
+        ShardedMap {
+            shards,
+            build_hasher,
+        }
+    }
+
+    /// Returns the index of the shard that holds `key`.
+    pub fn shard_index(&self, key: &K) -> usize {
+        let mut h = self.build_hasher.build_hasher();
+        key.hash(&mut h);
+        // the shards use the low bits to pick a bin, so we leave those alone
+        (h.finish() >> 32) as usize % N
+    }
+
+    /// Returns the shard that holds `key`.
+    pub fn shard(&self, key: &K) -> &FlurryHashMap<K, V, S> {
+        &self.shards[self.shard_index(key)]
+    }
+
+    /// Returns all the shards, in order of their index.
+    pub fn shards(&self) -> &[FlurryHashMap<K, V, S>] {
+        &self.shards
+    }
+
+    /// Returns the number of entries in the map, summed over all shards.
+    ///
+    /// If the map is being concurrently modified, the shards are counted at slightly different
+    /// times, so the sum may not match any state the map as a whole was in.
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(FlurryHashMap::len).sum()
+    }
+
+    /// Returns `true` if no shard contains any entries.
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(FlurryHashMap::is_empty)
+    }
+
+    /// Maps `key` to `value` in its shard.
+    ///
+    /// See [`FlurryHashMap::insert`].
+    pub fn insert(&self, key: K, value: V) -> Option<()> {
+        self.shard(&key).insert(key, value)
+    }
+
+    /// Returns the value to which `key` is mapped.
+    ///
+    /// See [`FlurryHashMap::get`].
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    pub fn get<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        self.shard(key).get(key, guard)
+    }
+
+    /// Tests if `key` is a key in this map.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.shard(key).contains_key(key)
+    }
+
+    /// Removes `key` from its shard, returning the value it was mapped to, if any.
+    ///
+    /// See [`FlurryHashMap::remove`].
+    ///
+    /// To obtain a `Guard`, use [`epoch::pin`](crate::epoch::pin).
+    pub fn remove<'g>(&'g self, key: &K, guard: &'g Guard) -> Option<&'g V> {
+        self.shard(key).remove(key, guard)
+    }
+}
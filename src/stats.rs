@@ -0,0 +1,539 @@
+use std::collections::VecDeque;
+#[cfg(any(feature = "lock-stats", feature = "entry-stats"))]
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The operations that take a bin lock, and whose lock hold times are tracked separately.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// [`insert`](crate::FlurryHashMap::insert) into a non-empty bin.
+    Insert,
+    /// [`compute_if_absent`](crate::FlurryHashMap::compute_if_absent), including the time spent
+    /// in the user-provided closure.
+    ComputeIfAbsent,
+    /// The final check-and-store of
+    /// [`replace_with_retries`](crate::FlurryHashMap::replace_with_retries).
+    Replace,
+    /// [`merge`](crate::FlurryHashMap::merge), including the time spent in the user-provided
+    /// closure.
+    Merge,
+    /// [`remove`](crate::FlurryHashMap::remove) from a non-empty bin.
+    Remove,
+    /// [`set_if_newer`](crate::FlurryHashMap::set_if_newer), including the time spent in the
+    /// user-provided closure.
+    SetIfNewer,
+    /// [`swap_values`](crate::FlurryHashMap::swap_values). Its two locks are counted separately.
+    SwapValues,
+    /// [`decrement_and_remove_at_zero`](crate::FlurryHashMap::decrement_and_remove_at_zero).
+    Decrement,
+    /// [`upsert`](crate::FlurryHashMap::upsert), including the time spent in the user-provided
+    /// closures.
+    Upsert,
+    /// [`fold_key_into`](crate::FlurryHashMap::fold_key_into), including the time spent in the
+    /// user-provided closure. Its two locks are counted separately.
+    FoldKey,
+    /// Moving a bin to the next table during a resize.
+    Transfer,
+    /// [`apply_deltas`](crate::FlurryHashMap::apply_deltas). Each acquisition covers all the
+    /// deltas applied to one bin.
+    ApplyDeltas,
+}
+
+/// The distribution of chain lengths over the bins of a table.
+///
+/// This `struct` is created by the [`bin_stats`] and [`simulate_resize`] methods on
+/// [`FlurryHashMap`].
+///
+/// [`bin_stats`]: crate::FlurryHashMap::bin_stats
+/// [`simulate_resize`]: crate::FlurryHashMap::simulate_resize
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BinStats {
+    /// The number of bins in the table.
+    pub bins: usize,
+    /// The number of entries in the table.
+    pub entries: usize,
+    /// `chains[n]` is the number of bins that hold exactly `n` entries.
+    pub chains: Vec<usize>,
+}
+
+impl BinStats {
+    pub(crate) fn new<I: IntoIterator<Item = usize>>(bins: usize, bin_indices: I) -> Self {
+        let mut lengths = vec![0; bins];
+        for i in bin_indices {
+            lengths[i] += 1;
+        }
+        let mut chains = vec![0; lengths.iter().copied().max().unwrap_or(0) + 1];
+        for len in lengths {
+            chains[len] += 1;
+        }
+        BinStats {
+            bins,
+            entries: chains.iter().enumerate().map(|(len, n)| len * n).sum(),
+            chains,
+        }
+    }
+
+    /// The number of bins that hold no entries.
+    pub fn empty(&self) -> usize {
+        self.chains[0]
+    }
+
+    /// The length of the longest chain.
+    pub fn max_len(&self) -> usize {
+        self.chains.len() - 1
+    }
+
+    /// The average chain length of the bins that are not empty, or `None` if all bins are.
+    pub fn mean_len(&self) -> Option<f64> {
+        let occupied = self.bins - self.empty();
+        if occupied == 0 {
+            None
+        } else {
+            Some(self.entries as f64 / occupied as f64)
+        }
+    }
+
+    /// Summarizes how unevenly the entries are spread over the bins.
+    pub fn skew(&self) -> SkewReport {
+        if self.entries == 0 {
+            return SkewReport {
+                max_to_mean: 0.0,
+                gini: 0.0,
+            };
+        }
+        let mean = self.entries as f64 / self.bins as f64;
+
+        // with the bins sorted by chain length, the gini coefficient is
+        // 2 * sum(rank * len) / (bins * entries) - (bins + 1) / bins, for ranks starting at 1.
+        // the bins of each length take up a run of consecutive ranks.
+        let mut ranked = 0.0;
+        let mut rank = 0.0;
+        for (len, &n) in self.chains.iter().enumerate() {
+            let n = n as f64;
+            // the sum of the ranks rank + 1 through rank + n
+            ranked += len as f64 * (n * rank + n * (n + 1.0) / 2.0);
+            rank += n;
+        }
+        let bins = self.bins as f64;
+        SkewReport {
+            max_to_mean: self.max_len() as f64 / mean,
+            gini: 2.0 * ranked / (bins * self.entries as f64) - (bins + 1.0) / bins,
+        }
+    }
+}
+
+/// How unevenly the entries of a table are spread over its bins.
+///
+/// This `struct` is created by the [`skew_report`] method on [`FlurryHashMap`], and by
+/// [`BinStats::skew`].
+///
+/// [`skew_report`]: crate::FlurryHashMap::skew_report
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkewReport {
+    /// The length of the longest chain divided by the average length over all bins, including
+    /// the empty ones. This is 1 if every bin holds the same number of entries, and the number
+    /// of bins if all entries are in one bin.
+    pub max_to_mean: f64,
+    /// The Gini coefficient of the chain lengths over all bins, between 0 if every bin holds the
+    /// same number of entries and almost 1 if all entries are in one bin.
+    ///
+    /// A table below its load factor always has empty bins, so even a perfect spread of the keys
+    /// leaves this at least the fraction of bins that are empty. A good hash function typically
+    /// gives about 0.6 at the load factor, and more in sparser tables.
+    pub gini: f64,
+}
+
+/// How often an entry of a map has been read and written.
+///
+/// This `struct` is created by the [`entry_stats`] method on [`FlurryHashMap`].
+///
+/// [`entry_stats`]: crate::FlurryHashMap::entry_stats
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[cfg(feature = "entry-stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EntryStats {
+    /// The number of times the value of the entry has been read.
+    pub reads: u64,
+    /// The number of times a value has been stored for the entry, including when it was inserted.
+    pub writes: u64,
+}
+
+/// The live counters behind [`EntryStats`].
+#[cfg(feature = "entry-stats")]
+#[derive(Debug)]
+pub(crate) struct EntryCounters {
+    reads: AtomicU64,
+    writes: AtomicU64,
+}
+
+#[cfg(feature = "entry-stats")]
+impl EntryCounters {
+    pub(crate) fn new(writes: u64) -> Self {
+        EntryCounters {
+            reads: AtomicU64::new(0),
+            writes: AtomicU64::new(writes),
+        }
+    }
+
+    pub(crate) fn read(&self) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> EntryStats {
+        EntryStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "entry-stats")]
+impl Clone for EntryCounters {
+    fn clone(&self) -> Self {
+        let stats = self.snapshot();
+        EntryCounters {
+            reads: AtomicU64::new(stats.reads),
+            writes: AtomicU64::new(stats.writes),
+        }
+    }
+}
+
+/// The number of bin lock acquisitions over which contention is judged.
+const CONTENTION_WINDOW: usize = 256;
+
+/// The share of contended acquisitions in a window, as `1 / CONTENDED_SHARE`, at or above which
+/// the map should resize early.
+const CONTENDED_SHARE: usize = 4;
+
+/// How the count of the entries of a map is striped over counter cells.
+///
+/// This `struct` is created by the [`counter_cell_stats`] method on [`FlurryHashMap`].
+///
+/// [`counter_cell_stats`]: crate::FlurryHashMap::counter_cell_stats
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CounterStats {
+    /// The number of cells the count is striped over. This is 0 until two updates of the count
+    /// first collide.
+    pub cells: usize,
+    /// The number of those cells that hold a part of the count, that is, that have been updated
+    /// since the cells were last [compacted](crate::FlurryHashMap::compact_counters), and whose
+    /// updates have not cancelled out.
+    pub active_cells: usize,
+    /// The part of the count that is held by the base count, rather than by the cells.
+    pub base: isize,
+    /// The number of updates of the count that collided with another update, and so failed to
+    /// update the base or their cell, since the map was created.
+    pub collisions: usize,
+}
+
+/// The state of the [adaptive resizing] of a map.
+///
+/// This `struct` is created by the [`adaptive_state`] method on [`FlurryHashMap`].
+///
+/// [adaptive resizing]: crate::builder::Builder::adaptive_resize
+/// [`adaptive_state`]: crate::FlurryHashMap::adaptive_state
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdaptiveState {
+    /// The number of bin lock acquisitions so far in the current window.
+    pub acquisitions: usize,
+    /// The number of those acquisitions that had to wait for another thread to release the lock.
+    pub contended: usize,
+    /// Whether a window was contended enough to call for a resize that has not been started yet.
+    pub resize_pending: bool,
+    /// The number of resizes that were started early because of contention.
+    pub early_resizes: usize,
+}
+
+/// How far the resize of a map has got.
+///
+/// This `struct` is created by the [`resize_progress`] method on [`FlurryHashMap`].
+///
+/// [`resize_progress`]: crate::FlurryHashMap::resize_progress
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeProgress {
+    /// Whether a resize is under way.
+    pub resizing: bool,
+    /// The number of bins in the table that is being resized, or in the current table if no
+    /// resize is under way.
+    pub bins: usize,
+    /// The number of bins that have been moved to the new table.
+    pub moved_bins: usize,
+    /// The frontier of the transfer. Resizing threads claim ranges of bins from the top of the
+    /// table down, so the bins at and above this index have been claimed, and those below it
+    /// have not. It is 0 when no resize is under way.
+    pub transfer_index: usize,
+    /// The number of entries in bins that have not been moved to the new table yet.
+    pub entries_in_old: usize,
+}
+
+impl ResizeProgress {
+    /// Returns the fraction of bins that have been moved to the new table, or 1 if no resize is
+    /// under way.
+    pub fn fraction(&self) -> f64 {
+        if !self.resizing || self.bins == 0 {
+            return 1.0;
+        }
+        self.moved_bins as f64 / self.bins as f64
+    }
+}
+
+/// The number of samples a [`CapacityHistory`] keeps.
+const CAPACITY_HISTORY: usize = 64;
+
+/// The size of a map at the moment its table was replaced.
+///
+/// These are returned by the [`capacity_history`] method on [`FlurryHashMap`].
+///
+/// [`capacity_history`]: crate::FlurryHashMap::capacity_history
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapacitySample {
+    /// When the table was replaced.
+    pub at: Instant,
+    /// The number of entries in the map at the time.
+    pub len: usize,
+    /// The number of bins in the new table.
+    pub capacity: usize,
+}
+
+/// The most recent [`CapacitySample`]s of a map, oldest first.
+#[derive(Debug, Default)]
+pub(crate) struct CapacityHistory {
+    samples: parking_lot::Mutex<VecDeque<CapacitySample>>,
+}
+
+impl CapacityHistory {
+    pub(crate) fn record(&self, len: usize, capacity: usize) {
+        let mut samples = self.samples.lock();
+        if samples.len() == CAPACITY_HISTORY {
+            samples.pop_front();
+        }
+        samples.push_back(CapacitySample {
+            at: Instant::now(),
+            len,
+            capacity,
+        });
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<CapacitySample> {
+        self.samples.lock().iter().copied().collect()
+    }
+}
+
+/// How long the resizes of a map have taken.
+#[derive(Debug, Default)]
+pub(crate) struct ResizeTimes {
+    inner: parking_lot::Mutex<ResizeTimesInner>,
+}
+
+#[derive(Debug, Default)]
+struct ResizeTimesInner {
+    /// When the resize under way started, if there is one.
+    started: Option<Instant>,
+    last: Option<Duration>,
+    total: Duration,
+    resizes: u32,
+}
+
+impl ResizeTimes {
+    pub(crate) fn start(&self) {
+        self.inner.lock().started = Some(Instant::now());
+    }
+
+    pub(crate) fn finish(&self) {
+        let mut inner = self.inner.lock();
+        if let Some(started) = inner.started.take() {
+            let took = started.elapsed();
+            inner.last = Some(took);
+            inner.total += took;
+            inner.resizes += 1;
+        }
+    }
+
+    pub(crate) fn last(&self) -> Option<Duration> {
+        self.inner.lock().last
+    }
+
+    pub(crate) fn average(&self) -> Option<Duration> {
+        let inner = self.inner.lock();
+        if inner.resizes == 0 {
+            None
+        } else {
+            Some(inner.total / inner.resizes)
+        }
+    }
+}
+
+/// The live counters behind [`AdaptiveState`].
+#[derive(Debug, Default)]
+pub(crate) struct Contention {
+    acquisitions: AtomicUsize,
+    contended: AtomicUsize,
+    pending: AtomicBool,
+    early_resizes: AtomicUsize,
+}
+
+impl Contention {
+    /// Records a bin lock acquisition, and whether it had to wait.
+    pub(crate) fn record(&self, contended: bool) {
+        if contended {
+            self.contended.fetch_add(1, Ordering::Relaxed);
+        }
+        let n = self.acquisitions.fetch_add(1, Ordering::Relaxed) + 1;
+        if n != CONTENTION_WINDOW {
+            return;
+        }
+        // we completed the window, so we judge it. acquisitions that race with this are counted
+        // towards whichever window they happen to land in.
+        self.acquisitions
+            .fetch_sub(CONTENTION_WINDOW, Ordering::Relaxed);
+        let contended = self.contended.swap(0, Ordering::Relaxed);
+        if contended * CONTENDED_SHARE >= CONTENTION_WINDOW {
+            self.pending.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Returns whether a resize has been called for since the last call.
+    pub(crate) fn take_pending(&self) -> bool {
+        self.pending.load(Ordering::Relaxed) && self.pending.swap(false, Ordering::SeqCst)
+    }
+
+    pub(crate) fn record_early_resize(&self) {
+        self.early_resizes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> AdaptiveState {
+        AdaptiveState {
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            contended: self.contended.load(Ordering::Relaxed),
+            resize_pending: self.pending.load(Ordering::Relaxed),
+            early_resizes: self.early_resizes.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(feature = "lock-stats")]
+impl Operation {
+    const COUNT: usize = 12;
+
+    fn index(self) -> usize {
+        match self {
+            Operation::Insert => 0,
+            Operation::ComputeIfAbsent => 1,
+            Operation::Replace => 2,
+            Operation::Merge => 3,
+            Operation::Remove => 4,
+            Operation::SetIfNewer => 5,
+            Operation::SwapValues => 6,
+            Operation::Decrement => 7,
+            Operation::Upsert => 8,
+            Operation::FoldKey => 9,
+            Operation::Transfer => 10,
+            Operation::ApplyDeltas => 11,
+        }
+    }
+}
+
+/// Summary of how long bin locks were held by one kind of [`Operation`].
+#[cfg(feature = "lock-stats")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LockHold {
+    /// The number of times the lock was taken.
+    pub count: u64,
+    /// The total time the lock was held across all acquisitions.
+    pub total: Duration,
+    /// The longest time the lock was held by a single acquisition.
+    pub max: Duration,
+}
+
+#[cfg(feature = "lock-stats")]
+impl LockHold {
+    /// The average time the lock was held, or `None` if it was never taken.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_nanos(
+                (self.total.as_nanos() / u128::from(self.count)) as u64,
+            ))
+        }
+    }
+}
+
+/// A snapshot of the bin lock hold times of a map.
+///
+/// This `struct` is created by the [`lock_hold_stats`] method on [`FlurryHashMap`].
+///
+/// [`lock_hold_stats`]: crate::FlurryHashMap::lock_hold_stats
+/// [`FlurryHashMap`]: crate::FlurryHashMap
+#[cfg(feature = "lock-stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockHoldStats {
+    ops: [LockHold; Operation::COUNT],
+}
+
+#[cfg(feature = "lock-stats")]
+impl LockHoldStats {
+    /// Returns the lock hold summary for `op`.
+    pub fn get(&self, op: Operation) -> LockHold {
+        self.ops[op.index()]
+    }
+}
+
+#[cfg(feature = "lock-stats")]
+#[derive(Debug, Default)]
+struct HoldCounters {
+    count: AtomicU64,
+    total_ns: AtomicU64,
+    max_ns: AtomicU64,
+}
+
+/// The live counters behind [`LockHoldStats`].
+#[cfg(feature = "lock-stats")]
+#[derive(Debug, Default)]
+pub(crate) struct LockStats {
+    ops: [HoldCounters; Operation::COUNT],
+}
+
+#[cfg(feature = "lock-stats")]
+impl LockStats {
+    pub(crate) fn record(&self, op: Operation, held: Duration) {
+        let ns = held.as_nanos() as u64;
+        let c = &self.ops[op.index()];
+        c.count.fetch_add(1, Ordering::Relaxed);
+        c.total_ns.fetch_add(ns, Ordering::Relaxed);
+        let mut max = c.max_ns.load(Ordering::Relaxed);
+        while ns > max {
+            match c
+                .max_ns
+                .compare_exchange_weak(max, ns, Ordering::Relaxed, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(current) => max = current,
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> LockHoldStats {
+        let mut ops = [LockHold::default(); Operation::COUNT];
+        for (hold, c) in ops.iter_mut().zip(self.ops.iter()) {
+            *hold = LockHold {
+                count: c.count.load(Ordering::Relaxed),
+                total: Duration::from_nanos(c.total_ns.load(Ordering::Relaxed)),
+                max: Duration::from_nanos(c.max_ns.load(Ordering::Relaxed)),
+            };
+        }
+        LockHoldStats { ops }
+    }
+}
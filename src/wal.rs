@@ -0,0 +1,42 @@
+use std::fmt;
+
+/// A mutation of a [`FlurryHashMap`](crate::FlurryHashMap), as passed to its write-ahead log.
+///
+/// See [`Builder::wal`](crate::builder::Builder::wal).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WalRecord<'a, K, V> {
+    /// `key` was absent, and is being mapped to `value`.
+    Insert {
+        /// The inserted key.
+        key: &'a K,
+        /// The value the key is being mapped to.
+        value: &'a V,
+    },
+    /// `key` was mapped to `old`, and is being mapped to `new`.
+    Replace {
+        /// The updated key.
+        key: &'a K,
+        /// The value the key was mapped to.
+        old: &'a V,
+        /// The value the key is being mapped to.
+        new: &'a V,
+    },
+    /// `key` was mapped to `value`, and is being removed.
+    Remove {
+        /// The removed key.
+        key: &'a K,
+        /// The value the key was mapped to.
+        value: &'a V,
+    },
+}
+
+type WalFn<K, V> = dyn Fn(&WalRecord<'_, K, V>) + Send + Sync;
+
+pub(crate) struct Wal<K, V>(pub(crate) Box<WalFn<K, V>>);
+
+impl<K, V> fmt::Debug for Wal<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Wal")
+    }
+}
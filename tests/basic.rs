@@ -1,7 +1,24 @@
 use crossbeam::epoch;
 use flurry::*;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasherDefault, Hasher};
 use std::sync::Arc;
 
+/// A hasher that gives every key the same hash, so that all the keys of a map end up in the same
+/// bin. That is bin 3, rather than the first one, so that tests notice if it is mixed up with a
+/// bin index of 0.
+#[derive(Default)]
+struct Constant;
+impl Hasher for Constant {
+    fn finish(&self) -> u64 {
+        3
+    }
+    fn write(&mut self, _: &[u8]) {}
+}
+
+/// Builds [`Constant`] hashers.
+type ConstantHasher = BuildHasherDefault<Constant>;
+
 #[test]
 fn new() {
     let _map = FlurryHashMap::<usize, usize>::new();
@@ -37,6 +54,20 @@ fn insert_and_get() {
     }
 }
 
+#[test]
+fn get_colliding() {
+    let map = FlurryHashMap::<usize, usize>::new();
+
+    // with this many keys, many of them share a bin with another key, and are not at its head
+    for i in 0..1000 {
+        map.insert(i, i * 2);
+    }
+    let guard = epoch::pin();
+    for i in 0..1000 {
+        assert_eq!(map.get(&i, &guard), Some(&(i * 2)));
+    }
+}
+
 #[test]
 fn update() {
     let map = FlurryHashMap::<usize, usize>::new();
@@ -119,3 +150,3590 @@ fn drop_value() {
     // Second NotifyOnDrop was dropped when the map was dropped
     assert_eq!(Arc::strong_count(&dropped2), 1);
 }
+
+#[test]
+fn compute_if_absent() {
+    let map = FlurryHashMap::<usize, usize>::new();
+
+    let guard = epoch::pin();
+    assert_eq!(map.compute_if_absent(42, |_| 0, &guard), &0);
+    // the key is now present, so the closure must not be called again
+    assert_eq!(map.compute_if_absent(42, |_| unreachable!(), &guard), &0);
+    assert_eq!(map.get(&42, &guard), Some(&0));
+}
+
+#[test]
+fn compute_if_absent_panic() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+
+    let map1 = map.clone();
+    let r = std::thread::spawn(move || {
+        let guard = epoch::pin();
+        map1.compute_if_absent(42, |_| panic!("computation failed"), &guard);
+    })
+    .join();
+    assert!(r.is_err());
+
+    // the key must stay absent, and the bin must not remain locked
+    let guard = epoch::pin();
+    assert!(map.get(&42, &guard).is_none());
+    map.insert(42, 1);
+    assert_eq!(map.get(&42, &guard), Some(&1));
+}
+
+#[test]
+fn compute_if_absent_indexed() {
+    const THREADS: usize = 8;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let index = Arc::new(FlurryHashMap::<String, usize>::new());
+    let barrier = Arc::new(std::sync::Barrier::new(THREADS));
+
+    // every thread would index the key under a name of its own if it created the value
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let (map, index, barrier) = (map.clone(), index.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                barrier.wait();
+                for key in 0..100 {
+                    map.compute_if_absent_indexed(
+                        key,
+                        |k| (t, format!("{}-by-{}", k, t)),
+                        &index,
+                        &guard,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // each key was indexed exactly once, by the thread whose value it holds
+    let guard = epoch::pin();
+    assert_eq!(map.len(), 100);
+    assert_eq!(index.len(), 100);
+    for key in 0..100 {
+        let t = map.get(&key, &guard).unwrap();
+        assert_eq!(index.get(&format!("{}-by-{}", key, t), &guard), Some(&key));
+    }
+
+    // a present key is neither recomputed nor indexed again
+    let v = map.compute_if_absent_indexed(0, |_| unreachable!(), &index, &guard);
+    assert!(*v < THREADS);
+    assert_eq!(index.len(), 100);
+}
+
+#[test]
+fn concurrent_compute_if_absent() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let calls = Arc::new(AtomicUsize::new(0));
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            let calls = calls.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for i in 0..64 {
+                    map.compute_if_absent(
+                        i,
+                        |_| {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                            t
+                        },
+                        &guard,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // every key was computed exactly once
+    assert_eq!(calls.load(Ordering::SeqCst), 64);
+    let guard = epoch::pin();
+    for i in 0..64 {
+        assert!(map.get(&i, &guard).is_some());
+    }
+}
+
+#[test]
+fn raw_parts_round_trip() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+
+    let (table, build_hasher) = map.into_raw_parts();
+    assert_eq!(table.len(), 100);
+    let map = FlurryHashMap::from_raw_parts(table, build_hasher);
+
+    let guard = epoch::pin();
+    assert_eq!(map.iter(&guard).count(), 100);
+    for i in 0..100 {
+        assert_eq!(map.get(&i, &guard), Some(&(i * 2)));
+    }
+    // the map must keep working as normal afterwards
+    map.insert(100, 200);
+    assert_eq!(map.get(&100, &guard), Some(&200));
+}
+
+#[test]
+fn raw_parts_empty() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let (table, build_hasher) = map.into_raw_parts();
+    assert!(table.is_empty());
+    let map = FlurryHashMap::from_raw_parts(table, build_hasher);
+    map.insert(1, 1);
+    assert!(map.contains_key(&1));
+}
+
+#[test]
+fn from_raw_table() {
+    use flurry::raw::RawTable;
+
+    let build_hasher = RandomState::new();
+    let mut table = RawTable::with_bins(64);
+    for i in 0..32 {
+        assert!(table.insert(i, i, &build_hasher));
+    }
+    assert!(!table.insert(0, 42, &build_hasher));
+    assert_eq!(table.len(), 32);
+
+    let map = FlurryHashMap::from_raw_parts(table, build_hasher);
+    let guard = epoch::pin();
+    assert_eq!(map.get(&0, &guard), Some(&42));
+    for i in 1..32 {
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+}
+
+#[test]
+fn classify() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..30 {
+        map.insert(i, i * 10);
+    }
+
+    let guard = epoch::pin();
+    let [zero, one, two] = map.classify(|k, _| k % 3, &guard);
+    for (bucket, class) in [zero, one, two].iter().enumerate() {
+        assert_eq!(class.iter(&guard).count(), 10);
+        for (k, v) in class.iter(&guard) {
+            assert_eq!(k % 3, bucket);
+            assert_eq!(*v, k * 10);
+        }
+    }
+
+    // out-of-range classes are dropped
+    let [low] = map.classify(|k, _| if *k < 5 { 0 } else { 1 }, &guard);
+    assert_eq!(low.iter(&guard).count(), 5);
+}
+
+#[test]
+fn pause_writes() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    map.insert(1, 1);
+    let written = Arc::new(AtomicBool::new(false));
+
+    let writer = map.pause_writes(|| {
+        let writer = {
+            let map = map.clone();
+            let written = written.clone();
+            std::thread::spawn(move || {
+                map.insert(2, 2);
+                written.store(true, Ordering::SeqCst);
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!written.load(Ordering::SeqCst));
+
+        // reads are not blocked
+        let reader = {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                map.get(&1, &guard).copied()
+            })
+        };
+        assert_eq!(reader.join().unwrap(), Some(1));
+        assert!(!written.load(Ordering::SeqCst));
+        writer
+    });
+
+    writer.join().unwrap();
+    assert!(written.load(Ordering::SeqCst));
+    assert!(map.contains_key(&2));
+}
+
+#[test]
+fn pause_writes_under_load() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let stop = Arc::new(AtomicBool::new(false));
+    let writers: Vec<_> = (0..4)
+        .map(|t| {
+            let (map, stop) = (map.clone(), stop.clone());
+            std::thread::spawn(move || {
+                let mut i = 0;
+                while !stop.load(Ordering::SeqCst) {
+                    map.insert(t * 1_000_000 + i % 1000, i);
+                    i += 1;
+                }
+            })
+        })
+        .collect();
+
+    // the writers never stop on their own, and do not keep the pauses from going through
+    let guard = epoch::pin();
+    for _ in 0..20 {
+        map.pause_writes(|| {
+            let before: Vec<_> = map.iter(&guard).map(|(k, v)| (*k, *v)).collect();
+            std::thread::sleep(Duration::from_millis(1));
+            let after: Vec<_> = map.iter(&guard).map(|(k, v)| (*k, *v)).collect();
+            assert_eq!(before, after);
+        });
+    }
+    stop.store(true, Ordering::SeqCst);
+    for w in writers {
+        w.join().unwrap();
+    }
+}
+
+#[test]
+fn pause_from_write() {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    // the write would never finish, so the pause could never start
+    let paused = catch_unwind(AssertUnwindSafe(|| {
+        map.compute_if_absent(
+            2,
+            |_| {
+                map.compact_counters();
+                2
+            },
+            &guard,
+        )
+    }));
+    assert!(paused.is_err());
+    assert!(!map.contains_key(&2));
+
+    // the write is over, so pausing works again, as do further writes
+    map.compact_counters();
+    map.insert(2, 2);
+    assert_eq!(map.get(&2, &guard), Some(&2));
+}
+
+#[test]
+fn len() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    assert!(map.is_empty());
+    assert_eq!(map.len(), 0);
+
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    map.insert(0, 1);
+    assert!(!map.is_empty());
+    assert_eq!(map.len(), 100);
+}
+
+#[test]
+fn approx_len() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    assert_eq!(map.approx_len(&epoch::pin()), 0);
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    map.insert(t * 1000 + i, i);
+                    // never more than the number of in-flight inserts off
+                    let approx = map.approx_len(&epoch::pin());
+                    assert!(approx <= 4000);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // exact once the map is quiescent
+    assert_eq!(map.approx_len(&epoch::pin()), map.len());
+    assert_eq!(map.len(), 4000);
+}
+
+#[test]
+fn collect_into() {
+    use std::collections::BTreeMap;
+
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..5 {
+        map.insert(i, i * i);
+    }
+
+    let guard = epoch::pin();
+    let sorted = map.collect_into(
+        BTreeMap::new(),
+        |m, k, v| {
+            m.insert(*k, *v);
+        },
+        &guard,
+    );
+    assert_eq!(
+        sorted.into_iter().collect::<Vec<_>>(),
+        vec![(0, 0), (1, 1), (2, 4), (3, 9), (4, 16)]
+    );
+
+    let s = map.collect_into(
+        String::new(),
+        |s, k, v| s.push_str(&format!("{}={};", k, v)),
+        &guard,
+    );
+    let mut parts: Vec<_> = s.split_terminator(';').collect();
+    parts.sort();
+    assert_eq!(parts, vec!["0=0", "1=1", "2=4", "3=9", "4=16"]);
+}
+
+#[test]
+fn replace_with_retries() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(42, 1);
+
+    let guard = epoch::pin();
+    assert_eq!(
+        map.replace_with_retries(&42, |v| v + 1, 0, &guard),
+        Ok(Some(&2))
+    );
+    assert_eq!(map.get(&42, &guard), Some(&2));
+    assert_eq!(
+        map.replace_with_retries(&43, |v| v + 1, 0, &guard),
+        Ok(None)
+    );
+    assert!(!map.contains_key(&43));
+}
+
+#[test]
+fn replace_with_retries_exhausted() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(42, 0);
+
+    let calls = AtomicUsize::new(0);
+    let guard = epoch::pin();
+    // every attempt is raced by a write from within f itself
+    let res = map.replace_with_retries(
+        &42,
+        |v| {
+            let n = calls.fetch_add(1, Ordering::SeqCst);
+            map.insert(42, 100 + n);
+            v + 1
+        },
+        3,
+        &guard,
+    );
+    assert_eq!(res, Err(RetryExhausted));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+    assert_eq!(map.get(&42, &guard), Some(&103));
+}
+
+#[test]
+fn replace_with_retries_concurrent() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    map.insert(42, 0);
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for _ in 0..1000 {
+                    map.replace_with_retries(&42, |v| v + 1, usize::MAX - 1, &guard)
+                        .unwrap()
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(map.get(&42, &guard), Some(&4000));
+}
+
+#[test]
+fn reserve_concurrent() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    let done = Arc::new(AtomicBool::new(false));
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                let mut ops = 0;
+                // keep going until the reserve is done, and have done at least some work
+                while !done.load(Ordering::SeqCst) || ops < 1000 {
+                    let key = 100 + ops * 4 + t;
+                    map.insert(key, ops);
+                    assert_eq!(map.get(&key, &guard), Some(&ops));
+                    assert_eq!(map.get(&(ops % 100), &guard), Some(&(ops % 100)));
+                    ops += 1;
+                }
+                ops
+            })
+        })
+        .collect();
+
+    let guard = epoch::pin();
+    map.reserve_concurrent(100_000, &guard);
+    drop(guard);
+    done.store(true, Ordering::SeqCst);
+    let mut inserted = 0;
+    for t in threads {
+        inserted += t.join().unwrap();
+    }
+
+    let map = Arc::try_unwrap(map).unwrap();
+    assert_eq!(map.len(), 100 + inserted);
+    let (table, _) = map.into_raw_parts();
+    // large enough to hold the reserved elements without exceeding the load factor
+    assert!(table.bins() as f64 * 0.75 >= 100_000.0);
+}
+
+#[test]
+fn find_by_arc() {
+    let map = FlurryHashMap::<usize, Arc<String>>::new();
+    let shared = Arc::new(String::from("shared"));
+    map.insert(1, shared.clone());
+    map.insert(2, Arc::new(String::from("shared")));
+    map.insert(3, shared.clone());
+    map.insert(4, Arc::new(String::from("other")));
+
+    let guard = epoch::pin();
+    let mut keys = map.find_by_arc(&shared, &guard);
+    keys.sort();
+    assert_eq!(keys, vec![1, 3]);
+    assert!(map
+        .find_by_arc(&Arc::new(String::from("other")), &guard)
+        .is_empty());
+}
+
+#[test]
+fn scoped_iter() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    let written = Arc::new(AtomicBool::new(false));
+
+    let (writer, mut snapshot) = map.scoped_iter(|iter| {
+        let writer = {
+            let map = map.clone();
+            let written = written.clone();
+            std::thread::spawn(move || {
+                map.insert(10, 10);
+                written.store(true, Ordering::SeqCst);
+            })
+        };
+
+        std::thread::sleep(Duration::from_millis(50));
+        let snapshot: Vec<_> = iter.map(|(k, v)| (*k, *v)).collect();
+        assert!(!written.load(Ordering::SeqCst));
+        (writer, snapshot)
+    });
+
+    snapshot.sort();
+    assert_eq!(snapshot, (0..10).map(|i| (i, i)).collect::<Vec<_>>());
+    writer.join().unwrap();
+    assert!(written.load(Ordering::SeqCst));
+    assert!(map.contains_key(&10));
+}
+
+#[test]
+fn merge() {
+    let map = FlurryHashMap::<&str, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.merge("a", 1, |a, b| a + b, &guard), &1);
+    assert_eq!(map.merge("a", 2, |a, b| a + b, &guard), &3);
+    assert_eq!(map.get(&"a", &guard), Some(&3));
+}
+
+#[test]
+fn upsert() {
+    let map = FlurryHashMap::<&str, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.upsert("a", || 1, |_| unreachable!(), &guard), &1);
+    assert_eq!(map.upsert("a", || unreachable!(), |v| v * 10, &guard), &10);
+    assert_eq!(map.get(&"a", &guard), Some(&10));
+}
+
+#[test]
+fn insert_if_under_capacity() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    for i in 0..10 {
+        assert_eq!(map.insert_if_under_capacity(i, i, 10, &guard), Ok(None));
+    }
+    assert_eq!(
+        map.insert_if_under_capacity(10, 10, 10, &guard),
+        Err(AtCapacity)
+    );
+    assert_eq!(map.get(&10, &guard), None);
+    assert_eq!(map.len(), 10);
+
+    // a present key can still be replaced
+    assert_eq!(
+        map.insert_if_under_capacity(3, 30, 10, &guard),
+        Ok(Some(&3))
+    );
+    assert_eq!(map.get(&3, &guard), Some(&30));
+
+    // and there is room again once an entry is removed
+    map.remove(&0, &guard);
+    assert_eq!(map.insert_if_under_capacity(10, 10, 10, &guard), Ok(None));
+    assert_eq!(map.len(), 10);
+}
+
+#[test]
+fn insert_if_under_capacity_concurrent() {
+    const THREADS: usize = 4;
+    const MAX: usize = 100;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                (0..1000)
+                    .filter(|i| {
+                        map.insert_if_under_capacity(t * 1000 + i, *i, MAX, &guard)
+                            .is_err()
+                    })
+                    .count()
+            })
+        })
+        .collect();
+    let rejected: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+
+    // every insertion was either rejected or counted, and the map overshot by less than the
+    // number of threads
+    assert!(map.len() >= MAX);
+    assert!(map.len() < MAX + THREADS);
+    assert_eq!(rejected + map.len(), THREADS * 1000);
+}
+
+#[test]
+fn insert_with_init() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const THREADS: usize = 4;
+    const KEYS: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let inits = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let (map, inits) = (map.clone(), inits.clone());
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for key in 0..KEYS {
+                    map.insert_with_init(
+                        key,
+                        t,
+                        |&v| {
+                            assert_eq!(v, t);
+                            inits.fetch_add(1, Ordering::SeqCst);
+                        },
+                        &guard,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    // every key was created exactly once
+    assert_eq!(inits.load(Ordering::SeqCst), KEYS);
+
+    // overwriting an entry does not count as creating it
+    let guard = epoch::pin();
+    let old = *map.get(&0, &guard).unwrap();
+    assert_eq!(
+        map.insert_with_init(0, 42, |_| panic!("entry already exists"), &guard),
+        Some(&old)
+    );
+    assert_eq!(map.get(&0, &guard), Some(&42));
+    let created = std::cell::Cell::new(false);
+    assert_eq!(
+        map.insert_with_init(KEYS, 42, |_| created.set(true), &guard),
+        None
+    );
+    assert!(created.get());
+}
+
+#[test]
+fn upsert_concurrent() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const THREADS: usize = 4;
+    const KEYS: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let inserts = Arc::new(AtomicUsize::new(0));
+    let updates = Arc::new(AtomicUsize::new(0));
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let (map, inserts, updates) = (map.clone(), inserts.clone(), updates.clone());
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for key in 0..KEYS {
+                    map.upsert(
+                        key,
+                        || {
+                            inserts.fetch_add(1, Ordering::SeqCst);
+                            1
+                        },
+                        |v| {
+                            updates.fetch_add(1, Ordering::SeqCst);
+                            v + 1
+                        },
+                        &guard,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // every key was inserted once, and every other upsert of it was an update
+    assert_eq!(inserts.load(Ordering::SeqCst), KEYS);
+    assert_eq!(updates.load(Ordering::SeqCst), KEYS * (THREADS - 1));
+    let guard = epoch::pin();
+    for key in 0..KEYS {
+        assert_eq!(map.get(&key, &guard), Some(&THREADS));
+    }
+}
+
+#[test]
+fn compute_from_bin() {
+    // every key ends up in the same bin
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    let guard = epoch::pin();
+    let sum_of_neighbors = |key: &usize, neighbors: &[(&usize, &usize)]| {
+        let mut keys: Vec<_> = neighbors.iter().map(|(k, _)| **k).collect();
+        keys.sort_unstable();
+        assert!(!keys.contains(key));
+        Some(neighbors.iter().map(|(_, v)| **v).sum::<usize>() + keys.len())
+    };
+
+    // the first key has no neighbors
+    assert_eq!(map.compute_from_bin(0, sum_of_neighbors, &guard), Some(&0));
+    for i in 1..4 {
+        map.insert(i, i * 10);
+    }
+    // a new key sees all the others
+    assert_eq!(
+        map.compute_from_bin(4, sum_of_neighbors, &guard),
+        Some(&(10 + 20 + 30 + 4))
+    );
+    // a present key sees all but itself
+    assert_eq!(
+        map.compute_from_bin(
+            2,
+            |key, neighbors| {
+                let mut keys: Vec<_> = neighbors.iter().map(|(k, _)| **k).collect();
+                keys.sort_unstable();
+                assert_eq!(keys, vec![0, 1, 3, 4]);
+                Some(key * 100)
+            },
+            &guard
+        ),
+        Some(&200)
+    );
+    assert_eq!(map.get(&2, &guard), Some(&200));
+    assert_eq!(map.get(&4, &guard), Some(&64));
+
+    // returning None leaves the map as it was
+    assert_eq!(map.compute_from_bin(2, |_, _| None, &guard), Some(&200));
+    assert_eq!(map.compute_from_bin(5, |_, _| None, &guard), None);
+    assert_eq!(map.len(), 5);
+}
+
+#[test]
+fn merge_many() {
+    let map = FlurryHashMap::<&str, usize>::new();
+    let guard = epoch::pin();
+    map.merge_many(vec![("a", 1), ("a", 2), ("b", 3)], |a, b| a + b, &guard);
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&"a", &guard), Some(&3));
+    assert_eq!(map.get(&"b", &guard), Some(&3));
+
+    map.merge_many(vec![("b", 4), ("c", 5)], |a, b| a + b, &guard);
+    assert_eq!(map.get(&"b", &guard), Some(&7));
+    assert_eq!(map.get(&"c", &guard), Some(&5));
+}
+
+#[test]
+fn remove() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.remove(&42, &guard), None);
+
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+    for i in (0..100).step_by(2) {
+        assert_eq!(map.remove(&i, &guard), Some(&(i * 2)));
+    }
+    assert_eq!(map.remove(&0, &guard), None);
+    assert_eq!(map.len(), 50);
+    for i in 0..100 {
+        let expected = if i % 2 == 0 { None } else { Some(i * 2) };
+        assert_eq!(map.get(&i, &guard).copied(), expected);
+    }
+
+    // removed keys can be inserted again
+    map.insert(0, 1);
+    assert_eq!(map.get(&0, &guard), Some(&1));
+    assert_eq!(map.len(), 51);
+}
+
+#[test]
+fn compare_remove() {
+    let map = FlurryHashMap::<usize, String>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.compare_remove(&1, &"one".to_string(), &guard), None);
+
+    map.insert(1, "one".to_string());
+    map.insert(2, "two".to_string());
+    // a mismatch leaves the entry alone
+    assert_eq!(map.compare_remove(&1, &"uno".to_string(), &guard), None);
+    assert_eq!(map.get(&1, &guard).unwrap(), "one");
+    assert_eq!(map.len(), 2);
+
+    assert_eq!(
+        map.compare_remove(&1, &"one".to_string(), &guard),
+        Some("one".to_string())
+    );
+    assert_eq!(map.get(&1, &guard), None);
+    assert_eq!(map.compare_remove(&1, &"one".to_string(), &guard), None);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.get(&2, &guard).unwrap(), "two");
+}
+
+#[test]
+fn compare_remove_concurrent() {
+    const THREADS: usize = 4;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    // every thread tries to remove every key, but each key goes to exactly one of them
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                (0..100)
+                    .filter(|i| map.compare_remove(i, i, &guard).is_some())
+                    .count()
+            })
+        })
+        .collect();
+    let removed: usize = threads.into_iter().map(|t| t.join().unwrap()).sum();
+    assert_eq!(removed, 100);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn remove_colliding() {
+    // every key ends up in the same bin
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    for i in 0..5 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    // from the middle, the head, and the tail of the bin
+    assert_eq!(map.remove(&2, &guard), Some(&2));
+    assert_eq!(map.remove(&0, &guard), Some(&0));
+    assert_eq!(map.remove(&4, &guard), Some(&4));
+    let mut left: Vec<_> = map.keys(&guard).copied().collect();
+    left.sort();
+    assert_eq!(left, vec![1, 3]);
+    assert_eq!(map.get(&1, &guard), Some(&1));
+    assert_eq!(map.get(&3, &guard), Some(&3));
+}
+
+#[test]
+fn concurrent_insert_remove() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for i in 0..1000 {
+                    let key = t * 1000 + i;
+                    map.insert(key, i);
+                    if i % 2 == 0 {
+                        assert_eq!(map.remove(&key, &guard), Some(&i));
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(map.len(), 2000);
+    for key in 0..4000 {
+        assert_eq!(map.contains_key(&key), key % 2 == 1);
+    }
+    assert_eq!(map.iter(&guard).count(), 2000);
+}
+
+#[test]
+fn validator() {
+    let map = FlurryHashMap::<usize, String>::builder()
+        .validator(|_, v: &String| !v.is_empty())
+        .build();
+    assert_eq!(map.try_insert(1, String::new()), Err(Rejected));
+    assert!(map.is_empty());
+    assert_eq!(map.try_insert(1, "one".to_string()), Ok(None));
+    assert_eq!(map.insert(1, "uno".to_string()), Some(()));
+
+    // a rejected replacement keeps the old value
+    assert_eq!(map.try_insert(1, String::new()), Err(Rejected));
+    let guard = epoch::pin();
+    assert_eq!(map.get(&1, &guard).unwrap(), "uno");
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+#[should_panic]
+fn validator_insert() {
+    let map = FlurryHashMap::<usize, usize>::builder()
+        .validator(|k, v| k == v)
+        .build();
+    map.insert(1, 1);
+    map.insert(1, 2);
+}
+
+#[test]
+fn visibility_barrier() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const KEYS: usize = 2000;
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize>::builder()
+            .visibility_barrier()
+            .build(),
+    );
+    // the number of keys inserted so far, which the producer publishes without releasing
+    let inserted = Arc::new(AtomicUsize::new(0));
+
+    let producer = {
+        let (map, inserted) = (map.clone(), inserted.clone());
+        std::thread::spawn(move || {
+            for key in 0..KEYS {
+                map.insert(key, key);
+                inserted.store(key + 1, Ordering::Relaxed);
+            }
+        })
+    };
+    let consumer = {
+        let (map, inserted) = (map.clone(), inserted.clone());
+        std::thread::spawn(move || {
+            let mut seen = 0;
+            while seen < KEYS {
+                let n = inserted.load(Ordering::Acquire);
+                let guard = epoch::pin();
+                for key in seen..n {
+                    assert_eq!(map.get(&key, &guard), Some(&key));
+                }
+                seen = n;
+                std::thread::yield_now();
+            }
+        })
+    };
+    producer.join().unwrap();
+    consumer.join().unwrap();
+    assert_eq!(map.len(), KEYS);
+}
+
+#[test]
+fn soft_cap() {
+    let map = FlurryHashMap::<usize, usize>::builder()
+        .soft_cap(100, EvictionPolicy::Random)
+        .build();
+    let guard = epoch::pin();
+    for i in 0..1000 {
+        map.insert(i, i);
+        assert!(map.len() <= 100);
+        // the key that was just inserted is never evicted
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+    assert_eq!(map.len(), 100);
+    // the table was sized for the cap, and did not grow past it
+    assert_eq!(map.bin_stats(&guard).bins, 256);
+    assert_eq!(map.capacity_history().len(), 1);
+
+    // replacing a value adds no entry, so evicts nothing
+    let key = *map.keys(&guard).next().unwrap();
+    map.insert(key, 0);
+    assert_eq!(map.len(), 100);
+    assert_eq!(map.get(&key, &guard), Some(&0));
+
+    // other ways of inserting do not evict
+    map.compute_if_absent(1000, |_| 1000, &guard);
+    assert_eq!(map.len(), 101);
+}
+
+#[test]
+fn soft_cap_concurrent() {
+    const THREADS: usize = 4;
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize>::builder()
+            .soft_cap(64, EvictionPolicy::Random)
+            .build(),
+    );
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    map.insert(t * 1000 + i, i);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    // every insertion has evicted down to the cap
+    assert!(map.len() <= 64);
+}
+
+#[test]
+fn read_cache_concurrent() {
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize>::builder()
+            .read_cache()
+            .build(),
+    );
+    for i in 0..8 {
+        map.insert(i, i);
+    }
+
+    let writer = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            for i in 0..2000 {
+                // churn the hot keys, and grow the table now and then
+                map.remove(&(i % 8), &guard);
+                map.insert(i % 8, i % 8);
+                map.insert(100 + i, i);
+            }
+        })
+    };
+    let readers: Vec<_> = (0..3)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..20000 {
+                    let guard = epoch::pin();
+                    let key = i % 8;
+                    if let Some(v) = map.get(&key, &guard) {
+                        assert_eq!(*v, key);
+                    }
+                }
+            })
+        })
+        .collect();
+    writer.join().unwrap();
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    for i in 0..8 {
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+}
+
+#[test]
+fn batch() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let repins = map.batch(100, |ctx| {
+        for i in 0..1000 {
+            ctx.insert(i, i);
+        }
+        for i in 0..1000 {
+            assert_eq!(ctx.get_and(&i, |v| *v), Some(i));
+        }
+        for i in (0..1000).step_by(2) {
+            assert!(ctx.remove(&i));
+        }
+        assert!(!ctx.contains_key(&0));
+        assert!(ctx.contains_key(&1));
+        ctx.repins()
+    });
+    // 2502 operations in all
+    assert_eq!(repins, 25);
+    assert_eq!(map.len(), 500);
+
+    // an operation issued from within another defers the re-pin to the next one
+    map.batch(1, |ctx| {
+        ctx.get_and(&1, |_| ctx.insert(1, 2));
+        assert_eq!(ctx.repins(), 1);
+        assert_eq!(ctx.get_and(&1, |v| *v), Some(2));
+        assert_eq!(ctx.repins(), 2);
+    });
+}
+
+#[test]
+fn contains_all() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in (0..200).step_by(3) {
+        map.insert(i, i);
+    }
+    let guard = epoch::pin();
+    assert!(map.contains_all(&[], &guard).is_empty());
+
+    // more than one word of bits, in an order of their own
+    let keys: Vec<usize> = (0..150).rev().collect();
+    let refs: Vec<&usize> = keys.iter().collect();
+    let present = map.contains_all(&refs, &guard);
+    assert_eq!(present.len(), 150);
+    for (i, key) in keys.iter().enumerate() {
+        assert_eq!(present.get(i), Some(key % 3 == 0));
+    }
+    assert_eq!(present.get(150), None);
+    assert_eq!(present.count_ones(), 50);
+    assert!(present
+        .iter()
+        .zip(&keys)
+        .all(|(bit, key)| bit == (key % 3 == 0)));
+}
+
+#[test]
+fn value_matches() {
+    // a value that is not Clone
+    let map = FlurryHashMap::<usize, std::sync::Mutex<usize>>::new();
+    map.insert(1, std::sync::Mutex::new(42));
+    let guard = epoch::pin();
+    assert_eq!(
+        map.value_matches(&1, |v| *v.lock().unwrap() == 42, &guard),
+        Some(true)
+    );
+    assert_eq!(
+        map.value_matches(&1, |v| *v.lock().unwrap() > 100, &guard),
+        Some(false)
+    );
+    assert_eq!(map.value_matches(&2, |_| true, &guard), None);
+}
+
+#[test]
+fn set_if_newer() {
+    // (timestamp, payload)
+    let map = FlurryHashMap::<&str, (u64, &str)>::new();
+    let newer = |a: &(u64, &str), b: &(u64, &str)| a.0 > b.0;
+
+    let guard = epoch::pin();
+    assert!(map.set_if_newer("k", (5, "five"), newer, &guard));
+    assert!(!map.set_if_newer("k", (3, "three"), newer, &guard));
+    assert!(!map.set_if_newer("k", (5, "five again"), newer, &guard));
+    assert_eq!(map.get(&"k", &guard), Some(&(5, "five")));
+    assert!(map.set_if_newer("k", (7, "seven"), newer, &guard));
+    assert_eq!(map.get(&"k", &guard), Some(&(7, "seven")));
+}
+
+#[test]
+fn set_if_newer_concurrent() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                // the threads' timestamps interleave, and each goes up to a different maximum
+                for ts in (t..1000 + t * 10).step_by(4) {
+                    map.set_if_newer(42, ts, |a, b| a > b, &guard);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    // the largest timestamp of thread 3
+    assert_eq!(map.get(&42, &guard), Some(&1027));
+}
+
+#[test]
+fn replace_if_version_gt() {
+    // (version, payload)
+    let map = FlurryHashMap::<&str, (u64, &str)>::new();
+    let version = |v: &(u64, &str)| v.0;
+
+    let guard = epoch::pin();
+    assert!(map.replace_if_version_gt("k", (2, "two"), version, &guard));
+    assert!(!map.replace_if_version_gt("k", (1, "one"), version, &guard));
+    assert!(!map.replace_if_version_gt("k", (2, "two again"), version, &guard));
+    assert_eq!(map.get(&"k", &guard), Some(&(2, "two")));
+    assert!(map.replace_if_version_gt("k", (3, "three"), version, &guard));
+    assert_eq!(map.get(&"k", &guard), Some(&(3, "three")));
+}
+
+#[test]
+fn replace_if_version_gt_concurrent() {
+    let map = Arc::new(FlurryHashMap::<usize, (u64, usize)>::new());
+
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                // every thread sends every version, highest first for half of them
+                let versions: Vec<u64> = if t % 2 == 0 {
+                    (0..500).collect()
+                } else {
+                    (0..500).rev().collect()
+                };
+                for v in versions {
+                    map.replace_if_version_gt(7, (v, t), |v| v.0, &guard);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(map.get(&7, &guard).map(|v| v.0), Some(499));
+}
+
+#[test]
+fn same_layout() {
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    // four distinct hashes, so that the keys share bins that hold them in insertion order
+    #[derive(Default)]
+    struct Mod4(u64);
+    impl Hasher for Mod4 {
+        fn finish(&self) -> u64 {
+            self.0 % 4
+        }
+        fn write(&mut self, bytes: &[u8]) {
+            for &b in bytes {
+                self.0 = self.0.wrapping_mul(31).wrapping_add(u64::from(b));
+            }
+        }
+    }
+    let new_map =
+        || FlurryHashMap::<usize, usize, _>::with_hasher(BuildHasherDefault::<Mod4>::default());
+
+    let (a, b) = (new_map(), new_map());
+    let guard = epoch::pin();
+    assert!(a.same_layout(&b, &guard));
+
+    let mut keys: Vec<usize> = (0..10).collect();
+    keys.sort_by_key(|k| {
+        let mut h = Mod4::default();
+        h.write_usize(*k);
+        h.finish()
+    });
+    for &k in &keys {
+        a.insert(k, k);
+        // the values are not part of the layout
+        b.insert(k, k + 1);
+    }
+    assert!(a.same_layout(&b, &guard));
+    assert!(b.same_layout(&a, &guard));
+
+    // the same entries, inserted in a different order
+    let c = new_map();
+    for &k in keys.iter().rev() {
+        c.insert(k, k);
+    }
+    assert_eq!(c.len(), a.len());
+    assert!(!a.same_layout(&c, &guard));
+
+    // the same entries, in a table of a different size
+    let d = new_map();
+    d.reserve_concurrent(1000, &guard);
+    for &k in &keys {
+        d.insert(k, k);
+    }
+    assert!(!a.same_layout(&d, &guard));
+}
+
+#[test]
+fn collision_estimate() {
+    let sample: Vec<usize> = (1_000_000..1_001_000).collect();
+    let dense = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(dense.collision_estimate(&sample, &guard), 0.0);
+    // just short of the load factor of the table they end up in
+    for i in 0..1500 {
+        dense.insert(i, i);
+    }
+    let sparse = FlurryHashMap::<usize, usize>::with_capacity(1_000_000);
+    for i in 0..1500 {
+        sparse.insert(i, i);
+    }
+
+    // about 1 - e^-0.73 of the bins are occupied
+    let high = dense.collision_estimate(&sample, &guard);
+    assert!(high > 0.4 && high < 0.65, "{}", high);
+    let low = sparse.collision_estimate(&sample, &guard);
+    assert!(low < 0.01, "{}", low);
+    // keys that are in the map always collide with themselves
+    assert_eq!(sparse.collision_estimate(&[0, 1, 2], &guard), 1.0);
+    assert_eq!(dense.collision_estimate(&[], &guard), 0.0);
+}
+
+#[test]
+fn simulate_resize() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    let stats = map.bin_stats(&guard);
+    assert_eq!((stats.bins, stats.entries, stats.max_len()), (0, 0, 0));
+
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+    let current = map.bin_stats(&guard);
+    assert_eq!(current.entries, 1000);
+    assert_eq!(current.chains.iter().sum::<usize>(), current.bins);
+
+    // spreading a dense map over many more bins shortens the chains
+    let larger = map.simulate_resize(current.bins * 8, &guard);
+    assert_eq!(larger.bins, current.bins * 8);
+    assert_eq!(larger.entries, 1000);
+    assert!(larger.mean_len().unwrap() < current.mean_len().unwrap());
+    assert!(larger.max_len() <= current.max_len());
+    assert!(larger.empty() > current.empty());
+
+    // simulating the current size reproduces the current layout
+    assert_eq!(map.simulate_resize(current.bins, &guard), current);
+    assert_eq!(map.simulate_resize(current.bins - 1, &guard), current);
+    assert_eq!(map.iter(&guard).count(), 1000);
+}
+
+#[test]
+fn swap_values() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(1, 10);
+    map.insert(2, 20);
+
+    let guard = epoch::pin();
+    assert!(map.swap_values(&1, &2, &guard));
+    assert_eq!(map.get(&1, &guard), Some(&20));
+    assert_eq!(map.get(&2, &guard), Some(&10));
+    assert!(map.swap_values(&1, &1, &guard));
+    assert_eq!(map.get(&1, &guard), Some(&20));
+
+    // nothing changes if either key is missing
+    assert!(!map.swap_values(&1, &3, &guard));
+    assert!(!map.swap_values(&3, &2, &guard));
+    assert_eq!(map.get(&1, &guard), Some(&20));
+    assert_eq!(map.get(&2, &guard), Some(&10));
+    assert_eq!(map.get(&3, &guard), None);
+}
+
+#[test]
+fn fold_key_into() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    assert!(map.fold_key_into(&10, 20, |into, from| into + from, &guard));
+    assert_eq!(map.get(&10, &guard), None);
+    assert_eq!(map.get(&20, &guard), Some(&30));
+    assert_eq!(map.len(), 99);
+
+    // an absent `into` takes over the value as is
+    assert!(map.fold_key_into(&20, 1000, |_, _| unreachable!(), &guard));
+    assert_eq!(map.get(&20, &guard), None);
+    assert_eq!(map.get(&1000, &guard), Some(&30));
+    assert_eq!(map.len(), 99);
+
+    assert!(!map.fold_key_into(&10, 30, |_, _| unreachable!(), &guard));
+    assert_eq!(map.get(&30, &guard), Some(&30));
+    assert!(map.fold_key_into(&30, 30, |_, _| unreachable!(), &guard));
+    assert_eq!(map.get(&30, &guard), Some(&30));
+    assert_eq!(map.len(), 99);
+}
+
+#[test]
+fn fold_key_into_colliding() {
+    // every key is in the same bin, so there is only one lock to take
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    for i in 0..4 {
+        map.insert(i, 1 << i);
+    }
+
+    let guard = epoch::pin();
+    // the head of the bin into the middle of it
+    assert!(map.fold_key_into(&0, 2, |into, from| into + from, &guard));
+    // the tail of the bin into a new key after it
+    assert!(map.fold_key_into(&3, 4, |_, _| unreachable!(), &guard));
+    assert!(map.fold_key_into(&4, 1, |into, from| into + from, &guard));
+    let mut entries: Vec<_> = map.iter(&guard).map(|(&k, &v)| (k, v)).collect();
+    entries.sort_unstable();
+    assert_eq!(entries, vec![(1, 10), (2, 5)]);
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn fold_key_into_concurrent() {
+    const KEYS: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..KEYS {
+        map.insert(i, 1);
+    }
+
+    // two threads fold each key into its neighbor and back, in opposite directions
+    let threads: Vec<_> = (0..2)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for _ in 0..50 {
+                    for i in 0..KEYS {
+                        let j = (i + 1) % KEYS;
+                        let (from, into) = if t == 0 { (i, j) } else { (j, i) };
+                        map.fold_key_into(&from, into, |a, b| a + b, &guard);
+                    }
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    // no value was lost or counted twice
+    let guard = epoch::pin();
+    assert_eq!(map.values(&guard).sum::<usize>(), KEYS);
+    assert_eq!(map.len(), map.iter(&guard).count());
+}
+
+#[test]
+fn fold_key_into_while_iterating() {
+    const KEYS: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..KEYS {
+        map.insert(i, 1);
+    }
+
+    // keys are folded into their neighbor and back again, so they keep disappearing and
+    // reappearing under the iterators
+    let folder = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            for _ in 0..100 {
+                for i in 0..KEYS {
+                    map.fold_key_into(&i, (i + 1) % KEYS, |a, b| a + b, &guard);
+                }
+                for i in 0..KEYS {
+                    map.fold_key_into(&i, (i + KEYS - 1) % KEYS, |a, b| a + b, &guard);
+                }
+            }
+        })
+    };
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    let guard = epoch::pin();
+                    for (k, v) in map.iter(&guard) {
+                        assert!(*k < KEYS);
+                        assert!(*v >= 1 && *v <= KEYS);
+                    }
+                }
+            })
+        })
+        .collect();
+    folder.join().unwrap();
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(map.values(&guard).sum::<usize>(), KEYS);
+}
+
+#[test]
+fn skew_report() {
+    let guard = epoch::pin();
+    let good = FlurryHashMap::<usize, usize>::new();
+    let skew = good.skew_report(&guard);
+    assert_eq!((skew.max_to_mean, skew.gini), (0.0, 0.0));
+    for i in 0..1500 {
+        good.insert(i, i);
+    }
+    let bad = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    for i in 0..200 {
+        bad.insert(i, i);
+    }
+
+    let skew = good.skew_report(&guard);
+    assert!(skew.gini > 0.4 && skew.gini < 0.75, "{:?}", skew);
+    assert!(skew.max_to_mean < 15.0, "{:?}", skew);
+    let bins = bad.bin_stats(&guard).bins;
+    let skew = bad.skew_report(&guard);
+    assert!(skew.gini > 0.99, "{:?}", skew);
+    assert!((skew.max_to_mean - bins as f64).abs() < 1e-9, "{:?}", skew);
+}
+
+#[test]
+fn swap_values_colliding() {
+    // both keys end up in the same bin, so there is only one lock to take
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    for i in 0..3 {
+        map.insert(i, i * 10);
+    }
+
+    let guard = epoch::pin();
+    assert!(map.swap_values(&0, &2, &guard));
+    assert_eq!(map.get(&0, &guard), Some(&20));
+    assert_eq!(map.get(&1, &guard), Some(&10));
+    assert_eq!(map.get(&2, &guard), Some(&0));
+}
+
+#[test]
+fn swap_values_concurrent() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    map.insert(1, 1);
+    map.insert(2, 2);
+
+    let swapper = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            for _ in 0..10_000 {
+                assert!(map.swap_values(&1, &2, &guard));
+            }
+        })
+    };
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10_000 {
+                    let guard = epoch::pin();
+                    // the two reads are not a snapshot, so only each value on its own is checked
+                    for key in &[1, 2] {
+                        if let Some(v) = map.get(key, &guard) {
+                            assert!(*v == 1 || *v == 2);
+                        }
+                    }
+                }
+            })
+        })
+        .collect();
+    swapper.join().unwrap();
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    let mut values = vec![*map.get(&1, &guard).unwrap(), *map.get(&2, &guard).unwrap()];
+    values.sort();
+    assert_eq!(values, vec![1, 2]);
+}
+
+#[test]
+fn swap_values_while_iterating() {
+    const KEYS: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..KEYS {
+        map.insert(i, i);
+    }
+
+    let swapper = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            for n in 0..10_000 {
+                let (a, b) = (n % KEYS, (n * 7 + 1) % KEYS);
+                map.swap_values(&a, &b, &guard);
+            }
+        })
+    };
+    let readers: Vec<_> = (0..2)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    let guard = epoch::pin();
+                    // every key stays present, and the values seen are still a permutation of
+                    // the original ones, with none of them seen twice
+                    let mut seen = [false; KEYS];
+                    for (_, v) in map.iter(&guard) {
+                        assert!(!seen[*v], "{} seen twice", v);
+                        seen[*v] = true;
+                    }
+                    assert!(seen.iter().all(|&s| s));
+                }
+            })
+        })
+        .collect();
+    swapper.join().unwrap();
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    let mut values: Vec<_> = map.values(&guard).copied().collect();
+    values.sort_unstable();
+    assert_eq!(values, (0..KEYS).collect::<Vec<_>>());
+}
+
+#[test]
+fn insert_outcome() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(
+        map.insert_outcome(1, 10, &guard),
+        InsertOutcome::Inserted { resized: false }
+    );
+    let outcome = map.insert_outcome(1, 11, &guard);
+    assert_eq!(
+        outcome,
+        InsertOutcome::Replaced {
+            old: &10,
+            resized: false
+        }
+    );
+    assert_eq!(outcome.old(), Some(&10));
+    assert_eq!(map.get(&1, &guard), Some(&11));
+}
+
+#[test]
+fn replace_returning() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.replace_returning(&1, 10, &guard), None);
+    assert!(map.is_empty());
+    assert_eq!(map.get(&1, &guard), None);
+
+    map.insert(1, 10);
+    assert_eq!(map.replace_returning(&1, 20, &guard), Some((&10, &20)));
+    assert_eq!(map.replace_returning(&1, 30, &guard), Some((&20, &30)));
+    assert_eq!(map.get(&1, &guard), Some(&30));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn insert_outcome_resize() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    map.insert(0, 0);
+    let bins = map.bin_stats(&guard).bins;
+
+    // keep inserting until the map outgrows its first table
+    let resized_at = (1..1000)
+        .find(|&i| map.insert_outcome(i, i, &guard).resized())
+        .expect("the map never resized");
+    assert!(map.bin_stats(&guard).bins > bins);
+    // the insertions before it reported no resize, and the map is complete
+    assert!(resized_at >= bins / 2);
+    assert_eq!(map.len(), resized_at + 1);
+    for i in 0..=resized_at {
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+}
+
+#[test]
+fn stream_to() {
+    let map = FlurryHashMap::<usize, String>::new();
+    for i in 0..100 {
+        map.insert(i, i.to_string());
+    }
+
+    let guard = epoch::pin();
+    let mut all = Vec::new();
+    assert!(map.stream_to(
+        |e| {
+            all.push(e);
+            true
+        },
+        &guard
+    ));
+    all.sort();
+    assert_eq!(
+        all,
+        (0..100).map(|i| (i, i.to_string())).collect::<Vec<_>>()
+    );
+
+    // stops as soon as the consumer signals
+    let mut some = 0;
+    let completed = map.stream_to(
+        |_| {
+            some += 1;
+            some < 10
+        },
+        &guard,
+    );
+    assert!(!completed);
+    assert_eq!(some, 10);
+}
+
+#[test]
+fn drain_to_sender() {
+    use std::time::{Duration, Instant};
+
+    let map = Arc::new(FlurryHashMap::<usize, String>::new());
+    for i in 0..1000 {
+        map.insert(i, i.to_string());
+    }
+
+    let (tx, rx) = crossbeam::channel::bounded(4);
+    let producer = {
+        let map = map.clone();
+        std::thread::spawn(move || map.drain_to_sender(&tx, &epoch::pin()))
+    };
+
+    // the producer fills the channel, removes one more entry, and then waits for us
+    let start = Instant::now();
+    while map.len() > 995 {
+        assert!(start.elapsed() < Duration::from_secs(10));
+        std::thread::yield_now();
+    }
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(map.len(), 995);
+    assert_eq!(rx.len(), 4);
+
+    let mut received: Vec<_> = rx.iter().collect();
+    assert_eq!(producer.join().unwrap().unwrap(), 1000);
+    assert!(map.is_empty());
+    received.sort_unstable();
+    assert_eq!(
+        received,
+        (0..1000).map(|i| (i, i.to_string())).collect::<Vec<_>>()
+    );
+
+    // without a receiver, the entry that could not be sent is handed back
+    map.insert(1, "one".to_string());
+    map.insert(2, "two".to_string());
+    let (tx, rx) = crossbeam::channel::bounded(1);
+    drop(rx);
+    let lost = map.drain_to_sender(&tx, &epoch::pin()).unwrap_err().0;
+    assert_eq!(map.len(), 1);
+    assert!(!map.contains_key(&lost.0));
+}
+
+#[test]
+fn take_all() {
+    let map = FlurryHashMap::<usize, String>::new();
+    let guard = epoch::pin();
+    assert!(map.take_all(&guard).is_empty());
+
+    for i in 0..1000 {
+        map.insert(i, i.to_string());
+    }
+    let mut taken = map.take_all(&guard);
+    assert!(map.is_empty());
+    assert_eq!(map.iter(&guard).count(), 0);
+    taken.sort_unstable();
+    assert_eq!(
+        taken,
+        (0..1000).map(|i| (i, i.to_string())).collect::<Vec<_>>()
+    );
+
+    // the map is usable again afterwards
+    map.insert(1, "one".to_string());
+    assert_eq!(map.take_all(&guard), vec![(1, "one".to_string())]);
+}
+
+#[test]
+fn take_all_concurrent() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..10_000 {
+        map.insert(i, i);
+    }
+
+    // two takers never take the same entry
+    let takers: Vec<_> = (0..2)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || map.take_all(&epoch::pin()))
+        })
+        .collect();
+    let mut taken: Vec<_> = takers.into_iter().flat_map(|t| t.join().unwrap()).collect();
+    assert!(map.is_empty());
+    taken.sort_unstable();
+    assert_eq!(taken, (0..10_000).map(|i| (i, i)).collect::<Vec<_>>());
+}
+
+#[test]
+fn stream_to_channel() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..1000 {
+        map.insert(i, i * 2);
+    }
+
+    let (tx, rx) = std::sync::mpsc::sync_channel(8);
+    let producer = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            map.stream_to(|e| tx.send(e).is_ok(), &guard)
+        })
+    };
+    let mut received: Vec<_> = rx.iter().collect();
+    assert!(producer.join().unwrap());
+    received.sort();
+    assert_eq!(received, (0..1000).map(|i| (i, i * 2)).collect::<Vec<_>>());
+}
+
+/// A key whose hash includes a counter that can be changed after the key is inserted.
+#[derive(Debug, Clone)]
+struct MutableKey {
+    id: usize,
+    salt: Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl std::hash::Hash for MutableKey {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+        self.salt
+            .load(std::sync::atomic::Ordering::SeqCst)
+            .hash(state);
+    }
+}
+
+impl PartialEq for MutableKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for MutableKey {}
+
+#[test]
+fn unchanged_key_hash() {
+    let map = FlurryHashMap::<MutableKey, usize>::new();
+    let salt = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let key = MutableKey { id: 1, salt };
+    map.insert(key.clone(), 1);
+    map.insert(key.clone(), 2);
+
+    let guard = epoch::pin();
+    assert_eq!(map.get(&key, &guard), Some(&2));
+    assert_eq!(map.remove(&key, &guard), Some(&2));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "the hash of a key changed after it was inserted")]
+fn changed_key_hash() {
+    let map = FlurryHashMap::<MutableKey, usize>::new();
+    let salt = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    map.insert(
+        MutableKey {
+            id: 1,
+            salt: salt.clone(),
+        },
+        1,
+    );
+    salt.store(1, std::sync::atomic::Ordering::SeqCst);
+
+    // an equal key with the hash the inserted key used to have still finds it
+    let lookup = MutableKey {
+        id: 1,
+        salt: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+    };
+    let guard = epoch::pin();
+    map.get(&lookup, &guard);
+}
+
+#[test]
+fn compute_if_absent_many() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(2, 200);
+    map.insert(4, 400);
+
+    let guard = epoch::pin();
+    let calls = std::cell::Cell::new(0);
+    let inserted = map.compute_if_absent_many(
+        vec![1, 2, 3, 4, 5, 3],
+        |keys| {
+            calls.set(calls.get() + 1);
+            assert_eq!(keys, &[&1, &3, &5]);
+            keys.iter().map(|&&k| k * 10).collect()
+        },
+        &guard,
+    );
+    assert_eq!(calls.get(), 1);
+    assert_eq!(inserted, 3);
+    for &(k, v) in &[(1, 10), (2, 200), (3, 30), (4, 400), (5, 50)] {
+        assert_eq!(map.get(&k, &guard), Some(&v));
+    }
+
+    // nothing to compute once every key is present
+    let inserted = map.compute_if_absent_many(1..=5, |_| unreachable!(), &guard);
+    assert_eq!(inserted, 0);
+}
+
+#[test]
+fn compute_if_absent_many_raced() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    let inserted = map.compute_if_absent_many(
+        0..4,
+        |keys| {
+            // as if another thread got to one of the keys first
+            map.insert(2, 2);
+            keys.iter().map(|&&k| k * 10).collect()
+        },
+        &guard,
+    );
+    assert_eq!(inserted, 3);
+    assert_eq!(map.get(&2, &guard), Some(&2));
+    assert_eq!(map.get(&3, &guard), Some(&30));
+}
+
+#[test]
+fn get_or_try_init_blocking() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+
+    let v = map.get_or_try_init_blocking(1, |&k| Ok::<_, ()>(k * 10), &guard);
+    assert_eq!(v, Ok(&10));
+    // present keys are not initialized again
+    let v = map.get_or_try_init_blocking(1, |_| -> Result<usize, ()> { unreachable!() }, &guard);
+    assert_eq!(v, Ok(&10));
+
+    let v = map.get_or_try_init_blocking(2, |_| Err("unavailable"), &guard);
+    assert_eq!(v, Err("unavailable"));
+    assert_eq!(map.get(&2, &guard), None);
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn get_or_try_init_blocking_race() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let barrier = Arc::new(std::sync::Barrier::new(4));
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            let barrier = barrier.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                let v = map.get_or_try_init_blocking(
+                    0,
+                    |_| {
+                        // make every thread compute a value of its own before any is stored
+                        barrier.wait();
+                        Ok::<_, ()>(t)
+                    },
+                    &guard,
+                );
+                *v.unwrap()
+            })
+        })
+        .collect();
+    let seen: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+
+    // every thread returned the one value that was stored
+    let guard = epoch::pin();
+    let stored = *map.get(&0, &guard).unwrap();
+    assert!(seen.iter().all(|&v| v == stored));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn refresh_values() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    // odd keys are gone from the source
+    map.refresh_values(|&k| if k % 2 == 0 { Some(k * 3) } else { None }, &guard);
+    assert_eq!(map.len(), 50);
+    for i in 0..100 {
+        if i % 2 == 0 {
+            assert_eq!(map.get(&i, &guard), Some(&(i * 3)));
+        } else {
+            assert_eq!(map.get(&i, &guard), None);
+        }
+    }
+
+    let empty = FlurryHashMap::<usize, usize>::new();
+    empty.refresh_values(|_| unreachable!(), &guard);
+    assert!(empty.is_empty());
+}
+
+/// Has 4 threads repeatedly merge into the same key, holding its bin lock for a while each time.
+fn contend(map: &Arc<FlurryHashMap<usize, usize>>) {
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for _ in 0..200 {
+                    map.merge(
+                        0,
+                        1,
+                        |a, b| {
+                            std::thread::sleep(std::time::Duration::from_micros(50));
+                            a + b
+                        },
+                        &guard,
+                    );
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+#[test]
+fn adaptive_resize() {
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize>::builder()
+            .adaptive_resize()
+            .build(),
+    );
+    // well below the load factor, but not sparse
+    for i in 0..8 {
+        map.insert(i, 0);
+    }
+    let guard = epoch::pin();
+    let bins = map.bin_stats(&guard).bins;
+    assert_eq!(map.adaptive_state().unwrap().early_resizes, 0);
+
+    contend(&map);
+    assert!(map.adaptive_state().unwrap().early_resizes >= 1);
+    assert!(map.bin_stats(&guard).bins > bins);
+    assert_eq!(map.len(), 8);
+    assert_eq!(map.get(&0, &guard), Some(&800));
+}
+
+#[test]
+fn adaptive_resize_sparse() {
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize>::builder()
+            .adaptive_resize()
+            .build(),
+    );
+    map.insert(0, 0);
+    let guard = epoch::pin();
+    let bins = map.bin_stats(&guard).bins;
+
+    // all the contention is over one key, which more bins would not help with
+    contend(&map);
+    let state = map.adaptive_state().unwrap();
+    assert_eq!(state.early_resizes, 0);
+    assert!(!state.resize_pending);
+    assert_eq!(map.bin_stats(&guard).bins, bins);
+}
+
+#[test]
+fn no_adaptive_resize() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..8 {
+        map.insert(i, 0);
+    }
+    let guard = epoch::pin();
+    let bins = map.bin_stats(&guard).bins;
+    contend(&map);
+    assert!(map.adaptive_state().is_none());
+    assert_eq!(map.bin_stats(&guard).bins, bins);
+}
+
+#[test]
+fn capacity_history() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    assert!(map.capacity_history().is_empty());
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+
+    // the first table, and every resize after it
+    let history = map.capacity_history();
+    let capacities: Vec<_> = history.iter().map(|s| s.capacity).collect();
+    assert_eq!(capacities, vec![16, 32, 64, 128, 256, 512, 1024, 2048]);
+    assert_eq!(history[0].len, 0);
+    assert!(history
+        .windows(2)
+        .all(|w| w[0].at <= w[1].at && w[0].len < w[1].len));
+    let last = history.last().unwrap();
+    assert!(last.len <= 1000);
+    assert_eq!(last.capacity, map.bin_stats(&epoch::pin()).bins);
+
+    map.rebuild(0, RandomState::new(), &epoch::pin());
+    let last = *map.capacity_history().last().unwrap();
+    assert_eq!((last.len, last.capacity), (1000, 2048));
+
+    // only the most recent samples are kept
+    for _ in 0..100 {
+        map.rebuild(0, RandomState::new(), &epoch::pin());
+    }
+    assert_eq!(map.capacity_history().len(), 64);
+}
+
+#[test]
+fn resize_policy() {
+    use std::hash::{BuildHasherDefault, Hasher};
+    use std::sync::Mutex;
+
+    // keys are their own hashes, so that they can be put in the bins of our choosing
+    #[derive(Default)]
+    struct Identity(u64);
+    impl Hasher for Identity {
+        fn finish(&self) -> u64 {
+            self.0
+        }
+        fn write(&mut self, _: &[u8]) {
+            unreachable!("only usize keys are hashed")
+        }
+        fn write_usize(&mut self, n: usize) {
+            self.0 = n as u64;
+        }
+    }
+
+    // doubles the table once a chain is longer than 3
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let policy = {
+        let calls = calls.clone();
+        move |len: usize, capacity: usize, chain_len: usize| {
+            calls.lock().unwrap().push((len, capacity, chain_len));
+            if chain_len > 3 {
+                Some(capacity * 2)
+            } else {
+                None
+            }
+        }
+    };
+    let map = FlurryHashMap::<usize, usize, _>::builder()
+        .hasher(BuildHasherDefault::<Identity>::default())
+        .resize_policy(policy)
+        .build();
+    let guard = epoch::pin();
+    let bins = || map.bin_stats(&guard).bins;
+
+    // one key per bin fills the map up, but chains stay short
+    for i in 0..16 {
+        map.insert(i, i);
+    }
+    assert_eq!(bins(), 16);
+    assert!(calls
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|&(_, c, chain)| c == 16 && chain == 1));
+
+    // bin 0 gets longer with each of these
+    for (i, key) in [16, 32].iter().enumerate() {
+        map.insert(*key, 0);
+        assert_eq!(calls.lock().unwrap().last(), Some(&(17 + i, 16, 2 + i)));
+        assert_eq!(bins(), 16);
+    }
+    map.insert(48, 0);
+    // and the policy is asked again about the new table, in which the chain has been split up
+    assert!(calls.lock().unwrap().ends_with(&[(19, 16, 4), (19, 32, 1)]));
+    assert_eq!(bins(), 32);
+
+    // replacing a value is not an insertion
+    let n = calls.lock().unwrap().len();
+    map.insert(48, 1);
+    assert_eq!(calls.lock().unwrap().len(), n);
+    assert_eq!(map.len(), 19);
+}
+
+#[test]
+fn builtin_resize_policies() {
+    // the load factor is the default
+    let default = FlurryHashMap::<usize, usize>::new();
+    let load_factor = FlurryHashMap::<usize, usize>::builder()
+        .resize_policy(LoadFactor)
+        .build();
+    for i in 0..1000 {
+        default.insert(i, i);
+        load_factor.insert(i, i);
+    }
+    let growth = |map: &FlurryHashMap<usize, usize>| -> Vec<_> {
+        map.capacity_history()
+            .iter()
+            .map(|s| (s.len, s.capacity))
+            .collect()
+    };
+    assert_eq!(growth(&default), growth(&load_factor));
+    assert_eq!(LoadFactor.should_resize(11, 16, 1), None);
+    assert_eq!(LoadFactor.should_resize(12, 16, 1), Some(32));
+
+    // every key is in the same chain
+    let max_chain = FlurryHashMap::<usize, usize, _>::builder()
+        .hasher(ConstantHasher::default())
+        .resize_policy(MaxChain(3))
+        .build();
+    let guard = epoch::pin();
+    for i in 0..3 {
+        max_chain.insert(i, i);
+    }
+    assert_eq!(max_chain.bin_stats(&guard).bins, 16);
+    // the fourth key makes the chain too long. doubling the table does not split it up, but the
+    // inserting thread does not find out, and so does not double the table again.
+    max_chain.insert(3, 3);
+    assert_eq!(max_chain.bin_stats(&guard).bins, 32);
+    // but the map is not grown for how full it is
+    let sparse = FlurryHashMap::<usize, usize>::builder()
+        .resize_policy(MaxChain(usize::MAX))
+        .build();
+    for i in 0..100 {
+        sparse.insert(i, i);
+    }
+    assert_eq!(sparse.bin_stats(&guard).bins, 16);
+
+    // adaptive resizing can be added on top of any policy
+    assert!(max_chain.adaptive_state().is_none());
+    let adaptive = FlurryHashMap::<usize, usize>::builder()
+        .resize_policy(Adaptive::new(MaxChain(3)))
+        .build();
+    assert!(adaptive.adaptive_state().is_some());
+    assert_eq!(Adaptive::default().should_resize_contended(3, 16), None);
+    assert_eq!(Adaptive::default().should_resize_contended(4, 16), Some(32));
+}
+
+#[test]
+fn resize_duration() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    assert_eq!(map.last_resize_duration(), None);
+    assert_eq!(map.average_resize_duration(), None);
+    // the first table is not a resize
+    map.insert(0, 0);
+    assert_eq!(map.last_resize_duration(), None);
+
+    for i in 0..100_000 {
+        map.insert(i, i);
+    }
+    let last = map.last_resize_duration().unwrap();
+    let average = map.average_resize_duration().unwrap();
+    // the last resize moved tens of thousands of entries
+    assert!(last > std::time::Duration::from_nanos(0));
+    assert!(last < std::time::Duration::from_secs(10));
+    assert!(average > std::time::Duration::from_nanos(0));
+    assert!(average <= last);
+}
+
+#[test]
+fn checkpoint_restore() {
+    let map = FlurryHashMap::<usize, String>::new();
+    for i in 0..100 {
+        map.insert(i, i.to_string());
+    }
+
+    let guard = epoch::pin();
+    let checkpoint = map.checkpoint(&guard);
+    assert_eq!(checkpoint.len(), 100);
+
+    // replace, remove, and add entries
+    for i in 0..10 {
+        map.insert(i, "changed".to_string());
+    }
+    for i in 50..60 {
+        map.remove(&i, &guard);
+    }
+    for i in 100..150 {
+        map.insert(i, i.to_string());
+    }
+
+    map.restore_from(&checkpoint, &guard);
+    assert_eq!(map.len(), 100);
+    for (k, v) in checkpoint.entries() {
+        assert_eq!(map.get(k, &guard), Some(v));
+    }
+    assert_eq!(map.get(&120, &guard), None);
+
+    // a new map from the checkpoint hashes the same way
+    let copy = FlurryHashMap::from_checkpoint(checkpoint.clone());
+    assert_eq!(copy.len(), 100);
+    for (k, v) in checkpoint.entries() {
+        assert_eq!(copy.get(k, &guard), Some(v));
+    }
+    let empty = FlurryHashMap::<usize, usize>::new().checkpoint(&guard);
+    assert!(empty.is_empty());
+    assert!(FlurryHashMap::from_checkpoint(empty).is_empty());
+}
+
+#[test]
+fn migrate_values() {
+    let map = FlurryHashMap::<usize, u32>::new();
+    for i in 0..1000 {
+        map.insert(i, i as u32 * 2);
+    }
+
+    let migrated: FlurryHashMap<usize, String> = map.migrate_values(|k, v| format!("{}:{}", k, v));
+    assert_eq!(migrated.len(), 1000);
+    let guard = epoch::pin();
+    for i in 0..1000 {
+        assert_eq!(
+            migrated.get(&i, &guard).map(String::as_str),
+            Some(format!("{}:{}", i, i * 2).as_str())
+        );
+    }
+    assert_eq!(migrated.get(&1000, &guard), None);
+    // the hash builder is kept, so the old keys are still found where writes look for them
+    migrated.insert(0, "replaced".to_string());
+    migrated.insert(1000, "new".to_string());
+    assert_eq!(migrated.len(), 1001);
+    assert_eq!(migrated.get(&0, &guard).unwrap(), "replaced");
+
+    let empty = FlurryHashMap::<usize, u32>::new().migrate_values(|_, v| u64::from(*v));
+    assert!(empty.is_empty());
+}
+
+#[test]
+fn any() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert!(!map.any(|_, _| true, &guard));
+    for i in 0..10_000 {
+        map.insert(i, i * 2);
+    }
+
+    for &(k, expected) in &[(4567, true), (20_000, false)] {
+        let calls = AtomicUsize::new(0);
+        let found = map.any(
+            |_, v| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                *v == k * 2
+            },
+            &guard,
+        );
+        assert_eq!(found, expected);
+        assert_eq!(found, map.iter(&guard).any(|(_, v)| *v == k * 2));
+        if !expected {
+            // nothing matched, so every entry was checked
+            assert_eq!(calls.load(Ordering::SeqCst), 10_000);
+        }
+    }
+
+    // every entry matches, so the search stops long before it gets through them all
+    let calls = AtomicUsize::new(0);
+    assert!(map.any(
+        |_, _| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            true
+        },
+        &guard
+    ));
+    assert!(calls.load(Ordering::SeqCst) < 10_000 / 16);
+}
+
+#[test]
+fn tee_fold() {
+    let map = FlurryHashMap::<usize, u64>::new();
+    let guard = epoch::pin();
+    assert_eq!(
+        map.tee_fold(0, |s, _, v| s + v, 0, |c, _, _| c + 1, &guard),
+        (0, 0)
+    );
+
+    for i in 0..500 {
+        map.insert(i, (i * 7 % 101) as u64);
+    }
+    let (sum, count) = map.tee_fold(0, |s, _, v| s + v, 0, |c, _, _| c + 1, &guard);
+    assert_eq!(sum, map.values(&guard).sum::<u64>());
+    assert_eq!(count, map.iter(&guard).count());
+
+    // the accumulators may be of different types, and see the keys too
+    let (max_key, min_value) = map.tee_fold(
+        None,
+        |m: Option<usize>, k, _| Some(m.map_or(*k, |m| m.max(*k))),
+        u64::MAX,
+        |m, _, v| m.min(*v),
+        &guard,
+    );
+    assert_eq!(max_key, Some(499));
+    assert_eq!(min_value, 0);
+}
+
+#[test]
+fn distinct_value_count() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.distinct_value_count(&guard), 0);
+
+    for i in 0..100 {
+        map.insert(i, 7);
+    }
+    assert_eq!(map.distinct_value_count(&guard), 1);
+
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.distinct_value_count(&guard), map.len());
+
+    for i in 0..50 {
+        map.insert(i, i + 50);
+    }
+    assert_eq!(map.distinct_value_count(&guard), 50);
+}
+
+#[test]
+fn snapshot_shared() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    let snapshot = map.snapshot_shared(&epoch::pin());
+    assert_eq!(snapshot.len(), 100);
+
+    let writer = {
+        let map = map.clone();
+        std::thread::spawn(move || {
+            let guard = epoch::pin();
+            for i in 0..100 {
+                map.insert(i, i + 1);
+                map.insert(i + 100, i);
+                map.remove(&(i / 2), &guard);
+            }
+        })
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let snapshot = snapshot.clone();
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    assert_eq!(snapshot.len(), 100);
+                    for i in 0..100 {
+                        assert_eq!(snapshot.get(&i), Some(&i));
+                    }
+                    assert!(!snapshot.contains_key(&100));
+                }
+            })
+        })
+        .collect();
+    writer.join().unwrap();
+    for r in readers {
+        r.join().unwrap();
+    }
+
+    // the old snapshot is unaffected, and a new one sees the changes
+    assert!(snapshot.iter().all(|(k, v)| k == v));
+    let guard = epoch::pin();
+    let fresh = map.snapshot_shared(&guard);
+    assert_eq!(fresh.len(), map.len());
+    assert_eq!(fresh.get(&99), Some(&100));
+    assert!(!fresh.contains_key(&0));
+}
+
+#[test]
+fn export_json_lines() {
+    use flurry::export::ExportFormat;
+
+    // the inverse of the escaping of JSON strings
+    fn unescape(s: &str) -> String {
+        let s = &s[1..s.len() - 1];
+        let mut out = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                out.push(c);
+                continue;
+            }
+            match chars.next().unwrap() {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(std::char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                }
+                c => out.push(c),
+            }
+        }
+        out
+    }
+
+    let map = FlurryHashMap::<usize, String>::new();
+    for i in 0..100 {
+        map.insert(i, format!("v{}", i));
+    }
+    map.insert(
+        100,
+        "quote \" backslash \\ comma , newline \n bell \u{7} é".to_string(),
+    );
+
+    let mut out = Vec::new();
+    map.export(&mut out, ExportFormat::JsonLines, &epoch::pin())
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    assert_eq!(out.lines().count(), 101);
+
+    let mut entries: Vec<(usize, String)> = out
+        .lines()
+        .map(|line| {
+            let line = line.strip_prefix("{\"key\":").unwrap();
+            let line = line.strip_suffix('}').unwrap();
+            let mut parts = line.splitn(2, ",\"value\":");
+            let key = parts.next().unwrap().parse().unwrap();
+            (key, unescape(parts.next().unwrap()))
+        })
+        .collect();
+    entries.sort_unstable();
+    let guard = epoch::pin();
+    let mut expected: Vec<_> = map.iter(&guard).map(|(k, v)| (*k, v.clone())).collect();
+    expected.sort_unstable();
+    assert_eq!(entries, expected);
+}
+
+#[test]
+fn export_csv() {
+    use flurry::export::ExportFormat;
+
+    let map = FlurryHashMap::<&str, i32>::new();
+    map.insert("plain", -1);
+    map.insert("with, comma", 2);
+    map.insert("with \"quotes\"", 3);
+
+    let mut out = Vec::new();
+    map.export(&mut out, ExportFormat::Csv, &epoch::pin())
+        .unwrap();
+    let out = String::from_utf8(out).unwrap();
+    let mut lines: Vec<_> = out.lines().collect();
+    assert_eq!(lines.remove(0), "key,value");
+    lines.sort_unstable();
+    assert_eq!(
+        lines,
+        vec!["\"with \"\"quotes\"\"\",3", "\"with, comma\",2", "plain,-1"]
+    );
+
+    // the first error from the writer is returned
+    struct Full;
+    impl std::io::Write for Full {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::WriteZero.into())
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+    let err = map
+        .export(&mut Full, ExportFormat::Csv, &epoch::pin())
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+}
+
+#[test]
+fn build_sorted_index() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    let empty = map.build_sorted_index(&guard);
+    assert!(empty.is_empty());
+    assert_eq!(empty.binary_search(&1), Err(0));
+    assert!(empty.range(..).is_empty());
+
+    // the even numbers below 200
+    for i in (0..200).step_by(2) {
+        map.insert(i, i);
+    }
+    let index = map.build_sorted_index(&guard);
+    assert_eq!(index.len(), 100);
+    assert_eq!(
+        index.as_slice(),
+        &(0..200).step_by(2).collect::<Vec<_>>()[..]
+    );
+    assert_eq!(index.binary_search(&0), Ok(0));
+    assert_eq!(index.binary_search(&84), Ok(42));
+    assert_eq!(index.binary_search(&85), Err(43));
+    assert_eq!(index.binary_search(&500), Err(100));
+    assert!(index.contains(&198));
+    assert!(!index.contains(&199));
+
+    assert_eq!(index.range(10..16), &[10, 12, 14]);
+    assert_eq!(index.range(9..=16), &[10, 12, 14, 16]);
+    assert_eq!(index.range(195..), &[196, 198]);
+    assert_eq!(index.range(..3), &[0, 2]);
+    assert!(index.range(20..20).is_empty());
+    assert!(index.range(300..).is_empty());
+
+    // later writes are not reflected
+    map.insert(1, 1);
+    map.remove(&0, &guard);
+    assert!(!index.contains(&1));
+    assert!(index.contains(&0));
+}
+
+#[test]
+fn freeze() {
+    let map = FlurryHashMap::<String, usize>::new();
+    for i in 0..100 {
+        map.insert(i.to_string(), i);
+    }
+    let frozen = map.freeze();
+    assert_eq!(frozen.len(), 100);
+    assert!(!frozen.is_empty());
+    for i in 0..100 {
+        let key = i.to_string();
+        assert_eq!(frozen.get(&key), Some(&i));
+        assert!(frozen.contains_key(&key));
+        assert_eq!(frozen[&key], i);
+    }
+    assert_eq!(frozen.get(&"100".to_string()), None);
+    assert!(!frozen.contains_key(&"100".to_string()));
+
+    let mut entries: Vec<(usize, usize)> = (&frozen)
+        .into_iter()
+        .map(|(k, v)| (k.parse().unwrap(), *v))
+        .collect();
+    entries.sort_unstable();
+    assert_eq!(entries, (0..100).map(|i| (i, i)).collect::<Vec<_>>());
+}
+
+#[test]
+#[should_panic]
+fn freeze_index_missing() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(1, 1);
+    let frozen = map.freeze();
+    let _ = frozen[&2];
+}
+
+#[test]
+fn replicate_into() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..1000 {
+        map.insert(i, i * 2);
+    }
+
+    let replica = FlurryHashMap::<usize, usize>::new();
+    let mut cursor = ReplicationCursor::new();
+    let mut calls = 1;
+    while map.replicate_into(&replica, &mut cursor, 64, &epoch::pin()) {
+        calls += 1;
+        assert!(replica.len() <= calls * 64);
+    }
+    assert!(calls >= 1000 / 64);
+    let guard = epoch::pin();
+    assert_eq!(replica.len(), 1000);
+    assert!(map.diff(&replica, &guard).is_empty());
+
+    // the next pass starts over, and picks up what changed since
+    map.insert(1000, 0);
+    map.insert(0, 0);
+    while map.replicate_into(&replica, &mut cursor, 1000, &guard) {}
+    assert!(map.diff(&replica, &guard).is_empty());
+
+    let empty = FlurryHashMap::<usize, usize>::new();
+    assert!(!empty.replicate_into(&replica, &mut ReplicationCursor::new(), 1, &guard));
+}
+
+#[test]
+fn replicate_into_several() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..1000 {
+        map.insert(i, i * 2);
+    }
+
+    // passes to different replicas that take turns each copy every entry
+    let guard = epoch::pin();
+    let replicas = [
+        FlurryHashMap::<usize, usize>::new(),
+        FlurryHashMap::<usize, usize>::new(),
+    ];
+    let mut cursors = [ReplicationCursor::new(), ReplicationCursor::new()];
+    let mut more = [true, true];
+    while more.iter().any(|&m| m) {
+        for r in 0..2 {
+            if more[r] {
+                more[r] = map.replicate_into(&replicas[r], &mut cursors[r], 10, &guard);
+            }
+        }
+    }
+    for replica in &replicas {
+        assert!(map.diff(replica, &guard).is_empty());
+    }
+
+    // the replica's own validator and soft cap apply
+    let odd = FlurryHashMap::<usize, usize>::builder()
+        .validator(|k: &usize, _: &usize| k % 2 == 1)
+        .build();
+    while map.replicate_into(&odd, &mut ReplicationCursor::new(), 2000, &guard) {}
+    assert_eq!(odd.len(), 500);
+    assert!(odd.keys(&guard).all(|k| k % 2 == 1));
+    let capped = FlurryHashMap::<usize, usize>::builder()
+        .soft_cap(100, EvictionPolicy::Random)
+        .build();
+    while map.replicate_into(&capped, &mut ReplicationCursor::new(), 2000, &guard) {}
+    assert_eq!(capped.len(), 100);
+}
+
+#[test]
+fn iter_step() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i * 2);
+    }
+
+    let mut cursor = Cursor::new();
+    let mut seen = std::collections::HashMap::new();
+    let mut calls = 0;
+    while !cursor.is_finished() {
+        let guard = epoch::pin();
+        seen.extend(map.iter_step(&mut cursor, 3, &guard));
+        calls += 1;
+        // grow the map, and with it the table, between calls
+        map.insert(100 + calls, 0);
+    }
+    // the table had at least 128 bins when the scan started
+    assert!(calls >= 128 / 3);
+    for i in 0..100 {
+        assert_eq!(seen.get(&i), Some(&(i * 2)));
+    }
+
+    let guard = epoch::pin();
+    assert!(map.iter_step(&mut cursor, 3, &guard).is_empty());
+    let empty = FlurryHashMap::<usize, usize>::new();
+    let mut cursor = Cursor::new();
+    assert!(empty.iter_step(&mut cursor, 3, &guard).is_empty());
+    assert!(cursor.is_finished());
+}
+
+#[test]
+fn swap_contents() {
+    let hasher = RandomState::new();
+    let a = Arc::new(FlurryHashMap::<usize, usize, _>::with_hasher(
+        hasher.clone(),
+    ));
+    let b = Arc::new(FlurryHashMap::<usize, usize, _>::with_hasher(hasher));
+    for i in 0..100 {
+        a.insert(i, i);
+    }
+    for i in 1000..1500 {
+        b.insert(i, i);
+    }
+
+    let reader = {
+        let a = Arc::clone(&a);
+        std::thread::spawn(move || {
+            for _ in 0..100 {
+                let guard = epoch::pin();
+                let mut keys: Vec<_> = a.keys(&guard).copied().collect();
+                keys.sort_unstable();
+                // either all of one set of entries, or all of the other
+                assert!(
+                    keys == (0..100).collect::<Vec<_>>()
+                        || keys == (1000..1500).collect::<Vec<_>>()
+                );
+            }
+        })
+    };
+    for _ in 0..11 {
+        a.swap_contents(&b, &epoch::pin());
+    }
+    reader.join().unwrap();
+
+    let guard = epoch::pin();
+    assert_eq!(a.len(), 500);
+    assert_eq!(b.len(), 100);
+    assert!((1000..1500).all(|i| a.get(&i, &guard) == Some(&i)));
+    assert!((0..100).all(|i| b.get(&i, &guard) == Some(&i)));
+    assert_eq!(a.get(&0, &guard), None);
+
+    // both keep working as usual afterwards
+    for i in 0..1000 {
+        b.insert(i, i);
+    }
+    assert_eq!(b.len(), 1000);
+    a.swap_contents(&a, &guard);
+    assert_eq!(a.len(), 500);
+}
+
+#[test]
+fn swap_contents_with_other_hasher() {
+    // each map has a hash builder with a seed of its own, which goes along with its entries
+    let a = FlurryHashMap::<usize, usize>::new();
+    let b = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        a.insert(i, i);
+        b.insert(i + 100, i);
+    }
+    let guard = epoch::pin();
+    a.swap_contents(&b, &guard);
+    assert!((0..100).all(|i| a.get(&(i + 100), &guard) == Some(&i)));
+    assert!((0..100).all(|i| b.get(&i, &guard) == Some(&i)));
+    a.insert(0, 0);
+    b.remove(&0, &guard);
+    assert_eq!((a.len(), b.len()), (101, 99));
+}
+
+#[test]
+fn for_each_owned_in_drop() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Registry {
+        handles: FlurryHashMap<usize, String>,
+        released: Arc<AtomicUsize>,
+    }
+
+    impl Drop for Registry {
+        fn drop(&mut self) {
+            let released = &self.released;
+            std::mem::take(&mut self.handles).for_each_owned(|k, v| {
+                assert_eq!(v, k.to_string());
+                released.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+    }
+
+    let released = Arc::new(AtomicUsize::new(0));
+    let registry = Registry {
+        handles: FlurryHashMap::new(),
+        released: Arc::clone(&released),
+    };
+    for i in 0..100 {
+        registry.handles.insert(i, i.to_string());
+    }
+    registry.handles.remove(&0, &epoch::pin());
+    drop(registry);
+    assert_eq!(released.load(Ordering::SeqCst), 99);
+}
+
+#[test]
+fn with_weak_self() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+    struct Spawner {
+        map: WeakHandle<usize, Spawner>,
+    }
+
+    impl Spawner {
+        fn spawn(&self, key: usize) {
+            let map = self.map.upgrade().unwrap();
+            map.insert(
+                key,
+                Spawner {
+                    map: self.map.clone(),
+                },
+            );
+        }
+    }
+
+    impl Drop for Spawner {
+        fn drop(&mut self) {
+            DROPPED.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let (map, handle) = FlurryHashMap::with_weak_self();
+    map.insert(0, Spawner { map: handle });
+    {
+        let guard = epoch::pin();
+        map.get(&0, &guard).unwrap().spawn(1);
+        map.get(&1, &guard).unwrap().spawn(2);
+        assert_eq!(map.len(), 3);
+    }
+
+    let handle = map.get(&0, &epoch::pin()).unwrap().map.clone();
+    drop(map);
+    // the handles in the values did not keep the map alive
+    assert!(handle.upgrade().is_none());
+    assert_eq!(DROPPED.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn reset_to() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    let before = map.iter(&guard);
+    map.reset_to((50..150).map(|i| (i, i * 2)).chain(Some((50, 0))), &guard);
+
+    assert_eq!(map.len(), 100);
+    let mut entries: Vec<_> = map.iter(&guard).map(|(&k, &v)| (k, v)).collect();
+    entries.sort_unstable();
+    let mut expected: Vec<_> = (50..150).map(|i| (i, i * 2)).collect();
+    expected[0] = (50, 0);
+    assert_eq!(entries, expected);
+    assert_eq!(map.get(&0, &guard), None);
+    // an iterator from before the reset only sees the old entries
+    let mut old: Vec<_> = before.map(|(&k, _)| k).collect();
+    old.sort_unstable();
+    assert_eq!(old, (0..100).collect::<Vec<_>>());
+
+    // the map keeps working as usual afterwards
+    map.reset_to(None, &guard);
+    assert!(map.is_empty());
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.len(), 1000);
+    assert_eq!(map.get(&999, &guard), Some(&999));
+}
+
+#[test]
+fn rebuild() {
+    let map = FlurryHashMap::<usize, String>::new();
+    for i in 0..1000 {
+        map.insert(i, i.to_string());
+    }
+    let guard = epoch::pin();
+    for i in 10..1000 {
+        map.remove(&i, &guard);
+    }
+    assert_eq!(map.bin_stats(&guard).bins, 2048);
+
+    // shrinks to fit the entries that are left
+    map.rebuild(0, RandomState::new(), &guard);
+    assert_eq!(map.bin_stats(&guard).bins, 16);
+    assert_eq!(map.len(), 10);
+    for i in 0..10 {
+        assert_eq!(map.get(&i, &guard), Some(&i.to_string()));
+    }
+
+    // and grows ahead of insertions
+    map.rebuild(10_000, RandomState::new(), &guard);
+    assert_eq!(map.bin_stats(&guard).bins, 16384);
+    for i in 0..10_000 {
+        map.insert(i, i.to_string());
+    }
+    assert_eq!(map.bin_stats(&guard).bins, 16384);
+    assert_eq!(map.len(), 10_000);
+    assert_eq!(map.get(&1234, &guard), Some(&"1234".to_string()));
+
+    let empty = FlurryHashMap::<usize, usize>::new();
+    empty.rebuild(100, RandomState::new(), &guard);
+    assert!(empty.is_empty());
+    empty.insert(1, 1);
+    assert_eq!(empty.get(&1, &guard), Some(&1));
+}
+
+#[test]
+fn rebuild_with_new_hasher() {
+    use std::hash::{BuildHasher, Hasher};
+
+    /// Hashes a `u64` key to the key times the factor.
+    #[derive(Clone)]
+    struct Scaled(u64);
+    struct ScaledHasher(u64, u64);
+    impl BuildHasher for Scaled {
+        type Hasher = ScaledHasher;
+        fn build_hasher(&self) -> ScaledHasher {
+            ScaledHasher(self.0, 0)
+        }
+    }
+    impl Hasher for ScaledHasher {
+        fn finish(&self) -> u64 {
+            self.1.wrapping_mul(self.0)
+        }
+        fn write_u64(&mut self, n: u64) {
+            self.1 = n;
+        }
+        fn write(&mut self, _: &[u8]) {
+            unreachable!("only u64 keys are hashed");
+        }
+    }
+
+    // every key hashes to 0 at first, as if an attacker had picked them to collide
+    let map = FlurryHashMap::<u64, u64, _>::with_hasher(Scaled(0));
+    let guard = epoch::pin();
+    for i in 0..8 {
+        map.insert(i, i);
+    }
+    assert!(map
+        .iter_with_shard(16, &guard)
+        .all(|(shard, _, _)| shard == 0));
+    assert_eq!(map.bin_stats(&guard).chains[8], 1);
+
+    // and to themselves once the map has been rebuilt, which spreads them out
+    map.rebuild(0, Scaled(1), &guard);
+    for (shard, key, _) in map.iter_with_shard(16, &guard) {
+        assert_eq!(shard as u64, key % 16);
+    }
+    for i in 0..8 {
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+    // later writes place keys with the new hash builder too
+    map.insert(8, 8);
+    assert_eq!(map.get(&8, &guard), Some(&8));
+    let stats = map.bin_stats(&guard);
+    assert_eq!((stats.entries, stats.chains[1]), (9, 9));
+}
+
+#[test]
+fn rebuild_concurrent() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    const KEYS: usize = 200;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    for i in 0..KEYS {
+        map.insert(i, i);
+    }
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let (map, done) = (map.clone(), done.clone());
+        std::thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                let guard = epoch::pin();
+                // every lookup sees one table or the other, and both hold every key
+                for i in 0..KEYS {
+                    assert_eq!(map.get(&i, &guard), Some(&i));
+                }
+                assert_eq!(map.iter(&guard).count(), KEYS);
+            }
+        })
+    };
+    for round in 0..50 {
+        let capacity = if round % 2 == 0 { 0 } else { 4096 };
+        map.rebuild(capacity, RandomState::new(), &epoch::pin());
+    }
+    done.store(true, Ordering::SeqCst);
+    reader.join().unwrap();
+    assert_eq!(map.len(), KEYS);
+}
+
+#[test]
+fn load_snapshot() {
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let guard = epoch::pin();
+    assert_eq!(map.load_snapshot(&guard), (0, 0));
+
+    let done = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let writer = {
+        let map = Arc::clone(&map);
+        let done = Arc::clone(&done);
+        std::thread::spawn(move || {
+            for i in 0..10_000 {
+                map.insert(i, i);
+            }
+            done.store(true, std::sync::atomic::Ordering::SeqCst);
+        })
+    };
+    while !done.load(std::sync::atomic::Ordering::SeqCst) {
+        let guard = epoch::pin();
+        let (len, bins) = map.load_snapshot(&guard);
+        if bins != 0 {
+            assert!(bins.is_power_of_two());
+            assert!(len <= bins);
+        }
+    }
+    writer.join().unwrap();
+
+    let (len, bins) = map.load_snapshot(&guard);
+    assert_eq!(len, 10_000);
+    assert_eq!(bins, map.bin_stats(&guard).bins);
+    let load_factor = len as f64 / bins as f64;
+    assert!(load_factor > 0.0 && load_factor <= 0.75);
+}
+
+#[test]
+fn walk() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    let mut visited = Vec::new();
+    map.walk(
+        |&k, &v| {
+            assert_eq!(k, v);
+            visited.push(k);
+            if visited.len() == 50 {
+                WalkAction::Stop
+            } else if k % 3 == 0 {
+                WalkAction::Replace(k * 10)
+            } else if k % 3 == 1 {
+                WalkAction::Remove
+            } else {
+                WalkAction::Keep
+            }
+        },
+        &guard,
+    );
+
+    assert_eq!(visited.len(), 50);
+    let (acted, rest) = visited.split_at(49);
+    for &k in acted {
+        match k % 3 {
+            0 => assert_eq!(map.get(&k, &guard), Some(&(k * 10))),
+            1 => assert_eq!(map.get(&k, &guard), None),
+            _ => assert_eq!(map.get(&k, &guard), Some(&k)),
+        }
+    }
+    // the entry that stopped the walk, and those after it, are untouched
+    for k in rest
+        .iter()
+        .copied()
+        .chain((0..100).filter(|k| !visited.contains(k)))
+    {
+        assert_eq!(map.get(&k, &guard), Some(&k));
+    }
+}
+
+#[test]
+fn diff_and_apply() {
+    let old = FlurryHashMap::<usize, String>::new();
+    let target = FlurryHashMap::<usize, String>::new();
+    for i in 0..100 {
+        old.insert(i, i.to_string());
+        if i % 10 != 0 {
+            target.insert(i, i.to_string());
+        }
+    }
+    for i in 0..5 {
+        target.insert(i * 10 + 1, "changed".to_string());
+    }
+    for i in 100..120 {
+        target.insert(i, i.to_string());
+    }
+
+    let guard = epoch::pin();
+    let diff = old.diff(&target, &guard);
+    assert_eq!(diff.removed().len(), 10);
+    assert_eq!(diff.changed().len(), 5);
+    assert_eq!(diff.added().len(), 20);
+    assert_eq!(diff.len(), 35);
+    assert!(old.diff(&old, &guard).is_empty());
+    for (k, old_value, new_value) in diff.changed() {
+        assert_eq!(old_value, &k.to_string());
+        assert_eq!(new_value, "changed");
+    }
+
+    // applied to a separate copy of the old state, as when the diff was computed elsewhere
+    let live = FlurryHashMap::from_checkpoint(old.checkpoint(&guard));
+    live.apply_diff(&diff, &guard);
+    assert_eq!(live.len(), target.len());
+    for (k, v) in target.iter(&guard) {
+        assert_eq!(live.get(k, &guard), Some(v));
+    }
+    assert!(live.diff(&target, &guard).is_empty());
+}
+
+#[test]
+fn iter_diagnostic() {
+    // every key ends up in the same bin
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    for i in 0..5 {
+        map.insert(i, i * 10);
+    }
+
+    let guard = epoch::pin();
+    let entries: Vec<_> = map.iter_diagnostic(&guard).collect();
+    assert_eq!(entries.len(), 5);
+    for (depth, e) in entries.iter().enumerate() {
+        assert_eq!(e.chain_depth, depth);
+        assert_eq!(e.bin_index, 3);
+        assert_eq!(e.hash, 3);
+        assert_eq!(*e.value, *e.key * 10);
+    }
+    // nodes are appended to the end of their bin
+    let keys: Vec<_> = entries.iter().map(|e| *e.key).collect();
+    assert_eq!(keys, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn get_with_neighbors() {
+    // every key ends up in the same bin
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    let guard = epoch::pin();
+    assert_eq!(map.get_with_neighbors(&0, &guard), None);
+    map.insert(0, 0);
+    assert_eq!(map.get_with_neighbors(&0, &guard), Some((&0, vec![])));
+
+    for i in 1..5 {
+        map.insert(i, i * 10);
+    }
+    assert_eq!(
+        map.get_with_neighbors(&2, &guard),
+        Some((&20, vec![&0, &10, &30, &40]))
+    );
+    assert_eq!(map.get_with_neighbors(&9, &guard), None);
+}
+
+#[test]
+fn iter_diagnostic_spread() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    let bins = map.bin_stats(&guard).bins;
+    let mut seen = 0;
+    for e in map.iter_diagnostic(&guard) {
+        assert_eq!(e.bin_index, e.hash as usize & (bins - 1));
+        seen += 1;
+    }
+    assert_eq!(seen, 1000);
+    // the deepest entry sits at the end of the longest chain
+    let deepest = map
+        .iter_diagnostic(&guard)
+        .map(|e| e.chain_depth)
+        .max()
+        .unwrap();
+    assert_eq!(deepest + 1, map.bin_stats(&guard).max_len());
+}
+
+#[test]
+fn iter_with_shard() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashMap;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+    // a hasher that another system can reproduce
+    let build_hasher = BuildHasherDefault::<DefaultHasher>::default();
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(build_hasher.clone());
+    for i in 0..100 {
+        map.insert(i, i * 10);
+    }
+
+    let guard = epoch::pin();
+    let shards: HashMap<usize, usize> = map
+        .iter_with_shard(7, &guard)
+        .map(|(shard, k, v)| {
+            assert_eq!(*v, k * 10);
+            let mut h = build_hasher.build_hasher();
+            k.hash(&mut h);
+            assert_eq!(shard as u64, h.finish() % 7);
+            (*k, shard)
+        })
+        .collect();
+    assert_eq!(shards.len(), 100);
+    assert!(shards.values().all(|&shard| shard < 7));
+
+    // the same shards again, even after a resize
+    for i in 100..1000 {
+        map.insert(i, i * 10);
+    }
+    for (shard, k, _) in map.iter_with_shard(7, &guard) {
+        if let Some(&before) = shards.get(k) {
+            assert_eq!(shard, before);
+        }
+    }
+    assert_eq!(
+        map.iter_with_shard(1, &guard).filter(|e| e.0 != 0).count(),
+        0
+    );
+}
+
+#[test]
+fn iter_partitioned() {
+    use std::collections::hash_map::DefaultHasher;
+    use std::collections::HashSet;
+    use std::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+    let build_hasher = BuildHasherDefault::<DefaultHasher>::default();
+    let map = FlurryHashMap::<usize, String, _>::with_hasher(build_hasher.clone());
+    let guard = epoch::pin();
+    let [a, b, c] = map.iter_partitioned::<3>(&guard);
+    assert!(a.is_empty() && b.is_empty() && c.is_empty());
+
+    for i in 0..1000 {
+        map.insert(i, i.to_string());
+    }
+    let partitions = map.iter_partitioned::<5>(&guard);
+    let mut all = HashSet::new();
+    for (p, partition) in partitions.iter().enumerate() {
+        assert!(!partition.is_empty());
+        for (k, v) in partition {
+            let mut h = build_hasher.build_hasher();
+            k.hash(&mut h);
+            assert_eq!(h.finish() % 5, p as u64);
+            assert_eq!(*v, k.to_string());
+            // no entry is in two partitions
+            assert!(all.insert(*k));
+        }
+    }
+    assert_eq!(all, (0..1000).collect::<HashSet<_>>());
+
+    let [only] = map.iter_partitioned::<1>(&guard);
+    assert_eq!(only.len(), 1000);
+}
+
+#[test]
+fn decrement_and_remove_at_zero() {
+    let map = FlurryHashMap::<&str, usize>::new();
+    map.insert("a", 3);
+    map.insert("zero", 0);
+
+    let guard = epoch::pin();
+    assert_eq!(map.decrement_and_remove_at_zero(&"a", &guard), Some(2));
+    assert_eq!(map.decrement_and_remove_at_zero(&"a", &guard), Some(1));
+    assert_eq!(map.get(&"a", &guard), Some(&1));
+    assert_eq!(map.decrement_and_remove_at_zero(&"a", &guard), Some(0));
+    assert!(!map.contains_key(&"a"));
+    assert_eq!(map.decrement_and_remove_at_zero(&"a", &guard), None);
+
+    assert_eq!(map.decrement_and_remove_at_zero(&"zero", &guard), Some(0));
+    assert!(map.is_empty());
+}
+
+#[test]
+fn decrement_and_remove_at_zero_concurrent() {
+    const N: usize = 64;
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    map.insert(0, N);
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                (0..N / 4)
+                    .map(|_| map.decrement_and_remove_at_zero(&0, &guard).unwrap())
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut seen: Vec<_> = threads
+        .into_iter()
+        .flat_map(|t| t.join().unwrap())
+        .collect();
+    seen.sort();
+
+    // every decrementer saw a different count, and only the last one saw the removal
+    assert_eq!(seen, (0..N).collect::<Vec<_>>());
+    assert!(map.is_empty());
+}
+
+#[test]
+fn collect_array() {
+    let map = FlurryHashMap::<usize, String>::new();
+    let guard = epoch::pin();
+    let (sample, total) = map.collect_array::<4>(&guard);
+    assert!(sample.is_empty());
+    assert_eq!(total, 0);
+
+    for i in 0..10 {
+        map.insert(i, i.to_string());
+    }
+    let (sample, total) = map.collect_array::<4>(&guard);
+    assert_eq!(total, 10);
+    assert!(sample.is_full());
+    assert_eq!(sample.capacity(), 4);
+    for (k, v) in sample.iter() {
+        assert_eq!(map.get(k, &guard), Some(v));
+    }
+
+    // room to spare
+    let (sample, total) = map.collect_array::<16>(&guard);
+    assert_eq!((sample.len(), total), (10, 10));
+    assert!(!sample.is_full());
+}
+
+#[test]
+fn move_matching_to() {
+    let hot = FlurryHashMap::<usize, usize>::new();
+    let cold = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        hot.insert(i, i);
+    }
+    cold.insert(10, 0);
+
+    let guard = epoch::pin();
+    let moved = hot.move_matching_to(&cold, |&k, _| k % 10 == 0, &guard);
+    assert_eq!(moved, 10);
+    assert_eq!(hot.len(), 90);
+    assert_eq!(cold.len(), 10);
+    for i in (0..100).step_by(10) {
+        assert_eq!(hot.get(&i, &guard), None);
+        // including over a value the key already had
+        assert_eq!(cold.get(&i, &guard), Some(&i));
+    }
+
+    // nothing matches any more
+    assert_eq!(hot.move_matching_to(&cold, |&k, _| k % 10 == 0, &guard), 0);
+    assert_eq!(hot.move_matching_to(&cold, |_, &v| v > 1000, &guard), 0);
+}
+
+#[test]
+fn key_bounds() {
+    use rand::seq::SliceRandom;
+
+    let map = FlurryHashMap::<i64, ()>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.key_bounds(&guard), None);
+
+    let mut keys: Vec<i64> = (-500..1500).collect();
+    keys.shuffle(&mut rand::thread_rng());
+    for &k in &keys {
+        map.insert(k, ());
+    }
+    assert_eq!(map.key_bounds(&guard), Some((-500, 1499)));
+
+    map.remove(&1499, &guard);
+    assert_eq!(map.key_bounds(&guard), Some((-500, 1498)));
+
+    let single = FlurryHashMap::<i64, ()>::new();
+    single.insert(7, ());
+    assert_eq!(single.key_bounds(&guard), Some((7, 7)));
+}
+
+#[test]
+fn split_for() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..1003 {
+        map.insert(i, i * 2);
+    }
+
+    let guard = epoch::pin();
+    let shares = map.split_for(4, &guard);
+    assert_eq!(shares.len(), 4);
+    let lens: Vec<_> = shares.iter().map(|s| s.len()).collect();
+    assert!(lens.iter().max().unwrap() - lens.iter().min().unwrap() <= 1);
+
+    // each share can be consumed on its own thread
+    let threads: Vec<_> = shares
+        .into_iter()
+        .map(|share| std::thread::spawn(move || share.collect::<Vec<_>>()))
+        .collect();
+    let mut all: Vec<_> = threads
+        .into_iter()
+        .flat_map(|t| t.join().unwrap())
+        .collect();
+    all.sort();
+    assert_eq!(all, (0..1003).map(|i| (i, i * 2)).collect::<Vec<_>>());
+
+    // more consumers than entries
+    let shares = map.split_for(2000, &guard);
+    assert_eq!(shares.len(), 2000);
+    assert_eq!(shares.iter().filter(|s| s.len() == 1).count(), 1003);
+    assert_eq!(
+        shares.iter().map(ExactSizeIterator::len).sum::<usize>(),
+        1003
+    );
+}
+
+#[test]
+#[should_panic]
+fn split_for_no_consumers() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.split_for(0, &epoch::pin());
+}
+
+#[test]
+fn apply_deltas() {
+    let map = FlurryHashMap::<&str, usize>::new();
+    let guard = epoch::pin();
+    map.insert("c", 7);
+
+    map.apply_deltas(vec![("a", 3), ("a", 2), ("b", 5)], &guard);
+    assert_eq!(map.get(&"a", &guard), Some(&5));
+    assert_eq!(map.get(&"b", &guard), Some(&5));
+    assert_eq!(map.get(&"c", &guard), Some(&7));
+    assert_eq!(map.len(), 3);
+
+    map.apply_deltas(vec![("c", 1), ("a", 10)], &guard);
+    assert_eq!(map.get(&"a", &guard), Some(&15));
+    assert_eq!(map.get(&"c", &guard), Some(&8));
+    map.apply_deltas(Vec::new(), &guard);
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn apply_deltas_same_bin() {
+    // every key ends up in the same bin
+    let map = FlurryHashMap::<usize, usize, _>::with_hasher(ConstantHasher::default());
+    let guard = epoch::pin();
+    map.apply_deltas((0..10).map(|i| (i % 4, i)), &guard);
+    assert_eq!(map.len(), 4);
+    for k in 0..4 {
+        let expected: usize = (0..10).filter(|i| i % 4 == k).sum();
+        assert_eq!(map.get(&k, &guard), Some(&expected));
+    }
+}
+
+#[test]
+fn apply_deltas_concurrent() {
+    const THREADS: usize = 4;
+    const ROUNDS: usize = 100;
+
+    let map = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let threads: Vec<_> = (0..THREADS)
+        .map(|_| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for _ in 0..ROUNDS {
+                    map.apply_deltas((0..64).map(|k| (k, 1)), &guard);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(map.len(), 64);
+    for k in 0..64 {
+        assert_eq!(map.get(&k, &guard), Some(&(THREADS * ROUNDS)));
+    }
+}
+
+#[test]
+fn insert_coalesced() {
+    let map = FlurryHashMap::<usize, usize>::builder()
+        .coalesce_writes(std::time::Duration::from_secs(3600))
+        .build();
+    let guard = epoch::pin();
+    map.insert(1, 0);
+    for i in 1..=1000 {
+        map.insert_coalesced(1, i, &guard);
+        map.insert_coalesced(2, i * 2, &guard);
+    }
+    // nothing is flushed within the interval
+    assert_eq!(map.get(&1, &guard), Some(&0));
+    assert_eq!(map.get(&2, &guard), None);
+
+    map.flush_coalesced(&guard);
+    assert_eq!(map.get(&1, &guard), Some(&1000));
+    assert_eq!(map.get(&2, &guard), Some(&2000));
+    assert_eq!(map.len(), 2);
+
+    // an elapsed interval flushes the shard as part of the write
+    let map = FlurryHashMap::<usize, usize>::builder()
+        .coalesce_writes(std::time::Duration::from_secs(0))
+        .build();
+    map.insert_coalesced(1, 42, &guard);
+    assert_eq!(map.get(&1, &guard), Some(&42));
+
+    // and without coalescing, writes are inserted right away
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert_coalesced(1, 42, &guard);
+    assert_eq!(map.get(&1, &guard), Some(&42));
+}
+
+#[test]
+fn insert_coalesced_concurrent() {
+    // every key is staged in the same shard, so the threads keep flushing each other's writes.
+    // the log slows the flushes down, so that the threads often switch while applying them.
+    const THREADS: usize = 4;
+    let map = Arc::new(
+        FlurryHashMap::<usize, usize, _>::builder()
+            .hasher(ConstantHasher::default())
+            .coalesce_writes(std::time::Duration::from_secs(3600))
+            .wal(|_| std::thread::yield_now())
+            .build(),
+    );
+    let threads: Vec<_> = (0..THREADS)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                let guard = epoch::pin();
+                for i in 1..=2000 {
+                    map.insert_coalesced(t, i, &guard);
+                    // so that another thread may take the write from the shard
+                    std::thread::yield_now();
+                    // only this thread writes its key, so once the flush returns, whichever
+                    // thread took the write from the shard has inserted it, and no earlier write
+                    // can be inserted after it
+                    map.flush_coalesced(&guard);
+                    assert_eq!(map.get(&t, &guard), Some(&i));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+
+    let guard = epoch::pin();
+    for t in 0..THREADS {
+        assert_eq!(map.get(&t, &guard), Some(&2000));
+    }
+}
+
+/// Compares full-scan throughput with and without prefetching on a map that does not fit in the
+/// processor's caches. Run with `cargo test --release -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn scan_throughput() {
+    use std::time::{Duration, Instant};
+
+    const ENTRIES: usize = 2_000_000;
+    const SCANS: usize = 10;
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..ENTRIES {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    let sum = ENTRIES * (ENTRIES - 1) / 2;
+    let (mut plain, mut prefetched) = (Duration::from_secs(0), Duration::from_secs(0));
+    // interleaved, and in alternating order, so that neither is favored by the state of the
+    // caches or of the machine
+    for scan in 0..SCANS {
+        for &prefetch in &[scan % 2 == 0, scan % 2 == 1] {
+            let start = Instant::now();
+            if prefetch {
+                assert_eq!(
+                    map.iter_prefetch(&guard).map(|(_, v)| *v).sum::<usize>(),
+                    sum
+                );
+                prefetched += start.elapsed();
+            } else {
+                assert_eq!(map.iter(&guard).map(|(_, v)| *v).sum::<usize>(), sum);
+                plain += start.elapsed();
+            }
+        }
+    }
+    println!(
+        "{} entries: iter {:?}, iter_prefetch {:?} per scan",
+        ENTRIES,
+        plain / SCANS as u32,
+        prefetched / SCANS as u32
+    );
+}
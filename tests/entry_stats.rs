@@ -0,0 +1,49 @@
+#![cfg(feature = "entry-stats")]
+
+use crossbeam::epoch;
+use flurry::*;
+
+#[test]
+fn entry_stats() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(map.entry_stats(&1, &guard), None);
+
+    map.insert(1, 1);
+    map.insert(2, 2);
+    for _ in 0..5 {
+        assert_eq!(map.get(&1, &guard), Some(&1));
+    }
+    assert!(map.contains_key(&1));
+    map.insert(1, 10);
+    map.merge(1, 1, |a, b| a + b, &guard);
+
+    let stats = map.entry_stats(&1, &guard).unwrap();
+    assert_eq!((stats.reads, stats.writes), (6, 3));
+    // looking at the stats is not a read, and other keys are counted separately
+    assert_eq!(map.entry_stats(&1, &guard).unwrap(), stats);
+    let other = map.entry_stats(&2, &guard).unwrap();
+    assert_eq!((other.reads, other.writes), (0, 1));
+
+    // the counts start over with a new entry
+    map.remove(&1, &guard);
+    assert_eq!(map.entry_stats(&1, &guard), None);
+    map.compute_if_absent(1, |_| 0, &guard);
+    let stats = map.entry_stats(&1, &guard).unwrap();
+    assert_eq!((stats.reads, stats.writes), (0, 1));
+}
+
+#[test]
+fn entry_stats_resize() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(0, 0);
+    let guard = epoch::pin();
+    map.get(&0, &guard);
+
+    // the counts move with the entry to the larger table
+    for i in 1..1000 {
+        map.insert(i, i);
+    }
+    let stats = map.entry_stats(&0, &guard).unwrap();
+    assert_eq!((stats.reads, stats.writes), (1, 1));
+}
@@ -29,8 +29,8 @@ where
     K: Sync + Send + Copy + Hash + Eq,
 {
     let mut sum = 0;
-    for i in 0..keys.len() {
-        if map.insert(keys[i], 0).is_none() {
+    for key in keys {
+        if map.insert(*key, 0).is_none() {
             sum += 1;
         }
     }
@@ -42,8 +42,8 @@ where
     K: Sync + Send + Copy + Hash + Eq,
 {
     let mut sum = 0;
-    for i in 0..keys.len() {
-        if map.contains_key(&keys[i]) {
+    for key in keys {
+        if map.contains_key(key) {
             sum += 1;
         }
     }
@@ -113,19 +113,19 @@ fn everything() {
     let keys = &keys[ABSENT_SIZE..];
 
     // put (absent)
-    t3(&map, &keys[..], SIZE);
+    t3(&map, keys, SIZE);
     // put (present)
-    t3(&map, &keys[..], 0);
+    t3(&map, keys, 0);
     // contains_key (present & absent)
-    t7(&map, &keys[..], &absent_keys[..]);
+    t7(&map, keys, absent_keys);
     // contains_key (present)
-    t4(&map, &keys[..], SIZE);
+    t4(&map, keys, SIZE);
     // contains_key (absent)
-    t4(&map, &absent_keys[..], 0);
+    t4(&map, absent_keys, 0);
     // get (present)
-    t1(&map, &keys[..], SIZE);
+    t1(&map, keys, SIZE);
     // get (absent)
-    t1(&map, &absent_keys[..], 0);
+    t1(&map, absent_keys, 0);
 
     // iter, keys, values (present)
     ittest1(&map, SIZE);
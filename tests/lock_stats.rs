@@ -0,0 +1,34 @@
+#![cfg(feature = "lock-stats")]
+
+use crossbeam::epoch;
+use flurry::stats::Operation;
+use flurry::*;
+use std::time::Duration;
+
+#[test]
+fn slow_compute_holds_lock_longer() {
+    let map = FlurryHashMap::<usize, usize>::new();
+
+    // enough keys that some inserts go to non-empty bins and have to take the lock
+    for i in 0..64 {
+        map.insert(i, i);
+    }
+    let guard = epoch::pin();
+    map.compute_if_absent(
+        1000,
+        |_| {
+            std::thread::sleep(Duration::from_millis(20));
+            0
+        },
+        &guard,
+    );
+
+    let stats = map.lock_hold_stats();
+    let insert = stats.get(Operation::Insert);
+    let compute = stats.get(Operation::ComputeIfAbsent);
+    assert!(insert.count > 0);
+    assert_eq!(compute.count, 1);
+    assert!(compute.max >= Duration::from_millis(20));
+    assert!(compute.max > insert.max);
+    assert!(compute.mean().unwrap() > insert.mean().unwrap());
+}
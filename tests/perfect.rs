@@ -0,0 +1,71 @@
+use flurry::FlurryHashMap;
+use std::hash::{BuildHasherDefault, Hasher};
+
+#[test]
+fn single_probe() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..10_000 {
+        map.insert(i, i * 2);
+    }
+
+    let frozen = map.freeze_perfect();
+    assert_eq!(frozen.len(), 10_000);
+    let mut seen = vec![false; frozen.len()];
+    for i in 0..10_000 {
+        assert_eq!(frozen.get(&i), Some(&(i * 2)));
+        // the one slot probed holds the key, and no other key probes the same slot
+        let slot = frozen.index_of(&i).unwrap();
+        assert_eq!(frozen.entries()[slot], (i, i * 2));
+        assert!(!seen[slot]);
+        seen[slot] = true;
+    }
+}
+
+#[test]
+fn absent() {
+    let map = FlurryHashMap::<String, usize>::new();
+    for i in 0..100 {
+        map.insert(i.to_string(), i);
+    }
+
+    let frozen = map.freeze_perfect();
+    for i in 100..10_000 {
+        assert_eq!(frozen.get(&i.to_string()), None);
+        assert!(!frozen.contains_key(&i.to_string()));
+    }
+    assert!(frozen.contains_key(&"42".to_string()));
+}
+
+#[test]
+fn small() {
+    let frozen = FlurryHashMap::<usize, usize>::new().freeze_perfect();
+    assert!(frozen.is_empty());
+    assert_eq!(frozen.get(&0), None);
+
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert(7, 7);
+    let frozen = map.freeze_perfect();
+    assert_eq!(frozen.entries(), &[(7, 7)]);
+    assert_eq!(frozen.get(&7), Some(&7));
+    assert_eq!(frozen.get(&8), None);
+}
+
+#[test]
+#[should_panic(expected = "cannot tell the keys apart")]
+fn indistinguishable_keys() {
+    // every key hashes the same, whatever seed is mixed in
+    #[derive(Default)]
+    struct Constant;
+    impl Hasher for Constant {
+        fn finish(&self) -> u64 {
+            0
+        }
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    let map =
+        FlurryHashMap::<usize, usize, _>::with_hasher(BuildHasherDefault::<Constant>::default());
+    map.insert(1, 1);
+    map.insert(2, 2);
+    map.freeze_perfect();
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "priority")]
+
+use crossbeam::epoch;
+use flurry::*;
+
+#[test]
+fn evict_lowest() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let priorities = [50, 7, 90, 3, 42, 11, 66, 5, 80, 23];
+    for (key, &priority) in priorities.iter().enumerate() {
+        map.insert_with_priority(key, key, priority);
+    }
+
+    let guard = epoch::pin();
+    let mut evicted = map.evict_lowest(3, &guard);
+    evicted.sort();
+    // priorities 3, 5, and 7
+    assert_eq!(evicted, vec![1, 3, 7]);
+    assert_eq!(map.len(), 7);
+    for key in &[1, 3, 7] {
+        assert!(!map.contains_key(key));
+    }
+
+    // then 11
+    assert_eq!(map.evict_lowest(1, &guard), vec![5]);
+    // more than there are
+    assert_eq!(map.evict_lowest(100, &guard).len(), 6);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn priority_survives_replace_and_resize() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    map.insert_with_priority(0, 0, 1);
+    for i in 1..1000 {
+        map.insert_with_priority(i, i, 10 + i as u64);
+    }
+    // keeps its priority
+    map.insert(0, 42);
+    // plain inserts have priority 0
+    map.insert(1000, 1000);
+
+    let guard = epoch::pin();
+    let mut evicted = map.evict_lowest(2, &guard);
+    evicted.sort();
+    assert_eq!(evicted, vec![0, 1000]);
+}
+
+#[test]
+fn peek_eviction_candidate() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..100 {
+        map.insert_with_priority(i, i, 100 - i as u64);
+    }
+
+    let guard = epoch::pin();
+    assert_eq!(
+        map.peek_eviction_candidate(EvictionPolicy::LowestPriority, &guard),
+        Some(&99)
+    );
+    assert_eq!(map.evict_lowest(1, &guard), vec![99]);
+    assert_eq!(
+        map.peek_eviction_candidate(EvictionPolicy::LowestPriority, &guard),
+        Some(&98)
+    );
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "rayon")]
+
+use crossbeam::epoch;
+use flurry::*;
+use std::collections::HashMap;
+
+#[test]
+fn par_group_reduce() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..10_000 {
+        map.insert(i, i * 3);
+    }
+
+    let guard = epoch::pin();
+    let sums = map.par_group_reduce(|k, _| k % 7, |_, v| *v as u64, |a, b| a + b, &guard);
+
+    let mut expected = HashMap::new();
+    for (k, v) in map.iter(&guard) {
+        *expected.entry(k % 7).or_insert(0u64) += *v as u64;
+    }
+    assert_eq!(sums, expected);
+}
+
+#[test]
+fn par_group_reduce_empty() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    let groups = map.par_group_reduce(|k, _| *k, |_, v| *v, |a, b| a + b, &guard);
+    assert!(groups.is_empty());
+}
@@ -0,0 +1,38 @@
+use crossbeam::epoch;
+use flurry::set::FlurryHashSet;
+
+#[test]
+fn insert_contains_remove() {
+    let set = FlurryHashSet::new();
+    assert!(set.insert(1));
+    assert!(!set.insert(1));
+    assert!(set.contains(&1));
+    assert_eq!(set.len(), 1);
+
+    let guard = epoch::pin();
+    assert!(set.remove(&1, &guard));
+    assert!(!set.remove(&1, &guard));
+    assert!(!set.contains(&1));
+    assert!(set.is_empty());
+}
+
+#[test]
+fn difference_into() {
+    let a = FlurryHashSet::new();
+    let b = FlurryHashSet::new();
+    let dst = FlurryHashSet::new();
+    for i in 0..100 {
+        a.insert(i);
+    }
+    for i in 50..150 {
+        b.insert(i);
+    }
+    // already present, and unique to a
+    dst.insert(0);
+
+    let guard = epoch::pin();
+    a.difference_into(&b, &dst, &guard);
+    let mut elements: Vec<_> = dst.iter(&guard).copied().collect();
+    elements.sort_unstable();
+    assert_eq!(elements, (0..50).collect::<Vec<_>>());
+}
@@ -0,0 +1,120 @@
+use crossbeam::epoch;
+use flurry::sharded::ShardedMap;
+use flurry::FlurryHashMap;
+use std::collections::hash_map::RandomState;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[test]
+fn routes_consistently() {
+    let map = ShardedMap::<usize, usize, RandomState, 8>::new();
+    for i in 0..1000 {
+        assert_eq!(map.insert(i, i), None);
+    }
+
+    let guard = epoch::pin();
+    for i in 0..1000 {
+        let shard = map.shard_index(&i);
+        assert!(shard < 8);
+        assert_eq!(shard, map.shard_index(&i));
+        // the entry lives in its shard, and in no other
+        for (j, s) in map.shards().iter().enumerate() {
+            assert_eq!(s.get(&i, &guard).is_some(), j == shard);
+        }
+        assert_eq!(map.get(&i, &guard), Some(&i));
+        assert!(map.contains_key(&i));
+    }
+    // with this many keys, every shard gets some
+    assert!(map.shards().iter().all(|s| !s.is_empty()));
+}
+
+#[test]
+fn aggregates() {
+    let map = ShardedMap::<usize, usize, RandomState, 4>::new();
+    assert!(map.is_empty());
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.insert(0, 1), Some(()));
+    assert_eq!(map.len(), 100);
+    assert_eq!(
+        map.shards().iter().map(FlurryHashMap::len).sum::<usize>(),
+        100
+    );
+
+    let guard = epoch::pin();
+    for i in 0..50 {
+        assert!(map.remove(&i, &guard).is_some());
+    }
+    assert_eq!(map.remove(&0, &guard), None);
+    assert_eq!(map.len(), 50);
+    assert!(!map.is_empty());
+}
+
+#[test]
+fn concurrent_insert() {
+    let map = Arc::new(ShardedMap::<usize, usize, RandomState, 4>::new());
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            let map = map.clone();
+            std::thread::spawn(move || {
+                for i in 0..1000 {
+                    map.insert(t * 1000 + i, i);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(map.len(), 4000);
+}
+
+fn throughput<F: Fn(usize) + Sync + Send + 'static>(threads: usize, insert: Arc<F>) -> Duration {
+    const PER_THREAD: usize = 200_000;
+    let start = Instant::now();
+    let threads: Vec<_> = (0..threads)
+        .map(|t| {
+            let insert = insert.clone();
+            std::thread::spawn(move || {
+                for i in 0..PER_THREAD {
+                    insert(t * PER_THREAD + i);
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+    start.elapsed()
+}
+
+/// Compares insert throughput against a single map. Run with `cargo test --release -- --ignored
+/// --nocapture`, with `FLURRY_BENCH_THREADS` set to the number of cores.
+#[test]
+#[ignore]
+fn insert_throughput() {
+    let threads = std::env::var("FLURRY_BENCH_THREADS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(16);
+
+    let single = Arc::new(FlurryHashMap::<usize, usize>::new());
+    let single = throughput(
+        threads,
+        Arc::new(move |i| {
+            single.insert(i, i);
+        }),
+    );
+    let sharded = Arc::new(ShardedMap::<usize, usize, RandomState, 64>::new());
+    let sharded = throughput(
+        threads,
+        Arc::new(move |i| {
+            sharded.insert(i, i);
+        }),
+    );
+    println!(
+        "{} threads: single map {:?}, 64 shards {:?}",
+        threads, single, sharded
+    );
+}
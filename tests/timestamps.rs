@@ -0,0 +1,157 @@
+#![cfg(feature = "timestamps")]
+
+use crossbeam::epoch;
+use flurry::*;
+use std::time::{Duration, Instant};
+
+#[test]
+fn iter_older_than() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    std::thread::sleep(Duration::from_millis(5));
+    let cutoff = Instant::now();
+    std::thread::sleep(Duration::from_millis(5));
+    for i in 10..20 {
+        map.insert(i, i);
+    }
+    // replacing a value makes the entry new again
+    map.insert(0, 100);
+
+    let guard = epoch::pin();
+    let mut old: Vec<_> = map
+        .iter_older_than(cutoff, &guard)
+        .map(|(&k, &v)| (k, v))
+        .collect();
+    old.sort();
+    assert_eq!(old, (1..10).map(|i| (i, i)).collect::<Vec<_>>());
+
+    // reads do not make an entry new
+    assert_eq!(map.get(&1, &guard), Some(&1));
+    assert_eq!(map.iter_older_than(cutoff, &guard).count(), 9);
+    assert_eq!(map.iter_older_than(Instant::now(), &guard).count(), 20);
+}
+
+#[test]
+fn iter_older_than_resize() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    std::thread::sleep(Duration::from_millis(5));
+    let cutoff = Instant::now();
+    // entries keep when they were written when they are moved to a larger table
+    for i in 10..1000 {
+        map.insert(i, i);
+    }
+
+    let guard = epoch::pin();
+    let mut old: Vec<_> = map
+        .iter_older_than(cutoff, &guard)
+        .map(|(&k, _)| k)
+        .collect();
+    old.sort();
+    assert_eq!(old, (0..10).collect::<Vec<_>>());
+}
+
+#[test]
+fn peek_eviction_candidate() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    assert_eq!(
+        map.peek_eviction_candidate(EvictionPolicy::OldestInsertion, &guard),
+        None
+    );
+
+    map.insert(7, 7);
+    std::thread::sleep(Duration::from_millis(5));
+    // enough to resize, which must keep when each entry was inserted
+    for i in 0..1000 {
+        if i != 7 {
+            map.insert(i, i);
+        }
+    }
+    std::thread::sleep(Duration::from_millis(5));
+    // replacing the value makes the entry newly written, but not newly inserted
+    map.insert(7, 0);
+
+    assert_eq!(
+        map.peek_eviction_candidate(EvictionPolicy::OldestInsertion, &guard),
+        Some(&7)
+    );
+    assert_ne!(
+        map.peek_eviction_candidate(EvictionPolicy::LeastRecentlyWritten, &guard),
+        Some(&7)
+    );
+    // nothing was evicted
+    assert_eq!(map.len(), 1000);
+}
+
+#[test]
+fn soft_cap_least_recently_written() {
+    let map = FlurryHashMap::<usize, usize>::builder()
+        .soft_cap(100, EvictionPolicy::LeastRecentlyWritten)
+        .build();
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    std::thread::sleep(Duration::from_millis(5));
+    // rewriting a key keeps it from being evicted first
+    map.insert(0, 1);
+    std::thread::sleep(Duration::from_millis(5));
+    for i in 100..150 {
+        map.insert(i, i);
+    }
+
+    assert_eq!(map.len(), 100);
+    let guard = epoch::pin();
+    let mut keys: Vec<_> = map.keys(&guard).copied().collect();
+    keys.sort_unstable();
+    let expected: Vec<_> = std::iter::once(0).chain(51..150).collect();
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn window_summary() {
+    let map = FlurryHashMap::<usize, usize>::new();
+    let guard = epoch::pin();
+    let sum = |acc: usize, _: &usize, v: &usize| acc + v;
+    assert_eq!(
+        map.window_summary(Duration::from_secs(60), 0, sum, &guard),
+        0
+    );
+
+    for i in 0..10 {
+        map.insert(i, 1);
+    }
+    std::thread::sleep(Duration::from_millis(100));
+    for i in 10..15 {
+        map.insert(i, 10);
+    }
+    // replacing a value brings the entry back into the window
+    map.insert(0, 100);
+
+    assert_eq!(
+        map.window_summary(Duration::from_millis(50), 0, sum, &guard),
+        5 * 10 + 100
+    );
+    let keys = map.window_summary(
+        Duration::from_millis(50),
+        Vec::new(),
+        |mut keys, k, _| {
+            keys.push(*k);
+            keys
+        },
+        &guard,
+    );
+    let mut keys = keys;
+    keys.sort_unstable();
+    assert_eq!(keys, vec![0, 10, 11, 12, 13, 14]);
+
+    // a wide enough window covers everything
+    assert_eq!(
+        map.window_summary(Duration::from_secs(3600), 0, sum, &guard),
+        9 + 5 * 10 + 100
+    );
+}
@@ -0,0 +1,103 @@
+use crossbeam::epoch;
+use flurry::wal::WalRecord;
+use flurry::*;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, PartialEq, Eq)]
+enum Record {
+    Insert(usize, usize),
+    Replace(usize, usize, usize),
+    Remove(usize, usize),
+}
+
+fn logged_map() -> (FlurryHashMap<usize, usize>, Arc<Mutex<Vec<Record>>>) {
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let map = {
+        let log = log.clone();
+        FlurryHashMap::builder()
+            .wal(move |r: &WalRecord<'_, usize, usize>| {
+                let r = match *r {
+                    WalRecord::Insert { key, value } => Record::Insert(*key, *value),
+                    WalRecord::Replace { key, old, new } => Record::Replace(*key, *old, *new),
+                    WalRecord::Remove { key, value } => Record::Remove(*key, *value),
+                    _ => unreachable!(),
+                };
+                log.lock().unwrap().push(r);
+            })
+            .build()
+    };
+    (map, log)
+}
+
+#[test]
+fn records_mutations_in_order() {
+    let (map, log) = logged_map();
+
+    map.insert(1, 10);
+    map.insert(2, 20);
+    map.insert(1, 11);
+    let guard = epoch::pin();
+    map.compute_if_absent(3, |_| 30, &guard);
+    // present, so not a mutation
+    map.compute_if_absent(3, |_| 31, &guard);
+    map.insert(3, 32);
+    map.remove(&2, &guard);
+    // absent, so not a mutation
+    map.remove(&4, &guard);
+
+    assert_eq!(
+        *log.lock().unwrap(),
+        vec![
+            Record::Insert(1, 10),
+            Record::Insert(2, 20),
+            Record::Replace(1, 10, 11),
+            Record::Insert(3, 30),
+            Record::Replace(3, 30, 32),
+            Record::Remove(2, 20),
+        ]
+    );
+    assert_eq!(map.get(&1, &guard), Some(&11));
+    assert_eq!(map.get(&3, &guard), Some(&32));
+}
+
+#[test]
+fn records_every_insert_across_resizes() {
+    let (map, log) = logged_map();
+
+    for i in 0..1000 {
+        map.insert(i, i);
+    }
+
+    let log = log.lock().unwrap();
+    assert_eq!(log.len(), 1000);
+    for (i, r) in log.iter().enumerate() {
+        assert_eq!(*r, Record::Insert(i, i));
+    }
+    let guard = epoch::pin();
+    for i in 0..1000 {
+        assert_eq!(map.get(&i, &guard), Some(&i));
+    }
+}
+
+#[test]
+fn reset_to() {
+    let (map, log) = logged_map();
+    map.insert(1, 10);
+    map.insert(2, 20);
+    log.lock().unwrap().clear();
+
+    let guard = epoch::pin();
+    map.reset_to(vec![(2, 21), (3, 30)], &guard);
+
+    let mut records = std::mem::take(&mut *log.lock().unwrap());
+    // the old entries are all removed before any of the new ones are inserted
+    let inserts = records.split_off(2);
+    records.sort_by_key(|r| match *r {
+        Record::Remove(k, _) => k,
+        _ => unreachable!(),
+    });
+    assert_eq!(records, vec![Record::Remove(1, 10), Record::Remove(2, 20)]);
+    assert_eq!(inserts.len(), 2);
+    assert!(inserts.contains(&Record::Insert(2, 21)));
+    assert!(inserts.contains(&Record::Insert(3, 30)));
+}